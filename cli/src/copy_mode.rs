@@ -0,0 +1,231 @@
+use crate::theme::Theme;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+
+/// A frozen, searchable copy of a run's captured output.
+pub struct CopyState {
+    pub instance_id: String,
+    lines: Vec<String>,
+    cursor: usize,
+    top: usize,
+    anchor: Option<usize>,
+    search: Option<String>,
+    last_search: Option<String>,
+    message: Option<String>,
+    page: usize,
+}
+
+pub enum CopyAction {
+    Stay,
+    Exit,
+    Yank(String),
+}
+
+impl CopyState {
+    pub fn new(instance_id: String, buffer: &[u8]) -> Self {
+        let lines = plain_lines(buffer);
+        let cursor = lines.len().saturating_sub(1);
+        Self {
+            instance_id,
+            lines,
+            cursor,
+            top: 0,
+            anchor: None,
+            search: None,
+            last_search: None,
+            message: None,
+            page: 1,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> CopyAction {
+        self.message = None;
+        if let Some(query) = self.search.as_mut() {
+            match key.code {
+                KeyCode::Esc => self.search = None,
+                KeyCode::Enter => {
+                    let query = std::mem::take(query);
+                    self.search = None;
+                    if !query.is_empty() {
+                        self.last_search = Some(query);
+                    }
+                    self.find(true);
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(ch) => query.push(ch),
+                _ => {}
+            }
+            return CopyAction::Stay;
+        }
+
+        let half_page = (self.page / 2).max(1);
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('q') => return CopyAction::Exit,
+            KeyCode::Esc if self.anchor.is_some() => self.anchor = None,
+            KeyCode::Esc => return CopyAction::Exit,
+            KeyCode::Char('d') if ctrl => self.move_by(half_page as isize),
+            KeyCode::Char('u') if ctrl => self.move_by(-(half_page as isize)),
+            KeyCode::Char('f') if ctrl => self.move_by(self.page as isize),
+            KeyCode::Char('b') if ctrl => self.move_by(-(self.page as isize)),
+            KeyCode::PageDown => self.move_by(self.page as isize),
+            KeyCode::PageUp => self.move_by(-(self.page as isize)),
+            KeyCode::Char('j') | KeyCode::Down => self.move_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_by(-1),
+            KeyCode::Char('g') | KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('G') | KeyCode::End => self.cursor = self.lines.len().saturating_sub(1),
+            KeyCode::Char('/') => self.search = Some(String::new()),
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            KeyCode::Char('v') | KeyCode::Char('V') | KeyCode::Char(' ') => {
+                self.anchor = match self.anchor {
+                    Some(_) => None,
+                    None => Some(self.cursor),
+                };
+            }
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let (start, end) = self.selection();
+                if self.lines.is_empty() {
+                    return CopyAction::Exit;
+                }
+                return CopyAction::Yank(self.lines[start..=end].join("\n"));
+            }
+            _ => {}
+        }
+        CopyAction::Stay
+    }
+
+    pub fn render(&mut self, frame: &mut ratatui::Frame, area: Rect, theme: &Theme) {
+        let height = area.height.saturating_sub(3) as usize;
+        self.page = height.max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.page {
+            self.top = self.cursor + 1 - self.page;
+        }
+
+        let (start, end) = self.selection();
+        let query = self.last_search.as_deref().filter(|q| !q.is_empty());
+        let mut rows = Vec::new();
+        for (idx, text) in self.lines.iter().enumerate().skip(self.top).take(self.page) {
+            let base = if idx == self.cursor {
+                theme.highlight
+            } else if self.anchor.is_some() && idx >= start && idx <= end {
+                Style::default().bg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            rows.push(highlight_matches(text, query, base));
+        }
+
+        let title = format!(
+            "Copy: {} [{}/{}]",
+            self.instance_id,
+            if self.lines.is_empty() { 0 } else { self.cursor + 1 },
+            self.lines.len()
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, theme.title))
+            .border_style(theme.focus_border);
+        let content = Rect { height: area.height.saturating_sub(1), ..area };
+        frame.render_widget(Paragraph::new(rows).block(block), content);
+
+        let footer = match (&self.search, &self.message) {
+            (Some(query), _) => Line::from(format!("/{}", query)),
+            (None, Some(message)) => Line::from(Span::styled(message.clone(), theme.error)),
+            (None, None) => Line::from("j/k: move  ^u/^d: page  g/G: top/bottom  /: search  n/N: next/prev  v: select  y: yank  q: quit"),
+        };
+        let footer_area = Rect {
+            y: area.y + area.height.saturating_sub(1),
+            height: 1,
+            ..area
+        };
+        frame.render_widget(Paragraph::new(footer), footer_area);
+    }
+
+    fn selection(&self) -> (usize, usize) {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        (anchor.min(self.cursor), anchor.max(self.cursor))
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.lines.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last.max(0)) as usize;
+    }
+
+    /// Moves to the next (or previous) line containing the last search,
+    /// wrapping around the ends.
+    fn find(&mut self, forward: bool) {
+        let query = match self.last_search.as_deref() {
+            Some(query) => query.to_lowercase(),
+            None => return,
+        };
+        let count = self.lines.len();
+        for step in 1..=count {
+            let idx = if forward {
+                (self.cursor + step) % count
+            } else {
+                (self.cursor + count - step % count) % count
+            };
+            if self.lines[idx].to_lowercase().contains(&query) {
+                self.cursor = idx;
+                return;
+            }
+        }
+        self.message = Some(format!("Pattern not found: {}", query));
+    }
+}
+
+fn highlight_matches(text: &str, query: Option<&str>, base: Style) -> Line<'static> {
+    let query = match query {
+        Some(query) => query.to_lowercase(),
+        None => return Line::from(Span::styled(text.to_string(), base)),
+    };
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte lengths; fall back to no highlight then.
+    if lower.len() != text.len() {
+        return Line::from(Span::styled(text.to_string(), base));
+    }
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = lower[pos..].find(&query) {
+        let start = pos + found;
+        let end = start + query.len();
+        if start > pos {
+            spans.push(Span::styled(text[pos..start].to_string(), base));
+        }
+        spans.push(Span::styled(
+            text[start..end].to_string(),
+            base.fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ));
+        pos = end;
+    }
+    if pos < text.len() {
+        spans.push(Span::styled(text[pos..].to_string(), base));
+    }
+    Line::from(spans)
+}
+
+/// Splits captured output into lines as a terminal would show them:
+/// carriage returns keep the last redraw, escape sequences are stripped.
+fn plain_lines(buffer: &[u8]) -> Vec<String> {
+    let buffer = buffer.strip_suffix(b"\n").unwrap_or(buffer);
+    if buffer.is_empty() {
+        return Vec::new();
+    }
+    buffer
+        .split(|&b| b == b'\n')
+        .map(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let visible = line.rsplit(|&b| b == b'\r').next().unwrap_or_default();
+            let plain = strip_ansi_escapes::strip(visible);
+            String::from_utf8_lossy(&plain).replace('\t', "    ")
+        })
+        .collect()
+}
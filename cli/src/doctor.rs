@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use crate::theme::Theme;
+use cmdhub_core::affinity::parse_cpuset;
+use cmdhub_core::backend::Backend;
+use cmdhub_core::prelude::*;
+use cmdhub_core::script::TaskScript;
+use cmdhub_core::storage;
+use portable_pty::{native_pty_system, PtySize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    level: Level,
+    name: &'static str,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { level: Level::Ok, name, detail: detail.into(), hint: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { level: Level::Warn, name, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { level: Level::Fail, name, detail: detail.into(), hint: Some(hint.into()) }
+    }
+}
+
+/// Runs all environment checks and prints a report; fails if any check failed.
+pub async fn run_doctor() -> Result<()> {
+    let mut checks = vec![check_shell(), check_pty(), check_temp_dir(), check_session_dir()];
+    checks.extend(check_config().await);
+    checks.push(check_orphaned_sessions());
+    checks.push(check_terminal());
+    checks.push(check_clipboard());
+    checks.push(check_notifications());
+
+    let color_enabled = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+    let mut failures = 0;
+    for check in &checks {
+        let (label, color) = match check.level {
+            Level::Ok => ("ok", "32"),
+            Level::Warn => ("warn", "33"),
+            Level::Fail => ("fail", "31"),
+        };
+        let tag = format!("[{:>4}]", label);
+        if color_enabled {
+            println!("\x1b[{}m{}\x1b[0m {}: {}", color, tag, check.name, check.detail);
+        } else {
+            println!("{} {}: {}", tag, check.name, check.detail);
+        }
+        if let Some(hint) = &check.hint {
+            println!("       -> {}", hint);
+        }
+        if check.level == Level::Fail {
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        return Err(anyhow!("{} check(s) failed", failures));
+    }
+    Ok(())
+}
+
+fn check_shell() -> Check {
+    match std::env::var("SHELL") {
+        Ok(shell) if Path::new(&shell).is_file() => Check::ok("shell", shell),
+        Ok(shell) => Check::fail(
+            "shell",
+            format!("$SHELL points to missing {}", shell),
+            "set SHELL to an installed shell, e.g. export SHELL=/bin/bash",
+        ),
+        Err(_) => match find_in_path("bash") {
+            Some(path) => Check::warn(
+                "shell",
+                format!("$SHELL not set, falling back to {}", path.display()),
+                "export SHELL to choose the shell used for tasks",
+            ),
+            None => Check::fail("shell", "$SHELL not set and bash not found", "install bash or export SHELL"),
+        },
+    }
+}
+
+fn check_pty() -> Check {
+    let size = PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 };
+    match native_pty_system().openpty(size) {
+        Ok(_) => Check::ok("pty", "allocated a pseudo-terminal"),
+        Err(err) => Check::fail(
+            "pty",
+            format!("cannot allocate a pseudo-terminal: {}", err),
+            "check that /dev/ptmx exists and devpts is mounted (containers may need --privileged or -t)",
+        ),
+    }
+}
+
+fn check_temp_dir() -> Check {
+    let dir = std::env::temp_dir();
+    match probe_writable(&dir) {
+        Ok(()) => Check::ok("temp dir", dir.display().to_string()),
+        Err(err) => Check::fail(
+            "temp dir",
+            format!("{} is not writable: {}", dir.display(), err),
+            "the bash integration script is written here; set TMPDIR to a writable directory",
+        ),
+    }
+}
+
+fn check_session_dir() -> Check {
+    let dir = match storage::data_dir() {
+        Ok(dir) => dir.join("sessions"),
+        Err(_) => return Check::fail("session dir", "HOME not set", "export HOME, or run with --no-store"),
+    };
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        return Check::fail(
+            "session dir",
+            format!("cannot create {}: {}", dir.display(), err),
+            "fix permissions on ~/.cmdhub, set CMDHUB_DATA_DIR, or run with --no-store",
+        );
+    }
+    match probe_writable(&dir) {
+        Ok(()) => Check::ok("session dir", dir.display().to_string()),
+        Err(err) => Check::fail(
+            "session dir",
+            format!("{} is not writable: {}", dir.display(), err),
+            "fix permissions on ~/.cmdhub/sessions, set CMDHUB_DATA_DIR, or run with --no-store",
+        ),
+    }
+}
+
+async fn check_config() -> Vec<Check> {
+    let paths = match resolve_config_paths() {
+        Ok(paths) => paths,
+        Err(err) => {
+            return vec![Check::fail(
+                "config",
+                err.to_string(),
+                "create config.toml in the working directory or ~/.config/cmdhub/",
+            )]
+        }
+    };
+    let config = match load_config_auto().await {
+        Ok(config) => config,
+        Err(err) => {
+            return vec![Check::fail(
+                "config",
+                err.to_string(),
+                "fix the TOML syntax or field types reported above",
+            )]
+        }
+    };
+
+    let shown = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let mut checks = vec![Check::ok("config", format!("{} ({} tasks)", shown, config.tasks.len()))];
+    if let Err(err) = storage::configure(config.store.as_ref()) {
+        checks.push(Check::fail("config", err.to_string(), "set [store] backend to \"files\" or \"sqlite\""));
+    }
+    if let Err(err) = Theme::resolve(config.theme.as_ref()) {
+        checks.push(Check::warn("config", err.to_string(), "set [theme] name to a built-in theme"));
+    }
+    let mut seen = HashSet::new();
+    for task in &config.tasks {
+        if !seen.insert(task.id.as_str()) {
+            checks.push(Check::warn(
+                "config",
+                format!("duplicate task id {}", task.id),
+                "give every task a unique id",
+            ));
+        }
+        if let Some(pattern) = &task.status_pattern {
+            if let Err(err) = regex::Regex::new(pattern) {
+                checks.push(Check::fail(
+                    "config",
+                    format!("task {}: invalid status_pattern: {}", task.id, err),
+                    "fix the regular expression",
+                ));
+            }
+        }
+        if let Some(cpuset) = &task.cpuset {
+            if let Err(err) = parse_cpuset(cpuset) {
+                checks.push(Check::fail(
+                    "config",
+                    format!("task {}: {}", task.id, err),
+                    "use a CPU list like \"0-3\" or \"0,2,4-5\"",
+                ));
+            }
+        }
+        if let Err(err) = TaskScript::for_task(task) {
+            checks.push(Check::fail(
+                "config",
+                format!("task {}: {}", task.id, err),
+                "fix the script path or its Rhai syntax",
+            ));
+        }
+        if let Err(err) = render_command(&task.command, &HashMap::new(), task.inputs.as_ref()) {
+            checks.push(Check::warn(
+                "config",
+                format!("task {}: {}", task.id, err),
+                "add an inline default ({{name|value}}) or an input with a default",
+            ));
+        }
+        match Backend::for_task(task, &task.id) {
+            Ok(Backend::Container(container)) if find_in_path(container.runtime()).is_none() => {
+                checks.push(Check::fail(
+                    "config",
+                    format!("task {}: container runtime {} not found", task.id, container.runtime()),
+                    "install docker or podman, or set container.runtime",
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => checks.push(Check::fail("config", err.to_string(), "set either container.image or container.exec")),
+        }
+        if let Some(cwd) = &task.cwd {
+            if !cwd.is_dir() {
+                checks.push(Check::warn(
+                    "config",
+                    format!("task {}: cwd {} does not exist", task.id, cwd.display()),
+                    "create the directory or fix the path",
+                ));
+            }
+        }
+    }
+    checks
+}
+
+fn check_orphaned_sessions() -> Check {
+    let store = match SessionStore::new() {
+        Ok(store) => store,
+        Err(err) => return Check::warn("sessions", format!("cannot open session store: {}", err), "see session dir above"),
+    };
+    let sessions = match store.list_sessions() {
+        Ok(sessions) => sessions,
+        Err(err) => return Check::warn("sessions", format!("cannot list sessions: {}", err), "see session dir above"),
+    };
+    let orphaned: Vec<String> = sessions
+        .iter()
+        .filter(|info| !matches!(info.status, SessionStatus::Exited | SessionStatus::Crashed))
+        .filter(|info| store.probe_session(info) != SessionHealth::Alive)
+        .map(|info| info.id.to_string())
+        .collect();
+    if orphaned.is_empty() {
+        Check::ok("sessions", format!("{} recorded, none orphaned", sessions.len()))
+    } else {
+        Check::warn(
+            "sessions",
+            format!("{} orphaned: {}", orphaned.len(), orphaned.join(", ")),
+            "their runner is gone; remove them from ~/.cmdhub/sessions/active",
+        )
+    }
+}
+
+fn check_terminal() -> Check {
+    let is_tty = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+    if !is_tty {
+        return Check::warn("terminal", "stdout is not a terminal", "run the TUI from an interactive terminal");
+    }
+    let term = std::env::var("TERM").unwrap_or_default();
+    if term.is_empty() || term == "dumb" {
+        return Check::warn(
+            "terminal",
+            format!("TERM={:?} lacks cursor and color support", term),
+            "export TERM=xterm-256color",
+        );
+    }
+    match crossterm::terminal::size() {
+        Ok((cols, rows)) if cols >= 40 && rows >= 10 => {
+            Check::ok("terminal", format!("TERM={} {}x{}", term, cols, rows))
+        }
+        Ok((cols, rows)) => Check::warn(
+            "terminal",
+            format!("TERM={} {}x{} is very small", term, cols, rows),
+            "enlarge the window to at least 40x10",
+        ),
+        Err(err) => Check::warn("terminal", format!("cannot query size: {}", err), "check the terminal emulator"),
+    }
+}
+
+fn check_clipboard() -> Check {
+    // Copy mode talks to the clipboard through arboard, not external tools.
+    match arboard::Clipboard::new() {
+        Ok(_) => Check::ok("clipboard", "available"),
+        Err(err) => Check::warn(
+            "clipboard",
+            err.to_string(),
+            "run the TUI with an X11 display (DISPLAY, or XWayland) to copy from it",
+        ),
+    }
+}
+
+fn check_notifications() -> Check {
+    let tools = ["notify-send", "osascript"];
+    match tools.iter().find_map(|tool| find_in_path(tool)) {
+        Some(path) => Check::ok("notifications", path.display().to_string()),
+        None => Check::warn(
+            "notifications",
+            "no desktop notification tool found",
+            "install libnotify (notify-send) or use [notifications.webhook]",
+        ),
+    }
+}
+
+fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".cmdhub-doctor-{}", std::process::id()));
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,172 @@
+use cmdhub_core::prelude::Task;
+use crate::theme::Theme;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use std::collections::BTreeMap;
+
+/// Pre-launch view of the environment a task will receive, with ad-hoc
+/// overrides that apply to the next run only.
+pub struct EnvEditor {
+    pub task: Task,
+    pub after: Option<String>,
+    base: BTreeMap<String, String>,
+    pub overrides: BTreeMap<String, String>,
+    cursor: usize,
+    top: usize,
+    page: usize,
+    input: String,
+    message: Option<String>,
+}
+
+pub enum EnvAction {
+    Stay,
+    Exit,
+    Launch,
+}
+
+impl EnvEditor {
+    pub fn new(task: Task, after: Option<String>) -> Self {
+        let mut base: BTreeMap<String, String> = if task.env_clear.unwrap_or(false) {
+            BTreeMap::new()
+        } else {
+            std::env::vars().collect()
+        };
+        if let Some(env) = &task.env {
+            base.extend(env.iter().map(|(key, value)| (key.clone(), value.describe())));
+        }
+        Self {
+            task,
+            after,
+            base,
+            overrides: BTreeMap::new(),
+            cursor: 0,
+            top: 0,
+            page: 1,
+            input: String::new(),
+            message: None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&String> {
+        let mut keys: Vec<&String> = self.base.keys().chain(self.overrides.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> EnvAction {
+        self.message = None;
+        let count = self.keys().len();
+        match key.code {
+            KeyCode::Esc if !self.input.is_empty() => self.input.clear(),
+            KeyCode::Esc => return EnvAction::Exit,
+            KeyCode::Down if self.cursor + 1 < count => self.cursor += 1,
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::PageDown => self.cursor = (self.cursor + self.page).min(count.saturating_sub(1)),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(self.page),
+            KeyCode::Tab => {
+                if let Some(key) = self.keys().get(self.cursor).map(|key| key.to_string()) {
+                    let value = self.value(&key).unwrap_or_default();
+                    self.input = format!("{}={}", key, value);
+                }
+            }
+            KeyCode::Delete => {
+                if let Some(key) = self.keys().get(self.cursor).map(|key| key.to_string()) {
+                    if self.overrides.remove(&key).is_none() {
+                        self.message = Some(format!("{} is not overridden", key));
+                    }
+                    self.cursor = self.cursor.min(self.keys().len().saturating_sub(1));
+                }
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(ch) => self.input.push(ch),
+            KeyCode::Enter if self.input.is_empty() => return EnvAction::Launch,
+            KeyCode::Enter => match parse_assignment(&self.input) {
+                Ok((key, value)) => {
+                    self.overrides.insert(key.clone(), value);
+                    self.input.clear();
+                    self.cursor = self.keys().iter().position(|k| **k == key).unwrap_or(0);
+                }
+                Err(message) => self.message = Some(message),
+            },
+            _ => {}
+        }
+        EnvAction::Stay
+    }
+
+    fn value(&self, key: &str) -> Option<String> {
+        self.overrides.get(key).or_else(|| self.base.get(key)).cloned()
+    }
+
+    pub fn render(&mut self, frame: &mut ratatui::Frame, area: Rect, theme: &Theme) {
+        self.page = (area.height.saturating_sub(4) as usize).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.page {
+            self.top = self.cursor + 1 - self.page;
+        }
+
+        let keys = self.keys();
+        let mut rows = Vec::new();
+        for (idx, key) in keys.iter().enumerate().skip(self.top).take(self.page) {
+            let overridden = self.overrides.contains_key(*key);
+            let mut style = if overridden {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            if idx == self.cursor {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let marker = if overridden { "* " } else { "  " };
+            let value = self.value(key).unwrap_or_default();
+            rows.push(Line::from(Span::styled(format!("{}{}={}", marker, key, value), style)));
+        }
+
+        let title = format!(
+            "Environment: {} [{} override(s)]",
+            self.task.name,
+            self.overrides.len()
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, theme.title))
+            .border_style(theme.focus_border);
+        let content = Rect { height: area.height.saturating_sub(2), ..area };
+        frame.render_widget(Paragraph::new(rows).block(block), content);
+
+        let prompt = Line::from(vec![
+            Span::styled("set> ", Style::default().fg(Color::Cyan)),
+            Span::raw(self.input.clone()),
+        ]);
+        let footer = match &self.message {
+            Some(message) => Line::from(Span::styled(message.clone(), theme.error)),
+            None => Line::from("type KEY=VALUE + Enter: override  Tab: edit selected  Del: drop override  Enter: launch  Esc: back"),
+        };
+        let footer_area = Rect {
+            y: area.y + area.height.saturating_sub(2),
+            height: 2.min(area.height),
+            ..area
+        };
+        frame.render_widget(Paragraph::new(vec![prompt, footer]), footer_area);
+    }
+}
+
+fn parse_assignment(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input
+        .split_once('=')
+        .ok_or_else(|| "expected KEY=VALUE".to_string())?;
+    let key = key.trim();
+    let valid = !key.is_empty()
+        && !key.starts_with(|ch: char| ch.is_ascii_digit())
+        && key.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if !valid {
+        return Err(format!("invalid variable name: {}", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
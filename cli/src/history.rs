@@ -0,0 +1,284 @@
+use anyhow::Result;
+use cmdhub_core::clock::now_epoch;
+use cmdhub_core::session::{HistoryQuery, SessionInfo, SessionStatus, SessionStore};
+use crossterm::event::{KeyCode, KeyEvent};
+use crate::theme::Theme;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use std::time::{Duration, Instant};
+
+/// How long the listed runs are kept before the store is read again, so
+/// runs that finish while the view is open show up.
+const RELOAD_AFTER: Duration = Duration::from_secs(2);
+
+/// Finished runs from the session store, newest first, narrowed by a fuzzy
+/// filter over task and status.
+pub struct HistoryView {
+    store: SessionStore,
+    runs: Vec<SessionInfo>,
+    loaded_at: Instant,
+    filter: String,
+    filtering: bool,
+    cursor: usize,
+    top: usize,
+    page: usize,
+    pub message: Option<String>,
+}
+
+pub enum HistoryAction {
+    Stay,
+    Exit,
+    ShowLog { title: String, output: Vec<u8> },
+    /// Run the same rendered command with the same settings again.
+    Rerun(Box<SessionInfo>),
+    /// Start the run's task afresh, through its input form.
+    StartTask(String),
+    Baseline { task_id: String, output: Vec<u8> },
+}
+
+impl HistoryView {
+    pub fn load() -> Result<Self> {
+        let store = SessionStore::new()?;
+        let runs = store.query_history(&HistoryQuery::default())?;
+        Ok(Self {
+            store,
+            runs,
+            loaded_at: Instant::now(),
+            filter: String::new(),
+            filtering: false,
+            cursor: 0,
+            top: 0,
+            page: 1,
+            message: None,
+        })
+    }
+
+    /// Reads the runs again, keeping the cursor on the selected run.
+    fn reload(&mut self) {
+        self.loaded_at = Instant::now();
+        let selected = self.selected().map(|run| run.id);
+        match self.store.query_history(&HistoryQuery::default()) {
+            Ok(runs) => self.runs = runs,
+            Err(err) => {
+                self.message = Some(format!("Cannot reload history: {}", err));
+                return;
+            }
+        }
+        let visible = self.visible();
+        self.cursor = selected
+            .and_then(|id| visible.iter().position(|run| run.id == id))
+            .unwrap_or(self.cursor)
+            .min(visible.len().saturating_sub(1));
+    }
+
+    fn visible(&self) -> Vec<&SessionInfo> {
+        self.runs
+            .iter()
+            .filter(|run| fuzzy_match(&self.filter, &format!("{} {} {} {} {}", run.task_name, run.task_id, outcome(run), run.tags.join(" "), annotation(run))))
+            .collect()
+    }
+
+    fn selected(&self) -> Option<&SessionInfo> {
+        self.visible().get(self.cursor).copied()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> HistoryAction {
+        self.message = None;
+        if self.filtering {
+            match key.code {
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.filtering = false;
+                }
+                KeyCode::Enter => self.filtering = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(ch) => self.filter.push(ch),
+                _ => {}
+            }
+            self.cursor = self.cursor.min(self.visible().len().saturating_sub(1));
+            return HistoryAction::Stay;
+        }
+        let count = self.visible().len();
+        match key.code {
+            KeyCode::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return HistoryAction::Exit,
+            KeyCode::Char('/') => self.filtering = true,
+            KeyCode::Down if self.cursor + 1 < count => self.cursor += 1,
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::PageDown => self.cursor = (self.cursor + self.page).min(count.saturating_sub(1)),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(self.page),
+            KeyCode::Enter => {
+                if let Some(run) = self.selected() {
+                    let title = format!("{} {}", run_name(run), relative_time(run.started_at));
+                    match self.store.read_history_log(run.id) {
+                        Ok(output) if !output.is_empty() => return HistoryAction::ShowLog { title, output },
+                        Ok(_) => self.message = Some("No output was kept for this run".to_string()),
+                        Err(err) => self.message = Some(format!("Cannot read log: {}", err)),
+                    }
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(run) = self.selected() {
+                    return HistoryAction::Rerun(Box::new(run.clone()));
+                }
+            }
+            KeyCode::Char('R') => {
+                if let Some(run) = self.selected() {
+                    return HistoryAction::StartTask(run.task_id.clone());
+                }
+            }
+            KeyCode::Char('b') => {
+                if let Some(run) = self.selected() {
+                    if run.exit_code != Some(0) {
+                        self.message = Some("Only successful runs can become the baseline".to_string());
+                        return HistoryAction::Stay;
+                    }
+                    match self.store.read_history_log(run.id) {
+                        Ok(output) if !output.is_empty() => {
+                            return HistoryAction::Baseline { task_id: run.task_id.clone(), output }
+                        }
+                        Ok(_) => self.message = Some("No output was kept for this run".to_string()),
+                        Err(err) => self.message = Some(format!("Cannot read log: {}", err)),
+                    }
+                }
+            }
+            _ => {}
+        }
+        HistoryAction::Stay
+    }
+
+    pub fn render(&mut self, frame: &mut ratatui::Frame, area: Rect, theme: &Theme) {
+        if self.loaded_at.elapsed() >= RELOAD_AFTER {
+            self.reload();
+        }
+        self.page = (area.height.saturating_sub(5) as usize).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.page {
+            self.top = self.cursor + 1 - self.page;
+        }
+
+        let visible = self.visible();
+        let name_width = visible.iter().map(|run| run_name(run).chars().count()).max().unwrap_or(4).max(4);
+        let mut rows = vec![Line::from(Span::styled(
+            format!("  {:<name_width$}  {:<10}  {:>8}  {:<8}  NOTE", "TASK", "STATUS", "DURATION", "WHEN"),
+            Style::default().add_modifier(Modifier::BOLD),
+        ))];
+        for (idx, run) in visible.iter().enumerate().skip(self.top).take(self.page) {
+            let mut style = match (run.status, run.exit_code) {
+                (SessionStatus::Crashed, _) => Style::default().fg(Color::Magenta),
+                (_, Some(0)) => Style::default().fg(Color::Green),
+                (_, Some(_)) => Style::default().fg(Color::Red),
+                _ => Style::default().fg(Color::Yellow),
+            };
+            if idx == self.cursor {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let duration = run
+                .duration_secs
+                .or_else(|| run.ended_at.or(run.crashed_at).map(|ended| ended.saturating_sub(run.started_at)))
+                .map(format_secs)
+                .unwrap_or_else(|| "-".to_string());
+            rows.push(Line::from(Span::styled(
+                format!(
+                    "  {:<name_width$}  {:<10}  {:>8}  {:<8}  {}",
+                    run_name(run),
+                    outcome(run),
+                    duration,
+                    relative_time(run.started_at),
+                    annotation(run)
+                )
+                .trim_end()
+                .to_string(),
+                style,
+            )));
+        }
+
+        let title = format!("History [{}/{} runs]", visible.len(), self.runs.len());
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, theme.title))
+            .border_style(theme.focus_border);
+        let content = Rect { height: area.height.saturating_sub(2), ..area };
+        frame.render_widget(Paragraph::new(rows).block(block), content);
+
+        let prompt_style = if self.filtering {
+            Style::default().fg(Color::Cyan)
+        } else {
+            theme.muted
+        };
+        let prompt = Line::from(vec![Span::styled("filter> ", prompt_style), Span::raw(self.filter.clone())]);
+        let footer = match &self.message {
+            Some(message) => Line::from(Span::styled(message.clone(), theme.error)),
+            None if self.filtering => Line::from("type to filter  Enter: done  Esc: clear"),
+            None => Line::from("/: filter  Enter: show log  r: rerun  R: new run  b: set as baseline  Esc: back"),
+        };
+        let footer_area = Rect {
+            y: area.y + area.height.saturating_sub(2),
+            height: 2.min(area.height),
+            ..area
+        };
+        frame.render_widget(Paragraph::new(vec![prompt, footer]), footer_area);
+    }
+}
+
+fn run_name(run: &SessionInfo) -> String {
+    match run.seq {
+        Some(seq) => format!("{} #{}", run.task_name, seq),
+        None => run.task_name.clone(),
+    }
+}
+
+/// The run's name and note, like `third migration attempt — kept the old index`.
+pub(crate) fn annotation(run: &SessionInfo) -> String {
+    match (&run.label, &run.note) {
+        (Some(label), Some(note)) => format!("{} — {}", label, note),
+        (Some(text), None) | (None, Some(text)) => text.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+pub(crate) fn outcome(run: &SessionInfo) -> String {
+    match (run.status, run.exit_code) {
+        (SessionStatus::Crashed, _) => "crashed".to_string(),
+        (_, Some(code)) => format!("exit {}", code),
+        _ => "stopped".to_string(),
+    }
+}
+
+/// Whether every character of `pattern` appears in `text` in order,
+/// ignoring case.
+pub(crate) fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut chars = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|ch| !ch.is_whitespace())
+        .all(|want| chars.any(|ch| ch == want))
+}
+
+pub(crate) fn format_secs(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+pub(crate) fn relative_time(epoch: u64) -> String {
+    let now = now_epoch();
+    let secs = now.saturating_sub(epoch);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
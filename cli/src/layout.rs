@@ -0,0 +1,175 @@
+use cmdhub_core::models::LayoutPreset;
+use crossterm::event::{MouseEvent, MouseEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+pub const GRID_MAX_TILES: usize = 4;
+const MIN_PERCENT: u16 = 10;
+const MAX_PERCENT: u16 = 90;
+
+/// How grid tiles are stacked: two rows of tiles, or two columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrangement {
+    Rows,
+    Columns,
+}
+
+/// Pane proportions, adjusted by dragging borders or applying a preset.
+#[derive(Debug, Clone, Copy)]
+pub struct PaneSizes {
+    /// Width of the task list in split view.
+    pub split: u16,
+    /// Width of the first grid column.
+    pub column: u16,
+    /// Height of the first grid row.
+    pub row: u16,
+    pub arrangement: Arrangement,
+}
+
+impl Default for PaneSizes {
+    fn default() -> Self {
+        Self {
+            split: 40,
+            column: 50,
+            row: 50,
+            arrangement: Arrangement::Rows,
+        }
+    }
+}
+
+impl PaneSizes {
+    pub fn apply(&mut self, preset: &LayoutPreset) {
+        if let Some(split) = preset.split {
+            self.split = clamp(split);
+        }
+        if let Some(column) = preset.column {
+            self.column = clamp(column);
+        }
+        if let Some(row) = preset.row {
+            self.row = clamp(row);
+        }
+        match preset.arrangement.as_deref() {
+            Some(value) if value.eq_ignore_ascii_case("columns") => self.arrangement = Arrangement::Columns,
+            Some(value) if value.eq_ignore_ascii_case("rows") => self.arrangement = Arrangement::Rows,
+            _ => {}
+        }
+    }
+
+    pub fn split_panes(&self, area: Rect) -> (Rect, Rect) {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(self.split), Constraint::Percentage(100 - self.split)])
+            .split(area);
+        (panes[0], panes[1])
+    }
+
+    /// Tile areas for `count` pinned runs. With fewer than three tiles they
+    /// sit side by side; otherwise the first row (or column) holds two.
+    pub fn grid_tiles(&self, area: Rect, count: usize) -> Vec<Rect> {
+        let count = count.min(GRID_MAX_TILES);
+        if count <= 1 {
+            return vec![area; count];
+        }
+        if count == 2 {
+            return halves(area, Direction::Horizontal, self.column).to_vec();
+        }
+        let (outer, first, inner, second) = match self.arrangement {
+            Arrangement::Rows => (Direction::Vertical, self.row, Direction::Horizontal, self.column),
+            Arrangement::Columns => (Direction::Horizontal, self.column, Direction::Vertical, self.row),
+        };
+        let mut tiles = Vec::new();
+        for (group, in_group) in halves(area, outer, first).iter().zip([2, count - 2]) {
+            if in_group == 1 {
+                tiles.push(*group);
+            } else {
+                tiles.extend(halves(*group, inner, second));
+            }
+        }
+        tiles
+    }
+}
+
+fn halves(area: Rect, direction: Direction, first: u16) -> [Rect; 2] {
+    let parts = Layout::default()
+        .direction(direction)
+        .constraints([Constraint::Percentage(first), Constraint::Percentage(100 - first)])
+        .split(area);
+    [parts[0], parts[1]]
+}
+
+fn clamp(percent: u16) -> u16 {
+    percent.clamp(MIN_PERCENT, MAX_PERCENT)
+}
+
+/// A border being dragged with the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Border {
+    Split,
+    Column,
+    Row,
+}
+
+/// Tracks mouse drags over the pane borders drawn last frame.
+#[derive(Default)]
+pub struct Dragging {
+    border: Option<Border>,
+}
+
+impl Dragging {
+    /// Handles a mouse event over `area` (the pane area drawn last frame).
+    /// Returns true when the sizes changed.
+    pub fn handle(&mut self, event: MouseEvent, area: Rect, sizes: &mut PaneSizes, borders: &[Border]) -> bool {
+        match event.kind {
+            MouseEventKind::Down(_) => {
+                self.border = borders
+                    .iter()
+                    .copied()
+                    .find(|border| on_border(*border, event.column, event.row, area, sizes));
+                false
+            }
+            MouseEventKind::Drag(_) => {
+                let border = match self.border {
+                    Some(border) => border,
+                    None => return false,
+                };
+                let (pos, start, len) = match border {
+                    Border::Split | Border::Column => (event.column, area.x, area.width),
+                    Border::Row => (event.row, area.y, area.height),
+                };
+                if len == 0 {
+                    return false;
+                }
+                let percent = clamp((pos.saturating_sub(start) as u32 * 100 / len as u32) as u16);
+                let target = match border {
+                    Border::Split => &mut sizes.split,
+                    Border::Column => &mut sizes.column,
+                    Border::Row => &mut sizes.row,
+                };
+                let changed = *target != percent;
+                *target = percent;
+                changed
+            }
+            MouseEventKind::Up(_) => {
+                self.border = None;
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+fn on_border(border: Border, column: u16, row: u16, area: Rect, sizes: &PaneSizes) -> bool {
+    let inside = column >= area.x && column < area.x + area.width && row >= area.y && row < area.y + area.height;
+    if !inside {
+        return false;
+    }
+    // A border is the last cell of the first pane or the first of the second.
+    let near = |pos: u16, start: u16, len: u16, percent: u16| {
+        let edge = start + (len as u32 * percent as u32 / 100) as u16;
+        pos + 1 >= edge && pos <= edge
+    };
+    match border {
+        Border::Split => near(column, area.x, area.width, sizes.split),
+        Border::Column => near(column, area.x, area.width, sizes.column),
+        Border::Row => near(row, area.y, area.height, sizes.row),
+    }
+}
@@ -0,0 +1,400 @@
+use cmdhub_core::prelude::SessionManager;
+use cmdhub_core::streams;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use std::collections::{HashMap, VecDeque};
+use unicode_width::UnicodeWidthChar;
+
+const LOG_LINE_LIMIT: usize = 4 * 1024;
+
+/// Styled lines of each run's output, parsed once per appended chunk.
+/// Each frame only fetches bytes past what was already parsed; lines whose
+/// bytes have been trimmed from the run's buffer are dropped. SGR colors
+/// carry over from one line, and chunk, to the next like in a terminal.
+#[derive(Default)]
+pub struct LogCache {
+    runs: HashMap<String, RunLines>,
+}
+
+/// A complete line and the stream offset it starts at.
+struct LogLine {
+    start: u64,
+    spans: Vec<(Style, String)>,
+    stderr: bool,
+}
+
+#[derive(Default)]
+struct RunLines {
+    lines: VecDeque<LogLine>,
+    /// Bytes after the last newline, kept raw until the line completes.
+    partial: Vec<u8>,
+    partial_start: u64,
+    /// SGR state at the start of `partial`.
+    style: Style,
+    /// Stream offset up to which output has been parsed.
+    end: u64,
+}
+
+impl RunLines {
+    fn update(&mut self, manager: &SessionManager, id: &str) {
+        let chunk = manager.buffer_since(id, self.end).unwrap_or_default();
+        let start = chunk.offset;
+        if start != self.end {
+            // The buffer was trimmed past what we have: start over from it.
+            *self = Self {
+                partial_start: start,
+                end: start,
+                ..Self::default()
+            };
+        }
+        while self.lines.front().is_some_and(|line| line.start < chunk.retained_from) {
+            self.lines.pop_front();
+        }
+        if self.partial_start < chunk.retained_from {
+            let trimmed = (chunk.retained_from - self.partial_start) as usize;
+            self.partial.drain(..trimmed.min(self.partial.len()));
+            self.partial_start = chunk.retained_from;
+        }
+        self.end = start + chunk.bytes.len() as u64;
+        let mut offset = start;
+        for piece in chunk.bytes.split_inclusive(|&b| b == b'\n') {
+            self.partial.extend_from_slice(piece);
+            offset += piece.len() as u64;
+            if self.partial.ends_with(b"\n") {
+                let raw = &self.partial[..self.partial.len() - 1];
+                self.lines.push_back(LogLine {
+                    start: self.partial_start,
+                    spans: styled_line(raw, &mut self.style),
+                    stderr: streams::is_stderr(raw),
+                });
+                self.partial.clear();
+                self.partial_start = offset;
+            }
+        }
+    }
+
+    /// Index of the last line starting at or before `anchor`; the first
+    /// line once the anchored one has been trimmed.
+    fn anchor_index(&self, anchor: u64) -> usize {
+        self.lines.partition_point(|line| line.start <= anchor).saturating_sub(1)
+    }
+}
+
+impl LogCache {
+    /// Renders the last `height` rows of run `id`, wrapping at `width`.
+    pub fn tail(&mut self, manager: &SessionManager, id: &str, width: usize, height: usize) -> Vec<Line<'static>> {
+        let run = self.runs.entry(id.to_string()).or_default();
+        run.update(manager, id);
+        let partial = (!run.partial.is_empty()).then(|| {
+            let mut style = run.style;
+            (styled_line(&run.partial, &mut style), streams::is_stderr(&run.partial))
+        });
+        let lines = partial
+            .iter()
+            .map(|(spans, stderr)| (spans.as_slice(), *stderr))
+            .chain(run.lines.iter().rev().map(|line| (line.spans.as_slice(), line.stderr)));
+        wrap_tail(lines, width, height)
+    }
+
+    /// Renders run `id` like `tail`, but when `anchor` is set the bottom row
+    /// stays on the line starting at that stream offset while output keeps
+    /// arriving. Also returns how many lines came in below it.
+    pub fn page(
+        &mut self,
+        manager: &SessionManager,
+        id: &str,
+        width: usize,
+        height: usize,
+        anchor: Option<u64>,
+    ) -> (Vec<Line<'static>>, usize) {
+        let Some(anchor) = anchor else {
+            return (self.tail(manager, id, width, height), 0);
+        };
+        let run = self.runs.entry(id.to_string()).or_default();
+        run.update(manager, id);
+        let bottom = run.anchor_index(anchor);
+        let below = run.lines.len().saturating_sub(bottom + 1) + usize::from(!run.partial.is_empty());
+        let lines = run.lines.iter().take(bottom + 1).rev().map(|line| (line.spans.as_slice(), line.stderr));
+        (wrap_tail(lines, width, height), below)
+    }
+
+    /// Moves the bottom line of a paused view of run `id` by `delta` lines,
+    /// starting from the tail when `anchor` is `None`. Returns the new anchor,
+    /// or `None` once it reaches the tail again.
+    pub fn scroll(&self, id: &str, anchor: Option<u64>, delta: isize) -> Option<u64> {
+        let run = self.runs.get(id)?;
+        let last = run.lines.len().checked_sub(1)?;
+        let current = match anchor {
+            Some(anchor) => run.anchor_index(anchor),
+            None => last,
+        };
+        let target = current.saturating_add_signed(delta).min(last);
+        if delta > 0 && target == last {
+            return None;
+        }
+        run.lines.get(target).map(|line| line.start)
+    }
+
+    /// Forgets runs that are no longer listed.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.runs.retain(|id, _| keep(id));
+    }
+}
+
+/// The visible text of one output line in the styles its SGR escapes set,
+/// starting from `style` and leaving the style in effect at its end there.
+/// Carriage returns redraw the line in place, other escapes are dropped and
+/// very long lines are capped at a character boundary.
+fn styled_line(line: &[u8], style: &mut Style) -> Vec<(Style, String)> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let mut spans = Vec::new();
+    let mut text = Vec::new();
+    let mut kept = 0;
+    let mut omitted = 0;
+    let mut i = 0;
+    while i < line.len() {
+        let b = line[i];
+        i += 1;
+        match b {
+            0x1b => {
+                let (new_style, len) = escape(&line[i..], *style);
+                i += len;
+                if new_style != *style {
+                    flush(&mut spans, &mut text, *style);
+                    *style = new_style;
+                }
+            }
+            b'\r' => {
+                spans.clear();
+                text.clear();
+                kept = 0;
+                omitted = 0;
+            }
+            b'\t' => text.push(b),
+            0x00..=0x1f | 0x7f => {}
+            // Past the cap, finish the character in progress and drop the rest.
+            _ if omitted > 0 || (kept >= LOG_LINE_LIMIT && !is_continuation(b)) => omitted += 1,
+            _ => {
+                kept += 1;
+                text.push(b);
+            }
+        }
+    }
+    flush(&mut spans, &mut text, *style);
+    if omitted > 0 {
+        spans.push((Style::default(), format!(" … [+{} bytes]", omitted)));
+    }
+    spans
+}
+
+fn is_continuation(b: u8) -> bool {
+    b & 0xc0 == 0x80
+}
+
+fn flush(spans: &mut Vec<(Style, String)>, text: &mut Vec<u8>, style: Style) {
+    if !text.is_empty() {
+        spans.push((style, String::from_utf8_lossy(text).into_owned()));
+        text.clear();
+    }
+}
+
+/// Skips the escape sequence after an ESC, returning the style with any SGR
+/// it holds applied and how many bytes it took.
+fn escape(rest: &[u8], style: Style) -> (Style, usize) {
+    match rest.first() {
+        Some(b'[') => {
+            let end = rest[1..].iter().position(|b| (0x40..=0x7e).contains(b));
+            match end {
+                Some(end) if rest[1 + end] == b'm' => (sgr(&rest[1..1 + end], style), end + 2),
+                Some(end) => (style, end + 2),
+                None => (style, rest.len()),
+            }
+        }
+        Some(b']' | b'P' | b'X' | b'^' | b'_') => {
+            let mut i = 1;
+            while i < rest.len() {
+                match rest[i] {
+                    0x07 => return (style, i + 1),
+                    0x1b if rest.get(i + 1) == Some(&b'\\') => return (style, i + 2),
+                    _ => i += 1,
+                }
+            }
+            (style, rest.len())
+        }
+        Some(_) => (style, 1),
+        None => (style, 0),
+    }
+}
+
+fn sgr(params: &[u8], mut style: Style) -> Style {
+    let params = String::from_utf8_lossy(params);
+    let mut codes = params.split([';', ':']).map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            30..=37 => style.fg(ansi_color(code - 30)),
+            90..=97 => style.fg(ansi_color(code - 90 + 8)),
+            40..=47 => style.bg(ansi_color(code - 40)),
+            100..=107 => style.bg(ansi_color(code - 100 + 8)),
+            38 | 48 => {
+                let color = match codes.next() {
+                    Some(5) => codes.next().map(|index| Color::Indexed(index as u8)),
+                    Some(2) => {
+                        let mut channel = || codes.next().unwrap_or(0) as u8;
+                        Some(Color::Rgb(channel(), channel(), channel()))
+                    }
+                    _ => None,
+                };
+                match (code, color) {
+                    (38, Some(color)) => style.fg(color),
+                    (_, Some(color)) => style.bg(color),
+                    _ => style,
+                }
+            }
+            39 => Style { fg: None, ..style },
+            49 => Style { bg: None, ..style },
+            _ => style,
+        };
+    }
+    style
+}
+
+fn ansi_color(index: u16) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+/// Wraps lines given newest first until `height` rows are filled; lines
+/// from stderr are red unless they set their own color.
+fn wrap_tail<'a>(
+    lines: impl Iterator<Item = (&'a [(Style, String)], bool)>,
+    width: usize,
+    height: usize,
+) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let mut rows: Vec<Line<'static>> = Vec::new();
+    for (spans, stderr) in lines {
+        if rows.len() >= height {
+            break;
+        }
+        let chars: Vec<(char, Style)> =
+            spans.iter().flat_map(|(style, text)| text.chars().map(move |ch| (ch, *style))).collect();
+        let mut chunks: Vec<Line<'static>> = rows_of(&chars, width)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, chunk)| {
+                let base = if stderr {
+                    Style::default().fg(Color::Red)
+                } else if idx == 0 {
+                    Style::default()
+                } else {
+                    Style::default().fg(Color::Gray)
+                };
+                let mut row: Vec<Span<'static>> = Vec::new();
+                let mut content = String::new();
+                let mut current = chunk[0].1;
+                for &(ch, style) in chunk {
+                    if style != current {
+                        row.push(Span::styled(std::mem::take(&mut content), base.patch(current)));
+                        current = style;
+                    }
+                    content.push(ch);
+                }
+                row.push(Span::styled(content, base.patch(current)));
+                Line::from(row)
+            })
+            .collect();
+        if chunks.is_empty() {
+            chunks.push(Line::from(String::new()));
+        }
+        let room = height - rows.len();
+        let skip = chunks.len().saturating_sub(room);
+        for chunk in chunks.into_iter().skip(skip).rev() {
+            rows.push(chunk);
+        }
+    }
+    rows.reverse();
+    rows
+}
+
+/// Splits `chars` into rows at most `width` columns wide, measured by what
+/// the terminal draws, so wide glyphs such as CJK take two columns.
+fn rows_of(chars: &[(char, Style)], width: usize) -> Vec<&[(char, Style)]> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (i, (ch, _)) in chars.iter().enumerate() {
+        let cols = ch.width().unwrap_or(0);
+        if used + cols > width && i > start {
+            rows.push(&chars[start..i]);
+            start = i;
+            used = 0;
+        }
+        used += cols;
+    }
+    if start < chars.len() {
+        rows.push(&chars[start..]);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(line: &str, width: usize) -> Vec<String> {
+        let spans = vec![(Style::default(), line.to_string())];
+        wrap_tail(std::iter::once((spans.as_slice(), false)), width, 10)
+            .iter()
+            .map(|row| row.spans.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn wraps_by_display_width() {
+        assert_eq!(texts("abcdef", 4), ["abcd", "ef"]);
+        assert_eq!(texts("日本語テキスト", 5), ["日本", "語テ", "キス", "ト"]);
+        assert_eq!(texts("a日本", 2), ["a", "日", "本"]);
+    }
+
+    #[test]
+    fn long_lines_are_capped_between_characters() {
+        let mut line = "a".repeat(LOG_LINE_LIMIT - 1).into_bytes();
+        line.extend_from_slice("é日本".as_bytes());
+        let spans = styled_line(&line, &mut Style::default());
+        let text: String = spans.iter().map(|(_, text)| text.as_str()).collect();
+        assert!(!text.contains('\u{fffd}'));
+        assert!(text.ends_with("aé … [+6 bytes]"));
+    }
+
+    #[test]
+    fn too_narrow_for_a_wide_glyph_still_shows_it() {
+        assert_eq!(texts("日本", 1), ["日", "本"]);
+        assert_eq!(texts("", 4), [""]);
+    }
+}
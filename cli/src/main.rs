@@ -1,9 +1,46 @@
+mod copy_mode;
+mod doctor;
+mod env_editor;
+mod history;
+mod layout;
+mod log_view;
+mod run;
+mod systemd;
+mod task;
+mod theme;
+mod toast;
+
 use anyhow::{anyhow, Result};
-use cmdhub_core::config::load_config_auto;
-use cmdhub_core::instance::{InstanceInfo, InstanceStatus, SessionManager, SpawnedInstance};
-use cmdhub_core::models::{AppConfig, InputConfig, Task, UiConfig, KeyBindings};
-use cmdhub_core::template::render_command;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use copy_mode::{CopyAction, CopyState};
+use env_editor::{EnvAction, EnvEditor};
+use history::{HistoryAction, HistoryView};
+use layout::{Border, Dragging, PaneSizes, GRID_MAX_TILES};
+use log_view::LogCache;
+use theme::{text_style, Theme};
+use toast::{Toast, Toasts};
+use cmdhub_core::ansi;
+use cmdhub_core::baseline::{self, BaselineResult, BaselineStore};
+use cmdhub_core::clock::now_epoch;
+use cmdhub_core::config;
+use cmdhub_core::events::{EventReceiver, SessionEvent};
+use cmdhub_core::export::SessionExport;
+use cmdhub_core::logevents::LogEvent;
+use cmdhub_core::models::{KeyBindings, LayoutPreset, UiConfig};
+use cmdhub_core::prelude::*;
+use cmdhub_core::receipt::ReceiptLog;
+use cmdhub_core::script::{validate_inputs, TaskScript};
+use cmdhub_core::recording::{replay, RecordingStore, ReplayOptions};
+use cmdhub_core::registry::HISTORY_LIMIT;
+use cmdhub_core::secrets;
+use cmdhub_core::session::RunSummary;
+use cmdhub_core::snapshot::{diff_snapshots, load_snapshot, SnapshotStore};
+use cmdhub_core::stats::{self, TaskStats};
+use cmdhub_core::storage::{self, Ephemeral};
+use cmdhub_core::supervisor::Supervisor;
+use cmdhub_core::usage::{ResourceUsage, UsageSampler};
+use cmdhub_server::ServeArgs;
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent};
 use crossterm::execute;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::cursor::{MoveTo, RestorePosition, SavePosition, Show};
@@ -16,32 +53,694 @@ use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wra
 use ratatui::Terminal;
 use signal_hook::consts::{SIGINT, SIGQUIT, SIGTERM};
 use signal_hook::iterator::Signals;
-use std::collections::{HashMap, HashSet};
-use std::io::{self, Read, Write};
-use std::sync::{Arc, Mutex};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use tokio::sync::broadcast::error::TryRecvError;
 use std::thread;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
 const BUFFER_CAP: usize = 16 * 1024;
+const RECENT_FAILED_LIMIT: usize = 5;
+// How long the details pane reuses a task's runs before reading them again.
+const RUN_STATS_TTL: Duration = Duration::from_secs(5);
+const DEFAULT_INPUT_GROUP: &str = "General";
+/// Category of the tasks made for commands typed at the `:` prompt.
+const ADHOC_CATEGORY: &str = "Ad hoc";
+const ADVANCED_PAGE: &str = "Advanced";
+const ADVANCED_CWD: &str = "cwd";
+const ADVANCED_ENV: &str = "env";
+const ADVANCED_SHELL: &str = "shell";
+const ADVANCED_LOGIN: &str = "login profile";
+const ADVANCED_TIMEOUT: &str = "timeout secs";
+const ADVANCED_PTY: &str = "pty";
+
+/// Runs configured tasks in PTYs you can attach to. Without a command,
+/// opens the task list.
+#[derive(Parser)]
+#[command(name = "cmdhub", bin_name = "cmdhub", version)]
+struct Cli {
+    /// Config file to load instead of searching for config.toml [env: CMDHUB_CONFIG]
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Directory of task files to merge instead of tasks/ next to the config [env: CMDHUB_TASKS_DIR]
+    #[arg(long, global = true, value_name = "DIR")]
+    tasks_dir: Option<PathBuf>,
+    /// Apply the env and input defaults of this [profiles] entry [env: CMDHUB_PROFILE]
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Log more to stderr: -v info, -vv debug, -vvv trace
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Keep all state in a temporary directory, removed on exit
+    #[arg(long, global = true)]
+    no_store: bool,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Check the shell, PTY support, data directory and config
+    Doctor,
+    /// Check or export the execution receipt log
+    Receipts {
+        #[command(subcommand)]
+        action: ReceiptsAction,
+    },
+    /// List, show and compare environment snapshots
+    Snapshots {
+        #[command(subcommand)]
+        action: SnapshotsAction,
+    },
+    /// Play a session recording back in this terminal
+    Replay(ReplayArgs),
+    /// Serve the HTTP API
+    Serve(ServeArgs),
+    /// Run a task in this terminal and exit with its exit code
+    Run(run::RunArgs),
+    /// List the configured tasks
+    List(ListArgs),
+    /// List runs recorded in the session store
+    Ls(LsArgs),
+    /// List finished runs, newest first
+    History(HistoryArgs),
+    /// Run an earlier run's command again with its cwd, env and inputs
+    Rerun(run::RerunArgs),
+    /// Print a run's output
+    Logs(LogsArgs),
+    /// Show run counts, success rates and durations per task
+    Stats(StatsArgs),
+    /// Write a run's metadata, output and timing as a tar archive or JSON
+    Export(ExportArgs),
+    /// Run tasks as systemd user services
+    Systemd {
+        #[command(subcommand)]
+        action: systemd::SystemdAction,
+    },
+    /// Add, remove or rename tasks in the config files
+    Task {
+        #[command(subcommand)]
+        action: task::TaskAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReceiptsAction {
+    /// Check the hash chain
+    Verify,
+    /// Print every receipt as a JSON line
+    Export,
+}
+
+#[derive(Subcommand)]
+enum SnapshotsAction {
+    List,
+    Show { name: String },
+    Diff { left: String, right: String },
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    /// Session id or recording file
+    target: String,
+    /// Playback speed; 2 plays twice as fast
+    #[arg(long, value_parser = positive_number)]
+    speed: Option<f64>,
+    /// Shorten pauses longer than this many seconds
+    #[arg(long, value_name = "SECS")]
+    max_idle: Option<f64>,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Also print each task's description and command
+    #[arg(short, long)]
+    long: bool,
+    /// Print a JSON array instead, for scripts and shell completion
+    #[arg(long, conflicts_with = "long")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct LsArgs {
+    /// Only runs with this tag; may be repeated to require several
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Include finished runs from history
+    #[arg(short, long)]
+    all: bool,
+    /// Move runs whose host died or stopped sending heartbeats to history
+    #[arg(long)]
+    prune: bool,
+}
+
+#[derive(Args)]
+struct HistoryArgs {
+    /// Only runs of this task id
+    #[arg(long)]
+    task: Option<String>,
+    /// Only runs that exited 0, or only those that failed or crashed
+    #[arg(long, value_enum)]
+    status: Option<HistoryStatus>,
+    /// Only runs started within this long, like 30m, 12h, 2d or 1w
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    since: Option<u64>,
+    /// Show at most this many runs
+    #[arg(short = 'n', long)]
+    limit: Option<usize>,
+    /// Only runs whose name or task#N id contains this, ignoring case
+    #[arg(long)]
+    name: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HistoryStatus {
+    Exited,
+    Failed,
+}
+
+#[derive(Args)]
+struct LogsArgs {
+    /// Session id, a unique prefix of it, or the run's task#N id
+    session: String,
+    /// Strip colors and other escape sequences, for grepping and diffing
+    #[arg(long)]
+    no_color: bool,
+    /// Print newline-delimited JSON events, one per line of output
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// Only this task, with its last failure
+    task: Option<String>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Session id, a unique prefix of it, or the run's task#N id
+    session: String,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Tar)]
+    format: ExportFormat,
+    /// File to write instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Tar,
+    Json,
+}
+
+/// `30m`, `12h`, `2d` and the like, in seconds.
+fn parse_age(value: &str) -> std::result::Result<u64, String> {
+    let split = value.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| "expected a number and a unit, like 2d".to_string())?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err("unit must be s, m, h, d or w".to_string()),
+    };
+    Ok(number * unit)
+}
+
+fn positive_number(value: &str) -> std::result::Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 => Ok(number),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let cli = Cli::parse();
+    let mut logger = env_logger::Builder::from_default_env();
+    match cli.verbose {
+        0 => {}
+        1 => {
+            logger.filter_level(log::LevelFilter::Info);
+        }
+        2 => {
+            logger.filter_level(log::LevelFilter::Debug);
+        }
+        _ => {
+            logger.filter_level(log::LevelFilter::Trace);
+        }
+    }
+    logger.init();
+    config::set_overrides(cli.config.as_deref(), cli.tasks_dir.as_deref(), cli.profile.as_deref())?;
+    // The doctor reports on the real data directory.
+    let ephemeral = if matches!(cli.command, Some(Commands::Doctor)) {
+        None
+    } else {
+        storage::init(cli.no_store)?
+    };
+    if let Some((dir, Ephemeral::Unwritable(err))) = &ephemeral {
+        eprintln!("cmdhub: cannot store state ({}); keeping it in {} for this run", err, dir.display());
+    }
     let runtime = tokio::runtime::Runtime::new()?;
-    runtime.block_on(async_main())
+    let result = runtime.block_on(async_main(cli));
+    if let Some((dir, _)) = ephemeral {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    result
+}
+
+async fn async_main(cli: Cli) -> Result<()> {
+    if let Some(Commands::Doctor) = cli.command {
+        return doctor::run_doctor().await;
+    }
+    let base = load_config_auto().await?;
+    let mut config = base.clone();
+    config.tasks = config::profile_tasks(&base, config::selected_profile().as_deref())?;
+    storage::configure(config.store.as_ref())?;
+
+    match cli.command {
+        Some(Commands::Doctor) => unreachable!("handled above"),
+        Some(Commands::Receipts { action }) => {
+            let receipts = match &config.receipts {
+                Some(receipt_config) => ReceiptLog::from_config(receipt_config)?,
+                None => None,
+            };
+            return run_receipts_command(action, receipts);
+        }
+        Some(Commands::Snapshots { action }) => {
+            let snapshots = config.snapshots.clone().unwrap_or_default();
+            return run_snapshots_command(action, SnapshotStore::from_config(&snapshots)?);
+        }
+        Some(Commands::Replay(args)) => {
+            let recording = config.recording.clone().unwrap_or_default();
+            return run_replay_command(args, RecordingStore::from_config(&recording)?);
+        }
+        Some(Commands::Serve(args)) => return run_serve_command(args, config).await,
+        Some(Commands::Run(args)) => return run::run_task_command(args, &config),
+        Some(Commands::Rerun(args)) => return run::rerun_command(args, &config),
+        Some(Commands::List(args)) => return run_list_command(args, &config),
+        Some(Commands::Ls(args)) => return run_ls_command(args),
+        Some(Commands::History(args)) => return run_history_command(args),
+        Some(Commands::Logs(args)) => return run_logs_command(args),
+        Some(Commands::Stats(args)) => return run_stats_command(args, &config),
+        Some(Commands::Export(args)) => {
+            let recording = config.recording.clone().unwrap_or_default();
+            return run_export_command(args, RecordingStore::from_config(&recording)?);
+        }
+        Some(Commands::Systemd { action }) => return systemd::run_systemd_command(action, &config),
+        Some(Commands::Task { action }) => return task::run_task_action(action, &config),
+        None => {}
+    }
+
+    let manager = SessionManager::from_config(&config, BUFFER_CAP)?;
+    start_metrics(&config, &manager)?;
+    setup_signal_handlers(manager.clone())?;
+    run_ui(config, base.tasks, manager)?;
+    Ok(())
+}
+
+fn start_metrics(config: &AppConfig, manager: &SessionManager) -> Result<()> {
+    if let Some(metrics) = &config.metrics {
+        cmdhub_core::metrics::serve(&metrics.listen, manager.clone())
+            .map_err(|err| anyhow!("metrics listener on {}: {}", metrics.listen, err))?;
+    }
+    Ok(())
 }
 
-async fn async_main() -> Result<()> {
-    let config = load_config_auto().await?;
-    let manager = SessionManager::new(BUFFER_CAP);
+async fn run_serve_command(args: ServeArgs, config: AppConfig) -> Result<()> {
+    let token = args.token()?;
+    let manager = SessionManager::from_config(&config, BUFFER_CAP)?;
+    start_metrics(&config, &manager)?;
+    if let Some(supervisor) = config.supervisor.clone().filter(|supervisor| supervisor.enabled) {
+        Supervisor::new(manager.clone(), config.tasks.clone(), supervisor)?.start();
+    }
     setup_signal_handlers(manager.clone())?;
-    run_ui(config, manager)?;
+    println!("CmdHub API listening on http://{}", args.addr());
+    cmdhub_server::serve(config, manager, args.addr(), token).await
+}
+
+fn run_receipts_command(action: ReceiptsAction, receipts: Option<ReceiptLog>) -> Result<()> {
+    let receipts = receipts.ok_or_else(|| anyhow!("receipts are not enabled; set [receipts] enabled = true"))?;
+    match action {
+        ReceiptsAction::Verify => {
+            let verified = receipts.verify()?;
+            println!("{}: {} receipts, chain intact", receipts.path().display(), verified.len());
+        }
+        ReceiptsAction::Export => {
+            let mut stdout = io::stdout();
+            for receipt in receipts.verify()? {
+                serde_json::to_writer(&mut stdout, &receipt)?;
+                stdout.write_all(b"\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_snapshots_command(action: SnapshotsAction, store: SnapshotStore) -> Result<()> {
+    match action {
+        SnapshotsAction::List => {
+            for path in store.list()? {
+                println!("{}", path.display());
+            }
+        }
+        SnapshotsAction::Show { name } => {
+            let snapshot = load_snapshot(&store.resolve(&name)?)?;
+            println!("{}", serde_json::to_string_pretty(&snapshot)?);
+        }
+        SnapshotsAction::Diff { left, right } => {
+            let left = load_snapshot(&store.resolve(&left)?)?;
+            let right = load_snapshot(&store.resolve(&right)?)?;
+            let lines = diff_snapshots(&left, &right);
+            if lines.is_empty() {
+                println!("snapshots are identical");
+            }
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_replay_command(args: ReplayArgs, store: RecordingStore) -> Result<()> {
+    let mut options = ReplayOptions::default();
+    if let Some(speed) = args.speed {
+        options.speed = speed;
+    }
+    options.max_idle = args.max_idle.map(|secs| Duration::from_secs_f64(secs.max(0.0)));
+    let path = store.resolve(&args.target)?;
+    let mut stdout = io::stdout();
+    replay(&path, options, &mut stdout)?;
+    // Recordings may end mid-line or with the cursor hidden.
+    stdout.write_all(b"\x1b[0m\x1b[?25h\r\n")?;
+    Ok(())
+}
+
+fn run_list_command(args: ListArgs, config: &AppConfig) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let needs_input = |task: &Task| task.inputs.iter().flatten().any(|(_, input)| input.needs_value());
+    if args.json {
+        let tasks: Vec<serde_json::Value> = config
+            .tasks
+            .iter()
+            .map(|task| {
+                let mut inputs: Vec<(&String, &InputConfig)> = task.inputs.iter().flatten().collect();
+                inputs.sort_by_key(|(name, _)| *name);
+                serde_json::json!({
+                    "id": task.id,
+                    "name": task.name,
+                    "category": task.category,
+                    "description": task.description,
+                    "command": task.members.is_none().then_some(&task.command),
+                    "members": task.members,
+                    "inputs": inputs
+                        .iter()
+                        .map(|(name, input)| serde_json::json!({ "name": name, "required": input.needs_value() }))
+                        .collect::<Vec<_>>(),
+                    "inputs_required": needs_input(task),
+                })
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut stdout, &tasks)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+    if args.long {
+        for (idx, task) in config.tasks.iter().enumerate() {
+            if idx > 0 {
+                writeln!(stdout)?;
+            }
+            writeln!(stdout, "{}", task.id)?;
+            writeln!(stdout, "  name:     {}", task.name)?;
+            writeln!(stdout, "  category: {}", task.category.as_deref().unwrap_or("Default"))?;
+            if let Some(description) = task.description.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+                writeln!(stdout, "  description:")?;
+                for line in description.lines() {
+                    writeln!(stdout, "    {}", line.trim_end())?;
+                }
+            }
+            match &task.members {
+                Some(members) => writeln!(stdout, "  members:  {}", members.join(", "))?,
+                None => {
+                    writeln!(stdout, "  command:")?;
+                    for line in task.command.trim().lines() {
+                        writeln!(stdout, "    {}", line.trim_end())?;
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+    let width = config.tasks.iter().map(|task| task.id.chars().count()).max().unwrap_or(2).max(2);
+    let name_width = config.tasks.iter().map(|task| task.name.chars().count()).max().unwrap_or(4).max(4);
+    writeln!(stdout, "{:<width$}  {:<name_width$}  {:<8}  CATEGORY", "ID", "NAME", "INPUTS")?;
+    for task in &config.tasks {
+        // "optional" when every input has a usable default, as `run --yes` needs.
+        let inputs = match task.inputs.as_ref().filter(|inputs| !inputs.is_empty()) {
+            None => "-",
+            Some(_) if needs_input(task) => "required",
+            Some(_) => "optional",
+        };
+        let line = format!(
+            "{:<width$}  {:<name_width$}  {:<8}  {}",
+            task.id,
+            task.name,
+            inputs,
+            task.category.as_deref().unwrap_or("Default"),
+        );
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn run_ls_command(args: LsArgs) -> Result<()> {
+    let store = SessionStore::new()?;
+    let mut runs = store.list_sessions()?;
+    if args.all {
+        runs.extend(store.list_history()?);
+    }
+    runs.retain(|run| args.tags.iter().all(|tag| run.tags.contains(tag)));
+    runs.sort_by_key(|run| run.started_at);
+    let mut orphaned = 0;
+    let name = |run: &SessionInfo| run.session_name.clone().unwrap_or_else(|| run.id.to_string());
+    let width = runs.iter().map(|run| name(run).chars().count()).max().unwrap_or(3).max(3);
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{:<width$}  {:<8}  {:<19}  {:<16}  NAME", "RUN", "STATUS", "STARTED", "TAGS")?;
+    for run in &runs {
+        let live = run.status == SessionStatus::Running;
+        // Its host died and no supervisor has marked it crashed yet.
+        let dead = live && store.probe_session(run) == SessionHealth::RunnerDead;
+        let stale = live && !dead && run.heartbeat_stale();
+        let status = match (run.status, run.exit_code) {
+            _ if (dead || stale) && args.prune => {
+                store.prune(run.id, HISTORY_LIMIT)?;
+                "pruned".to_string()
+            }
+            _ if dead => "crashed".to_string(),
+            _ if stale => "stale".to_string(),
+            (SessionStatus::Exited, Some(code)) => format!("exit {}", code),
+            (status, _) => format!("{:?}", status).to_lowercase(),
+        };
+        if (dead || stale) && !args.prune {
+            orphaned += 1;
+        }
+        let line = format!(
+            "{:<width$}  {:<8}  {:<19}  {:<16}  {}",
+            name(run),
+            status,
+            format_clock(run.started_at),
+            run.tags.join(","),
+            run.label.as_deref().unwrap_or_default(),
+        );
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+    if orphaned > 0 {
+        eprintln!(
+            "{} run(s) have no live host: it died or missed its heartbeats. `cmdhub ls --prune` moves them to history.",
+            orphaned
+        );
+    }
+    Ok(())
+}
+
+fn run_history_command(args: HistoryArgs) -> Result<()> {
+    let store = SessionStore::new()?;
+    let now = now_epoch();
+    let mut query = HistoryQuery {
+        task_id: args.task,
+        succeeded: args.status.map(|status| matches!(status, HistoryStatus::Exited)),
+        since: args.since.map(|age| now.saturating_sub(age)),
+        limit: args.limit,
+    };
+    // The name is not indexed, so the limit applies once it has been matched.
+    let name = args.name.map(|name| name.to_lowercase());
+    if name.is_some() {
+        query.limit = None;
+    }
+    let mut runs = store.query_history(&query)?;
+    if let Some(name) = &name {
+        runs.retain(|run| {
+            [&run.label, &run.session_name]
+                .into_iter()
+                .flatten()
+                .any(|text| text.to_lowercase().contains(name.as_str()))
+        });
+        if let Some(limit) = args.limit {
+            runs.truncate(limit);
+        }
+    }
+    let id = |run: &SessionInfo| run.session_name.clone().unwrap_or_else(|| run.id.to_string());
+    let width = runs.iter().map(|run| id(run).chars().count()).max().unwrap_or(3).max(3);
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{:<width$}  {:<8}  {:>8}  {:<8}  {:<19}  NAME", "RUN", "STATUS", "DURATION", "WHEN", "STARTED")?;
+    for run in &runs {
+        let line = format!(
+            "{:<width$}  {:<8}  {:>8}  {:<8}  {:<19}  {}",
+            id(run),
+            history::outcome(run),
+            run.duration_secs.map(history::format_secs).unwrap_or_default(),
+            history::relative_time(run.started_at),
+            format_clock(run.started_at),
+            history::annotation(run),
+        );
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn run_stats_command(args: StatsArgs, config: &AppConfig) -> Result<()> {
+    let store = SessionStore::new()?;
+    let mut stdout = io::stdout().lock();
+    let secs = |value: Option<u64>| value.map(stats::format_secs).unwrap_or_else(|| "-".to_string());
+    if let Some(task_id) = args.task {
+        let runs = store.task_runs(&task_id)?;
+        let name = config.tasks.iter().find(|task| task.id == task_id).map(|task| task.name.clone());
+        if runs.is_empty() && name.is_none() {
+            return Err(anyhow!("task not found: {}", task_id));
+        }
+        let stats = TaskStats::from_runs(&runs);
+        match name {
+            Some(name) => writeln!(stdout, "{} ({})", task_id, name)?,
+            None => writeln!(stdout, "{}", task_id)?,
+        }
+        writeln!(stdout, "  runs:         {}", stats.runs)?;
+        if let Some(rate) = stats.success_rate() {
+            writeln!(stdout, "  succeeded:    {} ({}%)", stats.succeeded, rate)?;
+        }
+        if stats.avg_secs.is_some() {
+            writeln!(
+                stdout,
+                "  duration:     avg {}  p50 {}  p90 {}  p99 {}",
+                secs(stats.avg_secs),
+                secs(stats.p50_secs),
+                secs(stats.p90_secs),
+                secs(stats.p99_secs),
+            )?;
+        }
+        if let Some(run) = &stats.last_failure {
+            writeln!(stdout, "  last failure: {}  {}  {}", format_clock(run.started_at), run_outcome(run), run.id)?;
+        }
+        return Ok(());
+    }
+    let mut rows = Vec::new();
+    for task in &config.tasks {
+        let runs = store.task_runs(&task.id)?;
+        if !runs.is_empty() {
+            rows.push((task, TaskStats::from_runs(&runs)));
+        }
+    }
+    let width = rows.iter().map(|(task, _)| task.id.chars().count()).max().unwrap_or(4).max(4);
+    writeln!(
+        stdout,
+        "{:<width$}  {:>5}  {:>4}  {:>7}  {:>7}  {:>7}  {:>7}  LAST FAILURE",
+        "TASK", "RUNS", "OK", "AVG", "P50", "P90", "P99"
+    )?;
+    for (task, stats) in rows {
+        let line = format!(
+            "{:<width$}  {:>5}  {:>4}  {:>7}  {:>7}  {:>7}  {:>7}  {}",
+            task.id,
+            stats.runs,
+            stats.success_rate().map(|rate| format!("{}%", rate)).unwrap_or_default(),
+            secs(stats.avg_secs),
+            secs(stats.p50_secs),
+            secs(stats.p90_secs),
+            secs(stats.p99_secs),
+            stats
+                .last_failure
+                .as_ref()
+                .map(|run| format!("{} ({})", format_clock(run.started_at), run_outcome(run)))
+                .unwrap_or_default(),
+        );
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Like `exit 1` or `crashed`.
+fn run_outcome(run: &RunSummary) -> String {
+    match (run.status, run.exit_code) {
+        (SessionStatus::Exited, Some(code)) => format!("exit {}", code),
+        (status, _) => format!("{:?}", status).to_lowercase(),
+    }
+}
+
+fn run_logs_command(args: LogsArgs) -> Result<()> {
+    let store = SessionStore::new()?;
+    let session = store.find_session(&args.session)?;
+    let mut stdout = io::stdout().lock();
+    if args.json {
+        let events = store
+            .read_session_events(session.id)?
+            .ok_or_else(|| anyhow!("{} has no event log; it ran with [logs] events = false", args.session))?;
+        if !args.no_color {
+            stdout.write_all(&events)?;
+            return Ok(stdout.flush()?);
+        }
+        for line in events.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            let mut event: LogEvent = serde_json::from_slice(line)?;
+            event.text = event.text.map(|text| ansi::plain_text(text.as_bytes()));
+            serde_json::to_writer(&mut stdout, &event)?;
+            stdout.write_all(b"\n")?;
+        }
+        return Ok(stdout.flush()?);
+    }
+    let output = store.read_session_log(session.id)?;
+    if args.no_color {
+        stdout.write_all(ansi::plain_text(&output).as_bytes())?;
+    } else {
+        stdout.write_all(&output)?;
+    }
+    stdout.flush()?;
     Ok(())
 }
 
+fn run_export_command(args: ExportArgs, recordings: RecordingStore) -> Result<()> {
+    let export = SessionExport::collect(&SessionStore::new()?, &recordings, &args.session)?;
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(io::BufWriter::new(
+            std::fs::File::create(path).map_err(|err| anyhow!("{}: {}", path.display(), err))?,
+        )),
+        None if matches!(args.format, ExportFormat::Tar) && io::stdout().is_terminal() => {
+            return Err(anyhow!("refusing to write a tar archive to a terminal; use --output or redirect stdout"));
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        ExportFormat::Tar => export.write_tar(&mut out),
+        ExportFormat::Json => export.write_json(&mut out),
+    }
+}
+
 fn setup_signal_handlers(manager: SessionManager) -> Result<()> {
     let mut signals = Signals::new([SIGINT, SIGTERM, SIGQUIT])?;
     thread::spawn(move || {
-        for _ in signals.forever() {
+        if signals.forever().next().is_some() {
             let _ = manager.terminate_all(libc::SIGHUP);
             std::process::exit(1);
         }
@@ -49,61 +748,124 @@ fn setup_signal_handlers(manager: SessionManager) -> Result<()> {
     Ok(())
 }
 
-fn run_ui(config: AppConfig, manager: SessionManager) -> Result<()> {
+fn run_ui(config: AppConfig, base_tasks: Vec<Task>, manager: SessionManager) -> Result<()> {
+    // The TUI and attached runs share the alternate screen, so the shell's
+    // own screen and scrollback are left as they were.
+    let alternate = config.ui.as_ref().and_then(|ui| ui.alternate_screen).unwrap_or(true);
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    if alternate {
+        execute!(stdout, EnterAlternateScreen)?;
+    }
+    execute!(stdout, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
+    if !alternate {
+        terminal.clear()?;
+    }
 
-    let mut app = App::new(config, manager);
-    let tick_rate = Duration::from_millis(200);
+    let mut app = App::new(config, base_tasks, manager);
+    app.autostart();
+    // Redraws are driven by session events; the tick only keeps runtimes current.
+    let tick_rate = Duration::from_secs(1);
+    let poll_rate = Duration::from_millis(50);
     let mut last_tick = Instant::now();
 
     loop {
-        app.refresh_instances();
-        terminal.draw(|frame| app.draw(frame))?;
+        app.drain_events();
+        app.drain_launches();
+        if last_tick.elapsed() >= tick_rate {
+            last_tick = Instant::now();
+            app.sample_usage();
+            app.needs_redraw = true;
+        }
+        if app.needs_redraw {
+            app.needs_redraw = false;
+            app.refresh_instances();
+            terminal.draw(|frame| app.draw(frame))?;
+        }
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_millis(0));
-        if event::poll(timeout)? {
+        if event::poll(poll_rate)? {
             match event::read()? {
                 Event::Key(key) => {
-                    if app.handle_key(key)? {
-                        break;
+                    app.needs_redraw = true;
+                    match app.handle_key(key) {
+                        Ok(true) => break,
+                        Ok(false) => {}
+                        Err(err) => app.toasts.push(Toast::new("Action failed", &err)),
                     }
                 }
                 Event::Resize(_, _) => {
                     app.needs_redraw = true;
                 }
+                Event::Mouse(mouse) => app.handle_mouse(mouse),
                 _ => {}
             }
         }
-        if last_tick.elapsed() >= tick_rate {
-            last_tick = Instant::now();
-        }
 
         if let Some(next) = app.take_passthrough() {
             disable_raw_mode()?;
-            execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-            let _outcome = run_passthrough(next, &app.manager)?;
-            execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+            execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            let instance_id = next.instance_id.clone();
+            match run_passthrough(next, &app.manager) {
+                Ok(PassthroughOutcome::CopyMode) => app.enter_copy_mode(&instance_id),
+                Ok(_) => {}
+                Err(err) => app.toasts.push(Toast::new(format!("Cannot attach to {}", instance_id), &err)),
+            }
+            // Again, in case the attached program switched back to the main screen.
+            if alternate {
+                execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+            }
+            execute!(terminal.backend_mut(), EnableMouseCapture)?;
             terminal.clear()?; // Force full redraw
             enable_raw_mode()?;
             terminal.hide_cursor()?;
+            app.needs_redraw = true;
+        }
+
+        if let Some(suspend) = app.take_suspend() {
+            disable_raw_mode()?;
+            execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            if alternate {
+                execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+            }
+            terminal.show_cursor()?;
+            let edited = matches!(suspend, Suspend::Edit { .. });
+            if let Err(err) = suspend.run() {
+                app.toasts.push(Toast::new("Cannot suspend", &err));
+            }
+            if alternate {
+                execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+            }
+            execute!(terminal.backend_mut(), EnableMouseCapture)?;
+            enable_raw_mode()?;
+            terminal.clear()?;
+            terminal.hide_cursor()?;
+            if edited {
+                app.reload_tasks();
+            }
+            app.needs_redraw = true;
         }
     }
 
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), DisableMouseCapture)?;
+    if alternate {
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    } else {
+        // Leave the last frame on the main screen and give the shell the line below it.
+        let area = terminal.size()?;
+        terminal.set_cursor(0, area.bottom().saturating_sub(1))?;
+        execute!(terminal.backend_mut(), crossterm::style::Print("\r\n"))?;
+    }
     terminal.show_cursor()?;
     Ok(())
 }
 
 struct App {
     config: AppConfig,
+    theme: Theme,
     manager: SessionManager,
     expanded: HashSet<String>,
     entries: Vec<Entry>,
@@ -112,14 +874,109 @@ struct App {
     instances: Vec<InstanceInfo>,
     mode: AppMode,
     last_error: Option<String>,
+    /// Failures that would otherwise end the TUI or be lost in the footer.
+    toasts: Toasts,
     needs_redraw: bool,
+    events: EventReceiver,
     next_passthrough: Option<PassthroughRequest>,
+    next_suspend: Option<Suspend>,
     key_bindings: KeyBindings,
+    split_view: bool,
+    sizes: PaneSizes,
+    dragging: Dragging,
+    // Where the panes were drawn last frame, for mouse hit-testing.
+    pane_area: Rect,
+    // Set while waiting for the number of the task link to open.
+    choosing_link: Option<String>,
+    pinned: Vec<String>,
+    subscribe_options: SubscribeOptions,
+    queued: Vec<QueuedRun>,
+    next_queue_id: u32,
+    enqueue_after: Option<String>,
+    // Runs whose tasks' secrets are being fetched on a worker thread, by id.
+    launching: HashMap<u32, Launch>,
+    next_launch_id: u32,
+    launched_tx: mpsc::Sender<(u32, Result<SpawnedInstance>)>,
+    launched_rx: mpsc::Receiver<(u32, Result<SpawnedInstance>)>,
+    // Kept alive so X11 selections stay available after yanking.
+    clipboard: Option<arboard::Clipboard>,
+    // Filled while drawing, which only borrows the app.
+    log_cache: RefCell<LogCache>,
+    // Run whose log pane stopped following, and the offset of its bottom line.
+    log_pause: Option<(String, u64)>,
+    // Reopened when the log opened from it is closed.
+    return_to_history: Option<Box<HistoryView>>,
+    usage_sampler: UsageSampler,
+    // CPU and memory of running runs, sampled on the tick.
+    usage: HashMap<String, ResourceUsage>,
+    // The name or note being typed for a run in the footer.
+    annotating: Option<Annotation>,
+    // Indexed runs per task for the details pane, and when they were read.
+    task_runs: RefCell<HashMap<String, (Instant, Vec<RunSummary>)>>,
+    // Fuzzy filter over the tasks listed, and whether it is still being typed.
+    search: String,
+    searching: bool,
+    palette: Option<Palette>,
+    // Commands run from the palette, oldest first.
+    palette_history: Vec<String>,
+    // The tasks as configured, before `profile` was applied to `config.tasks`.
+    base_tasks: Vec<Task>,
+    profile: Option<String>,
+}
+
+struct Annotation {
+    instance_id: String,
+    // The note rather than the name.
+    note: bool,
+    value: String,
+}
+
+/// The `:` prompt: a shell command to run, or once `saving` holds that
+/// command, the name to save it under as a task.
+#[derive(Default)]
+struct Palette {
+    value: String,
+    saving: Option<String>,
+    // Position in the commands typed before, while going through them.
+    recall: Option<usize>,
+}
+
+/// A one-off run that starts once `after` exits successfully and its
+/// task's `queue` has no run going.
+struct QueuedRun {
+    id: u32,
+    after: Option<String>,
+    task: Task,
+    values: HashMap<String, String>,
+    launch: LaunchOptions,
+    /// Rendered already, for a run replayed from history.
+    command: Option<String>,
+    /// The rest of a sequential group, queued after this run once it starts.
+    then: Vec<(Task, HashMap<String, String>)>,
+}
+
+/// A run being started, and what happens once it is up.
+struct Launch {
+    task: Task,
+    then: AfterLaunch,
+    /// Toast title when it fails to start.
+    failure: String,
+}
+
+enum AfterLaunch {
+    Attach,
+    Detach,
+    /// Queue the rest of a sequential group after it.
+    Queue(Vec<(Task, HashMap<String, String>)>, LaunchOptions),
 }
 
 enum AppMode {
     List,
     InputForm(InputFormState),
+    Grid { focus: usize },
+    Copy(CopyState),
+    EnvEditor(Box<EnvEditor>),
+    History(Box<HistoryView>),
 }
 
 enum InputResult {
@@ -128,9 +985,20 @@ enum InputResult {
 }
 
 struct InputFormState {
-    task_index: usize,
-    fields: Vec<InputField>,
+    task_id: String,
+    after: Option<String>,
+    launch: LaunchOptions,
+    pages: Vec<InputPage>,
+    page: usize,
     selected: usize,
+    message: Option<String>,
+}
+
+struct InputPage {
+    name: String,
+    fields: Vec<InputField>,
+    /// Launch overrides rather than task inputs.
+    advanced: bool,
 }
 
 struct InputField {
@@ -140,11 +1008,13 @@ struct InputField {
     cursor: usize,
     options: Vec<String>,
     option_index: usize,
+    chosen: Vec<bool>, // Per option, for multiselect inputs
 }
 
 impl App {
-    fn new(config: AppConfig, manager: SessionManager) -> Self {
+    fn new(config: AppConfig, base_tasks: Vec<Task>, manager: SessionManager) -> Self {
         let expanded = config.tasks.iter().map(|task| task.id.clone()).collect();
+        let (launched_tx, launched_rx) = mpsc::channel();
         
         let mut key_bindings = KeyBindings::default();
         if let Some(user_keys) = &config.keys {
@@ -159,8 +1029,39 @@ impl App {
             }
         }
 
-        Self {
+        let split_view = config
+            .ui
+            .as_ref()
+            .and_then(|ui| ui.layout.as_deref())
+            .is_some_and(|layout| layout.eq_ignore_ascii_case("split"));
+
+        let mut subscribe_options = SubscribeOptions::default();
+        if let Some(attach) = &config.attach {
+            if let Some(chunks) = attach.buffer_chunks {
+                subscribe_options.capacity = chunks;
+            }
+            if let Some(value) = attach.on_overflow.as_deref() {
+                match OverflowPolicy::parse(value) {
+                    Some(policy) => subscribe_options.policy = policy,
+                    None => log::warn!("unknown attach.on_overflow value: {}", value),
+                }
+            }
+        }
+
+        let startup_preset = config.ui.as_ref().and_then(|ui| {
+            let name = ui.layout.as_deref()?;
+            Some((name.to_string(), ui.layouts.as_ref()?.get(name)?.clone()))
+        });
+
+        let theme = Theme::resolve(config.theme.as_ref()).unwrap_or_else(|err| {
+            log::warn!("{}", err);
+            Theme::default()
+        });
+
+        let mut app = Self {
             config,
+            theme,
+            events: manager.subscribe_events(),
             manager,
             expanded,
             entries: Vec::new(),
@@ -169,19 +1070,221 @@ impl App {
             instances: Vec::new(),
             mode: AppMode::List,
             last_error: None,
+            toasts: Toasts::default(),
             needs_redraw: true,
             next_passthrough: None,
+            next_suspend: None,
             key_bindings,
+            split_view,
+            sizes: PaneSizes::default(),
+            dragging: Dragging::default(),
+            pane_area: Rect::default(),
+            choosing_link: None,
+            pinned: Vec::new(),
+            subscribe_options,
+            queued: Vec::new(),
+            next_queue_id: 1,
+            launching: HashMap::new(),
+            next_launch_id: 1,
+            launched_tx,
+            launched_rx,
+            enqueue_after: None,
+            clipboard: None,
+            log_cache: RefCell::new(LogCache::default()),
+            return_to_history: None,
+            usage_sampler: UsageSampler::new(),
+            usage: HashMap::new(),
+            log_pause: None,
+            annotating: None,
+            task_runs: RefCell::new(HashMap::new()),
+            search: String::new(),
+            searching: false,
+            palette: None,
+            palette_history: Vec::new(),
+            base_tasks,
+            profile: config::selected_profile(),
+        };
+        if let Some((name, preset)) = startup_preset {
+            app.apply_preset(&name, &preset, false);
+        }
+        app
+    }
+
+    /// Marks the UI dirty when sessions change. Output only matters while
+    /// runs are on screen; the input form hides them.
+    fn drain_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(SessionEvent::OutputChunk { .. }) if matches!(self.mode, AppMode::InputForm(_)) => {}
+                Ok(_) | Err(TryRecvError::Lagged(_)) => self.needs_redraw = true,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+            }
         }
     }
 
+    fn sample_usage(&mut self) {
+        let running: Vec<(String, u32)> = self
+            .instances
+            .iter()
+            .filter(|info| info.status == InstanceStatus::Running && !info.closed)
+            .filter_map(|info| Some((info.id.clone(), info.child_pid?)))
+            .collect();
+        let pgids: Vec<u32> = running.iter().map(|(_, pid)| *pid).collect();
+        let mut samples = self.usage_sampler.sample(&pgids);
+        self.usage = running
+            .into_iter()
+            .filter_map(|(id, pid)| Some((id, *samples.get_mut(&pid)?)))
+            .collect();
+    }
+
     fn refresh_instances(&mut self) {
         if let Ok(instances) = self.manager.list_instances() {
             self.instances = instances;
+            let instances = &self.instances;
+            self.pinned.retain(|id| instances.iter().any(|info| &info.id == id));
+            self.log_cache
+                .borrow_mut()
+                .retain(|id| instances.iter().any(|info| info.id == id));
+            self.process_queue();
+            self.prune_history();
             self.rebuild_entries();
         }
     }
 
+    /// Starts queued runs whose predecessor succeeded and whose queue is
+    /// free, first come first served, and drops those whose predecessor
+    /// failed or disappeared.
+    fn process_queue(&mut self) {
+        let before = self.queued.len();
+        let mut busy: HashSet<String> = self
+            .instances
+            .iter()
+            .filter(|info| info.status == InstanceStatus::Running)
+            .filter_map(|info| self.task_by_id(&info.task_id).and_then(|task| task.queue.clone()))
+            .collect();
+        busy.extend(self.launching.values().filter_map(|launch| launch.task.queue.clone()));
+        let mut pending = Vec::new();
+        for mut run in std::mem::take(&mut self.queued) {
+            if let Some(after) = &run.after {
+                let status = self
+                    .instances
+                    .iter()
+                    .find(|info| info.id == *after)
+                    .map(|info| info.status.clone());
+                match status {
+                    Some(InstanceStatus::Running) => {
+                        pending.push(run);
+                        continue;
+                    }
+                    Some(InstanceStatus::Exited(0)) => run.after = None,
+                    Some(_) | None => {
+                        self.last_error = Some(format!("Queued {} dropped: {} did not succeed", run.task.name, after));
+                        continue;
+                    }
+                }
+            }
+            if let Some(queue) = &run.task.queue {
+                if !busy.insert(queue.clone()) {
+                    pending.push(run);
+                    continue;
+                }
+            }
+            let mut values = run.values;
+            let command = match run.command {
+                Some(command) => Ok(command),
+                None => prepare_command(&run.task, &mut values),
+            };
+            let failure = format!("Queued {} failed to start", run.task.name);
+            match command {
+                Ok(command) => {
+                    let then = match run.then.is_empty() {
+                        true => AfterLaunch::Detach,
+                        false => AfterLaunch::Queue(run.then, run.launch.clone()),
+                    };
+                    self.launch(run.task, command, values, run.launch, failure, then);
+                }
+                Err(err) => self.toasts.push(Toast::new(failure, &err)),
+            }
+        }
+        // Queued by runs that started meanwhile.
+        pending.append(&mut self.queued);
+        self.queued = pending;
+        if self.queued.len() != before {
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Keeps at most `history_limit` finished runs per task, dropping the
+    /// oldest unpinned ones.
+    fn prune_history(&mut self) {
+        let limit = match self.config.history_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut finished: HashMap<&str, Vec<&InstanceInfo>> = HashMap::new();
+        for info in &self.instances {
+            if info.status != InstanceStatus::Running && !self.pinned.contains(&info.id) {
+                finished.entry(info.task_id.as_str()).or_default().push(info);
+            }
+        }
+        let mut stale = Vec::new();
+        for runs in finished.values_mut() {
+            runs.sort_by_key(|info| std::cmp::Reverse(info.ended_at.unwrap_or(info.started_at)));
+            stale.extend(runs.iter().skip(limit).map(|info| info.id.clone()));
+        }
+        stale.retain(|id| self.manager.remove_if_exited(id).unwrap_or(false));
+        if !stale.is_empty() {
+            self.instances.retain(|info| !stale.contains(&info.id));
+        }
+    }
+
+    fn enter_copy_mode(&mut self, instance_id: &str) {
+        let buffer = self.manager.buffer_snapshot(instance_id).unwrap_or_default();
+        self.mode = AppMode::Copy(CopyState::new(instance_id.to_string(), &buffer));
+    }
+
+    fn copy_to_clipboard(&mut self, text: String) -> Result<()> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+        if let Some(clipboard) = self.clipboard.as_mut() {
+            clipboard.set_text(text)?;
+        }
+        Ok(())
+    }
+
+    fn enqueue(&mut self, after: Option<String>, task: Task, values: HashMap<String, String>, launch: LaunchOptions) {
+        let id = self.next_queue_id;
+        self.next_queue_id += 1;
+        self.queued.push(QueuedRun { id, after, task, values, launch, command: None, then: Vec::new() });
+    }
+
+    /// Whether a new run of `task` must wait its turn in the task's queue.
+    fn queue_busy(&self, task: &Task) -> bool {
+        let queue = match &task.queue {
+            Some(queue) => queue,
+            None => return false,
+        };
+        let same_queue = |task_id: &str| self.task_by_id(task_id).is_some_and(|task| task.queue.as_ref() == Some(queue));
+        // Runs spawned since the last refresh are not in `self.instances` yet.
+        let running = self.manager.list_instances().unwrap_or_default();
+        self.queued.iter().any(|run| run.task.queue.as_ref() == Some(queue))
+            || self.launching.values().any(|launch| launch.task.queue.as_ref() == Some(queue))
+            || running
+                .iter()
+                .any(|info| info.status == InstanceStatus::Running && same_queue(&info.task_id))
+    }
+
+    /// 1-based place of a queued run among those waiting in its queue.
+    fn queue_position(&self, run: &QueuedRun) -> usize {
+        self.queued
+            .iter()
+            .filter(|other| other.task.queue == run.task.queue)
+            .take_while(|other| other.id != run.id)
+            .count()
+            + 1
+    }
+
     fn rebuild_entries(&mut self) {
         let mut entries = Vec::new();
         let mut by_task: HashMap<String, Vec<InstanceInfo>> = HashMap::new();
@@ -190,11 +1293,13 @@ impl App {
         }
 
         let mut by_category: HashMap<String, Vec<&Task>> = HashMap::new();
-        for task in &self.config.tasks {
+        for task in self.config.tasks.iter().filter(|task| self.search.is_empty() || task_matches(&self.search, task)) {
             let category = task.category.clone().unwrap_or_else(|| "Default".to_string());
             by_category.entry(category).or_default().push(task);
         }
 
+        self.push_dynamic_sections(&mut entries);
+
         let mut categories: Vec<String> = by_category.keys().cloned().collect();
         categories.sort();
         for category in categories {
@@ -203,18 +1308,36 @@ impl App {
                 for task in tasks {
                     entries.push(Entry::Task { task_id: task.id.clone() });
                     if self.expanded.contains(&task.id) {
-                        if let Some(list) = by_task.get_mut(&task.id) {
-                            list.sort_by_key(|info| info.started_at);
-                            for instance in list {
-                                entries.push(Entry::Instance {
-                                    instance_id: instance.id.clone(),
-                                });
-                            }
+                        // A group lists the runs it started, whatever their task.
+                        let mut list: Vec<&InstanceInfo> = match task.members {
+                            Some(_) => self.instances.iter().filter(|info| info.group.as_ref() == Some(&task.id)).collect(),
+                            None => by_task.get(&task.id).map(|list| list.iter().collect()).unwrap_or_default(),
+                        };
+                        list.sort_by_key(|info| info.started_at);
+                        for instance in list {
+                            entries.push(Entry::Instance {
+                                instance_id: instance.id.clone(),
+                            });
                         }
                     }
+                    for run in self.queued.iter().filter(|run| run.task.id == task.id) {
+                        entries.push(Entry::Queued { queue_id: run.id });
+                    }
                 }
             }
         }
+        // Keep the cursor on the same entry when sections above it grow or shrink.
+        if let Some(previous) = self.entries.get(self.selected) {
+            let nearest = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| *entry == previous)
+                .min_by_key(|(idx, _)| idx.abs_diff(self.selected))
+                .map(|(idx, _)| idx);
+            if let Some(idx) = nearest {
+                self.selected = idx;
+            }
+        }
         self.entries = entries;
         if self.selected >= self.entries.len() {
             self.selected = self.entries.len().saturating_sub(1);
@@ -226,62 +1349,194 @@ impl App {
         }
     }
 
-    fn draw(&mut self, frame: &mut ratatui::Frame) {
-        match &self.mode {
-            AppMode::InputForm(form) => {
-                let area = frame.size();
-                let block = Block::default().borders(Borders::ALL).title("Inputs");
-                frame.render_widget(block, area);
+    /// Virtual sections computed from runtime state, listed above the
+    /// config categories and omitted when empty.
+    fn push_dynamic_sections(&self, entries: &mut Vec<Entry>) {
+        let mut running: Vec<&InstanceInfo> = self
+            .instances
+            .iter()
+            .filter(|info| info.status == InstanceStatus::Running)
+            .collect();
+        running.sort_by_key(|info| info.started_at);
+
+        let mut failed: Vec<&InstanceInfo> = self
+            .instances
+            .iter()
+            .filter(|info| match info.status {
+                InstanceStatus::Exited(code) => code != 0,
+                InstanceStatus::Error(_) => true,
+                InstanceStatus::Running | InstanceStatus::Cancelled => false,
+            })
+            .collect();
+        failed.sort_by_key(|info| std::cmp::Reverse(info.ended_at.unwrap_or(info.started_at)));
+        failed.truncate(RECENT_FAILED_LIMIT);
+
+        for (name, list) in [("Running now", running), ("Recently failed", failed)] {
+            if list.is_empty() {
+                continue;
+            }
+            entries.push(Entry::Section { name: name.to_string() });
+            for info in list {
+                entries.push(Entry::Instance {
+                    instance_id: info.id.clone(),
+                });
+            }
+        }
+
+        // Queued runs start first come first served.
+        let mut scheduled: Vec<&QueuedRun> = self.queued.iter().collect();
+        scheduled.sort_by_key(|run| run.id);
+        if !scheduled.is_empty() {
+            entries.push(Entry::Section { name: "Scheduled next".to_string() });
+            entries.extend(scheduled.into_iter().map(|run| Entry::Queued { queue_id: run.id }));
+        }
+    }
+
+    fn draw(&mut self, frame: &mut ratatui::Frame) {
+        self.draw_view(frame);
+        self.toasts.render(frame, frame.size(), &self.theme);
+    }
+
+    fn draw_view(&mut self, frame: &mut ratatui::Frame) {
+        if let AppMode::Copy(state) = &mut self.mode {
+            state.render(frame, frame.size(), &self.theme);
+            return;
+        }
+        if let AppMode::EnvEditor(editor) = &mut self.mode {
+            editor.render(frame, frame.size(), &self.theme);
+            return;
+        }
+        if let AppMode::History(view) = &mut self.mode {
+            view.render(frame, frame.size(), &self.theme);
+            return;
+        }
+        // The footer grows a line while there is a message to show.
+        let footer_height = if self.last_error.is_some() { 2 } else { 1 };
+        match &self.mode {
+            AppMode::InputForm(form) => {
+                let area = frame.size();
+                let block = self.block("Inputs");
+                frame.render_widget(block, area);
                 self.render_input_form(frame, area, form);
             }
             AppMode::List => {
                 let chunks = Layout::default()
                     .direction(Direction::Vertical)
-                    .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                    .constraints([Constraint::Min(1), Constraint::Length(footer_height)].as_ref())
                     .split(frame.size());
                 let items = self.list_items();
+                let mut title = match &self.profile {
+                    Some(profile) => format!("CmdHub [{}]", profile),
+                    None => "CmdHub".to_string(),
+                };
+                if !self.search.is_empty() && !self.searching {
+                    title.push_str(&format!(" /{}", self.search));
+                }
                 let list = List::new(items)
-                    .block(Block::default().borders(Borders::ALL).title("CmdHub"))
-                    .highlight_style(
-                        Style::default()
-                            .bg(Color::Blue)
-                            .fg(Color::Black)
-                            .add_modifier(Modifier::BOLD),
-                    )
+                    .block(self.block(title))
+                    .highlight_style(self.theme.highlight)
                     .highlight_symbol(">> ");
-                frame.render_stateful_widget(list, chunks[0], &mut self.list_state);
+                self.pane_area = chunks[0];
+                if self.split_view {
+                    let (list_area, mut log_area) = self.sizes.split_panes(chunks[0]);
+                    frame.render_stateful_widget(list, list_area, &mut self.list_state);
+                    if let Some((details, count)) = self.build_details_pane(log_area.width.saturating_sub(2)) {
+                        let height = (count as u16 + 2).min(log_area.height / 2);
+                        let panes = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(3), Constraint::Length(height)])
+                            .split(log_area);
+                        frame.render_widget(details, panes[1]);
+                        log_area = panes[0];
+                    }
+                    let logs = self.build_log_pane(self.selected_instance(), log_area, false);
+                    frame.render_widget(logs, log_area);
+                } else {
+                    frame.render_stateful_widget(list, chunks[0], &mut self.list_state);
+                }
                 let help = self.build_help();
                 frame.render_widget(help, chunks[1]);
             }
+            AppMode::Grid { focus } => {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(footer_height)].as_ref())
+                    .split(frame.size());
+                self.pane_area = chunks[0];
+                for (idx, area) in self.sizes.grid_tiles(chunks[0], self.pinned.len()).into_iter().enumerate() {
+                    let info = self.instances.iter().find(|info| info.id == self.pinned[idx]);
+                    let tile = self.build_log_pane(info, area, idx == *focus);
+                    frame.render_widget(tile, area);
+                }
+                let help = self.build_help();
+                frame.render_widget(help, chunks[1]);
+            }
+            AppMode::Copy(_) | AppMode::EnvEditor(_) | AppMode::History(_) => {}
         }
     }
 
     fn list_items(&self) -> Vec<ListItem<'static>> {
         let mut items = Vec::new();
+        // Sections list the runs of any task, so their queued runs name it.
+        let mut in_section = false;
         for entry in &self.entries {
             match entry {
+                Entry::Section { name } => {
+                    in_section = true;
+                    let line = Line::from(vec![Span::styled(name.clone(), self.theme.section)]);
+                    items.push(ListItem::new(line));
+                }
                 Entry::Category { name } => {
-                    let line = Line::from(vec![Span::styled(
-                        name.clone(),
-                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-                    )]);
+                    in_section = false;
+                    let style = self.category_style(name).unwrap_or(self.theme.header);
+                    let line = Line::from(vec![Span::styled(name.clone(), style)]);
                     items.push(ListItem::new(line));
                 }
                 Entry::Task { task_id } => {
                     let task = self.task_by_id(task_id);
                     let name = task.map(|t| t.name.as_str()).unwrap_or(task_id);
                     let marker = if self.expanded.contains(task_id) { "-" } else { "+" };
-                    let line = Line::from(vec![
+                    // The task's own color, else its category's, else bold.
+                    let style = task
+                        .and_then(|t| t.color.as_deref())
+                        .map(text_style)
+                        .or_else(|| task.and_then(|t| t.category.as_deref()).and_then(|c| self.category_style(c)))
+                        .unwrap_or_else(|| Style::default().add_modifier(Modifier::BOLD));
+                    let mut spans = vec![
                         Span::styled(marker.to_string(), Style::default().fg(Color::Gray)),
                         Span::raw(" "),
-                        Span::styled(name.to_string(), Style::default().add_modifier(Modifier::BOLD)),
-                    ]);
+                    ];
+                    if let Some(icon) = task.and_then(|t| t.icon.as_deref()) {
+                        spans.push(Span::styled(format!("{} ", icon), style));
+                    }
+                    spans.push(Span::styled(name.to_string(), style));
+                    if let Some(members) = task.and_then(|t| t.members.as_ref()) {
+                        let separator = if task.and_then(|t| t.sequential) == Some(true) { " → " } else { ", " };
+                        spans.push(Span::styled(format!(" ({})", members.join(separator)), self.theme.hint));
+                    }
+                    let line = Line::from(spans);
                     items.push(ListItem::new(line));
                 }
+                Entry::Queued { queue_id } => {
+                    let run = self.queued.iter().find(|run| run.id == *queue_id);
+                    let waiting = match run {
+                        Some(QueuedRun { after: Some(after), .. }) => format!("waiting on {}", after),
+                        Some(run @ QueuedRun { task: Task { queue: Some(queue), .. }, .. }) => {
+                            format!("queued #{} in {}", self.queue_position(run), queue)
+                        }
+                        _ => String::new(),
+                    };
+                    let mut spans = vec![Span::raw("  "), Span::styled("~", self.theme.hint), Span::raw(" ")];
+                    if let Some(run) = run.filter(|_| in_section) {
+                        spans.push(Span::raw(format!("{} ", run.task.name)));
+                    }
+                    spans.push(Span::styled(waiting, self.theme.hint));
+                    items.push(ListItem::new(Line::from(spans)));
+                }
                 Entry::Instance { instance_id } => {
                     let instance = self.instances.iter().find(|i| &i.id == instance_id);
                     let line = if let Some(info) = instance {
-                        instance_line(info)
+                        instance_line(info, self.usage.get(&info.id), &self.theme)
                     } else {
                         Line::from(vec![Span::raw("  (missing)")])
                     };
@@ -296,20 +1551,194 @@ impl App {
     fn build_help(&self) -> Paragraph<'_> {
         let mut text = Vec::new();
         match self.mode {
-            AppMode::List => {
-                text.push(Line::from("Enter: run/attach  Tab: fold  d: delete  X: kill  Q: quit"));
+            AppMode::List if self.choosing_link.is_some() => {
+                let links = self.selected_task().and_then(|task| task.links.clone()).unwrap_or_default();
+                let choices: Vec<String> = links
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, link)| format!("{}) {}", idx + 1, link.name))
+                    .collect();
+                text.push(Line::from(Span::styled(
+                    format!("Open link: {}  Esc: cancel", choices.join("  ")),
+                    self.theme.hint,
+                )));
+            }
+            AppMode::List if self.searching => {
+                text.push(Line::from(vec![
+                    Span::styled("Search: ", self.theme.hint),
+                    Span::raw(self.search.clone()),
+                    Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+                    Span::styled("  Enter: keep  Esc: clear", self.theme.hint),
+                ]));
+            }
+            AppMode::List if self.palette.is_some() => {
+                if let Some(palette) = &self.palette {
+                    let (prompt, keys) = match &palette.saving {
+                        Some(command) => (format!("Save `{}` as task named: ", command), "  Enter: save  Esc: cancel"),
+                        None => (":".to_string(), "  Enter: run  Ctrl+S: save as task  Up/Down: recall  Esc: cancel"),
+                    };
+                    text.push(Line::from(vec![
+                        Span::styled(prompt, self.theme.hint),
+                        Span::raw(palette.value.clone()),
+                        Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+                        Span::styled(keys, self.theme.hint),
+                    ]));
+                }
+            }
+            AppMode::List if self.annotating.is_some() => {
+                if let Some(annotation) = &self.annotating {
+                    let what = if annotation.note { "Note" } else { "Name" };
+                    text.push(Line::from(vec![
+                        Span::styled(format!("{} for {}: ", what, annotation.instance_id), self.theme.hint),
+                        Span::raw(annotation.value.clone()),
+                        Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+                        Span::styled("  Enter: save (empty clears)  Esc: cancel", self.theme.hint),
+                    ]));
+                }
             }
+            AppMode::List => match &self.enqueue_after {
+                Some(after) => text.push(Line::from(Span::styled(
+                    format!("Queue after {}: Enter on a task to queue it  Esc: cancel", after),
+                    self.theme.hint,
+                ))),
+                None => text.push(Line::from(
+                    "Enter: run/attach  Tab: fold  d: delete  X: kill  s: cancel  e: env  t: edit task  l: launch opts  b: baseline  n: name  m: note  o: link  /: search  :: command  w: profile  h: history  !: shell  a: after  c: copy  v: split  PgUp/PgDn: scroll  f: follow  p: pin  g: grid  Q: quit",
+                )),
+            },
+            AppMode::Grid { .. } => {
+                text.push(Line::from("Tab: next tile  Enter: attach  PgUp/PgDn: scroll  f: follow  p: unpin  Esc: back"));
+            }
+            AppMode::Copy(_) | AppMode::EnvEditor(_) | AppMode::History(_) => {}
             AppMode::InputForm(_) => {
-                text.push(Line::from("Enter: next/submit  Esc: cancel  Up/Down: select  Left/Right: option"));
+                text.push(Line::from("Enter: next/submit  Tab: next page  Esc: cancel  Up/Down: select  Left/Right: option"));
             }
         }
         if let Some(err) = &self.last_error {
-            text.push(Line::from(Span::styled(
-                err.clone(),
-                Style::default().fg(Color::Red),
-            )));
+            text.push(Line::from(Span::styled(err.clone(), self.theme.error)));
+        }
+        Paragraph::new(text)
+    }
+
+    fn build_log_pane(&self, selected: Option<&InstanceInfo>, area: Rect, focused: bool) -> Paragraph<'static> {
+        let title = selected
+            .map(|info| {
+                let name = match &info.label {
+                    Some(label) => format!("{} {}", info.id, label),
+                    None => info.id.clone(),
+                };
+                match self.usage.get(&info.id) {
+                    Some(usage) => format!("Logs: {} ({})", name, usage.summary()),
+                    None => format!("Logs: {}", name),
+                }
+            })
+            .unwrap_or_else(|| "Logs".to_string());
+        let anchor = selected.and_then(|info| self.log_anchor(&info.id));
+        let title = if anchor.is_some() { format!("{} [paused]", title) } else { title };
+        let mut block = self.block(title);
+        if focused {
+            block = block.border_style(self.theme.focus_border);
+        }
+        let lines = match selected {
+            Some(info) => {
+                let width = area.width.saturating_sub(2) as usize;
+                let mut height = area.height.saturating_sub(2) as usize;
+                if anchor.is_some() {
+                    height = height.saturating_sub(1);
+                }
+                let (mut lines, below) =
+                    self.log_cache.borrow_mut().page(&self.manager, &info.id, width, height, anchor);
+                if anchor.is_some() {
+                    let indicator = match below {
+                        0 => "f: follow".to_string(),
+                        1 => "1 new line ↓  f: follow".to_string(),
+                        n => format!("{} new lines ↓  f: follow", n),
+                    };
+                    lines.resize(height, Line::from(""));
+                    lines.push(Line::from(Span::styled(indicator, self.theme.hint)));
+                }
+                lines
+            }
+            None => vec![Line::from(Span::styled("No run selected", self.theme.muted))],
+        };
+        Paragraph::new(lines).style(self.theme.log).block(block)
+    }
+
+    /// The selected task's links, numbered for the open_link key.
+    /// The selected task's description, wrapped to `width`, a sparkline of
+    /// its recent runs and its links.
+    fn build_details_pane(&self, width: u16) -> Option<(Paragraph<'static>, usize)> {
+        let task = self.selected_task()?;
+        let mut lines: Vec<Line<'static>> = task
+            .description
+            .as_deref()
+            .map(|description| wrap_words(description.trim(), width as usize))
+            .unwrap_or_default()
+            .into_iter()
+            .map(Line::from)
+            .collect();
+        if let Some(line) = self.run_sparkline(&task.id, width as usize) {
+            lines.push(line);
+        }
+        for (idx, link) in task.links.iter().flatten().enumerate() {
+            lines.push(Line::from(vec![
+                Span::styled(format!("{} ", idx + 1), Style::default().fg(Color::Yellow)),
+                Span::styled(link.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" "),
+                Span::styled(link.url.clone(), self.theme.muted),
+            ]));
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        let count = lines.len();
+        Some((Paragraph::new(lines).block(self.block(task.name.clone())), count))
+    }
+
+    /// The selected task, or the task of the selected instance.
+    fn selected_task(&self) -> Option<&Task> {
+        match self.entries.get(self.selected)? {
+            Entry::Task { task_id } => self.task_by_id(task_id),
+            Entry::Instance { instance_id } => {
+                let info = self.instances.iter().find(|info| &info.id == instance_id)?;
+                self.task_by_id(&info.task_id)
+            }
+            Entry::Category { .. } | Entry::Section { .. } | Entry::Queued { .. } => None,
+        }
+    }
+
+    /// Saves a past run's output as its task's baseline.
+    fn save_baseline(&self, task_id: &str, output: &[u8]) -> Result<std::path::PathBuf> {
+        let task = self
+            .task_by_id(task_id)
+            .ok_or_else(|| anyhow!("task {} is no longer configured", task_id))?;
+        let ignore = baseline::ignore_patterns(task)?;
+        let normalized = baseline::normalize(baseline::run_output(output), &ignore);
+        BaselineStore::new()?.save(task_id, &normalized)
+    }
+
+    fn open_task_link(&mut self, task_id: &str, idx: usize) {
+        let link = match self.task_by_id(task_id).and_then(|task| task.links.as_ref()?.get(idx)) {
+            Some(link) => link.clone(),
+            None => return,
+        };
+        self.last_error = Some(match open_url(&link.url) {
+            Ok(()) => format!("Opened {}", link.name),
+            Err(err) => format!("Cannot open {}: {}", link.url, err),
+        });
+    }
+
+    /// The run shown in the log pane: the selected instance, or the most
+    /// recent instance of the selected task.
+    fn selected_instance(&self) -> Option<&InstanceInfo> {
+        match self.entries.get(self.selected)? {
+            Entry::Instance { instance_id } => self.instances.iter().find(|i| &i.id == instance_id),
+            Entry::Task { task_id } => self
+                .instances
+                .iter()
+                .filter(|i| &i.task_id == task_id || i.group.as_ref() == Some(task_id))
+                .max_by_key(|i| i.started_at),
+            Entry::Category { .. } | Entry::Section { .. } | Entry::Queued { .. } => None,
         }
-        Paragraph::new(text).wrap(Wrap { trim: true })
     }
 
     fn render_input_form(&self, frame: &mut ratatui::Frame, area: Rect, form: &InputFormState) {
@@ -320,19 +1749,49 @@ impl App {
             height: area.height.saturating_sub(2),
         };
         let mut lines = Vec::new();
-        for (idx, field) in form.fields.iter().enumerate() {
+        if form.pages.len() > 1 {
+            let mut tabs = Vec::new();
+            for (idx, page) in form.pages.iter().enumerate() {
+                let style = if idx == form.page {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                tabs.push(Span::styled(format!(" {} ", page.name), style));
+                match page.error_count() {
+                    0 => tabs.push(Span::styled("✓ ", Style::default().fg(Color::Green))),
+                    n => tabs.push(Span::styled(format!("!{} ", n), self.theme.error)),
+                }
+            }
+            lines.push(Line::from(tabs));
+            lines.push(Line::from(""));
+        }
+        let page = &form.pages[form.page];
+        for (idx, field) in page.fields.iter().enumerate() {
             let title = format!("{}:", field.name);
-            let mut spans = vec![Span::styled(title, Style::default().fg(Color::Yellow))];
+            let mut spans = vec![Span::styled(title, self.theme.header)];
             spans.push(Span::raw(" "));
-            let value = field.value.clone();
-            let style = if idx == form.selected {
-                Style::default().add_modifier(Modifier::REVERSED)
-            } else {
-                Style::default()
-            };
-            spans.push(Span::styled(value, style));
+            spans.extend(field.display_spans(idx == form.selected));
+            if let Some(error) = field.error() {
+                spans.push(Span::styled(format!("  ({})", error), self.theme.error));
+            }
             lines.push(Line::from(spans));
         }
+        lines.push(Line::from(""));
+        let summary = match page.error_count() {
+            0 => Span::styled(
+                format!("{}: all fields valid", page.name),
+                Style::default().fg(Color::Green),
+            ),
+            n => Span::styled(
+                format!("{}: {} of {} fields need attention", page.name, n, page.fields.len()),
+                self.theme.error,
+            ),
+        };
+        lines.push(Line::from(summary));
+        if let Some(message) = &form.message {
+            lines.push(Line::from(Span::styled(message.clone(), self.theme.error)));
+        }
         let content = Paragraph::new(lines).wrap(Wrap { trim: true });
         let content_area = Rect {
             x: inner.x,
@@ -348,21 +1807,139 @@ impl App {
             width: inner.width,
             height: 1,
         };
-        let help = Paragraph::new(Line::from(
-            "Enter: next/submit  Esc: cancel  Up/Down: select  Left/Right: option",
-        ));
+        let help = if form.pages.len() > 1 {
+            "Enter: next/submit  Tab: next page  Esc: cancel  Up/Down: select  Left/Right: option  Space: toggle  Ctrl+O: advanced"
+        } else {
+            "Enter: next/submit  Esc: cancel  Up/Down: select  Left/Right: option  Space: toggle  Ctrl+O: advanced"
+        };
+        let help = Paragraph::new(Line::from(help));
         frame.render_widget(help, help_area);
     }
 
+    /// One bar per recent run, as tall as it took relative to the others and
+    /// red when it failed, followed by the success rate and percentiles.
+    fn run_sparkline(&self, task_id: &str, width: usize) -> Option<Line<'static>> {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let mut cache = self.task_runs.borrow_mut();
+        let fresh = cache.get(task_id).is_some_and(|(read_at, _)| read_at.elapsed() < RUN_STATS_TTL);
+        if !fresh {
+            let runs = SessionStore::new().and_then(|store| store.task_runs(task_id)).unwrap_or_default();
+            cache.insert(task_id.to_string(), (Instant::now(), runs));
+        }
+        let runs = &cache.get(task_id)?.1;
+        if runs.is_empty() {
+            return None;
+        }
+        let stats = TaskStats::from_runs(runs);
+        let mut summary = format!(" {}% ok", stats.success_rate().unwrap_or_default());
+        if let (Some(p50), Some(p90)) = (stats.p50_secs, stats.p90_secs) {
+            summary.push_str(&format!("  p50 {}  p90 {}", stats::format_secs(p50), stats::format_secs(p90)));
+        }
+        let recent = &runs[runs.len().saturating_sub(width.saturating_sub(summary.chars().count()).max(1))..];
+        let longest = recent.iter().filter_map(|run| run.duration_secs).max().unwrap_or_default().max(1);
+        let mut spans: Vec<Span<'static>> = recent
+            .iter()
+            .map(|run| {
+                let level = run.duration_secs.unwrap_or_default() * (BARS.len() as u64 - 1) / longest;
+                let color = if run.succeeded() { Color::Green } else { Color::Red };
+                Span::styled(BARS[level as usize].to_string(), Style::default().fg(color))
+            })
+            .collect();
+        spans.push(Span::styled(summary, self.theme.muted));
+        Some(Line::from(spans))
+    }
+
     fn handle_key(&mut self, key: KeyEvent) -> Result<bool> {
+        if self.toasts.handle_key(key) {
+            return Ok(false);
+        }
+        if self.key_bindings.global.get("suspend").is_some_and(|binding| matches_key(&key, binding)) {
+            self.next_suspend = Some(Suspend::Stop);
+            return Ok(false);
+        }
         let mode = std::mem::replace(&mut self.mode, AppMode::List);
         match mode {
             AppMode::List => {
                 self.mode = AppMode::List;
                 self.handle_list_key(key)
             }
+            AppMode::Grid { focus } => {
+                self.handle_grid_key(key, focus)?;
+                Ok(false)
+            }
+            AppMode::Copy(mut state) => {
+                match state.handle_key(key) {
+                    CopyAction::Stay => self.mode = AppMode::Copy(state),
+                    CopyAction::Exit => {}
+                    CopyAction::Yank(text) => {
+                        let count = text.lines().count().max(1);
+                        match self.copy_to_clipboard(text) {
+                            Ok(()) => self.last_error = Some(format!("Copied {} line(s)", count)),
+                            Err(err) => self.last_error = Some(format!("Clipboard unavailable: {}", err)),
+                        }
+                    }
+                }
+                if !matches!(self.mode, AppMode::Copy(_)) {
+                    if let Some(mut view) = self.return_to_history.take() {
+                        view.message = self.last_error.take();
+                        self.mode = AppMode::History(view);
+                    }
+                }
+                Ok(false)
+            }
+            AppMode::History(mut view) => {
+                match view.handle_key(key) {
+                    HistoryAction::Stay => self.mode = AppMode::History(view),
+                    HistoryAction::Exit => {}
+                    HistoryAction::ShowLog { title, output } => {
+                        self.mode = AppMode::Copy(CopyState::new(title, &output));
+                        self.return_to_history = Some(view);
+                    }
+                    HistoryAction::Rerun(run) => match self.task_by_id(&run.task_id).cloned() {
+                        Some(task) => self.replay_run(&task, &run)?,
+                        None => {
+                            view.message = Some(format!("Task {} is no longer configured", run.task_id));
+                            self.mode = AppMode::History(view);
+                        }
+                    },
+                    HistoryAction::StartTask(task_id) => match self.task_by_id(&task_id).cloned() {
+                        Some(task) => self.start_task(task, None, LaunchOptions::default())?,
+                        None => {
+                            view.message = Some(format!("Task {} is no longer configured", task_id));
+                            self.mode = AppMode::History(view);
+                        }
+                    },
+                    HistoryAction::Baseline { task_id, output } => {
+                        view.message = Some(match self.save_baseline(&task_id, &output) {
+                            Ok(path) => format!("Baseline for {} saved to {}", task_id, path.display()),
+                            Err(err) => format!("Cannot set baseline: {}", err),
+                        });
+                        self.mode = AppMode::History(view);
+                    }
+                }
+                Ok(false)
+            }
+            AppMode::EnvEditor(mut editor) => {
+                match editor.handle_key(key) {
+                    EnvAction::Stay => self.mode = AppMode::EnvEditor(editor),
+                    EnvAction::Exit => {}
+                    EnvAction::Launch => {
+                        let EnvEditor { task, after, overrides, .. } = *editor;
+                        self.start_task(task, after, LaunchOptions { env: overrides, ..Default::default() })?;
+                    }
+                }
+                Ok(false)
+            }
             AppMode::InputForm(mut form) => {
-                let result = self.handle_input_key(key, &mut form)?;
+                let result = match self.handle_input_key(key, &mut form) {
+                    Ok(result) => result,
+                    // Keep what was typed so the run can be fixed and retried.
+                    Err(err) => {
+                        let name = self.task_by_id(&form.task_id).map(|task| task.name.clone()).unwrap_or_default();
+                        self.toasts.push(Toast::new(format!("Cannot start {}", name), &err));
+                        InputResult::Stay
+                    }
+                };
                 match result {
                     InputResult::Stay => {
                         self.mode = AppMode::InputForm(form);
@@ -378,6 +1955,24 @@ impl App {
 
     fn handle_list_key(&mut self, key: KeyEvent) -> Result<bool> {
         self.last_error = None;
+        if let Some(task_id) = self.choosing_link.take() {
+            if let KeyCode::Char(ch @ '1'..='9') = key.code {
+                self.open_task_link(&task_id, ch as usize - '1' as usize);
+            }
+            return Ok(false);
+        }
+        if let Some(annotation) = self.annotating.take() {
+            self.handle_annotation_key(key, annotation);
+            return Ok(false);
+        }
+        if self.searching {
+            self.handle_search_key(key);
+            return Ok(false);
+        }
+        if let Some(palette) = self.palette.take() {
+            self.handle_palette_key(key, palette);
+            return Ok(false);
+        }
         let keys = &self.key_bindings.task_list;
         
         // Helper to check key
@@ -389,7 +1984,14 @@ impl App {
             }
         };
 
-        if check("quit", &key) {
+        if key.code == KeyCode::Esc && self.enqueue_after.is_some() {
+             self.enqueue_after = None;
+        } else if key.code == KeyCode::Esc && !self.search.is_empty() {
+             self.search.clear();
+             self.rebuild_entries();
+        } else if check("search_tasks", &key) {
+             self.searching = true;
+        } else if check("quit", &key) {
              let _ = self.manager.terminate_all(libc::SIGTERM);
              return Ok(true);
         } else if check("down", &key) {
@@ -408,22 +2010,152 @@ impl App {
                      self.expanded.insert(task_id.clone());
                  }
              }
+        } else if check("toggle_layout", &key) {
+             self.split_view = !self.split_view;
+        } else if check("scroll_up", &key) || check("scroll_down", &key) {
+             let delta = if check("scroll_up", &key) { -self.log_page() } else { self.log_page() };
+             match self.selected_instance().map(|info| info.id.clone()) {
+                 Some(instance_id) if self.split_view => self.scroll_log(&instance_id, delta),
+                 Some(_) => self.last_error = Some("Press v to show the log pane".to_string()),
+                 None => self.last_error = Some("Select a run to scroll its log".to_string()),
+             }
+        } else if check("follow", &key) || key.code == KeyCode::End {
+             self.log_pause = None;
+        } else if check("pin_instance", &key) {
+             if let Some(instance_id) = self.selected_instance().map(|info| info.id.clone()) {
+                 self.toggle_pin(instance_id);
+             }
+        } else if check("toggle_grid", &key) {
+             if self.pinned.is_empty() {
+                 self.last_error = Some("No runs pinned; press p on a run to pin it".to_string());
+             } else {
+                 self.mode = AppMode::Grid { focus: 0 };
+             }
+        } else if check("copy_mode", &key) {
+             match self.selected_instance().map(|info| info.id.clone()) {
+                 Some(instance_id) => self.enter_copy_mode(&instance_id),
+                 None => self.last_error = Some("Select a run to copy from".to_string()),
+             }
+        } else if check("open_link", &key) {
+             let task = self.selected_task().map(|task| (task.id.clone(), task.links.as_ref().map_or(0, Vec::len)));
+             match task {
+                 Some((_, 0)) | None => self.last_error = Some("Selected task has no links".to_string()),
+                 Some((task_id, 1)) => self.open_task_link(&task_id, 0),
+                 Some((task_id, _)) => self.choosing_link = Some(task_id),
+             }
+        } else if check("rename_instance", &key) || check("annotate_instance", &key) {
+             let note = check("annotate_instance", &key);
+             match self.selected_instance() {
+                 Some(info) => {
+                     let current = if note { &info.note } else { &info.label };
+                     self.annotating = Some(Annotation {
+                         instance_id: info.id.clone(),
+                         note,
+                         value: current.clone().unwrap_or_default(),
+                     });
+                 }
+                 None => self.last_error = Some("Select a run to name or annotate".to_string()),
+             }
+        } else if check("mark_baseline", &key) {
+             match self.selected_instance().map(|info| info.id.clone()) {
+                 Some(instance_id) => match self.manager.set_baseline(&instance_id) {
+                     Ok(path) => self.last_error = Some(format!("Baseline saved to {}", path.display())),
+                     Err(err) => self.last_error = Some(format!("Cannot set baseline: {}", err)),
+                 },
+                 None => self.last_error = Some("Select a finished run to use as baseline".to_string()),
+             }
+        } else if check("history", &key) {
+             match HistoryView::load() {
+                 Ok(view) => self.mode = AppMode::History(Box::new(view)),
+                 Err(err) => self.last_error = Some(format!("History unavailable: {}", err)),
+             }
+        } else if check("shell", &key) {
+             self.next_suspend = Some(Suspend::Shell);
+        } else if check("command_palette", &key) {
+             self.palette = Some(Palette::default());
+        } else if check("edit_task", &key) {
+             match self.selected_task() {
+                 Some(task) => match &task.source {
+                     Some(path) => {
+                         let line = task_line(path, &task.id);
+                         self.next_suspend = Some(Suspend::Edit { path: path.clone(), line });
+                     }
+                     None => self.last_error = Some(format!("{} is not defined in a config file", task.name)),
+                 },
+                 None => self.last_error = Some("Select a task to edit it".to_string()),
+             }
+        } else if check("edit_env", &key) {
+             match self.entries.get(self.selected) {
+                 Some(Entry::Task { task_id }) => {
+                     if let Some(task) = self.task_by_id(task_id).cloned() {
+                         let after = self.enqueue_after.take();
+                         self.mode = AppMode::EnvEditor(Box::new(EnvEditor::new(task, after)));
+                     }
+                 }
+                 _ => self.last_error = Some("Select a task to edit its environment".to_string()),
+             }
+        } else if check("launch_options", &key) {
+             match self.entries.get(self.selected) {
+                 Some(Entry::Task { task_id }) => {
+                     if let Some(task) = self.task_by_id(task_id).cloned() {
+                         let after = self.enqueue_after.take();
+                         self.open_task(task, after, LaunchOptions::default(), true)?;
+                     }
+                 }
+                 _ => self.last_error = Some("Select a task to set its launch options".to_string()),
+             }
+        } else if check("enqueue_after", &key) {
+             match self.selected_instance() {
+                 Some(info) if info.status == InstanceStatus::Running => {
+                     self.enqueue_after = Some(info.id.clone());
+                 }
+                 _ => self.last_error = Some("Select a running run to queue after".to_string()),
+             }
         } else if check("delete_instance", &key) {
-             if let Some(Entry::Instance { instance_id }) = self.entries.get(self.selected) {
-                 let _ = self.manager.remove_if_exited(instance_id);
+             match self.entries.get(self.selected) {
+                 Some(Entry::Instance { instance_id }) => {
+                     let _ = self.manager.remove_if_exited(instance_id);
+                 }
+                 Some(Entry::Queued { queue_id }) => {
+                     let queue_id = *queue_id;
+                     self.queued.retain(|run| run.id != queue_id);
+                 }
+                 _ => {}
              }
+        } else if check("cancel_instance", &key) {
+             match self.entries.get(self.selected) {
+                 Some(Entry::Instance { instance_id }) => match self.manager.cancel(instance_id) {
+                     Ok(true) => {}
+                     Ok(false) => self.last_error = Some(format!("{} is not running", instance_id)),
+                     Err(err) => self.last_error = Some(format!("Cancel failed: {}", err)),
+                 },
+                 Some(Entry::Queued { queue_id }) => {
+                     let queue_id = *queue_id;
+                     self.queued.retain(|run| run.id != queue_id);
+                 }
+                 _ => {}
+             }
+        } else if check("switch_profile", &key) {
+             self.switch_profile();
         } else if check("kill_instance", &key) {
-             if let Some(Entry::Instance { instance_id }) = self.entries.get(self.selected) {
-                 let _ = self.manager.kill_and_remove(instance_id);
+             match self.entries.get(self.selected).cloned() {
+                 Some(Entry::Instance { instance_id }) => {
+                     let _ = self.manager.kill_and_remove(&instance_id);
+                 }
+                 Some(Entry::Task { task_id }) if self.task_by_id(&task_id).is_some_and(|task| task.members.is_some()) => {
+                     self.kill_group(&task_id);
+                 }
+                 _ => {}
              }
         } else if check("select", &key) {
              if let Some(entry) = self.entries.get(self.selected).cloned() {
                  match entry {
-                     Entry::Category { .. } => {}
+                     Entry::Category { .. } | Entry::Section { .. } | Entry::Queued { .. } => {}
                      Entry::Task { task_id } => {
                          let task = self.task_by_id(&task_id).cloned();
                          if let Some(task) = task {
-                             self.start_task(task)?;
+                             let after = self.enqueue_after.take();
+                             self.start_task(task, after, LaunchOptions::default())?;
                          }
                      }
                      Entry::Instance { instance_id } => {
@@ -431,6 +2163,8 @@ impl App {
                      }
                  }
              }
+        } else {
+             self.apply_preset_key(&key, false);
         }
         
         if self.entries.is_empty() {
@@ -438,93 +2172,694 @@ impl App {
         } else {
             self.list_state.select(Some(self.selected));
         }
-        Ok(false)
+        Ok(false)
+    }
+
+    /// Edits the name or note in the footer; Enter saves it, blank clears it.
+    fn handle_annotation_key(&mut self, key: KeyEvent, mut annotation: Annotation) {
+        match key.code {
+            KeyCode::Esc => {}
+            KeyCode::Enter => {
+                let info = self.instances.iter().find(|info| info.id == annotation.instance_id);
+                let (mut label, mut note) = info.map(|info| (info.label.clone(), info.note.clone())).unwrap_or_default();
+                if annotation.note {
+                    note = Some(annotation.value);
+                } else {
+                    label = Some(annotation.value);
+                }
+                if let Err(err) = self.manager.annotate(&annotation.instance_id, label, note) {
+                    self.last_error = Some(format!("Cannot annotate {}: {}", annotation.instance_id, err));
+                }
+                self.refresh_instances();
+            }
+            KeyCode::Backspace => {
+                annotation.value.pop();
+                self.annotating = Some(annotation);
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                annotation.value.push(ch);
+                self.annotating = Some(annotation);
+            }
+            _ => self.annotating = Some(annotation),
+        }
+    }
+
+    fn handle_palette_key(&mut self, key: KeyEvent, mut palette: Palette) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => {}
+            KeyCode::Enter => {
+                let value = palette.value.trim().to_string();
+                match palette.saving.take() {
+                    Some(command) if !value.is_empty() => match self.save_command_as_task(&command, &value) {
+                        Ok(message) => self.last_error = Some(message),
+                        Err(err) => self.toasts.push(Toast::new(format!("Cannot save {} as a task", command), &err)),
+                    },
+                    Some(command) => {
+                        self.last_error = Some("Name the task to save it".to_string());
+                        self.palette = Some(Palette { saving: Some(command), ..palette });
+                    }
+                    None if value.is_empty() => {}
+                    None => {
+                        if self.palette_history.last() != Some(&value) {
+                            self.palette_history.push(value.clone());
+                        }
+                        self.run_command(&value);
+                    }
+                }
+            }
+            // Save what was typed instead of running it, named after the command at first.
+            KeyCode::Char('s') if ctrl && palette.saving.is_none() && !palette.value.trim().is_empty() => {
+                let command = palette.value.trim().to_string();
+                palette.value = command.clone();
+                palette.saving = Some(command);
+                self.palette = Some(palette);
+            }
+            KeyCode::Up | KeyCode::Down if palette.saving.is_none() && !self.palette_history.is_empty() => {
+                let last = self.palette_history.len() - 1;
+                palette.recall = match (key.code, palette.recall) {
+                    (KeyCode::Up, None) => Some(last),
+                    (KeyCode::Up, Some(idx)) => Some(idx.saturating_sub(1)),
+                    (_, Some(idx)) if idx < last => Some(idx + 1),
+                    _ => None,
+                };
+                palette.value = palette.recall.map(|idx| self.palette_history[idx].clone()).unwrap_or_default();
+                self.palette = Some(palette);
+            }
+            KeyCode::Backspace => {
+                palette.value.pop();
+                self.palette = Some(palette);
+            }
+            KeyCode::Char(ch) if !ctrl => {
+                palette.value.push(ch);
+                self.palette = Some(palette);
+            }
+            _ => self.palette = Some(palette),
+        }
+    }
+
+    /// Runs a command typed at the palette like a task of its own, listed
+    /// under the ad-hoc category for the rest of the session.
+    fn run_command(&mut self, command: &str) {
+        let base = format!("adhoc-{}", slug(command));
+        let mut id = base.clone();
+        let mut n = 1;
+        // Commands that differ only in punctuation share a slug.
+        while self.task_by_id(&id).is_some_and(|task| task.command != command) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        let task = match self.task_by_id(&id) {
+            Some(task) => task.clone(),
+            None => {
+                let task = Task {
+                    id: id.clone(),
+                    name: command.to_string(),
+                    command: command.to_string(),
+                    category: Some(ADHOC_CATEGORY.to_string()),
+                    ..Default::default()
+                };
+                self.base_tasks.push(task.clone());
+                self.config.tasks.push(task.clone());
+                task
+            }
+        };
+        if let Err(err) = self.start_task(task, None, LaunchOptions::default()) {
+            // Only list commands that ran at least once.
+            if !self.instances.iter().any(|info| info.task_id == id) {
+                self.base_tasks.retain(|task| task.id != id);
+                self.config.tasks.retain(|task| task.id != id);
+            }
+            self.toasts.push(Toast::new(format!("Cannot run {}", command), &err));
+        }
+        self.rebuild_entries();
+    }
+
+    /// Appends `command` to the config file as a task called `name`, and
+    /// lists it right away.
+    fn save_command_as_task(&mut self, command: &str, name: &str) -> Result<String> {
+        let base = match slug(name) {
+            slug if slug.is_empty() => "task".to_string(),
+            slug => slug,
+        };
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.task_by_id(&id).is_some() {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        let path = config::resolve_config_path()?;
+        let task = Task {
+            id: id.clone(),
+            name: name.to_string(),
+            command: command.to_string(),
+            source: Some(path.clone()),
+            ..Default::default()
+        };
+        config::edit::add_task(&path, &task)?;
+        self.base_tasks.push(task.clone());
+        self.config.tasks.push(task);
+        self.rebuild_entries();
+        Ok(format!("Saved {} to {}", id, path.display()))
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.searching = false;
+                self.search.clear();
+            }
+            KeyCode::Enter => self.searching = false,
+            KeyCode::Backspace => {
+                self.search.pop();
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => self.search.push(ch),
+            _ => return,
+        }
+        // Land on the first match rather than wherever the old cursor was.
+        self.rebuild_entries();
+        if let Some(idx) = self.entries.iter().position(|entry| matches!(entry, Entry::Task { .. })) {
+            self.selected = idx;
+            self.list_state.select(Some(idx));
+        }
+    }
+
+    fn handle_grid_key(&mut self, key: KeyEvent, focus: usize) -> Result<()> {
+        let mut focus = focus;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('g') | KeyCode::Char('q') => return Ok(()),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Down => {
+                focus = (focus + 1) % self.pinned.len().max(1);
+            }
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Up => {
+                focus = focus.checked_sub(1).unwrap_or(self.pinned.len().saturating_sub(1));
+            }
+            KeyCode::Char('p') if focus < self.pinned.len() => {
+                self.pinned.remove(focus);
+            }
+            KeyCode::PageUp | KeyCode::PageDown => {
+                if let Some(instance_id) = self.pinned.get(focus).cloned() {
+                    let page = self.log_page();
+                    self.scroll_log(&instance_id, if key.code == KeyCode::PageUp { -page } else { page });
+                }
+            }
+            KeyCode::End | KeyCode::Char('f') => self.log_pause = None,
+            KeyCode::Enter => {
+                if let Some(instance_id) = self.pinned.get(focus).cloned() {
+                    self.attach_instance(&instance_id)?;
+                }
+            }
+            _ if self.apply_preset_key(&key, true) => return Ok(()),
+            _ => {}
+        }
+        if !self.pinned.is_empty() {
+            self.mode = AppMode::Grid {
+                focus: focus.min(self.pinned.len() - 1),
+            };
+        }
+        Ok(())
+    }
+
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        let borders: &[Border] = match &self.mode {
+            AppMode::List if self.split_view => &[Border::Split],
+            AppMode::Grid { .. } if self.pinned.len() >= 3 => &[Border::Column, Border::Row],
+            AppMode::Grid { .. } if self.pinned.len() == 2 => &[Border::Column],
+            _ => &[],
+        };
+        if self.dragging.handle(event, self.pane_area, &mut self.sizes, borders) {
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Applies the layout preset bound to `key`, if there is one.
+    fn apply_preset_key(&mut self, key: &KeyEvent, in_grid: bool) -> bool {
+        let presets = self.config.ui.as_ref().and_then(|ui| ui.layouts.clone()).unwrap_or_default();
+        let found = presets
+            .into_iter()
+            .find(|(_, preset)| preset.key.as_deref().is_some_and(|binding| matches_key(key, binding)));
+        match found {
+            Some((name, preset)) => {
+                self.apply_preset(&name, &preset, in_grid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Restores a preset's pane sizes and view, pinning the latest run of
+    /// each task it lists. Without a `view` the current one is kept.
+    fn apply_preset(&mut self, name: &str, preset: &LayoutPreset, in_grid: bool) {
+        self.sizes.apply(preset);
+        if let Some(task_ids) = &preset.pins {
+            let mut missing = Vec::new();
+            self.pinned.clear();
+            for task_id in task_ids.iter().take(GRID_MAX_TILES) {
+                match self
+                    .instances
+                    .iter()
+                    .filter(|info| &info.task_id == task_id)
+                    .max_by_key(|info| info.started_at)
+                {
+                    Some(info) => self.pinned.push(info.id.clone()),
+                    None => missing.push(task_id.as_str()),
+                }
+            }
+            if !missing.is_empty() {
+                self.last_error = Some(format!("Layout {}: no runs of {}", name, missing.join(", ")));
+            }
+        }
+        let current = if in_grid { "grid" } else if self.split_view { "split" } else { "list" };
+        match preset.view.as_deref().unwrap_or(current) {
+            "grid" if !self.pinned.is_empty() => self.mode = AppMode::Grid { focus: 0 },
+            "grid" => {
+                self.last_error.get_or_insert_with(|| format!("Layout {}: nothing pinned to show", name));
+                self.mode = AppMode::List;
+            }
+            view => {
+                self.split_view = view.eq_ignore_ascii_case("split");
+                self.mode = AppMode::List;
+            }
+        }
+    }
+
+    fn toggle_pin(&mut self, instance_id: String) {
+        if let Some(pos) = self.pinned.iter().position(|id| *id == instance_id) {
+            self.pinned.remove(pos);
+        } else if self.pinned.len() >= GRID_MAX_TILES {
+            self.last_error = Some(format!("At most {} runs can be pinned", GRID_MAX_TILES));
+        } else {
+            self.pinned.push(instance_id);
+        }
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent, form: &mut InputFormState) -> Result<InputResult> {
+        form.message = None;
+        let field_count = form.pages[form.page].fields.len();
+        match key.code {
+            KeyCode::Esc => {
+                return Ok(InputResult::ExitToList);
+            }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(task) = self.task_by_id(&form.task_id) {
+                    form.toggle_advanced(task);
+                }
+            }
+            KeyCode::Tab => form.switch_page(true),
+            KeyCode::BackTab => form.switch_page(false),
+            KeyCode::Down if form.selected + 1 < field_count => {
+                form.selected += 1;
+            }
+            KeyCode::Up if form.selected > 0 => {
+                form.selected -= 1;
+            }
+            KeyCode::Left => {
+                if let Some(field) = form.selected_field() {
+                    field.cycle_option(false);
+                }
+            }
+            KeyCode::Right => {
+                if let Some(field) = form.selected_field() {
+                    field.cycle_option(true);
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(field) = form.selected_field() {
+                    field.backspace();
+                }
+            }
+            KeyCode::Char(ch) => {
+                if let Some(field) = form.selected_field() {
+                    field.insert_char(ch);
+                }
+            }
+            KeyCode::Enter => {
+                if form.selected + 1 < field_count {
+                    form.selected += 1;
+                } else if form.page + 1 < form.pages.len() {
+                    form.page += 1;
+                    form.selected = 0;
+                } else if let Some((page, selected)) = form.first_invalid() {
+                    form.page = page;
+                    form.selected = selected;
+                    form.message = Some(format!(
+                        "{} field(s) need attention before running",
+                        form.pages.iter().map(InputPage::error_count).sum::<usize>()
+                    ));
+                } else {
+                    let values = form.collect_values();
+                    let task = self.task_by_id(&form.task_id).cloned();
+                    let rejected = task.as_ref().map(|task| validate_inputs(task, &values));
+                    if let Some(reason) = rejected.and_then(|result| result.unwrap_or_else(|err| Some(err.to_string()))) {
+                        form.message = Some(reason);
+                        return Ok(InputResult::Stay);
+                    }
+                    let launch = match form.launch_options() {
+                        Ok(launch) => launch,
+                        Err(reason) => {
+                            form.message = Some(reason);
+                            return Ok(InputResult::Stay);
+                        }
+                    };
+                    if let Some(Err(err)) = task.as_ref().map(|task| prepare_cwd(&launch.apply(task), &values)) {
+                        form.message = Some(err.to_string());
+                        return Ok(InputResult::Stay);
+                    }
+                    match (task, form.after.take()) {
+                        (Some(task), Some(after)) => self.enqueue(Some(after), task, values, launch),
+                        (Some(task), None) => self.spawn_from_values(task, values, launch)?,
+                        (None, _) => {}
+                    }
+                    return Ok(InputResult::ExitToList);
+                }
+            }
+            _ => {}
+        }
+        Ok(InputResult::Stay)
+    }
+
+    /// Opens the input form or runs the task right away; with `after` set the
+    /// run is queued behind that instance instead.
+    fn start_task(&mut self, task: Task, after: Option<String>, launch: LaunchOptions) -> Result<()> {
+        self.open_task(task, after, launch, false)
+    }
+
+    /// Like `start_task`, with the form's advanced page open even when the
+    /// task has no inputs.
+    fn open_task(&mut self, task: Task, after: Option<String>, launch: LaunchOptions, advanced: bool) -> Result<()> {
+        if task.members.is_some() {
+            return self.start_group(&task, after);
+        }
+        // Queued runs are checked when they start.
+        if after.is_none() {
+            match self.manager.check_unique(&task) {
+                Ok(Some(warning)) => self.last_error = Some(warning),
+                Ok(None) => {}
+                Err(err) => {
+                    self.last_error = Some(err.to_string());
+                    return Ok(());
+                }
+            }
+        }
+        let inputs = task.inputs.clone().unwrap_or_default();
+        if !inputs.is_empty() || advanced {
+            let mut state = InputFormState {
+                task_id: task.id.clone(),
+                after,
+                launch,
+                pages: build_input_pages(&inputs, task.input_groups.as_deref()),
+                page: 0,
+                selected: 0,
+                message: None,
+            };
+            if advanced {
+                state.toggle_advanced(&task);
+            }
+            if let Some(script) = TaskScript::for_task(&task)? {
+                let defaults = script.defaults(&state.collect_values())?;
+                state.apply_values(&defaults);
+            }
+            self.mode = AppMode::InputForm(state);
+            Ok(())
+        } else if let Some(after) = after {
+            self.enqueue(Some(after), task, HashMap::new(), launch);
+            Ok(())
+        } else {
+            self.spawn_from_values(task, HashMap::new(), launch)
+        }
+    }
+
+    fn spawn_from_values(
+        &mut self,
+        task: Task,
+        mut values: HashMap<String, String>,
+        launch: LaunchOptions,
+    ) -> Result<()> {
+        // tmux owns these runs, so they skip queues and don't show in the list.
+        if self.config.ui.as_ref().and_then(|ui| ui.backend.as_deref()) == Some("tmux") {
+            prepare_command(&task, &mut values.clone())?;
+            let window = run::open_in_tmux(&task.id, &values, &launch, &[], false)?;
+            self.last_error = Some(format!("{} opened in tmux window {}", task.name, window));
+            return Ok(());
+        }
+        if self.queue_busy(&task) {
+            self.enqueue(None, task, values, launch);
+            self.rebuild_entries();
+            return Ok(());
+        }
+        let command = prepare_command(&task, &mut values)?;
+        if let Err(err) = prepare_cwd(&launch.apply(&task), &values) {
+            self.toasts.push(Toast::new(format!("{} not started", task.name), &err));
+            return Ok(());
+        }
+        let failure = format!("Cannot start {}", task.name);
+        self.launch(task, command, values, launch, failure, AfterLaunch::Attach);
+        Ok(())
+    }
+
+    /// Runs a history entry's rendered command again with the cwd, env and
+    /// other settings it had, queued behind its task's queue like any run.
+    fn replay_run(&mut self, task: &Task, run: &SessionInfo) -> Result<()> {
+        if self.config.ui.as_ref().and_then(|ui| ui.backend.as_deref()) == Some("tmux") {
+            let window = run::open_rerun_in_tmux(run)?;
+            self.last_error = Some(format!("{} opened in tmux window {}", task.name, window));
+            return Ok(());
+        }
+        let task = run.rerun_task(task);
+        match self.manager.check_unique(&task) {
+            Ok(Some(warning)) => self.last_error = Some(warning),
+            Ok(None) => {}
+            Err(err) => {
+                self.last_error = Some(err.to_string());
+                return Ok(());
+            }
+        }
+        if self.queue_busy(&task) {
+            let id = self.next_queue_id;
+            self.next_queue_id += 1;
+            self.queued.push(QueuedRun {
+                id,
+                after: None,
+                task,
+                values: run.inputs.clone(),
+                launch: LaunchOptions::default(),
+                command: Some(run.command.clone()),
+                then: Vec::new(),
+            });
+            self.rebuild_entries();
+            return Ok(());
+        }
+        let failure = format!("Cannot rerun {}", task.name);
+        self.launch(task, run.command.clone(), run.inputs.clone(), LaunchOptions::default(), failure, AfterLaunch::Attach);
+        Ok(())
+    }
+
+    /// Starts the tasks marked `autostart` without attaching to them, each
+    /// with its inputs' defaults; queued tasks wait their turn as usual.
+    fn autostart(&mut self) {
+        let tasks: Vec<Task> = self.config.tasks.iter().filter(|task| task.autostart == Some(true)).cloned().collect();
+        for task in tasks {
+            if let Err(err) = self.start_detached(&task) {
+                self.toasts.push(Toast::new(format!("Autostart of {} failed", task.name), &err));
+            }
+        }
+        self.rebuild_entries();
+    }
+
+    fn start_detached(&mut self, task: &Task) -> Result<()> {
+        if task.members.is_some() {
+            return self.start_group(task, None);
+        }
+        if let Some(warning) = self.manager.check_unique(task)? {
+            return Err(anyhow!("{}", warning));
+        }
+        let mut values = default_values(task)?;
+        if self.queue_busy(task) {
+            self.enqueue(None, task.clone(), values, LaunchOptions::default());
+            return Ok(());
+        }
+        let command = prepare_command(task, &mut values)?;
+        let failure = format!("Autostart of {} failed", task.name);
+        self.launch(task.clone(), command, values, LaunchOptions::default(), failure, AfterLaunch::Detach);
+        Ok(())
+    }
+
+    /// Starts the run, on a worker thread when its task has secrets so a
+    /// slow keyring or secret command doesn't stall the UI.
+    fn launch(
+        &mut self,
+        task: Task,
+        command: String,
+        values: HashMap<String, String>,
+        options: LaunchOptions,
+        failure: String,
+        then: AfterLaunch,
+    ) {
+        if !secrets::has_secrets(options.apply(&task).env.as_ref()) {
+            let result = self.manager.spawn_raw_with(&task, &command, &values, &options);
+            self.launched(Launch { task, then, failure }, result);
+            return;
+        }
+        let id = self.next_launch_id;
+        self.next_launch_id += 1;
+        let manager = self.manager.clone();
+        let runtime = tokio::runtime::Handle::current();
+        let launched = self.launched_tx.clone();
+        let spawn_task = task.clone();
+        thread::spawn(move || {
+            let _runtime = runtime.enter();
+            let _ = launched.send((id, manager.spawn_raw_with(&spawn_task, &command, &values, &options)));
+        });
+        self.launching.insert(id, Launch { task, then, failure });
     }
 
-    fn handle_input_key(&mut self, key: KeyEvent, form: &mut InputFormState) -> Result<InputResult> {
-        match key.code {
-            KeyCode::Esc => {
-                return Ok(InputResult::ExitToList);
-            }
-            KeyCode::Down => {
-                if form.selected + 1 < form.fields.len() {
-                    form.selected += 1;
-                }
-            }
-            KeyCode::Up => {
-                if form.selected > 0 {
-                    form.selected -= 1;
-                }
-            }
-            KeyCode::Left => {
-                if let Some(field) = form.fields.get_mut(form.selected) {
-                    field.cycle_option(false);
-                }
+    /// Finishes the runs started on worker threads.
+    fn drain_launches(&mut self) {
+        while let Ok((id, result)) = self.launched_rx.try_recv() {
+            if let Some(launch) = self.launching.remove(&id) {
+                self.launched(launch, result);
+                self.needs_redraw = true;
             }
-            KeyCode::Right => {
-                if let Some(field) = form.fields.get_mut(form.selected) {
-                    field.cycle_option(true);
-                }
+        }
+    }
+
+    fn launched(&mut self, launch: Launch, result: Result<SpawnedInstance>) {
+        let stored = result.and_then(|spawned| match launch.then {
+            AfterLaunch::Attach => {
+                self.attach_spawned(spawned);
+                Ok(())
             }
-            KeyCode::Backspace => {
-                if let Some(field) = form.fields.get_mut(form.selected) {
-                    field.backspace();
+            AfterLaunch::Detach => self.manager.detach_spawned(spawned).map(drop),
+            AfterLaunch::Queue(then, options) => {
+                let after = self.manager.detach_spawned(spawned)?.id;
+                let mut then = then.into_iter();
+                if let Some((task, values)) = then.next() {
+                    let id = self.next_queue_id;
+                    self.next_queue_id += 1;
+                    self.queued.push(QueuedRun {
+                        id,
+                        after: Some(after),
+                        task,
+                        values,
+                        launch: options,
+                        command: None,
+                        then: then.collect(),
+                    });
                 }
+                Ok(())
             }
-            KeyCode::Char(ch) => {
-                if let Some(field) = form.fields.get_mut(form.selected) {
-                    field.insert_char(ch);
+        });
+        if let Err(err) = stored {
+            self.toasts.push(Toast::new(launch.failure, &err));
+        }
+    }
+
+    /// Queues a group's members with their inputs' defaults: all at once, or
+    /// when `sequential` each after the one before it succeeded. Nothing
+    /// starts when a member's defaults don't validate.
+    fn start_group(&mut self, group: &Task, after: Option<String>) -> Result<()> {
+        let mut runs = Vec::new();
+        for id in group.members.iter().flatten() {
+            let task = self.task_by_id(id).cloned().ok_or_else(|| anyhow!("group {}: unknown member {}", group.id, id))?;
+            let values = default_values(&task).map_err(|err| anyhow!("{}: {}", task.name, err))?;
+            runs.push((task, values));
+        }
+        let launch = LaunchOptions { group: Some(group.id.clone()), ..Default::default() };
+        if group.sequential == Some(true) {
+            let mut runs = runs.into_iter();
+            if let Some((task, values)) = runs.next() {
+                self.enqueue(after, task, values, launch);
+                if let Some(run) = self.queued.last_mut() {
+                    run.then = runs.collect();
                 }
             }
-            KeyCode::Enter => {
-                if form.selected + 1 < form.fields.len() {
-                    form.selected += 1;
-                } else {
-                    let task_index = form.task_index;
-                    let values = form.collect_values();
-                    let task = self.config.tasks.get(task_index).cloned();
-                    if let Some(task) = task {
-                        self.spawn_from_values(task, values)?;
-                    }
-                    return Ok(InputResult::ExitToList);
-                }
+        } else {
+            for (task, values) in runs {
+                self.enqueue(after.clone(), task, values, launch.clone());
             }
-            _ => {}
         }
-        Ok(InputResult::Stay)
+        self.expanded.insert(group.id.clone());
+        self.process_queue();
+        self.rebuild_entries();
+        Ok(())
     }
 
-    fn start_task(&mut self, task: Task) -> Result<()> {
-        if let Some(inputs) = task.inputs.as_ref() {
-            let mut fields = Vec::new();
-            for (name, config) in inputs {
-                fields.push(InputField::from_config(name, config));
+    /// Moves to the next profile in name order, then back to none; runs
+    /// already started or queued keep the tasks they had.
+    fn switch_profile(&mut self) {
+        let mut names: Vec<String> = self.config.profiles.iter().flatten().map(|(name, _)| name.clone()).collect();
+        if names.is_empty() {
+            self.last_error = Some("No [profiles] are configured".to_string());
+            return;
+        }
+        names.sort();
+        let next = match &self.profile {
+            Some(current) => names.iter().skip_while(|name| *name != current).nth(1).cloned(),
+            None => names.first().cloned(),
+        };
+        let mut base = self.config.clone();
+        base.tasks = self.base_tasks.clone();
+        match config::profile_tasks(&base, next.as_deref()) {
+            Ok(tasks) => {
+                self.config.tasks = tasks;
+                self.last_error = Some(format!("Profile: {}", next.as_deref().unwrap_or("none")));
+                self.profile = next;
             }
-            let state = InputFormState {
-                task_index: self
-                    .config
-                    .tasks
-                    .iter()
-                    .position(|t| t.id == task.id)
-                    .ok_or_else(|| anyhow!("task not found"))?,
-                fields,
-                selected: 0,
-            };
-            self.mode = AppMode::InputForm(state);
-            Ok(())
-        } else {
-            self.spawn_from_values(task, HashMap::new())
+            Err(err) => self.toasts.push(Toast::new(format!("Cannot switch to profile {}", next.as_deref().unwrap_or("none")), &err)),
         }
     }
 
-    fn spawn_from_values(&mut self, task: Task, values: HashMap<String, String>) -> Result<()> {
-        let command = render_command(&task.command, &values, task.inputs.as_ref())
-            .map_err(|err| anyhow!("render command: {}", err))?;
-        let spawned = self.manager.spawn_raw(&task, &command)?;
-        self.attach_spawned(spawned)
+    /// Loads the config files again after they were edited, replacing the
+    /// tasks and profiles; other settings apply from the next start. The
+    /// session's ad-hoc tasks are kept.
+    fn reload_tasks(&mut self) {
+        let loaded = tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(load_config_auto()));
+        let profile = self.profile.clone();
+        let result = loaded.and_then(|base| {
+            let tasks = config::profile_tasks(&base, profile.as_deref())?;
+            Ok((base, tasks))
+        });
+        let (base, mut tasks) = match result {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.toasts.push(Toast::new("Config not reloaded; the tasks are unchanged", &err));
+                return;
+            }
+        };
+        let adhoc: Vec<Task> = self
+            .base_tasks
+            .iter()
+            .filter(|task| task.category.as_deref() == Some(ADHOC_CATEGORY) && task.source.is_none())
+            .filter(|task| !base.tasks.iter().any(|loaded| loaded.id == task.id))
+            .cloned()
+            .collect();
+        let mut base_tasks = base.tasks;
+        base_tasks.extend(adhoc.iter().cloned());
+        tasks.extend(adhoc);
+        self.last_error = Some(format!("Reloaded {} tasks", base_tasks.len()));
+        self.base_tasks = base_tasks;
+        self.config.tasks = tasks;
+        self.config.profiles = base.profiles;
+        self.rebuild_entries();
+    }
+
+    /// Kills the group's running runs and drops its queued ones.
+    fn kill_group(&mut self, group_id: &str) {
+        self.queued.retain(|run| run.launch.group.as_deref() != Some(group_id));
+        let running: Vec<String> = self
+            .instances
+            .iter()
+            .filter(|info| info.group.as_deref() == Some(group_id) && info.status == InstanceStatus::Running)
+            .map(|info| info.id.clone())
+            .collect();
+        for id in running {
+            if let Err(err) = self.manager.kill_and_remove(&id) {
+                self.last_error = Some(format!("Kill {} failed: {}", id, err));
+            }
+        }
     }
 
-    fn attach_spawned(&mut self, spawned: SpawnedInstance) -> Result<()> {
+    fn attach_spawned(&mut self, spawned: SpawnedInstance) {
         self.next_passthrough = Some(PassthroughRequest {
             instance_id: spawned.info.id.clone(),
             task_name: spawned.info.task_name.clone(),
@@ -532,11 +2867,16 @@ impl App {
             writer: spawned.writer,
             ui_config: self.config.ui.clone().unwrap_or_default(),
             key_config: self.key_bindings.clone(),
+            subscribe_options: self.subscribe_options,
         });
-        Ok(())
     }
 
     fn attach_instance(&mut self, instance_id: &str) -> Result<()> {
+        // A run whose shell is gone has nothing to attach to; review its output instead.
+        if self.instances.iter().any(|info| info.id == instance_id && info.closed) {
+            self.enter_copy_mode(instance_id);
+            return Ok(());
+        }
         let result = self.manager.take_master(instance_id)?;
         if let Some((master, writer)) = result {
             let task_name = self
@@ -552,6 +2892,7 @@ impl App {
                 writer,
                 ui_config: self.config.ui.clone().unwrap_or_default(),
                 key_config: self.key_bindings.clone(),
+                subscribe_options: self.subscribe_options,
             });
         } else {
             let status = self.manager.get_status(instance_id).ok().flatten();
@@ -568,70 +2909,168 @@ impl App {
         self.next_passthrough.take()
     }
 
+    fn take_suspend(&mut self) -> Option<Suspend> {
+        self.next_suspend.take()
+    }
+
     fn task_by_id(&self, task_id: &str) -> Option<&Task> {
         self.config.tasks.iter().find(|task| task.id == task_id)
     }
+
+    fn log_anchor(&self, instance_id: &str) -> Option<u64> {
+        self.log_pause
+            .as_ref()
+            .filter(|(id, _)| id == instance_id)
+            .map(|(_, anchor)| *anchor)
+    }
+
+    /// Scrolls the log pane of `instance_id` by `delta` lines; it stops
+    /// following new output until it is scrolled back to the end.
+    fn scroll_log(&mut self, instance_id: &str, delta: isize) {
+        let anchor = self.log_anchor(instance_id);
+        let moved = self.log_cache.borrow().scroll(instance_id, anchor, delta);
+        self.log_pause = moved.map(|anchor| (instance_id.to_string(), anchor));
+    }
+
+    /// Lines a page key scrolls a log pane by.
+    fn log_page(&self) -> isize {
+        (self.pane_area.height.saturating_sub(3) as isize / 2).max(1)
+    }
+
+    /// A bordered pane in the theme's border and title styles.
+    fn block(&self, title: impl Into<String>) -> Block<'static> {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.theme.border)
+            .title(Span::styled(title.into(), self.theme.title))
+    }
+
+    fn category_style(&self, category: &str) -> Option<Style> {
+        let styles = self.config.ui.as_ref()?.category_styles.as_ref()?;
+        styles.get(category).map(|spec| text_style(spec))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 enum Entry {
+    Section { name: String },
     Category { name: String },
     Task { task_id: String },
     Instance { instance_id: String },
+    Queued { queue_id: u32 },
 }
 
 impl InputField {
     fn from_config(name: &str, config: &InputConfig) -> Self {
-        match config {
-            InputConfig::Select { options, default } => {
-                let mut option_index = 0;
-                if let Some(pos) = options.iter().position(|opt| opt == default) {
-                    option_index = pos;
-                }
-                let value = options.get(option_index).cloned().unwrap_or_default();
-                Self {
-                    name: name.to_string(),
-                    config: config.clone(),
-                    value,
-                    cursor: 0,
-                    options: options.clone(),
-                    option_index,
-                }
-            }
-            InputConfig::Text { default, .. } => {
-                let value = default.clone().unwrap_or_default();
-                let cursor = value.len();
-                Self {
-                    name: name.to_string(),
-                    config: config.clone(),
-                    value,
-                    cursor,
-                    options: Vec::new(),
-                    option_index: 0,
+        let options = match config {
+            InputConfig::Select { options, .. } | InputConfig::MultiSelect { options, .. } => options.clone(),
+            _ => Vec::new(),
+        };
+        let mut field = Self {
+            name: name.to_string(),
+            config: config.clone(),
+            value: String::new(),
+            cursor: 0,
+            chosen: vec![false; options.len()],
+            options,
+            option_index: 0,
+        };
+        field.set_value(&config.default_value().unwrap_or_default());
+        if matches!(config, InputConfig::Select { .. }) && field.value.is_empty() {
+            field.value = field.options.first().cloned().unwrap_or_default();
+        }
+        field
+    }
+
+    fn error(&self) -> Option<String> {
+        self.config.validate(&self.value)
+    }
+
+    fn set_value(&mut self, value: &str) {
+        match &self.config {
+            InputConfig::Select { .. } => match self.options.iter().position(|opt| opt == value) {
+                Some(pos) => {
+                    self.option_index = pos;
+                    self.value = value.to_string();
                 }
+                None => return,
+            },
+            InputConfig::MultiSelect { .. } => {
+                let picked = self.config.split(value);
+                self.chosen = self.options.iter().map(|opt| picked.contains(&opt.as_str())).collect();
+                self.sync_choices();
             }
+            InputConfig::Bool { .. } if value != "true" && value != "false" => return,
+            _ => self.value = value.to_string(),
         }
+        self.cursor = self.value.len();
+    }
+
+    fn is_editable(&self) -> bool {
+        matches!(
+            self.config,
+            InputConfig::Text { .. } | InputConfig::Password { .. } | InputConfig::Number { .. }
+        )
     }
 
     fn insert_char(&mut self, ch: char) {
-        if matches!(self.config, InputConfig::Select { .. }) {
+        if ch == ' ' && !self.is_editable() {
+            self.toggle();
+            return;
+        }
+        let accepted = match self.config {
+            InputConfig::Number { .. } => ch.is_ascii_digit() || ch == '.' || ch == '-',
+            _ => self.is_editable(),
+        };
+        if !accepted {
             return;
         }
         self.value.insert(self.cursor, ch);
-        self.cursor += 1;
+        self.cursor += ch.len_utf8();
     }
 
     fn backspace(&mut self) {
-        if matches!(self.config, InputConfig::Select { .. }) {
+        if !self.is_editable() {
             return;
         }
-        if self.cursor > 0 {
-            self.cursor -= 1;
+        if let Some(ch) = self.value[..self.cursor].chars().next_back() {
+            self.cursor -= ch.len_utf8();
             self.value.remove(self.cursor);
         }
     }
 
+    /// Flips a checkbox, or the highlighted option of a multiselect.
+    fn toggle(&mut self) {
+        match self.config {
+            InputConfig::Bool { .. } => {
+                self.value = (self.value != "true").to_string();
+            }
+            InputConfig::MultiSelect { .. } => {
+                if let Some(chosen) = self.chosen.get_mut(self.option_index) {
+                    *chosen = !*chosen;
+                }
+                self.sync_choices();
+            }
+            _ => {}
+        }
+    }
+
+    fn sync_choices(&mut self) {
+        let picked: Vec<String> = self
+            .options
+            .iter()
+            .zip(&self.chosen)
+            .filter(|(_, chosen)| **chosen)
+            .map(|(opt, _)| opt.clone())
+            .collect();
+        self.value = self.config.join(&picked);
+    }
+
     fn cycle_option(&mut self, forward: bool) {
+        if matches!(self.config, InputConfig::Bool { .. }) {
+            self.toggle();
+            return;
+        }
         if self.options.is_empty() {
             return;
         }
@@ -644,17 +3083,229 @@ impl InputField {
                 self.option_index - 1
             };
         }
-        self.value = self.options[self.option_index].clone();
+        if matches!(self.config, InputConfig::Select { .. }) {
+            self.value = self.options[self.option_index].clone();
+        }
+    }
+
+    fn display_spans(&self, selected: bool) -> Vec<Span<'static>> {
+        let style = if selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        match &self.config {
+            InputConfig::Bool { .. } => {
+                let mark = if self.value == "true" { "[x]" } else { "[ ]" };
+                vec![Span::styled(mark.to_string(), style)]
+            }
+            InputConfig::MultiSelect { .. } => {
+                let mut spans = Vec::new();
+                for (idx, (opt, chosen)) in self.options.iter().zip(&self.chosen).enumerate() {
+                    if idx > 0 {
+                        spans.push(Span::raw("  "));
+                    }
+                    let mark = if *chosen { "[x]" } else { "[ ]" };
+                    let option_style = if selected && idx == self.option_index { style } else { Style::default() };
+                    spans.push(Span::styled(format!("{} {}", mark, opt), option_style));
+                }
+                spans
+            }
+            InputConfig::Password { .. } => {
+                vec![Span::styled("•".repeat(self.value.chars().count()), style)]
+            }
+            _ => vec![Span::styled(self.value.clone(), style)],
+        }
+    }
+}
+
+impl InputPage {
+    fn error_count(&self) -> usize {
+        self.fields.iter().filter(|field| field.error().is_some()).count()
     }
 }
 
 impl InputFormState {
     fn collect_values(&self) -> HashMap<String, String> {
-        self.fields
+        self.pages
             .iter()
+            .filter(|page| !page.advanced)
+            .flat_map(|page| page.fields.iter())
             .map(|field| (field.name.clone(), field.value.clone()))
             .collect()
     }
+
+    fn apply_values(&mut self, values: &HashMap<String, String>) {
+        for field in self.pages.iter_mut().filter(|page| !page.advanced).flat_map(|page| page.fields.iter_mut()) {
+            if let Some(value) = values.get(&field.name) {
+                field.set_value(value);
+            }
+        }
+    }
+
+    fn selected_field(&mut self) -> Option<&mut InputField> {
+        self.pages[self.page].fields.get_mut(self.selected)
+    }
+
+    fn switch_page(&mut self, forward: bool) {
+        let count = self.pages.len();
+        self.page = if forward {
+            (self.page + 1) % count
+        } else {
+            (self.page + count - 1) % count
+        };
+        self.selected = 0;
+    }
+
+    /// Shows or hides the advanced page; hiding it drops its edits. A form
+    /// with nothing but that page keeps it.
+    fn toggle_advanced(&mut self, task: &Task) {
+        match self.pages.iter().position(|page| page.advanced) {
+            Some(_) if self.pages.len() == 1 => {}
+            Some(pos) => {
+                self.pages.remove(pos);
+                if self.page >= self.pages.len() {
+                    self.page = self.pages.len() - 1;
+                    self.selected = 0;
+                }
+            }
+            None => {
+                self.pages.push(advanced_page(task, &self.launch));
+                self.page = self.pages.len() - 1;
+                self.selected = 0;
+            }
+        }
+    }
+
+    /// The launch options with the advanced page's changes applied.
+    fn launch_options(&self) -> Result<LaunchOptions, String> {
+        let mut launch = self.launch.clone();
+        let fields = self.pages.iter().filter(|page| page.advanced).flat_map(|page| page.fields.iter());
+        for field in fields {
+            if Some(&field.value) == field.config.default_value().as_ref() {
+                continue;
+            }
+            let value = field.value.trim();
+            match field.name.as_str() {
+                ADVANCED_CWD => launch.cwd = Some(PathBuf::from(value)).filter(|_| !value.is_empty()),
+                ADVANCED_ENV => {
+                    launch.env.clear();
+                    for pair in value.split_whitespace() {
+                        let (key, value) = pair
+                            .split_once('=')
+                            .ok_or_else(|| format!("env: expected KEY=VALUE, got {}", pair))?;
+                        launch.env.insert(key.to_string(), value.to_string());
+                    }
+                }
+                ADVANCED_SHELL => launch.shell = Some(value.to_string()).filter(|_| !value.is_empty()),
+                ADVANCED_LOGIN => launch.login_profile = Some(value == "true"),
+                ADVANCED_TIMEOUT => {
+                    let secs = if value.is_empty() { Ok(0.0) } else { value.parse::<f64>() };
+                    launch.timeout_secs = Some(secs.map_err(|_| "timeout: not a number".to_string())?.max(0.0) as u64);
+                }
+                ADVANCED_PTY => launch.pty = Some(value == "true"),
+                _ => {}
+            }
+        }
+        Ok(launch)
+    }
+
+    fn first_invalid(&self) -> Option<(usize, usize)> {
+        self.pages.iter().enumerate().find_map(|(page_idx, page)| {
+            page.fields
+                .iter()
+                .position(|field| field.error().is_some())
+                .map(|field_idx| (page_idx, field_idx))
+        })
+    }
+}
+
+/// Fields of the advanced page, prefilled with what the run would use.
+fn advanced_page(task: &Task, launch: &LaunchOptions) -> InputPage {
+    let effective = launch.apply(task);
+    let text = |default: String, placeholder: &str| InputConfig::Text {
+        placeholder: Some(placeholder.to_string()),
+        default: Some(default),
+        group: None,
+        raw: None,
+        required: None,
+    };
+    let flag = |default: bool| InputConfig::Bool {
+        default: Some(default),
+        group: None,
+        raw: None,
+    };
+    let env = launch.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(" ");
+    let configs = [
+        (ADVANCED_CWD, text(effective.cwd.map(|cwd| cwd.display().to_string()).unwrap_or_default(), "current directory")),
+        (ADVANCED_ENV, text(env, "KEY=VALUE ...")),
+        (ADVANCED_SHELL, text(effective.shell.unwrap_or_default(), "$SHELL")),
+        (ADVANCED_LOGIN, flag(effective.login_profile.unwrap_or(false))),
+        (
+            ADVANCED_TIMEOUT,
+            InputConfig::Number {
+                default: Some(effective.timeout_secs.unwrap_or(0) as f64),
+                min: Some(0.0),
+                max: None,
+                group: None,
+                raw: None,
+            },
+        ),
+        (ADVANCED_PTY, flag(effective.pty.unwrap_or(true))),
+    ];
+    InputPage {
+        name: ADVANCED_PAGE.to_string(),
+        fields: configs.iter().map(|(name, config)| InputField::from_config(name, config)).collect(),
+        advanced: true,
+    }
+}
+
+/// Groups inputs into form pages: ungrouped inputs first, then groups in
+/// `order`, then any remaining groups alphabetically. Fields sort by name.
+/// Inputs for a run started without the form: the script's defaults, then
+/// each input's own, all of which must validate.
+fn default_values(task: &Task) -> Result<HashMap<String, String>> {
+    let mut values = match TaskScript::for_task(task)? {
+        Some(script) => script.defaults(&HashMap::new())?,
+        None => HashMap::new(),
+    };
+    for (name, config) in task.inputs.iter().flatten() {
+        let value = values.entry(name.clone()).or_insert_with(|| config.default_value().unwrap_or_default());
+        if let Some(reason) = config.validate(value) {
+            return Err(anyhow!("input {}: {}", name, reason));
+        }
+    }
+    Ok(values)
+}
+
+fn build_input_pages(inputs: &HashMap<String, InputConfig>, order: Option<&[String]>) -> Vec<InputPage> {
+    let mut grouped: BTreeMap<String, Vec<InputField>> = BTreeMap::new();
+    for (name, config) in inputs {
+        let group = config.group().unwrap_or(DEFAULT_INPUT_GROUP).to_string();
+        grouped.entry(group).or_default().push(InputField::from_config(name, config));
+    }
+    let mut names: Vec<String> = Vec::new();
+    if grouped.contains_key(DEFAULT_INPUT_GROUP) {
+        names.push(DEFAULT_INPUT_GROUP.to_string());
+    }
+    for name in order.unwrap_or_default() {
+        if grouped.contains_key(name) && !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    for name in grouped.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let mut fields = grouped.remove(&name)?;
+            fields.sort_by(|a, b| a.name.cmp(&b.name));
+            Some(InputPage { name, fields, advanced: false })
+        })
+        .collect()
 }
 
 struct PassthroughRequest {
@@ -664,10 +3315,79 @@ struct PassthroughRequest {
     writer: Box<dyn std::io::Write + Send>,
     ui_config: UiConfig,
     key_config: KeyBindings,
+    subscribe_options: SubscribeOptions,
+}
+
+/// Ways of handing the terminal back for a while; runs keep going.
+enum Suspend {
+    /// Stop CmdHub like any job, until the shell continues it with `fg`.
+    Stop,
+    /// Open `$SHELL` and come back when it exits.
+    Shell,
+    /// Open a config file in `$VISUAL` or `$EDITOR`, at `line` if the
+    /// editor takes `+N`; the tasks are reloaded afterwards.
+    Edit { path: PathBuf, line: Option<usize> },
+}
+
+impl Suspend {
+    fn run(&self) -> Result<()> {
+        match self {
+            Suspend::Stop => {
+                if unsafe { libc::raise(libc::SIGTSTP) } != 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+            }
+            Suspend::Shell => {
+                let shell = std::env::var("SHELL").ok().filter(|shell| !shell.is_empty()).unwrap_or_else(|| "sh".to_string());
+                println!("CmdHub keeps running; exit the shell to return to it.");
+                std::process::Command::new(&shell)
+                    .env("CMDHUB_SUBSHELL", "1")
+                    .status()
+                    .map_err(|err| anyhow!("{}: {}", shell, err))?;
+            }
+            Suspend::Edit { path, line } => {
+                let editor = ["VISUAL", "EDITOR"]
+                    .iter()
+                    .find_map(|key| std::env::var(key).ok().filter(|editor| !editor.trim().is_empty()))
+                    .unwrap_or_else(|| "vi".to_string());
+                let program = editor.split_whitespace().next().unwrap_or_default();
+                let program = Path::new(program).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                let mut args = Vec::new();
+                if let Some(line) = line.filter(|_| ["vi", "vim", "nvim", "nano", "emacs", "micro", "kak"].contains(&program)) {
+                    args.push(format!("+{}", line));
+                }
+                args.push(path.to_string_lossy().to_string());
+                // Through the shell, so an editor given with arguments like `code -w` works.
+                let status = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{} \"$@\"", editor))
+                    .arg("sh")
+                    .args(&args)
+                    .status()
+                    .map_err(|err| anyhow!("{}: {}", editor, err))?;
+                if !status.success() {
+                    return Err(anyhow!("{} exited with {}", editor, status));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The line of `path` declaring task `id`, counted from 1.
+fn task_line(path: &Path, id: &str) -> Option<usize> {
+    let content = std::fs::read_to_string(path).ok()?;
+    content.lines().position(|line| {
+        let mut parts = line.splitn(2, '=');
+        parts.next().map(str::trim) == Some("id")
+            && parts.next().map(|value| value.trim().trim_matches(|ch| ch == '"' || ch == '\'')) == Some(id)
+    })
+    .map(|idx| idx + 1)
 }
 
 enum PassthroughOutcome {
     BackToList,
+    CopyMode,
 }
 
 fn run_passthrough(mut request: PassthroughRequest, manager: &SessionManager) -> Result<PassthroughOutcome> {
@@ -716,61 +3436,38 @@ fn run_passthrough_inner(request: &mut PassthroughRequest, manager: &SessionMana
     
     // Remove: execute!(stdout, MoveTo(0, 0))?; 
 
-    let replay = manager.buffer_snapshot(&request.instance_id)?;
+    let (replay, output_rx) = manager.subscribe_output_with(&request.instance_id, request.subscribe_options)?;
     if !replay.is_empty() {
         stdout.write_all(&replay)?;
         stdout.flush()?;
     }
-
-    // Use existing writer instead of taking it
-    // let mut writer = request.master.take_writer()?; 
-    #[cfg(unix)]
-    {
-        if let Some(fd) = request.master.as_raw_fd() {
-            // Make reads non-blocking so we can stop the reader thread on detach.
-            unsafe {
-                let flags = libc::fcntl(fd, libc::F_GETFL);
-                if flags != -1 {
-                    libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
-                }
-            }
-        }
+    if matches!(manager.get_status(&request.instance_id), Ok(Some(InstanceStatus::Running))) {
+        fit_pty(&*request.master, size.0, size.1, true);
     }
 
-    let mut reader = request.master.try_clone_reader()?;
     let stop = Arc::new(Mutex::new(false));
     let stop_reader = Arc::clone(&stop);
-    let manager_clone = manager.clone();
-    let instance_id = request.instance_id.clone();
 
     let reader_handle = thread::spawn(move || {
-        let mut buf = [0u8; 8192];
         let mut out = io::stdout();
         loop {
-            let stopped = stop_reader.lock().map(|lock| *lock).unwrap_or(true);
-            if stopped {
+            if stop_reader.lock().map(|lock| *lock).unwrap_or(true) {
                 break;
             }
-            match reader.read(&mut buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let _ = out.write_all(&buf[..n]);
+            match output_rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(chunk) => {
+                    let _ = out.write_all(&chunk);
                     let _ = out.flush();
-                    let _ = manager_clone.append_output(&instance_id, &buf[..n]);
-                }
-                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
-                    if stop_reader.lock().map(|lock| *lock).unwrap_or(true) {
-                        break;
-                    }
-                    thread::sleep(Duration::from_millis(10));
                 }
-                Err(_) => break,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
             }
         }
     });
 
     let mut command_mode = false;
     let mut last_status_running = true;
+    let mut last_drawn = Instant::now();
 
     let exit = loop {
         let is_running = matches!(manager.get_status(&request.instance_id), Ok(Some(InstanceStatus::Running)));
@@ -782,11 +3479,7 @@ fn run_passthrough_inner(request: &mut PassthroughRequest, manager: &SessionMana
                     let toggle_key = request.key_config.task_running.get("toggle_command_mode").map(|s| s.as_str()).unwrap_or("ctrl+p");
                     
                     if matches_key(&key, toggle_key) {
-                        if command_mode {
-                            command_mode = false;
-                        } else {
-                            command_mode = true;
-                        }
+                        command_mode = !command_mode;
                         // Redraw status bar immediately
                         let size = crossterm::terminal::size()?;
                         draw_status_bar(&mut stdout, size.0, size.1, request, manager, command_mode)?;
@@ -812,12 +3505,29 @@ fn run_passthrough_inner(request: &mut PassthroughRequest, manager: &SessionMana
                             .get("kill_task")
                             .map(|s| s.as_str())
                             .unwrap_or("k");
+                        let cancel_key = request
+                            .key_config
+                            .task_running
+                            .get("cancel_task")
+                            .map(|s| s.as_str())
+                            .unwrap_or("c");
+                        let copy_key = request
+                            .key_config
+                            .task_running
+                            .get("copy_mode")
+                            .map(|s| s.as_str())
+                            .unwrap_or("[");
 
                         if matches_key(&key, quit_key) || matches_key(&key, back_key) {
                             break PassthroughOutcome::BackToList;
                         } else if matches_key(&key, kill_key) {
                             let _ = manager.kill_and_remove(&request.instance_id);
                             break PassthroughOutcome::BackToList;
+                        } else if matches_key(&key, cancel_key) {
+                            let _ = manager.cancel(&request.instance_id);
+                            command_mode = false;
+                        } else if matches_key(&key, copy_key) {
+                            break PassthroughOutcome::CopyMode;
                         }
                     } else if let Some(bytes) = key_event_to_bytes(&key) {
                         let _ = request.writer.write_all(&bytes);
@@ -829,21 +3539,18 @@ fn run_passthrough_inner(request: &mut PassthroughRequest, manager: &SessionMana
                 }
                 Event::Resize(cols, rows) => {
                     if is_running {
-                        let _ = request.master.resize(PtySize {
-                            rows,
-                            cols,
-                            pixel_width: 0,
-                            pixel_height: 0,
-                        });
+                        fit_pty(&*request.master, cols, rows, false);
                     }
                     set_scroll_region(rows)?;
                     draw_status_bar(&mut stdout, cols, rows, request, manager, command_mode)?;
                 }
                 _ => {}
             }
-        } else if last_status_running != is_running {
+        } else if last_status_running != is_running || (is_running && last_drawn.elapsed() >= Duration::from_secs(1)) {
+             // Also once a second while running, for the elapsed time.
              let size = crossterm::terminal::size()?;
              draw_status_bar(&mut stdout, size.0, size.1, request, manager, command_mode)?;
+             last_drawn = Instant::now();
         }
         last_status_running = is_running;
     };
@@ -857,6 +3564,66 @@ fn run_passthrough_inner(request: &mut PassthroughRequest, manager: &SessionMana
     Ok(exit)
 }
 
+/// Whether `search` fuzzily matches the task's id, name, category or
+/// description.
+fn task_matches(search: &str, task: &Task) -> bool {
+    let text = format!(
+        "{} {} {} {}",
+        task.id,
+        task.name,
+        task.category.as_deref().unwrap_or_default(),
+        task.description.as_deref().unwrap_or_default()
+    );
+    history::fuzzy_match(search, &text)
+}
+
+/// Breaks `text` into lines at most `width` columns wide, at spaces where
+/// it can; existing line breaks are kept.
+/// Lowercase letters and digits of `text` with single dashes between the
+/// words, at most 40 characters, for task ids.
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for ch in text.chars().flat_map(char::to_lowercase) {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 40 {
+            break;
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let sep = if line.is_empty() { "" } else { " " };
+            if Span::raw(format!("{}{}{}", line, sep, word)).width() <= width {
+                line.push_str(sep);
+                line.push_str(word);
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // A word wider than the pane, like a URL or a run of CJK text.
+            for ch in word.chars() {
+                if Span::raw(format!("{}{}", line, ch)).width() > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(ch);
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
 fn matches_key(event: &KeyEvent, binding: &str) -> bool {
     let binding = binding.trim().to_lowercase();
     let mut parts: Vec<&str> = binding.split('+').collect();
@@ -932,42 +3699,140 @@ fn key_event_to_bytes(key: &KeyEvent) -> Option<Vec<u8>> {
     }
 }
 
-fn instance_line(info: &InstanceInfo) -> Line<'static> {
+fn instance_line(info: &InstanceInfo, usage: Option<&ResourceUsage>, theme: &Theme) -> Line<'static> {
     let status = match &info.status {
-        InstanceStatus::Running => ("Running".to_string(), Color::Green),
-        InstanceStatus::Exited(code) => (format!("Exited({})", code), Color::Gray),
-        InstanceStatus::Error(_) => ("Error".to_string(), Color::Red),
+        InstanceStatus::Running => ("●", "running".to_string(), Color::Green),
+        InstanceStatus::Exited(0) => ("✓", "exit 0".to_string(), Color::Gray),
+        InstanceStatus::Exited(code) => ("✗", format!("exit {}", code), Color::Red),
+        InstanceStatus::Error(_) => ("!", "error".to_string(), Color::Red),
+        InstanceStatus::Cancelled => ("⊘", "cancelled".to_string(), Color::Yellow),
     };
     let runtime = format_duration(info.started_at, info.ended_at);
     let pid = info
         .child_pid
         .map(|pid| format!("pid:{}", pid))
         .unwrap_or_else(|| "pid:-".to_string());
-    Line::from(vec![
+    let mut spans = vec![
         Span::raw("  "),
-        Span::styled("*", Style::default().fg(status.1)),
+        Span::styled(status.0, Style::default().fg(status.2)),
         Span::raw(" "),
         Span::styled(info.id.clone(), Style::default().fg(Color::Cyan)),
         Span::raw(" "),
-        Span::styled(status.0, Style::default().fg(status.1)),
+        Span::styled(format!("{:<8}", status.1), Style::default().fg(status.2)),
         Span::raw(" "),
-        Span::styled(pid, Style::default().fg(Color::DarkGray)),
+        Span::styled(pid, theme.muted),
         Span::raw(" "),
-        Span::styled(runtime, Style::default().fg(Color::DarkGray)),
-    ])
+        Span::styled(runtime, theme.muted),
+        Span::styled(format!(" since {}", format_clock(info.started_at)), theme.muted),
+    ];
+    if let Some(usage) = usage {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(usage.summary(), theme.muted));
+    }
+    if let Some(progress) = &info.progress {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(progress.summary(), Style::default().fg(Color::Yellow)));
+    }
+    match &info.baseline {
+        Some(BaselineResult::Match) => spans.push(Span::styled(" = baseline", Style::default().fg(Color::Green))),
+        Some(BaselineResult::Regression { line, .. }) => spans.push(Span::styled(
+            format!(" ≠ baseline (line {})", line),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )),
+        Some(BaselineResult::TooLong { .. }) => {
+            spans.push(Span::styled(" ≠ baseline (too long)", Style::default().fg(Color::Yellow)))
+        }
+        None => {}
+    }
+    if info.restarts > 0 {
+        spans.push(Span::styled(format!(" ↻{}", info.restarts), Style::default().fg(Color::Yellow)));
+    }
+    if let Some(restart_at) = info.restart_at {
+        let now = now_epoch();
+        spans.push(Span::styled(
+            format!(" restarting in {}s", restart_at.saturating_sub(now)),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if info.closed {
+        spans.push(Span::styled(" (closed)", theme.muted));
+    }
+    if !info.tags.is_empty() {
+        spans.push(Span::styled(format!(" [{}]", info.tags.join(", ")), theme.hint));
+    }
+    if let Some(label) = &info.label {
+        spans.push(Span::styled(format!(" {}", label), Style::default().add_modifier(Modifier::BOLD)));
+    }
+    if let Some(note) = &info.note {
+        spans.push(Span::styled(format!(" — {}", note), theme.muted));
+    }
+    Line::from(spans)
+}
+
+/// Opens `url` with the desktop's default handler.
+fn open_url(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    std::process::Command::new(opener)
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|err| anyhow!("{}: {}", opener, err))?;
+    Ok(())
 }
 
 fn format_duration(started_at: u64, ended_at: Option<u64>) -> String {
-    let now = ended_at.unwrap_or_else(|| {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or_default()
-    });
+    let now = ended_at.unwrap_or_else(now_epoch);
     let secs = now.saturating_sub(started_at);
-    let minutes = secs / 60;
-    let seconds = secs % 60;
-    format!("{:02}:{:02}", minutes, seconds)
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Local `HH:MM:SS`, with the date in front when it is not today.
+fn format_clock(epoch: u64) -> String {
+    let local = |secs: u64| {
+        let secs = secs as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::localtime_r(&secs, &mut tm);
+        }
+        tm
+    };
+    let now = now_epoch();
+    let (tm, today) = (local(epoch), local(now));
+    let time = format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec);
+    if (tm.tm_year, tm.tm_yday) == (today.tm_year, today.tm_yday) {
+        time
+    } else {
+        format!("{}-{:02}-{:02} {}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, time)
+    }
+}
+
+/// Sizes the run's PTY to a terminal of `cols` x `rows` less the status
+/// bar. The kernel sends SIGWINCH when the size changes; with `repaint` it
+/// is sent anyway, so a full-screen program redraws for a client that has
+/// just attached instead of leaving it the replayed bytes.
+fn fit_pty(master: &dyn portable_pty::MasterPty, cols: u16, rows: u16, repaint: bool) {
+    let size = PtySize {
+        rows: rows.saturating_sub(1).max(1),
+        cols: cols.max(1),
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    let changed = master.get_size().map_or(true, |current| current.rows != size.rows || current.cols != size.cols);
+    if changed {
+        let _ = master.resize(size);
+    } else if repaint {
+        if let Some(pgid) = master.process_group_leader() {
+            unsafe {
+                libc::killpg(pgid, libc::SIGWINCH);
+            }
+        }
+    }
 }
 
 fn ctrl_byte(ch: char) -> u8 {
@@ -993,16 +3858,16 @@ fn reset_scroll_region(stdout: &mut impl Write) -> Result<()> {
 fn format_start_time(manager: &SessionManager, instance_id: &str) -> String {
     if let Ok(infos) = manager.list_instances() {
         if let Some(info) = infos.iter().find(|i| i.id == instance_id) {
-            // Simple formatting since chrono is not in dependencies, just use raw timestamp or relative time if preferred
-            // But let's stick to format_duration logic for simplicity or just a simple string
-            return format!("{}s ago", format_duration(info.started_at, None));
+            return format!("{} ({} ago)", format_clock(info.started_at), format_duration(info.started_at, None));
         }
     }
     "Unknown".to_string()
 }
 
-fn instance_status_details(manager: &SessionManager, instance_id: &str) -> (String, String, String, String) {
+fn instance_status_details(manager: &SessionManager, instance_id: &str) -> (String, String, String, String, String, String) {
     let mut title = String::new();
+    let mut timing = String::new();
+    let mut progress = String::new();
     let mut pid = "-".to_string();
     let mut status_str = "Unknown".to_string();
     let mut status_color = "0"; // Default
@@ -1010,7 +3875,9 @@ fn instance_status_details(manager: &SessionManager, instance_id: &str) -> (Stri
     if let Ok(infos) = manager.list_instances() {
         if let Some(info) = infos.iter().find(|i| i.id == instance_id) {
              title = info.title.clone().unwrap_or_default();
+             progress = info.progress.as_ref().map(|p| p.summary()).unwrap_or_default();
              pid = info.child_pid.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string());
+             timing = format!("{} since {}", format_duration(info.started_at, info.ended_at), format_clock(info.started_at));
              match &info.status {
                 InstanceStatus::Running => {
                     status_str = "Running".to_string();
@@ -1024,10 +3891,14 @@ fn instance_status_details(manager: &SessionManager, instance_id: &str) -> (Stri
                     status_str = "Error".to_string();
                     status_color = "31"; // Red
                 }
+                InstanceStatus::Cancelled => {
+                    status_str = "Cancelled".to_string();
+                    status_color = "33"; // Yellow
+                }
              }
         }
     }
-    (title, pid, status_str, status_color.to_string())
+    (title, pid, status_str, status_color.to_string(), progress, timing)
 }
 
 fn draw_status_bar(
@@ -1038,16 +3909,22 @@ fn draw_status_bar(
     manager: &SessionManager,
     command_mode: bool,
 ) -> Result<()> {
-    let (title, pid, status, _status_color) = instance_status_details(manager, &request.instance_id);
+    let (title, pid, status, _status_color, progress, timing) = instance_status_details(manager, &request.instance_id);
     
     // Construct the status line
-    // Format: [TaskName] | ID | PID: 123 | Status: Running | Title: bash
+    // Format: [TaskName] | ID | PID: 123 | Status: Running | 01:23 since 14:03:12 | Title: bash
     let mut parts = vec![
         format!("[{}]", request.task_name),
         request.instance_id.clone(),
         format!("PID: {}", pid),
         format!("Status: {}", status),
     ];
+    if !timing.is_empty() {
+        parts.push(timing);
+    }
+    if !progress.is_empty() {
+        parts.push(format!("Progress: {}", progress));
+    }
     if !title.is_empty() {
         parts.push(format!("Title: {}", title));
     }
@@ -0,0 +1,761 @@
+use anyhow::{anyhow, Result};
+use clap::Args;
+use cmdhub_core::backend::Backend;
+use cmdhub_core::baseline::{self, BaselineResult, BaselineStore};
+use cmdhub_core::lineprefix::LinePrefixer;
+use cmdhub_core::logevents::LogEventWriter;
+use cmdhub_core::models::UniqueScope;
+use cmdhub_core::prelude::*;
+use cmdhub_core::registry::{running_elsewhere_message, HISTORY_LIMIT};
+use cmdhub_core::secrets;
+use cmdhub_core::script::TaskScript;
+use cmdhub_core::session::HEARTBEAT_INTERVAL;
+use cmdhub_core::streams::StderrTagger;
+use cmdhub_core::tmux;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT_EXIT_CODE: i32 = 124;
+const SPAWN_FAILED_EXIT_CODE: i32 = 127;
+const TIMEOUT_POLL: Duration = Duration::from_millis(50);
+
+/// `cmdhub run`: fills the task's inputs from flags, defaults or prompts,
+/// then runs it in this terminal. The run is recorded in the session store.
+#[derive(Args)]
+pub struct RunArgs {
+    /// Id of the task to run
+    task: String,
+    /// Value for one of the task's inputs
+    #[arg(short, long = "input", value_name = "NAME=VALUE", value_parser = key_value)]
+    inputs: Vec<(String, String)>,
+    /// Use the defaults for inputs not given instead of prompting
+    #[arg(short, long)]
+    yes: bool,
+    /// Fail when the output differs from the task's baseline
+    #[arg(long, conflicts_with = "set_baseline")]
+    check: bool,
+    /// Save the output of a successful run as the task's baseline
+    #[arg(long)]
+    set_baseline: bool,
+    /// Run in this directory instead of the task's cwd
+    #[arg(long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+    /// Set an environment variable for this run
+    #[arg(short, long = "env", value_name = "KEY=VALUE", value_parser = key_value)]
+    env: Vec<(String, String)>,
+    /// Run in this shell instead of the task's
+    #[arg(long, value_name = "PATH")]
+    shell: Option<String>,
+    /// Load the shell's login profile first
+    #[arg(long, overrides_with = "no_login")]
+    login: bool,
+    #[arg(long, overrides_with = "login")]
+    no_login: bool,
+    /// Kill the run after this many seconds; 0 turns the task's timeout off
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+    #[arg(long, overrides_with = "no_pty")]
+    pty: bool,
+    /// Give the command a pipe instead of this terminal
+    #[arg(long, overrides_with = "pty")]
+    no_pty: bool,
+    /// Tag the run, on top of the task's tags; may be repeated
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Open the run in a new tmux window
+    #[arg(long)]
+    tmux: bool,
+}
+
+impl RunArgs {
+    fn launch_options(&self) -> LaunchOptions {
+        let flag = |on: bool, off: bool| match (on, off) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        LaunchOptions {
+            env: self.env.iter().cloned().collect(),
+            cwd: self.cwd.clone(),
+            shell: self.shell.clone(),
+            login_profile: flag(self.login, self.no_login),
+            timeout_secs: self.timeout,
+            pty: flag(self.pty, self.no_pty),
+            tags: self.tags.clone(),
+            group: None,
+        }
+    }
+}
+
+pub(crate) fn key_value(pair: &str) -> std::result::Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_string(), value.to_string())),
+        None => Err(format!("expected NAME=VALUE, got {}", pair)),
+    }
+}
+
+/// `--check` fails when the output differs from the task's baseline;
+/// `--set-baseline` saves the output of a successful run as it. The launch
+/// flags override the task's settings for this run; `--tmux` runs it in a
+/// new tmux window instead.
+pub fn run_task_command(args: RunArgs, config: &AppConfig) -> Result<()> {
+    let launch = args.launch_options();
+    let RunArgs { task: task_id, inputs, yes: assume_defaults, check, set_baseline, tmux: in_tmux, .. } = args;
+    let mut values: HashMap<String, String> = inputs.into_iter().collect();
+    let task = config
+        .tasks
+        .iter()
+        .find(|task| task.id == task_id)
+        .ok_or_else(|| anyhow!("task not found: {}", task_id))?;
+    if task.members.is_some() {
+        return Err(anyhow!("{} is a group; start it from the TUI", task.id));
+    }
+    let task = &launch.apply(task);
+    let baselines = BaselineStore::new()?;
+    let expected = if check {
+        let expected = baselines.load(&task.id)?;
+        Some(expected.ok_or_else(|| {
+            anyhow!("task {} has no baseline; run it with --set-baseline or press b on a run in the TUI", task.id)
+        })?)
+    } else {
+        None
+    };
+    let ignore = baseline::ignore_patterns(task)?;
+
+    let inputs = task.inputs.clone().unwrap_or_default();
+    if let Some(name) = values.keys().find(|name| !inputs.contains_key(*name)) {
+        return Err(anyhow!("task {} has no input named {}", task.id, name));
+    }
+    for (name, value) in &values {
+        if let Some(reason) = inputs[name].validate(value) {
+            return Err(anyhow!("input {}: {}", name, reason));
+        }
+    }
+
+    let script_defaults = match TaskScript::for_task(task)? {
+        Some(script) => script.defaults(&values)?,
+        None => HashMap::new(),
+    };
+    let mut names: Vec<&String> = inputs.keys().filter(|name| !values.contains_key(*name)).collect();
+    names.sort();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    for name in names {
+        let config = &inputs[name];
+        let default = script_defaults.get(name).cloned().or_else(|| config.default_value());
+        let value = if assume_defaults {
+            let value = default.unwrap_or_default();
+            if let Some(reason) = config.validate(&value) {
+                return Err(anyhow!("input {}: {} (pass --input {}=...)", name, reason, name));
+            }
+            value
+        } else {
+            prompt(name, config, default, &mut lines)?
+        };
+        values.insert(name.clone(), value);
+    }
+
+    if in_tmux {
+        // Fail here on inputs the script rejects; the window's run renders the command itself.
+        prepare_command(task, &mut values.clone())?;
+        let mut extra = Vec::new();
+        if check {
+            extra.push("--check".to_string());
+        }
+        if set_baseline {
+            extra.push("--set-baseline".to_string());
+        }
+        let window = open_in_tmux(&task_id, &values, &launch, &extra, false)?;
+        eprintln!("cmdhub: {} opened in tmux window {}", task.id, window);
+        return Ok(());
+    }
+
+    let command = prepare_command(task, &mut values)?;
+    let task = &Task { cwd: prepare_cwd(task, &values)?, ..task.clone() };
+    let baseline = Baseline { store: &baselines, expected, ignore, save: set_baseline };
+    execute(task, &command, &values, config, baseline)
+}
+
+/// `cmdhub rerun`: a run from history or still going, again.
+#[derive(Args)]
+pub struct RerunArgs {
+    /// Session id, a unique prefix of it, or the run's task#N id
+    run: String,
+    /// Open the run in a new tmux window
+    #[arg(long)]
+    tmux: bool,
+}
+
+/// Runs the rendered command of an earlier run again in this terminal, with
+/// the cwd, env, shell, timeout and PTY mode it had and its input values.
+pub fn rerun_command(args: RerunArgs, config: &AppConfig) -> Result<()> {
+    let store = SessionStore::new()?;
+    let run = store.find_session(&args.run)?;
+    let configured = config
+        .tasks
+        .iter()
+        .find(|task| task.id == run.task_id)
+        .ok_or_else(|| anyhow!("task {} of {} is no longer configured", run.task_id, args.run))?;
+    if args.tmux {
+        let window = open_rerun_in_tmux(&run)?;
+        eprintln!("cmdhub: {} opened in tmux window {}", run.task_id, window);
+        return Ok(());
+    }
+    let task = &run.rerun_task(configured);
+    eprintln!("cmdhub: {}", run.command);
+    let ignore = baseline::ignore_patterns(task)?;
+    let baseline = Baseline { store: &BaselineStore::new()?, expected: None, ignore, save: false };
+    execute(task, &run.command, &run.inputs, config, baseline)
+}
+
+/// Opens a tmux window that runs `cmdhub rerun` for `run` and returns the
+/// window id.
+pub fn open_rerun_in_tmux(run: &SessionInfo) -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let argv = vec![exe.to_string_lossy().to_string(), "rerun".to_string(), run.id.to_string()];
+    tmux::open_window(&run.task_id, &std::env::current_dir()?, &argv, false)
+}
+
+/// How a run's output is compared with, or saved as, the task's baseline.
+struct Baseline<'a> {
+    store: &'a BaselineStore,
+    /// Set for `--check`.
+    expected: Option<String>,
+    ignore: Vec<regex::Regex>,
+    /// `--set-baseline`.
+    save: bool,
+}
+
+/// Runs `command` for `task`, whose cwd is ready, and exits with its exit
+/// code unless the output is to be compared with or saved as the baseline.
+fn execute(task: &Task, command: &str, values: &HashMap<String, String>, config: &AppConfig, baseline: Baseline) -> Result<()> {
+    let check = baseline.expected.is_some();
+    let set_baseline = baseline.save;
+    let run_name = format!("{}-run-{}", task.id, std::process::id());
+    // Without a PTY the command writes to a pipe this process echoes.
+    let captured = check || set_baseline || task.pty == Some(false);
+    let env = secrets::resolve_env(task.env.as_ref())?;
+    let mut cmd = match Backend::for_task(task, &run_name)? {
+        Backend::Local => {
+            let mut cmd = Command::new(task.shell.as_deref().unwrap_or("sh"));
+            if task.login_profile.unwrap_or(false) {
+                cmd.arg("-l");
+            }
+            cmd.arg("-c").arg(command);
+            if task.env_clear.unwrap_or(false) {
+                cmd.env_clear();
+            }
+            cmd
+        }
+        Backend::Container(container) => {
+            let tty = !captured && io::stdin().is_terminal() && io::stdout().is_terminal();
+            let mut cmd = Command::new(container.runtime());
+            cmd.args(container.args(command, env.keys(), tty));
+            cmd
+        }
+    };
+    if let Some(cwd) = &task.cwd {
+        cmd.current_dir(cwd);
+    }
+    cmd.envs(&env);
+    let timeout = task.timeout_secs.map(Duration::from_secs);
+    let record = RunRecord::start(task, command, values)?;
+    cmdhub_core::sdnotify::ready();
+    if !captured {
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                finish_run(record, SPAWN_FAILED_EXIT_CODE, None);
+                return Err(anyhow!("failed to run {}: {}", task.id, err));
+            }
+        };
+        let status = wait_with_timeout(child, timeout, &task.id)?;
+        std::process::exit(finish_run(record, exit_code(status), None));
+    }
+
+    if task.pty == Some(false) {
+        cmd.stdin(std::process::Stdio::null());
+    }
+    let timestamps = config.logs.as_ref().is_some_and(|logs| logs.timestamps);
+    let ordinal = timestamps.then(|| record.as_ref().and_then(|r| r.session.seq).unwrap_or(1));
+    let events = config.logs.as_ref().and_then(|logs| logs.events).unwrap_or(true);
+    let event_log = record.as_ref().filter(|_| events).and_then(RunRecord::event_log);
+    let (status, output) = match run_captured(cmd, timeout, &task.id, ordinal, task.pty == Some(false), event_log) {
+        Ok(result) => result,
+        Err(err) => {
+            finish_run(record, SPAWN_FAILED_EXIT_CODE, None);
+            return Err(anyhow!("failed to run {}: {}", task.id, err));
+        }
+    };
+    let code = finish_run(record, exit_code(status), Some(&output));
+    if code != 0 || (!check && !set_baseline) {
+        std::process::exit(code);
+    }
+    let normalized = baseline::normalize(&output, &baseline.ignore);
+    if set_baseline {
+        let path = baseline.store.save(&task.id, &normalized)?;
+        eprintln!("baseline for {} saved to {}", task.id, path.display());
+        return Ok(());
+    }
+    let result = baseline::compare(baseline.expected.as_deref().unwrap_or_default(), &normalized);
+    eprintln!("{}: {}", task.id, result.summary());
+    if result != BaselineResult::Match {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Opens a tmux window that runs `cmdhub run` for the task with these
+/// values, so the run is recorded like any other, and returns the window id.
+pub fn open_in_tmux(
+    task_id: &str,
+    values: &HashMap<String, String>,
+    launch: &LaunchOptions,
+    extra: &[String],
+    detached: bool,
+) -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let mut argv = vec![exe.to_string_lossy().to_string(), "run".to_string(), task_id.to_string(), "--yes".to_string()];
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    for name in names {
+        argv.extend(["--input".to_string(), format!("{}={}", name, values[name])]);
+    }
+    if let Some(cwd) = &launch.cwd {
+        argv.extend(["--cwd".to_string(), cwd.to_string_lossy().to_string()]);
+    }
+    for (key, value) in &launch.env {
+        argv.extend(["--env".to_string(), format!("{}={}", key, value)]);
+    }
+    if let Some(shell) = &launch.shell {
+        argv.extend(["--shell".to_string(), shell.clone()]);
+    }
+    match launch.login_profile {
+        Some(true) => argv.push("--login".to_string()),
+        Some(false) => argv.push("--no-login".to_string()),
+        None => {}
+    }
+    if let Some(secs) = launch.timeout_secs {
+        argv.extend(["--timeout".to_string(), secs.to_string()]);
+    }
+    match launch.pty {
+        Some(true) => argv.push("--pty".to_string()),
+        Some(false) => argv.push("--no-pty".to_string()),
+        None => {}
+    }
+    for tag in &launch.tags {
+        argv.extend(["--tag".to_string(), tag.clone()]);
+    }
+    argv.extend(extra.iter().cloned());
+    tmux::open_window(task_id, &std::env::current_dir()?, &argv, detached)
+}
+
+/// This run in the session store, so it shows in history next to runs
+/// started from the TUI or the server. Recording is best effort.
+struct RunRecord {
+    store: SessionStore,
+    session: SessionInfo,
+    // Dropped when the run finishes, which stops its heartbeats.
+    heartbeat: Option<mpsc::Sender<()>>,
+}
+
+impl RunRecord {
+    /// Errors when a `unique = "global"` run of the task is live in another
+    /// process; a run the store cannot record still goes ahead.
+    fn start(task: &Task, command: &str, values: &HashMap<String, String>) -> Result<Option<Self>> {
+        match Self::try_start(task, command, values) {
+            Ok(Ok(record)) => Ok(Some(record)),
+            Ok(Err(other)) => Err(anyhow!(running_elsewhere_message(&task.id, &other))),
+            Err(err) => {
+                log::warn!("run of {} is not recorded in the session store: {}", task.id, err);
+                Ok(None)
+            }
+        }
+    }
+
+    fn try_start(task: &Task, command: &str, values: &HashMap<String, String>) -> Result<std::result::Result<Self, SessionInfo>> {
+        let store = SessionStore::new()?;
+        let seq = store.next_seq(&task.id)?;
+        let mut session = SessionInfo::from(NewSession::for_task(task, Some(format!("{}#{}", task.id, seq)), command));
+        session.status = SessionStatus::Running;
+        session.runner_pid = Some(std::process::id());
+        session.seq = Some(seq);
+        if task.container.is_none() {
+            session.shell = Some(task.shell.clone().unwrap_or_else(|| "sh".to_string()));
+        }
+        session.login_profile = task.login_profile.unwrap_or(false);
+        session.timeout_secs = task.timeout_secs;
+        session.pty = Some(task.pty.unwrap_or(true));
+        session.tags = task.tags.clone().unwrap_or_default();
+        session.heartbeat_at = Some(session.started_at);
+        session.record_inputs(task, values);
+        match task.unique.unwrap_or_default() {
+            UniqueScope::Global => {
+                if let Some(other) = store.claim_session(&session, |other| store.hosted_elsewhere(other))? {
+                    return Ok(Err(other));
+                }
+            }
+            scope => {
+                if scope == UniqueScope::Session {
+                    let sessions = store.list_sessions()?;
+                    if let Some(other) = sessions.iter().find(|other| other.task_id == task.id && store.hosted_elsewhere(other)) {
+                        eprintln!("cmdhub: {}", running_elsewhere_message(&task.id, other));
+                    }
+                }
+                store.insert_session(&session)?;
+            }
+        }
+        let (heartbeat, stopped) = mpsc::channel::<()>();
+        let beats = SessionStore::new()?;
+        let id = session.id;
+        std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(HEARTBEAT_INTERVAL) {
+                if let Err(err) = beats.beat(id) {
+                    log::debug!("no heartbeat for this run: {}", err);
+                }
+            }
+        });
+        Ok(Ok(Self { store, session, heartbeat: Some(heartbeat) }))
+    }
+
+    fn event_log(&self) -> Option<LogEventWriter> {
+        let path = self.store.session_events_path(self.session.id);
+        let run = self.session.session_name.as_deref().unwrap_or(&self.session.task_id);
+        match LogEventWriter::open(&path, run) {
+            Ok(writer) => Some(writer),
+            Err(err) => {
+                log::warn!("failed to open event log for this run: {}", err);
+                None
+            }
+        }
+    }
+
+    fn finish(mut self, code: i32, output: Option<&[u8]>) -> Result<()> {
+        self.heartbeat.take();
+        if let Some(output) = output {
+            self.store.write_log(self.session.id, output)?;
+        }
+        self.session.exit_code = u32::try_from(code).ok();
+        self.store.write_session(&self.session)?;
+        self.store.mark_exited(&mut self.session)?;
+        self.store.move_to_history(self.session.id, HISTORY_LIMIT)
+    }
+}
+
+/// Records the end of the run and passes its exit code through.
+fn finish_run(record: Option<RunRecord>, code: i32, output: Option<&[u8]>) -> i32 {
+    if let Some(record) = record {
+        if let Err(err) = record.finish(code, output) {
+            log::warn!("failed to record the exit of this run: {}", err);
+        }
+    }
+    code
+}
+
+/// The exit code of a finished run; 124 when it timed out, like timeout(1).
+fn exit_code(status: Option<ExitStatus>) -> i32 {
+    match status {
+        Some(status) => status.code().unwrap_or(1),
+        None => TIMEOUT_EXIT_CODE,
+    }
+}
+
+/// Waits for `child`, killing it once `timeout` passes; `None` means it
+/// timed out.
+fn wait_with_timeout(mut child: Child, timeout: Option<Duration>, task_id: &str) -> Result<Option<ExitStatus>> {
+    let deadline = match timeout {
+        Some(timeout) => Instant::now() + timeout,
+        None => return Ok(Some(child.wait()?)),
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            eprintln!("cmdhub: {} timed out after {}s", task_id, timeout.unwrap_or_default().as_secs());
+            return Ok(None);
+        }
+        std::thread::sleep(TIMEOUT_POLL);
+    }
+}
+
+/// Runs `cmd` with stdout and stderr on one pipe, echoing it here while
+/// collecting it.
+fn run_captured(
+    mut cmd: Command,
+    timeout: Option<Duration>,
+    task_id: &str,
+    ordinal: Option<u32>,
+    separate_stderr: bool,
+    events: Option<LogEventWriter>,
+) -> Result<(Option<ExitStatus>, Vec<u8>)> {
+    let output = Arc::new(Mutex::new(Capture { output: Vec::new(), events }));
+    let (reader, writer) = io::pipe()?;
+    let mut echoes = Vec::new();
+    if separate_stderr {
+        let (err_reader, err_writer) = io::pipe()?;
+        cmd.stdout(writer).stderr(err_writer);
+        let tagger = Some(StderrTagger::default());
+        echoes.push(echo(err_reader, io::stderr(), tagger, ordinal.map(LinePrefixer::new), Arc::clone(&output)));
+    } else {
+        cmd.stdout(writer.try_clone()?).stderr(writer);
+    }
+    echoes.push(echo(reader, io::stdout(), None, ordinal.map(LinePrefixer::new), Arc::clone(&output)));
+    let child = cmd.spawn()?;
+    // Drop our copies of the write ends so the reads below see EOF.
+    drop(cmd);
+    let status = wait_with_timeout(child, timeout, task_id)?;
+    // Leftover children of a killed run may hold the pipes open; don't wait
+    // for them, and keep what it printed until then.
+    if status.is_some() {
+        for echo in echoes {
+            echo.join().map_err(|_| anyhow!("output reader panicked"))??;
+        }
+    }
+    let mut capture = output.lock().map_err(|_| anyhow!("output lock poisoned"))?;
+    if let Some(mut events) = capture.events.take() {
+        if let Err(err) = events.finish() {
+            log::warn!("event log incomplete: {}", err);
+        }
+    }
+    Ok((status, std::mem::take(&mut capture.output)))
+}
+
+/// Output collected from both streams, also written to the run's event log.
+struct Capture {
+    output: Vec<u8>,
+    events: Option<LogEventWriter>,
+}
+
+impl Capture {
+    fn push(&mut self, data: &[u8]) {
+        self.output.extend_from_slice(data);
+        if let Some(events) = self.events.as_mut() {
+            if let Err(err) = events.feed(data) {
+                log::warn!("event log stopped: {}", err);
+                self.events = None;
+            }
+        }
+    }
+}
+
+/// Copies one of the command's streams to ours as it is read and appends it
+/// to `output`, stderr tagged by `tagger`.
+fn echo(
+    mut reader: io::PipeReader,
+    mut out: impl Write + Send + 'static,
+    mut tagger: Option<StderrTagger>,
+    mut prefixer: Option<LinePrefixer>,
+    output: Arc<Mutex<Capture>>,
+) -> std::thread::JoinHandle<io::Result<()>> {
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            let data = match prefixer.as_mut() {
+                Some(prefixer) => prefixer.apply(&buf[..n]),
+                None => Cow::Borrowed(&buf[..n]),
+            };
+            out.write_all(&data)?;
+            out.flush()?;
+            let tagged = tagger.as_mut().map(|tagger| tagger.tag(&data));
+            if let Ok(mut output) = output.lock() {
+                output.push(tagged.as_deref().unwrap_or(&data));
+            }
+        }
+        if let (Some(tagger), Ok(mut output)) = (tagger.as_mut(), output.lock()) {
+            output.push(&tagger.finish());
+        }
+        Ok(())
+    })
+}
+
+/// Asks for one input on stdin until the answer validates; an empty answer takes the default.
+fn prompt(
+    name: &str,
+    config: &InputConfig,
+    default: Option<String>,
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+) -> Result<String> {
+    let mut stderr = io::stderr();
+    loop {
+        match config {
+            InputConfig::Select { options, .. } | InputConfig::MultiSelect { options, .. } => {
+                let defaults = default.as_deref().map(|value| config.split(value)).unwrap_or_default();
+                writeln!(stderr, "{}:", name)?;
+                for (idx, option) in options.iter().enumerate() {
+                    let marker = if defaults.contains(&option.as_str()) { " (default)" } else { "" };
+                    writeln!(stderr, "  {}) {}{}", idx + 1, option, marker)?;
+                }
+                if matches!(config, InputConfig::MultiSelect { .. }) {
+                    write!(stderr, "choose any of [1-{}], comma separated: ", options.len())?;
+                } else {
+                    write!(stderr, "choose [1-{}]: ", options.len())?;
+                }
+            }
+            InputConfig::Bool { .. } => {
+                let hint = if default.as_deref() == Some("true") { "Y/n" } else { "y/N" };
+                write!(stderr, "{} [{}]: ", name, hint)?;
+            }
+            InputConfig::Number { min, max, .. } => {
+                write!(stderr, "{}", name)?;
+                match (min, max) {
+                    (None, None) => {}
+                    (min, max) => write!(
+                        stderr,
+                        " ({}..{})",
+                        min.map(|v| v.to_string()).unwrap_or_default(),
+                        max.map(|v| v.to_string()).unwrap_or_default()
+                    )?,
+                }
+                if let Some(default) = &default {
+                    write!(stderr, " [{}]", default)?;
+                }
+                write!(stderr, ": ")?;
+            }
+            InputConfig::Text { placeholder, .. } => {
+                write!(stderr, "{}", name)?;
+                if let Some(placeholder) = placeholder {
+                    write!(stderr, " ({})", placeholder)?;
+                }
+                if let Some(default) = &default {
+                    write!(stderr, " [{}]", default)?;
+                }
+                write!(stderr, ": ")?;
+            }
+            InputConfig::Password { .. } => write!(stderr, "{}: ", name)?,
+        }
+        stderr.flush()?;
+
+        let line = if matches!(config, InputConfig::Password { .. }) {
+            let _echo = EchoOff::new();
+            let line = lines.next();
+            writeln!(stderr)?;
+            line
+        } else {
+            lines.next()
+        };
+        let answer = match line {
+            Some(line) => line?.trim().to_string(),
+            None => return Err(anyhow!("stdin closed while reading input {}; use --input or --yes", name)),
+        };
+        let value = match config {
+            _ if answer.is_empty() => default.clone().unwrap_or_default(),
+            InputConfig::Select { options, .. } => pick_option(options, &answer),
+            InputConfig::MultiSelect { options, .. } => {
+                let picked: Vec<String> = answer
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|part| !part.is_empty())
+                    .map(|part| pick_option(options, part))
+                    .collect();
+                config.join(&picked)
+            }
+            InputConfig::Bool { .. } => match answer.to_ascii_lowercase().as_str() {
+                "y" | "yes" | "true" => "true".to_string(),
+                "n" | "no" | "false" => "false".to_string(),
+                _ => answer,
+            },
+            _ => answer,
+        };
+        match config.validate(&value) {
+            Some(reason) => writeln!(stderr, "  {}", reason)?,
+            None => return Ok(value),
+        }
+    }
+}
+
+/// Accepts a 1-based option number or the option itself.
+fn pick_option(options: &[String], answer: &str) -> String {
+    answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|idx| idx.checked_sub(1))
+        .and_then(|idx| options.get(idx).cloned())
+        .unwrap_or_else(|| answer.to_string())
+}
+
+/// Turns off terminal echo on stdin until dropped.
+struct EchoOff {
+    saved: Option<libc::termios>,
+}
+
+impl EchoOff {
+    fn new() -> Self {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::isatty(0) } != 1 || unsafe { libc::tcgetattr(0, &mut termios) } != 0 {
+            return Self { saved: None };
+        }
+        let saved = termios;
+        termios.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(0, libc::TCSANOW, &termios) };
+        Self { saved: Some(saved) }
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            unsafe { libc::tcsetattr(0, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        run: RunArgs,
+    }
+
+    #[test]
+    fn launch_flags_reach_the_task() {
+        let cli = Cli::parse_from([
+            "cmdhub", "build", "--cwd", "/srv", "-e", "MODE=fast", "--shell", "/bin/sh", "--login", "--no-login",
+            "--timeout", "0", "--no-pty", "--tag", "manual",
+        ]);
+        let task = Task { id: "build".into(), timeout_secs: Some(60), ..Default::default() };
+        let task = cli.run.launch_options().apply(&task);
+        assert_eq!(task.cwd, Some(PathBuf::from("/srv")));
+        assert_eq!(task.env.unwrap()["MODE"].describe(), "fast");
+        assert_eq!(task.shell.as_deref(), Some("/bin/sh"));
+        assert_eq!(task.login_profile, Some(false));
+        assert_eq!(task.timeout_secs, None);
+        assert_eq!(task.pty, Some(false));
+        assert_eq!(task.tags, Some(vec!["manual".to_string()]));
+    }
+
+    #[test]
+    fn timed_out_runs_keep_their_output() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-run-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        let events = LogEventWriter::open(&path, "t#1").unwrap();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo first; printf partial; sleep 5"]);
+        let (status, output) =
+            run_captured(cmd, Some(Duration::from_millis(500)), "t", None, false, Some(events)).unwrap();
+        assert!(status.is_none());
+        assert_eq!(output, b"first\npartial");
+        let events = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(events.lines().count(), 2);
+        assert!(events.contains("\"partial\""));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
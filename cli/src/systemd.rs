@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use cmdhub_core::config::{CONFIG_ENV, TASKS_DIR_ENV};
+use cmdhub_core::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// `WatchdogSec` of generated units; `cmdhub run` pings at half of it.
+const WATCHDOG_SECS: u64 = 30;
+
+#[derive(Subcommand)]
+pub enum SystemdAction {
+    /// Write a user unit that runs the task with `cmdhub run`
+    Install(InstallArgs),
+}
+
+#[derive(Args)]
+pub struct InstallArgs {
+    /// Id of the task to run as a service
+    task: String,
+    /// Value for one of the task's inputs; the others use their defaults
+    #[arg(short, long = "input", value_name = "NAME=VALUE", value_parser = crate::run::key_value)]
+    inputs: Vec<(String, String)>,
+    /// Print the unit instead of writing it
+    #[arg(long)]
+    print: bool,
+}
+
+pub fn run_systemd_command(action: SystemdAction, config: &AppConfig) -> Result<()> {
+    match action {
+        SystemdAction::Install(args) => install(args, config),
+    }
+}
+
+fn install(args: InstallArgs, config: &AppConfig) -> Result<()> {
+    let task = config
+        .tasks
+        .iter()
+        .find(|task| task.id == args.task)
+        .ok_or_else(|| anyhow!("task not found: {}", args.task))?;
+    if task.members.is_some() {
+        return Err(anyhow!("{} is a group; install a unit per member instead", task.id));
+    }
+    let values: HashMap<String, String> = args.inputs.into_iter().collect();
+    let inputs = task.inputs.clone().unwrap_or_default();
+    for (name, config) in &inputs {
+        let reason = match values.get(name) {
+            Some(value) => config.validate(value),
+            // The unit runs with --yes, so the default has to do.
+            None => config.validate(&config.default_value().unwrap_or_default()).map(|reason| {
+                format!("{} (pass --input {}=...)", reason, name)
+            }),
+        };
+        if let Some(reason) = reason {
+            return Err(anyhow!("input {}: {}", name, reason));
+        }
+    }
+    if let Some(name) = values.keys().find(|name| !inputs.contains_key(*name)) {
+        return Err(anyhow!("task {} has no input named {}", task.id, name));
+    }
+
+    let unit = unit_file(task, &values)?;
+    if args.print {
+        print!("{}", unit);
+        return Ok(());
+    }
+    let name = unit_name(&task.id);
+    let dir = unit_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(&name);
+    std::fs::write(&path, unit)?;
+    println!("wrote {}", path.display());
+    println!("start it with: systemctl --user daemon-reload && systemctl --user enable --now {}", name);
+    Ok(())
+}
+
+/// A `Type=notify` service running `cmdhub run <task> --yes` from the
+/// current directory, so the same config files are found.
+fn unit_file(task: &Task, values: &HashMap<String, String>) -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let mut argv = vec![exe.to_string_lossy().to_string(), "run".to_string(), task.id.clone(), "--yes".to_string()];
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    for name in names {
+        argv.extend(["--input".to_string(), format!("{}={}", name, values[name])]);
+    }
+    let exec = argv.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ");
+    let cwd = std::env::current_dir()?;
+
+    let mut unit = String::new();
+    unit.push_str("[Unit]\n");
+    unit.push_str(&format!("Description=CmdHub task {}\n", escape(&task.name)));
+    unit.push_str("After=network.target\n\n");
+    unit.push_str("[Service]\n");
+    unit.push_str("Type=notify\n");
+    unit.push_str("NotifyAccess=main\n");
+    unit.push_str(&format!("ExecStart={}\n", exec));
+    unit.push_str(&format!("WorkingDirectory={}\n", quote(&cwd.to_string_lossy())));
+    for key in [CONFIG_ENV, TASKS_DIR_ENV] {
+        if let Some(value) = std::env::var_os(key) {
+            unit.push_str(&format!("Environment={}\n", quote(&format!("{}={}", key, value.to_string_lossy()))));
+        }
+    }
+    unit.push_str(&format!("WatchdogSec={}\n", WATCHDOG_SECS));
+    unit.push_str("Restart=on-failure\n\n");
+    unit.push_str("[Install]\n");
+    unit.push_str("WantedBy=default.target\n");
+    Ok(unit)
+}
+
+fn unit_name(task_id: &str) -> String {
+    let id: String = task_id
+        .chars()
+        .map(|ch| if ch.is_ascii_alphanumeric() || "-_.".contains(ch) { ch } else { '-' })
+        .collect();
+    format!("cmdhub-{}.service", id)
+}
+
+fn unit_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .ok_or_else(|| anyhow!("HOME is not set"))?,
+    };
+    Ok(base.join("systemd").join("user"))
+}
+
+/// `%` and `$` would be expanded by systemd.
+fn escape(value: &str) -> String {
+    value.replace('%', "%%").replace('$', "$$")
+}
+
+/// One word of a unit's command line, quoted when it needs to be.
+fn quote(value: &str) -> String {
+    let value = escape(value);
+    if !value.is_empty() && !value.contains(|ch: char| ch.is_whitespace() || "\"'\\;".contains(ch)) {
+        return value;
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use cmdhub_core::config::{self, edit};
+use cmdhub_core::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Subcommand)]
+pub enum TaskAction {
+    /// Add a task to a file in the tasks directory
+    Add(AddArgs),
+    /// Remove a task from the file that declares it
+    Remove {
+        /// Id of the task to remove
+        id: String,
+    },
+    /// Change a task's id, and the groups listing it, or its name
+    Rename(RenameArgs),
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    /// Id of the new task
+    id: String,
+    /// Command line to run
+    #[arg(short, long)]
+    command: String,
+    /// Name shown in the task list [default: the id]
+    #[arg(short, long)]
+    name: Option<String>,
+    #[arg(long)]
+    category: Option<String>,
+    /// Directory to run the command in
+    #[arg(long)]
+    cwd: Option<PathBuf>,
+    /// File to add the task to, relative to the tasks directory [default: <id>.toml]
+    #[arg(short, long, value_name = "PATH")]
+    file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct RenameArgs {
+    /// Id of the task to rename
+    id: String,
+    /// Id to give it
+    new_id: String,
+    /// Name shown in the task list
+    #[arg(short, long)]
+    name: Option<String>,
+}
+
+pub fn run_task_action(action: TaskAction, config: &AppConfig) -> Result<()> {
+    match action {
+        TaskAction::Add(args) => add(args, config),
+        TaskAction::Remove { id } => remove(&id, config),
+        TaskAction::Rename(args) => rename(args, config),
+    }
+}
+
+fn add(args: AddArgs, config: &AppConfig) -> Result<()> {
+    check_new_id(&args.id, config)?;
+    let config_path = config::resolve_config_path()?;
+    let path = match (config::tasks_dir(&config_path), args.file) {
+        (_, Some(file)) if file.is_absolute() => file,
+        (Some(dir), file) => dir.join(file.unwrap_or_else(|| PathBuf::from(format!("{}.toml", args.id)))),
+        // Such as a project's cmdhub.toml, which merges no task files.
+        (None, None) => config_path,
+        (None, Some(_)) => {
+            return Err(anyhow!("{} has no tasks directory; pass --tasks-dir or an absolute --file", config_path.display()))
+        }
+    };
+    let task = Task {
+        name: args.name.unwrap_or_else(|| args.id.clone()),
+        id: args.id,
+        command: args.command,
+        category: args.category,
+        cwd: args.cwd,
+        ..Default::default()
+    };
+    edit::add_task(&path, &task)?;
+    println!("added {} to {}", task.id, path.display());
+    Ok(())
+}
+
+fn remove(id: &str, config: &AppConfig) -> Result<()> {
+    let path = source(id, config)?;
+    if let Some(group) = groups_listing(id, config).next() {
+        return Err(anyhow!("{} is a member of group {}; take it out of the group first", id, group.id));
+    }
+    if !edit::remove_task(&path, id)? {
+        return Err(anyhow!("{} does not declare {}", path.display(), id));
+    }
+    println!("removed {} from {}", id, path.display());
+    Ok(())
+}
+
+fn rename(args: RenameArgs, config: &AppConfig) -> Result<()> {
+    let path = source(&args.id, config)?;
+    if args.new_id != args.id {
+        check_new_id(&args.new_id, config)?;
+    }
+    if !edit::rename_task(&path, &args.id, &args.new_id, args.name.as_deref())? {
+        return Err(anyhow!("{} does not declare {}", path.display(), args.id));
+    }
+    println!("renamed {} to {} in {}", args.id, args.new_id, path.display());
+    if args.new_id == args.id {
+        return Ok(());
+    }
+    let mut files: Vec<PathBuf> = groups_listing(&args.id, config).filter_map(|group| group.source.clone()).collect();
+    files.sort();
+    files.dedup();
+    for file in files {
+        if edit::rename_member(&file, &args.id, &args.new_id)? {
+            println!("updated the groups in {}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Ids are typed in `cmdhub run` and `cmdhub rerun <id>#<n>`.
+fn check_new_id(id: &str, config: &AppConfig) -> Result<()> {
+    if id.is_empty() || id.contains(|ch: char| ch.is_whitespace() || ch == '#') {
+        return Err(anyhow!("invalid task id {:?}: use no spaces or #", id));
+    }
+    match config.tasks.iter().find(|task| task.id == id) {
+        Some(task) => Err(anyhow!(
+            "task {} already exists{}",
+            id,
+            task.source.as_ref().map(|path| format!(" in {}", path.display())).unwrap_or_default()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The file declaring task `id`.
+fn source(id: &str, config: &AppConfig) -> Result<PathBuf> {
+    let task = config
+        .tasks
+        .iter()
+        .find(|task| task.id == id)
+        .ok_or_else(|| anyhow!("task not found: {}", id))?;
+    task.source.clone().ok_or_else(|| anyhow!("{} is not declared in a config file", id))
+}
+
+fn groups_listing<'a>(id: &'a str, config: &'a AppConfig) -> impl Iterator<Item = &'a Task> {
+    config
+        .tasks
+        .iter()
+        .filter(move |task| task.members.as_ref().is_some_and(|members| members.iter().any(|member| member == id)))
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use cmdhub_core::models::ThemeConfig;
+use ratatui::style::{Color, Modifier, Style};
+
+/// Resolved TUI styles; every pane takes its colors from here.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub highlight: Style,
+    pub section: Style,
+    pub header: Style,
+    pub border: Style,
+    pub focus_border: Style,
+    pub title: Style,
+    pub log: Style,
+    pub muted: Style,
+    pub hint: Style,
+    pub error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// The built-in theme named in `config`, with its overrides applied.
+    pub fn resolve(config: Option<&ThemeConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self::dark());
+        };
+        let mut theme = match config.name.as_deref() {
+            None | Some("dark") => Self::dark(),
+            Some("light") => Self::light(),
+            Some("solarized") => Self::solarized(),
+            Some(other) => {
+                return Err(anyhow!("unknown theme {:?}, expected \"dark\", \"light\" or \"solarized\"", other))
+            }
+        };
+        let overrides = [
+            (&config.highlight, &mut theme.highlight),
+            (&config.section, &mut theme.section),
+            (&config.header, &mut theme.header),
+            (&config.border, &mut theme.border),
+            (&config.focus_border, &mut theme.focus_border),
+            (&config.title, &mut theme.title),
+            (&config.log, &mut theme.log),
+            (&config.muted, &mut theme.muted),
+            (&config.hint, &mut theme.hint),
+            (&config.error, &mut theme.error),
+        ];
+        for (spec, style) in overrides {
+            if let Some(spec) = spec {
+                *style = text_style(spec);
+            }
+        }
+        Ok(theme)
+    }
+
+    fn dark() -> Self {
+        Self {
+            highlight: Style::default().bg(Color::Blue).fg(Color::Black).add_modifier(Modifier::BOLD),
+            section: Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            header: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            border: Style::default(),
+            focus_border: Style::default().fg(Color::Yellow),
+            title: Style::default(),
+            log: Style::default(),
+            muted: Style::default().fg(Color::DarkGray),
+            hint: Style::default().fg(Color::Blue),
+            error: Style::default().fg(Color::Red),
+        }
+    }
+
+    fn light() -> Self {
+        Self {
+            highlight: Style::default().bg(Color::Blue).fg(Color::White).add_modifier(Modifier::BOLD),
+            section: Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            header: Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+            border: Style::default().fg(Color::DarkGray),
+            focus_border: Style::default().fg(Color::Blue),
+            title: Style::default().fg(Color::Black).add_modifier(Modifier::BOLD),
+            log: Style::default().fg(Color::Black),
+            muted: Style::default().fg(Color::DarkGray),
+            hint: Style::default().fg(Color::Blue),
+            error: Style::default().fg(Color::Red),
+        }
+    }
+
+    fn solarized() -> Self {
+        let base03 = Color::Rgb(0x00, 0x2b, 0x36);
+        let base01 = Color::Rgb(0x58, 0x6e, 0x75);
+        let base0 = Color::Rgb(0x83, 0x94, 0x96);
+        let yellow = Color::Rgb(0xb5, 0x89, 0x00);
+        let orange = Color::Rgb(0xcb, 0x4b, 0x16);
+        let red = Color::Rgb(0xdc, 0x32, 0x2f);
+        let magenta = Color::Rgb(0xd3, 0x36, 0x82);
+        let blue = Color::Rgb(0x26, 0x8b, 0xd2);
+        let cyan = Color::Rgb(0x2a, 0xa1, 0x98);
+        Self {
+            highlight: Style::default().bg(blue).fg(base03).add_modifier(Modifier::BOLD),
+            section: Style::default().fg(magenta).add_modifier(Modifier::BOLD),
+            header: Style::default().fg(yellow).add_modifier(Modifier::BOLD),
+            border: Style::default().fg(base01),
+            focus_border: Style::default().fg(orange),
+            title: Style::default().fg(cyan).add_modifier(Modifier::BOLD),
+            log: Style::default().fg(base0),
+            muted: Style::default().fg(base01),
+            hint: Style::default().fg(blue),
+            error: Style::default().fg(red),
+        }
+    }
+}
+
+/// A style like "red bold" or "black on light_blue", with the names
+/// `UiConfig::parse_style` accepts; other words are tried as ratatui colors
+/// ("#ff8800", "208"). The color after `on` is the background.
+pub fn text_style(spec: &str) -> Style {
+    let mut style = Style::default();
+    let mut background = false;
+    for part in spec.split_whitespace() {
+        let part = part.to_lowercase();
+        let modifier = match part.as_str() {
+            "on" => {
+                background = true;
+                continue;
+            }
+            "bold" => Some(Modifier::BOLD),
+            "dim" => Some(Modifier::DIM),
+            "italic" => Some(Modifier::ITALIC),
+            "underline" => Some(Modifier::UNDERLINED),
+            "blink" => Some(Modifier::SLOW_BLINK),
+            "reverse" => Some(Modifier::REVERSED),
+            "hidden" => Some(Modifier::HIDDEN),
+            _ => None,
+        };
+        if let Some(modifier) = modifier {
+            style = style.add_modifier(modifier);
+            continue;
+        }
+        if let Some(color) = parse_color(&part) {
+            style = if background { style.bg(color) } else { style.fg(color) };
+            background = false;
+        }
+    }
+    style
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    match name {
+        "reset" | "default" => Some(Color::Reset),
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "magenta" => Some(Color::Magenta),
+        "cyan" => Some(Color::Cyan),
+        "white" => Some(Color::Gray),
+        "light_black" | "gray" | "grey" => Some(Color::DarkGray),
+        "light_red" => Some(Color::LightRed),
+        "light_green" => Some(Color::LightGreen),
+        "light_yellow" => Some(Color::LightYellow),
+        "light_blue" => Some(Color::LightBlue),
+        "light_magenta" => Some(Color::LightMagenta),
+        "light_cyan" => Some(Color::LightCyan),
+        "light_white" => Some(Color::White),
+        other => other.parse().ok(),
+    }
+}
@@ -0,0 +1,123 @@
+use crate::theme::Theme;
+use crate::{format_clock, wrap_words};
+use cmdhub_core::clock::now_epoch;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::Rect;
+use ratatui::style::Modifier;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use std::collections::VecDeque;
+
+/// At most this many errors wait to be read; older ones are dropped.
+const MAX_PENDING: usize = 20;
+
+/// An error that stopped an action but not the TUI.
+pub struct Toast {
+    /// What was being done, like `Cannot start deploy`.
+    pub summary: String,
+    pub message: String,
+    /// The error's causes, outermost first.
+    pub causes: Vec<String>,
+    pub at: u64,
+}
+
+impl Toast {
+    pub fn new(summary: impl Into<String>, err: &anyhow::Error) -> Self {
+        Self {
+            summary: summary.into(),
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(|cause| cause.to_string()).collect(),
+            at: now_epoch(),
+        }
+    }
+}
+
+/// Errors shown one at a time in a popup over the current view. While one
+/// is shown it takes every key: Enter or Esc dismisses it and `d` toggles
+/// its causes.
+#[derive(Default)]
+pub struct Toasts {
+    pending: VecDeque<Toast>,
+    details: bool,
+}
+
+impl Toasts {
+    pub fn push(&mut self, toast: Toast) {
+        log::warn!("{}: {}", toast.summary, toast.message);
+        // The same failure again, like a queued run retried every tick.
+        if self.pending.back().is_some_and(|last| last.summary == toast.summary && last.message == toast.message) {
+            return;
+        }
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(toast);
+    }
+
+    /// Returns false, leaving the key to the view, when nothing is shown.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.pending.is_empty() {
+            return false;
+        }
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => {
+                self.pending.pop_front();
+                self.details = false;
+            }
+            KeyCode::Char('D') => {
+                self.pending.clear();
+                self.details = false;
+            }
+            KeyCode::Char('d') => self.details = !self.details,
+            _ => {}
+        }
+        true
+    }
+
+    pub fn render(&self, frame: &mut ratatui::Frame, area: Rect, theme: &Theme) {
+        let toast = match self.pending.front() {
+            Some(toast) => toast,
+            None => return,
+        };
+        let width = area.width.saturating_sub(4).min(72);
+        let text_width = width.saturating_sub(2) as usize;
+        let mut lines = vec![Line::from(Span::styled(toast.summary.clone(), theme.error.add_modifier(Modifier::BOLD)))];
+        lines.extend(wrap_words(&toast.message, text_width).into_iter().map(Line::from));
+        if self.details {
+            for cause in &toast.causes {
+                lines.extend(
+                    wrap_words(&format!("caused by: {}", cause), text_width)
+                        .into_iter()
+                        .map(|line| Line::from(Span::styled(line, theme.muted))),
+                );
+            }
+        }
+        let mut hint = format!("at {}  Enter: dismiss", format_clock(toast.at));
+        if !toast.causes.is_empty() {
+            hint.push_str(if self.details { "  d: hide causes" } else { "  d: causes" });
+        }
+        if self.pending.len() > 1 {
+            hint.push_str("  D: dismiss all");
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(hint, theme.hint)));
+
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        let title = match self.pending.len() {
+            1 => "Error".to_string(),
+            count => format!("Error (1/{})", count),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, theme.title))
+            .border_style(theme.error);
+        frame.render_widget(Clear, popup);
+        frame.render_widget(Paragraph::new(lines).block(block), popup);
+    }
+}
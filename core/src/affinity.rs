@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Result};
+
+/// Parses a cpuset list like "0-3,6" into CPU indices.
+pub fn parse_cpuset(value: &str) -> Result<Vec<usize>> {
+    let max = libc::CPU_SETSIZE as usize;
+    let mut cpus = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (part, part),
+        };
+        let start: usize = start.parse().map_err(|_| anyhow!("invalid cpuset entry: {}", part))?;
+        let end: usize = end.parse().map_err(|_| anyhow!("invalid cpuset entry: {}", part))?;
+        if start > end || end >= max {
+            return Err(anyhow!("invalid cpuset range: {}", part));
+        }
+        cpus.extend(start..=end);
+    }
+    if cpus.is_empty() {
+        return Err(anyhow!("cpuset is empty"));
+    }
+    Ok(cpus)
+}
+
+/// Pins process `pid` to `cpus`; what it starts from then on inherits the mask.
+pub fn set_affinity(pid: u32, cpus: &[usize]) -> Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_setaffinity(pid as libc::pid_t, size, &set) } != 0 {
+        return Err(anyhow!("cannot apply cpuset: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpusets_parse_ranges_and_lists() {
+        assert_eq!(parse_cpuset("0-2, 5").unwrap(), vec![0, 1, 2, 5]);
+        assert!(parse_cpuset("3-1").is_err());
+        assert!(parse_cpuset(" , ").is_err());
+        assert!(parse_cpuset("x").is_err());
+    }
+
+    #[test]
+    fn the_mask_applies_to_the_child_only() {
+        let before = std::fs::read_to_string("/proc/self/status").unwrap();
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        set_affinity(child.id(), &[0]).unwrap();
+        let status = std::fs::read_to_string(format!("/proc/{}/status", child.id())).unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+        let allowed = |status: &str| {
+            status.lines().find(|line| line.starts_with("Cpus_allowed_list:")).map(str::to_string)
+        };
+        assert_eq!(allowed(&status).as_deref(), Some("Cpus_allowed_list:\t0"));
+        assert_eq!(allowed(&before), allowed(&std::fs::read_to_string("/proc/self/status").unwrap()));
+    }
+}
@@ -0,0 +1,117 @@
+/// Removes terminal escape sequences from output that may arrive in chunks:
+/// CSI (`ESC [`), OSC (`ESC ]`, ended by BEL or `ESC \`), DCS, SOS, PM and
+/// APC strings, and two-byte escapes. Control characters other than tab,
+/// newline and carriage return are dropped too. A sequence split across
+/// chunks is still removed whole.
+#[derive(Debug, Default)]
+pub struct AnsiStripper {
+    state: State,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Intermediate,
+    Csi,
+    /// The body of an OSC, DCS, SOS, PM or APC string.
+    Text,
+    /// `ESC` inside a string: `\` ends it, anything else starts a new escape.
+    TextEscape,
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+// CAN and SUB abort a sequence.
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+
+impl AnsiStripper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the visible bytes of `data` to `out`.
+    pub fn strip_into(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let mut i = 0;
+        while i < data.len() {
+            let b = data[i];
+            match self.state {
+                State::Ground => match b {
+                    ESC => self.state = State::Escape,
+                    b'\t' | b'\n' | b'\r' => out.push(b),
+                    0x00..=0x1f | 0x7f => {}
+                    _ => out.push(b),
+                },
+                State::Escape => {
+                    self.state = match b {
+                        b'[' => State::Csi,
+                        b']' | b'P' | b'X' | b'^' | b'_' => State::Text,
+                        0x20..=0x2f => State::Intermediate,
+                        ESC => State::Escape,
+                        // A control character ends the escape and is handled as usual.
+                        0x00..=0x1f => {
+                            self.state = State::Ground;
+                            continue;
+                        }
+                        _ => State::Ground,
+                    }
+                }
+                State::Intermediate => match b {
+                    0x20..=0x2f => {}
+                    ESC => self.state = State::Escape,
+                    _ => self.state = State::Ground,
+                },
+                State::Csi => match b {
+                    0x40..=0x7e | CAN | SUB => self.state = State::Ground,
+                    ESC => self.state = State::Escape,
+                    // Not part of any CSI: the sequence was cut short, keep the byte.
+                    0x80..=0xff => {
+                        self.state = State::Ground;
+                        continue;
+                    }
+                    _ => {}
+                },
+                State::Text => match b {
+                    BEL | CAN | SUB => self.state = State::Ground,
+                    ESC => self.state = State::TextEscape,
+                    _ => {}
+                },
+                State::TextEscape => {
+                    if b == b'\\' {
+                        self.state = State::Ground;
+                    } else {
+                        self.state = State::Escape;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+/// `data` without escape sequences.
+pub fn strip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    AnsiStripper::new().strip_into(data, &mut out);
+    out
+}
+
+/// `data` as plain text for grepping and diffing: escapes stripped, CRLF
+/// line endings turned into LF, and each line reduced to what was left
+/// after its last carriage-return redraw.
+pub fn plain_text(data: &[u8]) -> String {
+    let stripped = strip(data);
+    let text = String::from_utf8_lossy(&stripped);
+    let mut plain = String::with_capacity(text.len());
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            plain.push('\n');
+        }
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        plain.push_str(line.rsplit('\r').next().unwrap_or_default());
+    }
+    plain
+}
@@ -0,0 +1,178 @@
+use crate::models::{ContainerConfig, Task};
+use crate::template::shell_quote;
+use anyhow::{anyhow, Result};
+use portable_pty::ChildKiller;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const DEFAULT_RUNTIME: &str = "docker";
+
+/// Where a run's command executes. The PTY is always local; a backend only
+/// decides which process is started in it and how that process is stopped.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// The user's shell, kept open after the command.
+    Local,
+    Container(Container),
+}
+
+impl Backend {
+    /// `run_name` identifies the run inside the container runtime, so it
+    /// must be unique among live runs.
+    pub fn for_task(task: &Task, run_name: &str) -> Result<Self> {
+        match &task.container {
+            Some(config) => Ok(Backend::Container(Container::new(task, config, run_name)?)),
+            None => Ok(Backend::Local),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    /// `docker run` a fresh container from this image.
+    Image(String),
+    /// `docker exec` into this running container.
+    Exec(String),
+}
+
+/// A command run through docker or podman.
+#[derive(Debug, Clone)]
+pub struct Container {
+    runtime: String,
+    target: Target,
+    /// Container name for `run`, pid file name for `exec`.
+    name: String,
+    volumes: Vec<String>,
+    workdir: Option<String>,
+}
+
+impl Container {
+    fn new(task: &Task, config: &ContainerConfig, run_name: &str) -> Result<Self> {
+        let target = match (&config.image, &config.exec) {
+            (Some(image), None) => Target::Image(image.clone()),
+            (None, Some(container)) => Target::Exec(container.clone()),
+            _ => return Err(anyhow!("task {}: container needs exactly one of image or exec", task.id)),
+        };
+        if matches!(target, Target::Exec(_)) && config.volumes.as_ref().is_some_and(|volumes| !volumes.is_empty()) {
+            return Err(anyhow!("task {}: container volumes only apply to image, not exec", task.id));
+        }
+        let base = match &task.cwd {
+            Some(cwd) => cwd.clone(),
+            None => std::env::current_dir()?,
+        };
+        let volumes = config
+            .volumes
+            .iter()
+            .flatten()
+            .map(|volume| absolute_volume(volume, &base))
+            .collect();
+        let name: String = format!("cmdhub-{}", run_name)
+            .chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' { ch } else { '-' })
+            .collect();
+        Ok(Self {
+            runtime: config.runtime.clone().unwrap_or_else(|| DEFAULT_RUNTIME.to_string()),
+            target,
+            name,
+            volumes,
+            workdir: config.workdir.clone(),
+        })
+    }
+
+    pub fn runtime(&self) -> &str {
+        &self.runtime
+    }
+
+    /// Arguments to the runtime that run `command` under `sh -c`. `env` names
+    /// variables passed through from the runtime's own environment, so their
+    /// values stay out of the process list.
+    pub fn args<'a>(&self, command: &str, env: impl IntoIterator<Item = &'a String>, tty: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        match &self.target {
+            Target::Image(_) => args.extend(["run".to_string(), "--rm".to_string(), "--name".to_string(), self.name.clone()]),
+            Target::Exec(_) => args.push("exec".to_string()),
+        }
+        args.push(if tty { "-it" } else { "-i" }.to_string());
+        if let Some(workdir) = &self.workdir {
+            args.extend(["-w".to_string(), workdir.clone()]);
+        }
+        for volume in &self.volumes {
+            args.extend(["-v".to_string(), volume.clone()]);
+        }
+        for key in env {
+            args.extend(["-e".to_string(), key.clone()]);
+        }
+        match &self.target {
+            Target::Image(image) => args.extend([image.clone(), "sh".to_string(), "-c".to_string(), command.to_string()]),
+            Target::Exec(container) => {
+                // Record the command's pid so a kill can reach it; killing the
+                // exec client alone leaves the command running.
+                let script = format!("echo $$ > {}; exec sh -c {}", self.pid_file(), shell_quote(command));
+                args.extend([container.clone(), "sh".to_string(), "-c".to_string(), script]);
+            }
+        }
+        args
+    }
+
+    fn pid_file(&self) -> String {
+        format!("/tmp/.{}.pid", self.name)
+    }
+
+    /// Stops the command inside the container, then the local client.
+    pub fn killer(&self, client: Box<dyn ChildKiller + Send + Sync>) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(ContainerKiller { container: self.clone(), client })
+    }
+
+    fn stop(&self) -> io::Result<()> {
+        let mut cmd = Command::new(&self.runtime);
+        match &self.target {
+            Target::Image(_) => cmd.args(["kill", &self.name]),
+            Target::Exec(container) => {
+                let pid_file = self.pid_file();
+                let script = format!("kill -KILL \"$(cat {})\"; rm -f {}", pid_file, pid_file);
+                cmd.args(["exec", container, "sh", "-c", &script])
+            }
+        };
+        cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ContainerKiller {
+    container: Container,
+    client: Box<dyn ChildKiller + Send + Sync>,
+}
+
+impl ChildKiller for ContainerKiller {
+    fn kill(&mut self) -> io::Result<()> {
+        if let Err(err) = self.container.stop() {
+            log::warn!("{} could not stop {}: {}", self.container.runtime, self.container.name, err);
+        }
+        self.client.kill()
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(ContainerKiller {
+            container: self.container.clone(),
+            client: self.client.clone_killer(),
+        })
+    }
+}
+
+/// Resolves a relative host path in `host:container[:options]` against `base`;
+/// runtimes treat a bare name as a named volume, so only `./` and `../` move.
+fn absolute_volume(volume: &str, base: &Path) -> String {
+    match volume.split_once(':') {
+        Some((host, rest)) if host.starts_with("./") || host.starts_with("../") || host == "." => {
+            let host: PathBuf = match host.strip_prefix("./") {
+                Some(relative) => base.join(relative),
+                None if host == "." => base.to_path_buf(),
+                None => base.join(host),
+            };
+            format!("{}:{}", host.display(), rest)
+        }
+        _ => volume.to_string(),
+    }
+}
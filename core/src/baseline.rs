@@ -0,0 +1,138 @@
+use crate::lineprefix;
+use crate::models::Task;
+use crate::storage;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+const EXIT_MARKER: &[u8] = b"\x1b]0;CMDHUB:state=exited";
+
+/// How a run's output compared with its task's baseline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", rename_all = "lowercase")]
+pub enum BaselineResult {
+    Match,
+    /// The first differing line (1-based) and what each side had there.
+    Regression { line: usize, expected: String, actual: String },
+    /// The run printed more than `limit` bytes, so it was not compared.
+    TooLong { limit: usize },
+}
+
+impl BaselineResult {
+    pub fn summary(&self) -> String {
+        match self {
+            BaselineResult::Match => "matches baseline".to_string(),
+            BaselineResult::Regression { line, expected, actual } => {
+                format!("differs from baseline at line {}: expected {:?}, got {:?}", line, expected, actual)
+            }
+            BaselineResult::TooLong { limit } => format!("printed more than {} bytes, not compared with baseline", limit),
+        }
+    }
+}
+
+/// Normalized baseline outputs, one file per task under `baselines/` in the
+/// data directory.
+pub struct BaselineStore {
+    dir: PathBuf,
+}
+
+impl BaselineStore {
+    pub fn new() -> Result<Self> {
+        Ok(Self { dir: storage::data_dir()?.join("baselines") })
+    }
+
+    pub fn path(&self, task_id: &str) -> PathBuf {
+        let name: String = task_id
+            .chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' { ch } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.txt", name))
+    }
+
+    pub fn load(&self, task_id: &str) -> Result<Option<String>> {
+        let path = self.path(task_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?))
+    }
+
+    pub fn save(&self, task_id: &str, normalized: &str) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(task_id);
+        fs::write(&path, normalized)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+impl BaselineStore {
+    pub(crate) fn in_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+/// The task's `baseline_ignore` patterns, compiled.
+pub fn ignore_patterns(task: &Task) -> Result<Vec<Regex>> {
+    task.baseline_ignore
+        .iter()
+        .flatten()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|err| anyhow!("invalid baseline_ignore for task {}: {}", task.id, err))
+        })
+        .collect()
+}
+
+/// The command's own output in a PTY buffer: everything before the shell
+/// reports the exit and prints its prompt.
+pub fn run_output(buffer: &[u8]) -> &[u8] {
+    match buffer.windows(EXIT_MARKER.len()).position(|window| window == EXIT_MARKER) {
+        Some(pos) => &buffer[..pos],
+        None => buffer,
+    }
+}
+
+/// Strips escapes, timestamp prefixes and carriage-return redraws, trims
+/// trailing whitespace and masks `ignore` matches, so output compares equal across runs and terminals.
+pub fn normalize(output: &[u8], ignore: &[Regex]) -> String {
+    let plain = strip_ansi_escapes::strip(output);
+    let text = String::from_utf8_lossy(&plain);
+    let mut lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            let line = lineprefix::strip(line.strip_suffix('\r').unwrap_or(line));
+            let visible = line.rsplit('\r').next().unwrap_or_default();
+            let mut line = visible.trim_end().to_string();
+            for pattern in ignore {
+                line = pattern.replace_all(&line, "*").into_owned();
+            }
+            line
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+pub fn compare(expected: &str, actual: &str) -> BaselineResult {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return BaselineResult::Match,
+            (want, got) if want == got => continue,
+            (want, got) => {
+                return BaselineResult::Regression {
+                    line,
+                    expected: want.unwrap_or("<end of output>").to_string(),
+                    actual: got.unwrap_or("<end of output>").to_string(),
+                }
+            }
+        }
+    }
+}
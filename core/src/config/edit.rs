@@ -0,0 +1,119 @@
+//! Changes to config and task files that keep their comments and layout.
+
+use crate::models::Task;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// Appends `task` to `path` as a `[[tasks]]` table, creating the file if
+/// there is none.
+pub fn add_task(path: &Path, task: &Task) -> Result<()> {
+    let mut doc = read(path)?;
+    let mut table = toml_edit::ser::to_document(task)?.as_table().clone();
+    if !doc.to_string().trim().is_empty() {
+        table.decor_mut().set_prefix("\n");
+    }
+    tasks_mut(&mut doc, path)?.push(table);
+    write(path, &doc)
+}
+
+/// Removes task `id` from `path`, and the file once nothing else is left
+/// in it; false when the file does not declare the task.
+pub fn remove_task(path: &Path, id: &str) -> Result<bool> {
+    let mut doc = read(path)?;
+    let tasks = tasks_mut(&mut doc, path)?;
+    let index = match position(tasks, id) {
+        Some(index) => index,
+        None => return Ok(false),
+    };
+    tasks.remove(index);
+    if doc.to_string().trim().is_empty() {
+        std::fs::remove_file(path).map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+        return Ok(true);
+    }
+    write(path, &doc)?;
+    Ok(true)
+}
+
+/// Gives task `id` in `path` the id `new_id`, and `name` if set; false when
+/// the file does not declare the task.
+pub fn rename_task(path: &Path, id: &str, new_id: &str, name: Option<&str>) -> Result<bool> {
+    let mut doc = read(path)?;
+    let tasks = tasks_mut(&mut doc, path)?;
+    let table = match position(tasks, id).and_then(|index| tasks.get_mut(index)) {
+        Some(table) => table,
+        None => return Ok(false),
+    };
+    set_str(table, "id", new_id);
+    if let Some(name) = name {
+        set_str(table, "name", name);
+    }
+    write(path, &doc)?;
+    Ok(true)
+}
+
+/// Replaces `id` with `new_id` in the `members` of the groups in `path`;
+/// false when no group there lists it.
+pub fn rename_member(path: &Path, id: &str, new_id: &str) -> Result<bool> {
+    let mut doc = read(path)?;
+    let mut changed = false;
+    for table in tasks_mut(&mut doc, path)?.iter_mut() {
+        let members = match table.get_mut("members").and_then(Item::as_array_mut) {
+            Some(members) => members,
+            None => continue,
+        };
+        for member in members.iter_mut().filter(|member| member.as_str() == Some(id)) {
+            replace(member, new_id);
+            changed = true;
+        }
+    }
+    if changed {
+        write(path, &doc)?;
+    }
+    Ok(changed)
+}
+
+fn read(path: &Path) -> Result<DocumentMut> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(anyhow!("{}: {}", path.display(), err)),
+    };
+    content.parse().map_err(|err| anyhow!("{}: {}", path.display(), err))
+}
+
+fn write(path: &Path, doc: &DocumentMut) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, doc.to_string()).map_err(|err| anyhow!("{}: {}", path.display(), err))
+}
+
+fn tasks_mut<'a>(doc: &'a mut DocumentMut, path: &Path) -> Result<&'a mut ArrayOfTables> {
+    doc.as_table_mut()
+        .entry("tasks")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        // Such as `tasks = [...]` written inline.
+        .ok_or_else(|| anyhow!("{}: tasks is not written as [[tasks]] tables", path.display()))
+}
+
+fn position(tasks: &ArrayOfTables, id: &str) -> Option<usize> {
+    tasks.iter().position(|table| table.get("id").and_then(Item::as_str) == Some(id))
+}
+
+fn set_str(table: &mut Table, key: &str, value: &str) {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => replace(existing, value),
+        None => {
+            table.insert(key, toml_edit::value(value));
+        }
+    }
+}
+
+/// Keeps the comments and spacing around the value.
+fn replace(existing: &mut Value, value: &str) {
+    let decor = existing.decor().clone();
+    *existing = Value::from(value);
+    *existing.decor_mut() = decor;
+}
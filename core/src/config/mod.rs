@@ -1,23 +1,157 @@
-use crate::models::AppConfig;
+use crate::models::{AppConfig, Task};
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use tokio::fs;
 
+pub mod edit;
+
 const CONFIG_FILE_NAME: &str = "config.toml";
+const PROJECT_CONFIG_NAMES: &[&str] = &[".cmdhub/config.toml", "cmdhub.toml"];
 const TASKS_DIR_NAME: &str = "tasks";
+/// Config file to load instead of searching for one.
+pub const CONFIG_ENV: &str = "CMDHUB_CONFIG";
+/// Directory of task files to merge instead of `tasks/` next to the config.
+pub const TASKS_DIR_ENV: &str = "CMDHUB_TASKS_DIR";
+/// Name of the `[profiles]` entry applied to the tasks.
+pub const PROFILE_ENV: &str = "CMDHUB_PROFILE";
+
+/// Applies `--config`, `--tasks-dir` and `--profile` by exporting them (the
+/// paths made absolute) in `CMDHUB_CONFIG`, `CMDHUB_TASKS_DIR` and
+/// `CMDHUB_PROFILE`, so every later load in this process and its children
+/// uses them. Must be called before other threads start.
+pub fn set_overrides(config: Option<&Path>, tasks_dir: Option<&Path>, profile: Option<&str>) -> Result<()> {
+    for (key, path) in [(CONFIG_ENV, config), (TASKS_DIR_ENV, tasks_dir)] {
+        if let Some(path) = path {
+            std::env::set_var(key, std::path::absolute(path)?);
+        }
+    }
+    if let Some(profile) = profile {
+        std::env::set_var(PROFILE_ENV, profile);
+    }
+    Ok(())
+}
+
+/// The profile named in `CMDHUB_PROFILE`, if any.
+pub fn selected_profile() -> Option<String> {
+    std::env::var(PROFILE_ENV).ok().filter(|name| !name.is_empty())
+}
+
+/// The config's tasks with the env and input defaults of a profile applied
+/// over their own: the `selected` one, else the one their category names.
+pub fn profile_tasks(config: &AppConfig, selected: Option<&str>) -> Result<Vec<Task>> {
+    let find = |name: &str| {
+        config
+            .profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .ok_or_else(|| anyhow!("unknown profile {}", name))
+    };
+    if let Some(name) = selected {
+        find(name)?;
+    }
+    let mut tasks = config.tasks.clone();
+    for task in &mut tasks {
+        let category = task.category.as_ref().and_then(|name| config.categories.as_ref()?.get(name));
+        let name = match selected.or_else(|| category?.profile.as_deref()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let profile = find(name)?;
+        if let Some(env) = &profile.env {
+            task.env.get_or_insert_with(HashMap::new).extend(env.clone());
+        }
+        for (input, value) in profile.inputs.iter().flatten() {
+            if let Some(config) = task.inputs.as_mut().and_then(|inputs| inputs.get_mut(input)) {
+                config
+                    .set_default(value)
+                    .map_err(|err| anyhow!("profile {}: input {} of {}: {}", name, input, task.id, err))?;
+            }
+        }
+    }
+    Ok(tasks)
+}
 
+/// Loads `path` with its task files and the configs it `extends`.
 pub async fn load_config<P: AsRef<Path>>(path: P) -> Result<AppConfig> {
-    let content = fs::read_to_string(&path).await?;
+    let config = load_layers(path.as_ref().to_path_buf(), Vec::new()).await?;
+    finish(config)
+}
+
+/// Applies templates, then category defaults, once every layer is merged.
+fn finish(mut config: AppConfig) -> Result<AppConfig> {
+    apply_task_templates(&mut config)?;
+    apply_category_defaults(&mut config);
+    check_groups(&config)?;
+    check_category_profiles(&config)?;
+    Ok(config)
+}
+
+/// Loads each `extends` entry, resolved against the file's directory, and
+/// merges the file over them with [`merge_config`]. `chain` holds the files
+/// being loaded, so a cycle is an error instead of endless recursion.
+fn load_layers(path: PathBuf, mut chain: Vec<PathBuf>) -> Pin<Box<dyn Future<Output = Result<AppConfig>> + Send>> {
+    Box::pin(async move {
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if chain.contains(&key) {
+            return Err(anyhow!("extends cycle"));
+        }
+        chain.push(key);
+        let mut config = load_file(&path).await?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let mut merged: Option<AppConfig> = None;
+        for entry in config.extends.take().unwrap_or_default() {
+            let base_path = expand_path(&entry, base_dir);
+            let base = load_layers(base_path.clone(), chain.clone())
+                .await
+                .map_err(|err| anyhow!("{}: {}", base_path.display(), err))?;
+            merged = Some(match merged {
+                Some(merged) => merge_config(merged, base),
+                None => base,
+            });
+        }
+        Ok(match merged {
+            Some(base) => merge_config(base, config),
+            None => config,
+        })
+    })
+}
+
+/// `~/` is the home directory; other relative paths start at `base`.
+fn expand_path(entry: &str, base: &Path) -> PathBuf {
+    if let (Some(rest), Ok(home)) = (entry.strip_prefix("~/"), std::env::var("HOME")) {
+        return Path::new(&home).join(rest);
+    }
+    base.join(entry)
+}
+
+async fn load_file(path: &Path) -> Result<AppConfig> {
+    let content = fs::read_to_string(path).await?;
     let mut config: AppConfig = toml::from_str(&content)?;
-    
-    // Check for tasks directory relative to config file
-    if let Some(parent) = path.as_ref().parent() {
-        let tasks_dir = parent.join(TASKS_DIR_NAME);
+    for task in &mut config.tasks {
+        task.source = Some(path.to_path_buf());
+    }
+
+    // Merge the task files; only a missing $CMDHUB_TASKS_DIR is an error
+    let tasks_dir = tasks_dir(path);
+    if let Some(dir) = &tasks_dir {
+        if std::env::var_os(TASKS_DIR_ENV).is_some() && !dir.is_dir() {
+            return Err(anyhow!("tasks directory {} does not exist", dir.display()));
+        }
+    }
+    if let Some(tasks_dir) = tasks_dir {
         if tasks_dir.exists() && tasks_dir.is_dir() {
             let mut entries = fs::read_dir(tasks_dir).await?;
+            let mut paths = Vec::new();
             while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                if path.extension().map_or(false, |ext| ext == "toml") {
+                paths.push(entry.path());
+            }
+            // The directory's own order can change between loads.
+            paths.sort();
+            for path in paths {
+                if path.extension().is_some_and(|ext| ext == "toml") {
                     if let Ok(content) = fs::read_to_string(&path).await {
                         // We assume task files contain a [[tasks]] array or similar structure
                         // For simplicity, let's try to parse as AppConfig partial and merge tasks
@@ -28,7 +162,7 @@ pub async fn load_config<P: AsRef<Path>>(path: P) -> Result<AppConfig> {
                         
                         if let Ok(partial) = toml::from_str::<PartialConfig>(&content) {
                             if let Some(tasks) = partial.tasks {
-                                config.tasks.extend(tasks);
+                                config.tasks.extend(tasks.into_iter().map(|task| Task { source: Some(path.clone()), ..task }));
                             }
                         }
                     }
@@ -36,30 +170,264 @@ pub async fn load_config<P: AsRef<Path>>(path: P) -> Result<AppConfig> {
             }
         }
     }
-    
+
+    check_unique_ids(path, &config.tasks)?;
+
+    if let Some(parent) = path.parent() {
+        resolve_script_paths(&mut config, parent);
+    }
     Ok(config)
 }
 
+/// Runs, history and `cmdhub run` find tasks by id, so a file and its task
+/// files declare each id once. Across `extends` layers a later declaration
+/// still replaces an earlier one, see [`merge_config`].
+fn check_unique_ids(path: &Path, tasks: &[Task]) -> Result<()> {
+    let mut seen: HashMap<&str, &Path> = HashMap::new();
+    for task in tasks {
+        let source = task.source.as_deref().unwrap_or(path);
+        match seen.insert(&task.id, source) {
+            Some(first) if first == source => {
+                return Err(anyhow!("task id {} is declared twice in {}", task.id, source.display()))
+            }
+            Some(first) => {
+                return Err(anyhow!(
+                    "task id {} is declared in both {} and {}",
+                    task.id,
+                    first.display(),
+                    source.display()
+                ))
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Where the task files merged into the config at `path` live:
+/// `$CMDHUB_TASKS_DIR`, or `tasks/` next to a `config.toml`. Other
+/// configs, like a project's `cmdhub.toml`, have none.
+pub fn tasks_dir(path: &Path) -> Option<PathBuf> {
+    match std::env::var_os(TASKS_DIR_ENV) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None if path.file_name().is_some_and(|name| name == CONFIG_FILE_NAME) => {
+            path.parent().map(|parent| parent.join(TASKS_DIR_NAME))
+        }
+        None => None,
+    }
+}
+
+fn resolve_script_paths(config: &mut AppConfig, base: &Path) {
+    for task in &mut config.tasks {
+        if let Some(script) = task.script.as_mut().filter(|script| script.is_relative()) {
+            *script = base.join(&*script);
+        }
+    }
+}
+
+fn apply_task_templates(config: &mut AppConfig) -> Result<()> {
+    let templates = config.task_templates.as_ref();
+    for task in &mut config.tasks {
+        let name = match &task.template {
+            Some(name) => name,
+            None => continue,
+        };
+        let template = templates
+            .and_then(|templates| templates.get(name))
+            .ok_or_else(|| anyhow!("task {}: unknown template {}", task.id, name))?;
+        if let Some(prefix) = &template.command_prefix {
+            task.command = format!("{} {}", prefix, task.command);
+        }
+        if task.cwd.is_none() {
+            task.cwd = template.cwd.clone();
+        }
+        if let Some(base) = &template.env {
+            let mut env = base.clone();
+            env.extend(task.env.take().unwrap_or_default());
+            task.env = Some(env);
+        }
+        if let Some(base) = &template.inputs {
+            let mut inputs = base.clone();
+            inputs.extend(task.inputs.take().unwrap_or_default());
+            task.inputs = Some(inputs);
+        }
+    }
+    Ok(())
+}
+
+/// Group members must be configured tasks with commands of their own.
+fn check_groups(config: &AppConfig) -> Result<()> {
+    for group in &config.tasks {
+        for id in group.members.iter().flatten() {
+            match config.tasks.iter().find(|task| &task.id == id) {
+                None => return Err(anyhow!("group {}: unknown member {}", group.id, id)),
+                Some(task) if task.members.is_some() => {
+                    return Err(anyhow!("group {}: member {} is itself a group", group.id, id))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_category_profiles(config: &AppConfig) -> Result<()> {
+    for (name, category) in config.categories.iter().flatten() {
+        if let Some(profile) = &category.profile {
+            if !config.profiles.as_ref().is_some_and(|profiles| profiles.contains_key(profile)) {
+                return Err(anyhow!("category {}: unknown profile {}", name, profile));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_category_defaults(config: &mut AppConfig) {
+    let categories = match &config.categories {
+        Some(categories) => categories,
+        None => return,
+    };
+    for task in &mut config.tasks {
+        let defaults = match task.category.as_ref().and_then(|name| categories.get(name)) {
+            Some(defaults) => defaults,
+            None => continue,
+        };
+        if task.cwd.is_none() {
+            task.cwd = defaults.cwd.clone();
+        }
+        if task.env_clear.is_none() {
+            task.env_clear = defaults.env_clear;
+        }
+        if let Some(base) = &defaults.env {
+            let mut env = base.clone();
+            env.extend(task.env.take().unwrap_or_default());
+            task.env = Some(env);
+        }
+    }
+}
+
+/// Loads every config from [`resolve_config_paths`], each later one merged
+/// over the ones before it.
 pub async fn load_config_auto() -> Result<AppConfig> {
-    let path = resolve_config_path()?;
-    load_config(path).await
+    let mut merged: Option<AppConfig> = None;
+    for path in resolve_config_paths()? {
+        let config = load_layers(path.clone(), Vec::new())
+            .await
+            .map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+        merged = Some(match merged {
+            Some(base) => merge_config(base, config),
+            None => config,
+        });
+    }
+    finish(merged.ok_or_else(|| anyhow!("no config file found"))?)
+}
+
+/// Lays `over` on top of `base`: its tasks replace those with the same id
+/// and new ones are appended, and each section it sets replaces the base one.
+pub fn merge_config(mut base: AppConfig, over: AppConfig) -> AppConfig {
+    for task in over.tasks {
+        match base.tasks.iter_mut().find(|existing| existing.id == task.id) {
+            Some(existing) => *existing = task,
+            None => base.tasks.push(task),
+        }
+    }
+    let categories = merge_tables(base.categories, over.categories);
+    let task_templates = merge_tables(base.task_templates, over.task_templates);
+    let profiles = merge_tables(base.profiles, over.profiles);
+    AppConfig {
+        extends: None,
+        tasks: base.tasks,
+        history_limit: over.history_limit.or(base.history_limit),
+        ui: over.ui.or(base.ui),
+        theme: over.theme.or(base.theme),
+        keys: over.keys.or(base.keys),
+        receipts: over.receipts.or(base.receipts),
+        notifications: over.notifications.or(base.notifications),
+        attach: over.attach.or(base.attach),
+        metrics: over.metrics.or(base.metrics),
+        snapshots: over.snapshots.or(base.snapshots),
+        categories,
+        task_templates,
+        recording: over.recording.or(base.recording),
+        logs: over.logs.or(base.logs),
+        supervisor: over.supervisor.or(base.supervisor),
+        store: over.store.or(base.store),
+        profiles,
+    }
 }
 
+fn merge_tables<V>(base: Option<HashMap<String, V>>, over: Option<HashMap<String, V>>) -> Option<HashMap<String, V>> {
+    match (base, over) {
+        (Some(mut base), Some(over)) => {
+            base.extend(over);
+            Some(base)
+        }
+        (base, over) => over.or(base),
+    }
+}
+
+/// The config that takes precedence: the last of [`resolve_config_paths`].
 pub fn resolve_config_path() -> Result<PathBuf> {
+    let mut paths = resolve_config_paths()?;
+    paths.pop().ok_or_else(|| anyhow!("no config file found"))
+}
+
+/// Config files to load, lowest precedence first. `$CMDHUB_CONFIG` is used
+/// alone. Otherwise the first user config found is followed by the nearest
+/// project config, `.cmdhub/config.toml` or `cmdhub.toml` in the working
+/// directory or one of its parents.
+pub fn resolve_config_paths() -> Result<Vec<PathBuf>> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        let path = PathBuf::from(path);
+        if !path.exists() {
+            return Err(anyhow!("config file {} does not exist", path.display()));
+        }
+        return Ok(vec![path]);
+    }
     let candidates = config_candidates();
-    for path in &candidates {
-        if path.exists() {
-            return Ok(path.clone());
+    let mut paths: Vec<PathBuf> = candidates.iter().find(|path| path.exists()).cloned().into_iter().collect();
+    if let Some(project) = find_project_config() {
+        if !paths.iter().any(|path| same_file(path, &project)) {
+            paths.push(project);
         }
     }
+    if !paths.is_empty() {
+        return Ok(paths);
+    }
     let searched = candidates
         .iter()
         .map(|path| path.display().to_string())
+        .chain(PROJECT_CONFIG_NAMES.iter().map(|name| format!("{} in parent directories", name)))
         .collect::<Vec<_>>()
         .join(", ");
     Err(anyhow!("config.toml not found; searched: {}", searched))
 }
 
+/// Walks up from the working directory like git does, stopping below HOME,
+/// whose `.cmdhub` holds the user config and CmdHub's state.
+fn find_project_config() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    for dir in cwd.ancestors() {
+        if home.as_deref() == Some(dir) {
+            break;
+        }
+        for name in PROJECT_CONFIG_NAMES {
+            let path = dir.join(name);
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
 fn config_candidates() -> Vec<PathBuf> {
     let mut paths = Vec::new();
 
@@ -83,3 +451,65 @@ fn config_candidates() -> Vec<PathBuf> {
 
     paths
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvValue;
+
+    fn config(toml: &str) -> AppConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    const CONFIG: &str = r#"
+        [[tasks]]
+        id = "api"
+        name = "API"
+        command = "run-api"
+        category = "Backend"
+
+        [[tasks]]
+        id = "web"
+        name = "Web"
+        command = "run-web"
+
+        [categories.Backend]
+        profile = "staging"
+
+        [profiles.staging]
+        env = { API_URL = "https://staging.example.com" }
+
+        [profiles.prod]
+        env = { API_URL = "https://example.com" }
+    "#;
+
+    fn api_url(tasks: &[Task], id: &str) -> Option<String> {
+        let task = tasks.iter().find(|task| task.id == id)?;
+        match task.env.as_ref()?.get("API_URL")? {
+            EnvValue::Value(value) => Some(value.clone()),
+            EnvValue::Secret { .. } => None,
+        }
+    }
+
+    #[test]
+    fn categories_pick_the_profile_while_none_is_selected() {
+        let tasks = profile_tasks(&config(CONFIG), None).unwrap();
+        assert_eq!(api_url(&tasks, "api").as_deref(), Some("https://staging.example.com"));
+        assert_eq!(api_url(&tasks, "web"), None);
+    }
+
+    #[test]
+    fn the_selected_profile_wins_over_the_category_one() {
+        let tasks = profile_tasks(&config(CONFIG), Some("prod")).unwrap();
+        assert_eq!(api_url(&tasks, "api").as_deref(), Some("https://example.com"));
+        assert_eq!(api_url(&tasks, "web").as_deref(), Some("https://example.com"));
+        assert!(profile_tasks(&config(CONFIG), Some("qa")).is_err());
+    }
+
+    #[test]
+    fn categories_must_name_a_configured_profile() {
+        let config = config(&CONFIG.replace("profile = \"staging\"", "profile = \"qa\""));
+        let err = check_category_profiles(&config).unwrap_err().to_string();
+        assert_eq!(err, "category Backend: unknown profile qa");
+    }
+}
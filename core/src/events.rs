@@ -0,0 +1,121 @@
+use crate::instance::InstanceInfo;
+use serde::Serialize;
+use std::sync::{mpsc, Arc, Mutex};
+use tokio::sync::broadcast;
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+pub type EventReceiver = broadcast::Receiver<SessionEvent>;
+/// Gets every start and exit, however much output is published between them.
+pub type LifecycleReceiver = mpsc::Receiver<SessionEvent>;
+
+/// Lifecycle and output events published by the session manager.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum SessionEvent {
+    TaskStarted { info: InstanceInfo },
+    OutputChunk { instance_id: String, data: Vec<u8> },
+    TaskExited { info: InstanceInfo },
+    SessionAttached { instance_id: String },
+    SessionDetached { instance_id: String },
+}
+
+impl SessionEvent {
+    pub fn instance_id(&self) -> &str {
+        match self {
+            SessionEvent::TaskStarted { info } | SessionEvent::TaskExited { info } => &info.id,
+            SessionEvent::OutputChunk { instance_id, .. }
+            | SessionEvent::SessionAttached { instance_id }
+            | SessionEvent::SessionDetached { instance_id } => instance_id,
+        }
+    }
+
+    fn is_lifecycle(&self) -> bool {
+        matches!(self, SessionEvent::TaskStarted { .. } | SessionEvent::TaskExited { .. })
+    }
+}
+
+/// Broadcast channel shared by all clones of a session manager. Receivers
+/// that fall more than the channel capacity behind see `RecvError::Lagged`
+/// and skip ahead; attach clients that need every byte use
+/// `SessionManager::subscribe_output` instead, and consumers that must see
+/// every start and exit use [`EventBus::subscribe_lifecycle`].
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<SessionEvent>,
+    lifecycle: Arc<Mutex<Vec<mpsc::Sender<SessionEvent>>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            tx,
+            lifecycle: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn publish(&self, event: SessionEvent) {
+        if event.is_lifecycle() {
+            if let Ok(mut senders) = self.lifecycle.lock() {
+                senders.retain(|tx| tx.send(event.clone()).is_ok());
+            }
+        }
+        // Sending only fails when nobody is listening.
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> EventReceiver {
+        self.tx.subscribe()
+    }
+
+    /// An unbounded receiver of `TaskStarted` and `TaskExited` only. It
+    /// disconnects once every clone of the bus is dropped.
+    pub fn subscribe_lifecycle(&self) -> LifecycleReceiver {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut senders) = self.lifecycle.lock() {
+            senders.push(tx);
+        }
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::InstanceStatus;
+
+    #[test]
+    fn lifecycle_events_survive_a_lagging_bus() {
+        let bus = EventBus::new(4);
+        let mut output = bus.subscribe();
+        let lifecycle = bus.subscribe_lifecycle();
+        bus.publish(SessionEvent::TaskStarted { info: InstanceInfo::for_test("build", InstanceStatus::Running) });
+        for _ in 0..100 {
+            bus.publish(SessionEvent::OutputChunk { instance_id: "build#1".to_string(), data: vec![b'x'; 8192] });
+        }
+        bus.publish(SessionEvent::TaskExited { info: InstanceInfo::for_test("build", InstanceStatus::Exited(1)) });
+
+        assert!(matches!(output.try_recv(), Err(broadcast::error::TryRecvError::Lagged(_))));
+        assert!(matches!(lifecycle.try_recv(), Ok(SessionEvent::TaskStarted { .. })));
+        match lifecycle.try_recv() {
+            Ok(SessionEvent::TaskExited { info }) => assert_eq!(info.status, InstanceStatus::Exited(1)),
+            other => panic!("expected the exit, got {:?}", other),
+        }
+        assert!(lifecycle.try_recv().is_err());
+    }
+
+    #[test]
+    fn lifecycle_receiver_disconnects_with_the_bus() {
+        let bus = EventBus::default();
+        let lifecycle = bus.subscribe_lifecycle();
+        drop(bus);
+        assert_eq!(lifecycle.recv().err(), Some(mpsc::RecvError));
+    }
+}
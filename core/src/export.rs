@@ -0,0 +1,164 @@
+use crate::recording::{cast_output, RecordingStore};
+use crate::session::{SessionInfo, SessionStore};
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Serializer};
+use std::io::Write;
+
+/// A run's metadata, output and timing, gathered for a bug report or a CI
+/// artifact.
+#[derive(Debug, Serialize)]
+pub struct SessionExport {
+    pub meta: SessionInfo,
+    /// `output.log`, written when the run last finished.
+    #[serde(serialize_with = "lossy")]
+    pub output: Vec<u8>,
+    /// The output of each start of the run, from its recordings.
+    pub runs: Vec<RunLog>,
+    pub timing: Timing,
+    #[serde(skip)]
+    casts: Vec<Vec<u8>>,
+    #[serde(skip)]
+    events: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunLog {
+    pub name: String,
+    pub output: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Timing {
+    pub started_at: u64,
+    pub ended_at: Option<u64>,
+    pub duration_secs: Option<u64>,
+    pub exit_code: Option<u32>,
+    pub restarts: u32,
+    pub runs: Vec<RunTiming>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunTiming {
+    pub name: String,
+    pub started_at: u64,
+    pub duration_secs: f64,
+}
+
+impl SessionExport {
+    /// Reads the output of the session `query` names, as matched by
+    /// [`SessionStore::find_session`], and the recordings of its run.
+    pub fn collect(store: &SessionStore, recordings: &RecordingStore, query: &str) -> Result<Self> {
+        let meta = store.find_session(query)?;
+        let output = store.read_session_log(meta.id)?;
+        let mut runs = Vec::new();
+        let mut timings = Vec::new();
+        let mut casts = Vec::new();
+        if let Some(instance_id) = &meta.session_name {
+            for (index, path) in recordings.matching(instance_id)?.iter().enumerate() {
+                let (header, duration, text) = cast_output(path)?;
+                let name = format!("{}", index + 1);
+                timings.push(RunTiming { name: name.clone(), started_at: header.timestamp, duration_secs: duration });
+                runs.push(RunLog { name, output: text });
+                casts.push(std::fs::read(path)?);
+            }
+        }
+        let timing = Timing {
+            started_at: meta.started_at,
+            ended_at: meta.ended_at,
+            duration_secs: meta.duration_secs.or_else(|| meta.ended_at.map(|ended| ended.saturating_sub(meta.started_at))),
+            exit_code: meta.exit_code,
+            restarts: meta.restarts,
+            runs: timings,
+        };
+        let events = store.read_session_events(meta.id)?;
+        Ok(Self { meta, output, runs, timing, casts, events })
+    }
+
+    /// Directory the archive's files sit under, like `build-3`.
+    pub fn archive_root(&self) -> String {
+        match (&self.meta.session_name, self.meta.seq) {
+            (Some(name), _) => name.replace(['#', '/'], "-"),
+            (None, Some(seq)) => format!("{}-{}", self.meta.task_id, seq),
+            (None, None) => self.meta.id.to_string(),
+        }
+    }
+
+    pub fn write_json<W: Write>(&self, out: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes a tar archive of `meta.json`, `output.log`, `timing.json`, the
+    /// run's `events.jsonl`, and a `.log` and `.cast` per start of the run
+    /// under `runs/`.
+    pub fn write_tar<W: Write>(&self, out: &mut W) -> Result<()> {
+        let root = self.archive_root();
+        let mtime = self.meta.ended_at.unwrap_or(self.meta.started_at);
+        let mut tar = TarWriter { out, mtime };
+        tar.file(&format!("{}/meta.json", root), &serde_json::to_vec_pretty(&self.meta)?)?;
+        tar.file(&format!("{}/output.log", root), &self.output)?;
+        tar.file(&format!("{}/timing.json", root), &serde_json::to_vec_pretty(&self.timing)?)?;
+        if let Some(events) = &self.events {
+            tar.file(&format!("{}/events.jsonl", root), events)?;
+        }
+        for (run, cast) in self.runs.iter().zip(&self.casts) {
+            tar.file(&format!("{}/runs/{}.log", root, run.name), run.output.as_bytes())?;
+            tar.file(&format!("{}/runs/{}.cast", root, run.name), cast)?;
+        }
+        tar.finish()
+    }
+}
+
+fn lossy<S: Serializer>(bytes: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(bytes))
+}
+
+const BLOCK: usize = 512;
+
+/// Just enough of the ustar format for regular files.
+struct TarWriter<'a, W: Write> {
+    out: &'a mut W,
+    mtime: u64,
+}
+
+impl<W: Write> TarWriter<'_, W> {
+    fn file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        if name.len() > 100 {
+            return Err(anyhow!("{}: name too long for a tar archive", name));
+        }
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // The checksum is computed with its own field filled with spaces.
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&byte| byte as u64).sum();
+        octal(&mut header[148..155], sum);
+        self.out.write_all(&header)?;
+        self.out.write_all(data)?;
+        let padding = (BLOCK - data.len() % BLOCK) % BLOCK;
+        self.out.write_all(&[0u8; BLOCK][..padding])?;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        self.out.write_all(&[0u8; BLOCK * 2])?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Zero-padded octal followed by a NUL, filling `field`.
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
@@ -1,21 +1,62 @@
-use crate::models::Task;
+use crate::affinity::{parse_cpuset, set_affinity};
+use crate::backend::Backend;
+use crate::baseline::{self, BaselineResult, BaselineStore};
+use crate::clock::now_epoch;
+use crate::events::{EventBus, EventReceiver, SessionEvent};
+use crate::lineprefix::LinePrefixer;
+use crate::logevents::LogEventWriter;
+use crate::logsink::{parse_targets, LineTee};
+use crate::metrics::Metrics;
+use crate::pty::{dup_master, pty_size, PtyReader, PtyWriter};
+use crate::models::{AppConfig, EnvValue, RestartPolicy, Task, UniqueScope};
+use crate::notify::{LifecycleEvent, WebhookNotifier};
+use crate::receipt::{ReceiptDraft, ReceiptLog};
+use crate::recording::{Recorder, RecordingStore};
+use crate::registry::{running_elsewhere_message, RunRegistry};
+use crate::secrets;
+use crate::session::{SessionInfo, SessionStore};
+use crate::snapshot::SnapshotStore;
+use crate::streams;
+use crate::template::prepare_cwd;
+use crate::terminate::{terminate, Termination};
 use anyhow::{anyhow, Result};
-use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
-use std::collections::{HashMap, VecDeque};
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize};
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
 use std::io::Write;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
 use std::sync::{Arc, Mutex};
 use std::sync::OnceLock;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use uuid::Uuid;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum InstanceStatus {
     Running,
     Exited(u32),
     Error(String),
+    /// Stopped by an explicit cancel rather than failing on its own.
+    Cancelled,
 }
 
-#[derive(Debug, Clone)]
+impl InstanceStatus {
+    pub fn exit_code(&self) -> Option<u32> {
+        match self {
+            InstanceStatus::Exited(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Snapshot of one running or finished task instance.
+#[derive(Debug, Clone, Serialize)]
 pub struct InstanceInfo {
     pub id: String,
     pub task_id: String,
@@ -25,8 +66,152 @@ pub struct InstanceInfo {
     pub ended_at: Option<u64>,
     pub child_pid: Option<u32>,
     pub title: Option<String>,
+    pub progress: Option<RunProgress>,
+    pub snapshot: Option<PathBuf>,
+    /// The PTY's process has exited, so the run can be reviewed but not attached.
+    pub closed: bool,
+    /// Environment set at launch for this run only, on top of the task's
+    /// `env`. Kept out of API and webhook payloads, since it may hold secrets.
+    #[serde(skip)]
+    pub env_overrides: BTreeMap<String, String>,
+    /// Set when the run finished and its task has a baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BaselineResult>,
+    /// Per-task run number in `id`, fixed at spawn and unique across CmdHub
+    /// processes sharing a session store.
+    pub seq: u32,
+    /// How many times the command had been restarted before this run, by the
+    /// task's restart policy or the supervisor.
+    pub restarts: u32,
+    /// When the restart policy will relaunch the command, while it waits to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_at: Option<u64>,
+    /// The task's tags and those given at launch.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Name the user gave the run, like `third migration attempt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Free-form note the user attached to the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Id of the group task that started the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[cfg(test)]
+impl InstanceInfo {
+    /// Run `task_id#1`, started at 1000.
+    pub(crate) fn for_test(task_id: &str, status: InstanceStatus) -> Self {
+        Self {
+            id: format!("{}#1", task_id),
+            task_id: task_id.to_string(),
+            task_name: task_id.to_string(),
+            status,
+            started_at: 1000,
+            ended_at: None,
+            child_pid: None,
+            title: None,
+            progress: None,
+            snapshot: None,
+            closed: false,
+            env_overrides: BTreeMap::new(),
+            baseline: None,
+            seq: 1,
+            restarts: 0,
+            restart_at: None,
+            tags: Vec::new(),
+            label: None,
+            note: None,
+            group: None,
+        }
+    }
+}
+
+/// Per-run changes made at launch on top of the task's config, from
+/// `cmdhub run` flags or the input form's advanced options.
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub shell: Option<String>,
+    pub login_profile: Option<bool>,
+    /// Zero turns off the task's timeout.
+    pub timeout_secs: Option<u64>,
+    pub pty: Option<bool>,
+    /// Added to the task's `tags`.
+    pub tags: Vec<String>,
+    /// Group task the run is started for, kept in [`InstanceInfo::group`].
+    pub group: Option<String>,
+}
+
+impl LaunchOptions {
+    /// The task as it runs with these overrides.
+    pub fn apply(&self, task: &Task) -> Task {
+        let mut task = task.clone();
+        if !self.env.is_empty() {
+            let mut env = task.env.take().unwrap_or_default();
+            env.extend(self.env.iter().map(|(key, value)| (key.clone(), EnvValue::from(value.clone()))));
+            task.env = Some(env);
+        }
+        if let Some(cwd) = &self.cwd {
+            task.cwd = Some(cwd.clone());
+        }
+        if let Some(shell) = &self.shell {
+            task.shell = Some(shell.clone());
+        }
+        if let Some(login) = self.login_profile {
+            task.login_profile = Some(login);
+        }
+        if let Some(secs) = self.timeout_secs {
+            task.timeout_secs = Some(secs).filter(|secs| *secs > 0);
+        }
+        if let Some(pty) = self.pty {
+            task.pty = Some(pty);
+        }
+        if !self.tags.is_empty() {
+            let mut tags = task.tags.take().unwrap_or_default();
+            for tag in &self.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+            task.tags = Some(tags);
+        }
+        task
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunProgress {
+    pub phase: Option<String>,
+    pub percent: Option<u8>,
+    pub message: Option<String>,
+}
+
+impl RunProgress {
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(phase) = &self.phase {
+            parts.push(format!("[{}]", phase));
+        }
+        if let Some(percent) = self.percent {
+            parts.push(format!("{}%", percent));
+        }
+        if let Some(message) = &self.message {
+            parts.push(message.clone());
+        }
+        parts.join(" ")
+    }
 }
 
+pub type PtyHandles = (Box<dyn MasterPty + Send>, Box<dyn Write + Send>);
+
+/// Raw PTY output chunks for one subscriber, in the order they were read.
+pub type OutputStream = Receiver<Vec<u8>>;
+
+/// A freshly spawned instance whose PTY handles are still owned by the caller.
 pub struct SpawnedInstance {
     pub info: InstanceInfo,
     pub master: Box<dyn MasterPty + Send>,
@@ -36,6 +221,8 @@ pub struct SpawnedInstance {
 struct RingBuffer {
     buf: VecDeque<u8>,
     cap: usize,
+    /// Total bytes ever pushed; the buffer holds `written - buf.len()..written`.
+    written: u64,
 }
 
 impl RingBuffer {
@@ -43,6 +230,7 @@ impl RingBuffer {
         Self {
             buf: VecDeque::with_capacity(cap),
             cap,
+            written: 0,
         }
     }
 
@@ -50,6 +238,7 @@ impl RingBuffer {
         if data.is_empty() {
             return;
         }
+        self.written += data.len() as u64;
         if data.len() >= self.cap {
             self.buf.clear();
             let start = data.len() - self.cap;
@@ -65,6 +254,109 @@ impl RingBuffer {
     fn snapshot(&self) -> Vec<u8> {
         self.buf.iter().copied().collect()
     }
+
+    /// Bytes from stream offset `offset` on. If `offset` has been trimmed
+    /// away the whole buffer is returned.
+    fn since(&self, offset: u64) -> BufferChunk {
+        let retained_from = self.written - self.buf.len() as u64;
+        let offset = offset.clamp(retained_from, self.written);
+        let skip = (offset - retained_from) as usize;
+        BufferChunk {
+            retained_from,
+            offset,
+            bytes: self.buf.iter().skip(skip).copied().collect(),
+        }
+    }
+}
+
+/// Output read from a run's buffer by stream offset.
+#[derive(Debug, Clone, Default)]
+pub struct BufferChunk {
+    /// Oldest offset still held; everything before it has been trimmed.
+    pub retained_from: u64,
+    /// Offset `bytes` starts at.
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+}
+
+const DEFAULT_SUBSCRIBER_CAPACITY: usize = 256;
+// Output already waiting in the PTY is read into one chunk of up to this
+// size, so a flood becomes few large events instead of many 4KB ones.
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// What happens when an attached client's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop chunks for that client and tell it how many bytes it missed.
+    #[default]
+    Drop,
+    /// Pause the PTY reader until the client catches up.
+    Block,
+}
+
+impl OverflowPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "drop" => Some(OverflowPolicy::Drop),
+            "block" => Some(OverflowPolicy::Block),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SubscribeOptions {
+    pub capacity: usize, // chunks queued per client
+    pub policy: OverflowPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_SUBSCRIBER_CAPACITY,
+            policy: OverflowPolicy::Drop,
+        }
+    }
+}
+
+type BlockedSend = (SyncSender<Vec<u8>>, Vec<u8>);
+
+struct Subscriber {
+    tx: SyncSender<Vec<u8>>,
+    policy: OverflowPolicy,
+    dropped: usize,
+}
+
+impl Subscriber {
+    /// Queues a chunk without blocking; returns false once the client is gone.
+    /// Chunks that must wait under `OverflowPolicy::Block` are pushed to `blocked`.
+    fn offer(&mut self, data: &[u8], blocked: &mut Vec<BlockedSend>) -> bool {
+        if self.dropped > 0 {
+            let marker = format!(
+                "\r\n\x1b[33m[cmdhub: {} bytes dropped, client too slow]\x1b[0m\r\n",
+                self.dropped
+            );
+            match self.tx.try_send(marker.into_bytes()) {
+                Ok(()) => self.dropped = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += data.len();
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        match self.tx.try_send(data.to_vec()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(chunk)) => {
+                match self.policy {
+                    OverflowPolicy::Drop => self.dropped += chunk.len(),
+                    OverflowPolicy::Block => blocked.push((self.tx.clone(), chunk)),
+                }
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
 }
 
 struct InstanceEntry {
@@ -72,15 +364,206 @@ struct InstanceEntry {
     killer: Box<dyn ChildKiller + Send + Sync>,
     buffer: RingBuffer,
     osc_parser: OscParser,
+    status_parser: Option<StatusParser>,
+    subscribers: Vec<Subscriber>,
     master: Option<Box<dyn MasterPty + Send>>,
     writer: Option<Box<dyn Write + Send>>,
+    cancel: CancelSpec,
+    cancel_requested: bool,
+    termination: Termination,
+    /// The run's shell leads its own session and process group, which the
+    /// command and whatever it forks share unless they start their own.
+    pgid: Option<u32>,
+    baseline: BaselineCheck,
+    /// Written once the command stops, on its exit title or its shell's exit.
+    receipt: Option<ReceiptDraft>,
+    backend: Backend,
+    /// Taken once the run stops, so it is restarted at most once.
+    restart: Option<RestartSpec>,
+    /// Dropped with the entry, which stops its output reader.
+    _shutdown: watch::Sender<()>,
+    /// Keeps the PTY open while the master is lent to a client or a kill
+    /// waits out its grace period, so the command is not hung up early.
+    _pty: OwnedFd,
+}
+
+/// Most raw output kept for a baseline comparison; a longer run is reported
+/// as too long rather than held in memory.
+const BASELINE_CAPTURE_LIMIT: usize = 8 * 1024 * 1024;
+
+/// What a run's output is compared against once it exits.
+#[derive(Default)]
+struct BaselineCheck {
+    expected: Option<String>,
+    ignore: Vec<Regex>,
+    /// Everything the run printed so far, up to [`BASELINE_CAPTURE_LIMIT`];
+    /// `None` when the task has no baseline to compare with.
+    captured: Option<Vec<u8>>,
+    /// Set once the output outgrew the limit and capturing stopped.
+    overflowed: bool,
+    /// The command's output, cut from `captured` when it exits.
+    output: Option<Vec<u8>>,
+}
+
+impl BaselineCheck {
+    fn for_task(task: &Task, store: Option<&BaselineStore>) -> Result<Self> {
+        let expected = match store {
+            Some(store) => store.load(&task.id)?,
+            None => None,
+        };
+        Ok(Self {
+            captured: expected.as_ref().map(|_| Vec::new()),
+            expected,
+            ignore: baseline::ignore_patterns(task)?,
+            overflowed: false,
+            output: None,
+        })
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        if let Some(captured) = self.captured.as_mut() {
+            if captured.len() + data.len() > BASELINE_CAPTURE_LIMIT {
+                self.captured = None;
+                self.overflowed = true;
+            } else {
+                captured.extend_from_slice(data);
+            }
+        }
+    }
+
+    /// Stops capturing and returns the comparison, the first time only.
+    fn finish(&mut self) -> Option<BaselineResult> {
+        if self.output.is_some() {
+            return None;
+        }
+        let mut output = self.captured.take().unwrap_or_default();
+        output.truncate(baseline::run_output(&output).len());
+        self.output = Some(output);
+        if self.overflowed {
+            return Some(BaselineResult::TooLong { limit: BASELINE_CAPTURE_LIMIT });
+        }
+        let output = self.output.as_deref().unwrap_or_default();
+        self.expected
+            .as_ref()
+            .map(|expected| baseline::compare(expected, &baseline::normalize(output, &self.ignore)))
+    }
+}
+
+const DEFAULT_CANCEL_TIMEOUT_SECS: u64 = 10;
+
+/// How a task asks its running command to stop before it is killed.
+#[derive(Clone)]
+struct CancelSpec {
+    sequence: Vec<u8>,
+    command: Option<String>,
+    cwd: Option<PathBuf>,
+    env: HashMap<String, String>,
+    timeout: Duration,
+}
+
+impl CancelSpec {
+    fn for_task(task: &Task, env: &HashMap<String, String>) -> Result<Self> {
+        let sequence = match task.cancel_sequence.as_deref() {
+            Some(value) => parse_cancel_sequence(value)
+                .ok_or_else(|| anyhow!("task {}: invalid cancel_sequence {:?}", task.id, value))?,
+            None => vec![0x03],
+        };
+        Ok(Self {
+            sequence,
+            command: task.cancel_command.clone(),
+            cwd: task.cwd.clone(),
+            env: env.clone(),
+            timeout: Duration::from_secs(task.cancel_timeout_secs.unwrap_or(DEFAULT_CANCEL_TIMEOUT_SECS)),
+        })
+    }
 }
 
+const DEFAULT_MAX_RESTARTS: u32 = 5;
+const DEFAULT_RESTART_BACKOFF_SECS: u64 = 1;
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How to launch a run again under its task's `restart` policy.
+#[derive(Clone)]
+struct RestartSpec {
+    policy: RestartPolicy,
+    max_restarts: u32,
+    backoff: Duration,
+    task: Task,
+    command: String,
+    inputs: HashMap<String, String>,
+    options: LaunchOptions,
+}
+
+impl RestartSpec {
+    fn for_task(task: &Task, command: &str, inputs: &HashMap<String, String>, options: &LaunchOptions) -> Option<Self> {
+        let policy = task.restart.unwrap_or_default();
+        if policy == RestartPolicy::Never {
+            return None;
+        }
+        Some(Self {
+            policy,
+            max_restarts: task.max_restarts.unwrap_or(DEFAULT_MAX_RESTARTS),
+            backoff: Duration::from_secs(task.restart_backoff_secs.unwrap_or(DEFAULT_RESTART_BACKOFF_SECS)),
+            task: task.clone(),
+            command: command.to_string(),
+            inputs: inputs.clone(),
+            options: options.clone(),
+        })
+    }
+
+    fn wants_restart(&self, status: &InstanceStatus) -> bool {
+        match status {
+            InstanceStatus::Running | InstanceStatus::Cancelled => false,
+            InstanceStatus::Exited(0) => self.policy == RestartPolicy::Always,
+            InstanceStatus::Exited(_) | InstanceStatus::Error(_) => true,
+        }
+    }
+
+    /// The wait before restart number `attempt + 1`.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(MAX_RESTART_BACKOFF)
+            .min(MAX_RESTART_BACKOFF)
+    }
+}
+
+/// Accepts "ctrl+<key>" for a control byte, otherwise the literal text.
+fn parse_cancel_sequence(value: &str) -> Option<Vec<u8>> {
+    let lower = value.to_ascii_lowercase();
+    match lower.strip_prefix("ctrl+") {
+        Some(key) => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if ('@'..='_').contains(&c.to_ascii_uppercase()) => {
+                    Some(vec![c.to_ascii_uppercase() as u8 & 0x1f])
+                }
+                _ => None,
+            }
+        }
+        None if value.is_empty() => None,
+        None => Some(value.as_bytes().to_vec()),
+    }
+}
+
+/// Spawns tasks in PTYs and tracks their output and status in memory.
+/// Cloning is cheap; clones share the same instances.
 #[derive(Clone)]
 pub struct SessionManager {
     instances: Arc<Mutex<HashMap<String, InstanceEntry>>>,
     counters: Arc<Mutex<HashMap<String, u32>>>,
     buffer_cap: usize,
+    receipts: Option<Arc<ReceiptLog>>,
+    events: EventBus,
+    metrics: Arc<Metrics>,
+    snapshots: Option<Arc<SnapshotStore>>,
+    recordings: Option<Arc<RecordingStore>>,
+    registry: Arc<OnceLock<Option<Arc<RunRegistry>>>>,
+    // Held by launches of unique tasks from their check until the run is listed.
+    launching: Arc<Mutex<()>>,
+    baselines: Option<Arc<BaselineStore>>,
+    timestamps: bool,
+    event_logs: bool,
 }
 
 impl SessionManager {
@@ -89,10 +572,170 @@ impl SessionManager {
             instances: Arc::new(Mutex::new(HashMap::new())),
             counters: Arc::new(Mutex::new(HashMap::new())),
             buffer_cap,
+            receipts: None,
+            events: EventBus::default(),
+            metrics: Arc::new(Metrics::default()),
+            snapshots: None,
+            recordings: None,
+            registry: Arc::new(OnceLock::new()),
+            launching: Arc::new(Mutex::new(())),
+            baselines: None,
+            timestamps: false,
+            event_logs: false,
+        }
+    }
+
+    pub fn with_webhook(self, webhook: WebhookNotifier) -> Self {
+        webhook.listen(self.events.subscribe_lifecycle());
+        self
+    }
+
+    /// Builds a manager with the receipt log, webhook and snapshot store configured in `config`.
+    pub fn from_config(config: &AppConfig, buffer_cap: usize) -> Result<Self> {
+        let mut manager = Self::new(buffer_cap);
+        if let Some(receipts) = config.receipts.as_ref() {
+            if let Some(log) = ReceiptLog::from_config(receipts)? {
+                manager = manager.with_receipts(log);
+            }
+        }
+        if let Some(webhook) = config.notifications.as_ref().and_then(|n| n.webhook.as_ref()) {
+            manager = manager.with_webhook(WebhookNotifier::new(webhook));
+        }
+        let snapshots = config.snapshots.clone().unwrap_or_default();
+        manager = manager.with_snapshots(SnapshotStore::from_config(&snapshots)?);
+        let recording = config.recording.clone().unwrap_or_default();
+        manager = manager.with_recordings(RecordingStore::from_config(&recording)?);
+        manager = manager.with_baselines(BaselineStore::new()?);
+        manager = manager.with_timestamps(config.logs.as_ref().is_some_and(|logs| logs.timestamps));
+        manager = manager.with_event_logs(config.logs.as_ref().and_then(|logs| logs.events).unwrap_or(true));
+        Ok(manager)
+    }
+
+    pub fn with_receipts(mut self, receipts: ReceiptLog) -> Self {
+        self.receipts = Some(Arc::new(receipts));
+        self
+    }
+
+    pub fn with_recordings(mut self, recordings: RecordingStore) -> Self {
+        self.recordings = Some(Arc::new(recordings));
+        self
+    }
+
+    pub fn with_baselines(mut self, baselines: BaselineStore) -> Self {
+        self.baselines = Some(Arc::new(baselines));
+        self
+    }
+
+    /// Prefixes captured output lines with their time and run number.
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Writes each run's output lines as JSON events next to its `output.log`.
+    pub fn with_event_logs(mut self, event_logs: bool) -> Self {
+        self.event_logs = event_logs;
+        self
+    }
+
+    pub fn with_snapshots(mut self, snapshots: SnapshotStore) -> Self {
+        self.snapshots = Some(Arc::new(snapshots));
+        self
+    }
+
+    pub fn spawn_raw(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+    ) -> Result<SpawnedInstance> {
+        self.spawn_raw_with(task, command, inputs, &LaunchOptions::default())
+    }
+
+    pub fn spawn_raw_with(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+        options: &LaunchOptions,
+    ) -> Result<SpawnedInstance> {
+        self.spawn_inner(task, command, inputs, options, None, 0)
+    }
+
+    /// Restarts the run recorded in `session` after its host died, keeping
+    /// the session's id and directory.
+    pub fn readopt_session(&self, task: &Task, session: &SessionInfo) -> Result<InstanceInfo> {
+        let mut task = task.clone();
+        task.cwd = session.cwd.clone();
+        task.env = session.env.clone();
+        task.env_clear = Some(session.env_clear);
+        if !session.tags.is_empty() {
+            task.tags = Some(session.tags.clone());
         }
+        let options = LaunchOptions::default();
+        let spawned = self.spawn_inner(&task, &session.command, &HashMap::new(), &options, Some(session), session.restarts + 1)?;
+        self.store_master(&spawned.info.id, spawned.master, spawned.writer)?;
+        Ok(spawned.info)
     }
 
-    pub fn spawn_raw(&self, task: &Task, command: &str) -> Result<SpawnedInstance> {
+    fn spawn_inner(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+        options: &LaunchOptions,
+        adopt: Option<&SessionInfo>,
+        restarts: u32,
+    ) -> Result<SpawnedInstance> {
+        let restart = RestartSpec::for_task(task, command, inputs, options);
+        let mut task = options.apply(task);
+        task.cwd = prepare_cwd(&task, inputs)?;
+        let task = &task;
+        let status_parser = match task.status_pattern.as_deref() {
+            Some(pattern) => Some(StatusParser::new(pattern).map_err(|err| {
+                anyhow!("invalid status_pattern for task {}: {}", task.id, err)
+            })?),
+            None => None,
+        };
+        let log_targets = match task.log_to.as_deref() {
+            Some(values) => parse_targets(values).map_err(|err| anyhow!("task {}: {}", task.id, err))?,
+            None => Vec::new(),
+        };
+        // Fetched once per run, before taking the launch lock; only the
+        // references reach the session store.
+        let env = secrets::resolve_env(task.env.as_ref())?;
+        let scope = task.unique.unwrap_or_default();
+        let launching = match scope {
+            UniqueScope::None => None,
+            _ => Some(self.launching.lock().map_err(|_| anyhow!("launch lock poisoned"))?),
+        };
+        if let Some(warning) = self.check_unique(task)? {
+            log::warn!("{}", warning);
+        }
+        let cancel = CancelSpec::for_task(task, &env)?;
+        let termination = Termination::for_task(task)?;
+        let baseline = BaselineCheck::for_task(task, self.baselines.as_deref())?;
+        let mut command = match task.stdin_from.as_deref() {
+            Some(source) => redirect_stdin(command, &StdinSource::prepare(source, task)?),
+            None => command.to_string(),
+        };
+        let pty = task.pty.unwrap_or(true);
+        if !pty && task.container.is_none() {
+            command = without_terminal(&command);
+        }
+        let command = command.as_str();
+        let cpuset = match task.cpuset.as_deref() {
+            Some(value) => Some(parse_cpuset(value).map_err(|err| anyhow!("task {}: {}", task.id, err))?),
+            None => None,
+        };
+
+        let seq = match adopt.and_then(|session| session.seq) {
+            Some(seq) => seq,
+            None => self.next_seq(&task.id),
+        };
+        let instance_id = format!("{}#{}", task.id, seq);
+        let backend = Backend::for_task(task, &instance_id)?;
+
         let pty_system = native_pty_system();
         let pair = pty_system.openpty(PtySize {
             rows: 24,
@@ -100,101 +743,471 @@ impl SessionManager {
             pixel_width: 0,
             pixel_height: 0,
         })?;
+        let size = pair.master.get_size().map_or((24, 80), |size| (size.rows, size.cols));
 
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-        let mut cmd = CommandBuilder::new(&shell);
-        if is_bash_shell(&shell) {
-            let rcfile = ensure_bash_rcfile()?;
-            cmd.arg("--noprofile");
-            cmd.arg("--rcfile");
-            cmd.arg(&rcfile);
-            cmd.arg("-i");
-            cmd.env("CMDHUB_INIT_CMD", command);
-        } else {
-            cmd.arg("-c");
-            // Ensure the shell remains open after the command finishes
-            let final_command = format!("{}; exec {}", command, shell);
-            cmd.arg(final_command);
-        }
-
-        if let Some(cwd) = task.cwd.clone() {
-            cmd.cwd(cwd);
-        }
-        if task.env_clear.unwrap_or(false) {
-            cmd.env_clear();
-        }
-        if let Some(env) = task.env.clone() {
-            for (key, value) in env {
-                cmd.env(key, value);
+        let cmd = match &backend {
+            Backend::Local => local_command(task, command, &env)?,
+            Backend::Container(container) => {
+                // The container starts from the image's environment; only the
+                // task's variables are passed through.
+                let env: BTreeMap<&String, &String> = env.iter().collect();
+                let mut cmd = CommandBuilder::new(container.runtime());
+                cmd.args(container.args(command, env.keys().copied(), pty));
+                if let Some(cwd) = task.cwd.clone() {
+                    cmd.cwd(cwd);
+                }
+                for (key, value) in &env {
+                    cmd.env(key, value);
+                }
+                cmd
             }
-        }
-
-        let mut child = pair.slave.spawn_command(cmd)?;
-        let child_pid = child.process_id();
-        let killer = child.clone_killer();
-
-        // Take the writer immediately to avoid "cannot take writer more than once" later
-        let writer = pair.master.take_writer()?;
+        };
 
-        let instance_id = self.next_instance_id(&task.id);
         let now = now_epoch();
-        let info = InstanceInfo {
+        let mut info = InstanceInfo {
             id: instance_id.clone(),
             task_id: task.id.clone(),
             task_name: task.name.clone(),
             status: InstanceStatus::Running,
             started_at: now,
             ended_at: None,
-            child_pid,
+            child_pid: None,
             title: None,
+            progress: None,
+            snapshot: None,
+            closed: false,
+            env_overrides: options.env.clone(),
+            baseline: None,
+            seq,
+            restarts,
+            restart_at: None,
+            tags: task.tags.clone().unwrap_or_default(),
+            label: adopt.and_then(|session| session.label.clone()),
+            note: adopt.and_then(|session| session.note.clone()),
+            group: options.group.clone(),
+        };
+
+        // Claimed before spawning so no other process can start the task meanwhile.
+        let claimed = match (scope, adopt, self.registry()) {
+            (UniqueScope::Global, None, Some(registry)) => {
+                if let Some(other) = registry.claim(task, command, inputs, &info)? {
+                    return Err(anyhow!(running_elsewhere_message(&task.id, &other)));
+                }
+                Some(registry)
+            }
+            _ => None,
+        };
+        let spawned = pair.slave.spawn_command(cmd).and_then(|mut child| {
+            // Set as soon as the shell exists, before it has read its rc files
+            // and started the command, which inherits the mask.
+            if let (Some(cpus), Some(pid)) = (&cpuset, child.process_id()) {
+                if let Err(err) = set_affinity(pid, cpus) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(err);
+                }
+            }
+            Ok(child)
+        });
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(err) => {
+                if let Some(registry) = &claimed {
+                    if let Err(err) = registry.release(&instance_id) {
+                        log::warn!("failed to release {} in session store: {}", instance_id, err);
+                    }
+                }
+                return Err(err);
+            }
+        };
+        let child_pid = child.process_id();
+        info.child_pid = child_pid;
+        let killer = match &backend {
+            Backend::Container(container) => container.killer(child.clone_killer()),
+            Backend::Local => child.clone_killer(),
         };
 
+        // Take the writer immediately to avoid "cannot take writer more than once" later
+        let reader = PtyReader::new(&*pair.master)?;
+        let pty = dup_master(&*pair.master)?;
+        let writer: Box<dyn Write + Send> = Box::new(PtyWriter::new(&*pair.master, pair.master.take_writer()?)?);
+        let (shutdown, mut shutdown_rx) = watch::channel(());
+
         let entry = InstanceEntry {
             info: info.clone(),
             killer,
             buffer: RingBuffer::new(self.buffer_cap),
             osc_parser: OscParser::new(),
+            status_parser,
+            subscribers: Vec::new(),
             master: None,
             writer: None,
+            cancel,
+            cancel_requested: false,
+            termination,
+            // portable-pty starts the shell with setsid, so its pid is the group's id.
+            pgid: match backend {
+                Backend::Local => child_pid,
+                Backend::Container(_) => None,
+            },
+            baseline,
+            receipt: self
+                .receipts
+                .as_ref()
+                .map(|_| ReceiptDraft::new(&instance_id, &task.id, command, inputs, now)),
+            backend,
+            restart,
+            _shutdown: shutdown,
+            _pty: pty,
         };
 
         {
             let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
             guard.insert(instance_id.clone(), entry);
         }
+        drop(launching);
+        self.metrics.record_run(&task.id);
+        self.events.publish(SessionEvent::TaskStarted { info: info.clone() });
+        self.register_run(task, command, inputs, &info, adopt.map(|session| session.id), claimed.is_some());
+        if task.snapshot_env.unwrap_or(false) {
+            self.capture_snapshot(task, &env, &instance_id, now);
+        }
+        if let Some(secs) = task.timeout_secs {
+            self.watch_timeout(&instance_id, Duration::from_secs(secs));
+        }
 
+        let copies = OutputCopies {
+            tee: match log_targets.is_empty() {
+                true => None,
+                false => LineTee::connect(&log_targets, &task.id, &instance_id),
+            },
+            recorder: self.start_recording(task, &info, size),
+            event_log: self.start_event_log(&info),
+        };
+        // Clients resize the PTY while they hold the master, so a recording
+        // notices it from the size seen with each chunk.
+        let mut recorded_size = match copies.recorder {
+            Some(_) => Some((dup_master(&*pair.master)?, size)),
+            None => None,
+        };
+        let mut copies = copies.spawn(&instance_id);
+        let mut prefixer = self.timestamps.then(|| LinePrefixer::new(seq));
+
+        // The manager owns the only PTY reader so output keeps flowing into the
+        // buffer while no client is attached; attached clients subscribe to it.
         let instances = Arc::clone(&self.instances);
         let instance_id_clone = instance_id.clone();
-        tokio::task::spawn_blocking(move || {
-            let status = child.wait();
-            let mut guard = match instances.lock() {
-                Ok(guard) => guard,
-                Err(_) => return,
-            };
-            if let Some(entry) = guard.get_mut(&instance_id_clone) {
-                let ended_at = now_epoch();
-                entry.info.ended_at = Some(ended_at);
-                entry.info.status = match status {
-                    Ok(exit) => InstanceStatus::Exited(exit.exit_code()),
-                    Err(err) => InstanceStatus::Error(err.to_string()),
+        let events = self.events.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_CHUNK];
+            loop {
+                let read = tokio::select! {
+                    read = reader.read(&mut buf) => read,
+                    // The entry was removed.
+                    _ = shutdown_rx.changed() => break,
                 };
+                match read {
+                    Ok(0) => break,
+                    Ok(mut n) => {
+                        while n < buf.len() {
+                            match reader.try_read(&mut buf[n..]) {
+                                Ok(Some(more)) if more > 0 => n += more,
+                                _ => break,
+                            }
+                        }
+                        let data = match prefixer.as_mut() {
+                            Some(prefixer) => prefixer.apply(&buf[..n]),
+                            None => Cow::Borrowed(&buf[..n]),
+                        };
+                        if let Some(tx) = &copies {
+                            let resized = recorded_size.as_mut().and_then(|(fd, last)| {
+                                let size = pty_size(fd).filter(|size| size != last)?;
+                                *last = size;
+                                Some(size)
+                            });
+                            let sent = match resized {
+                                Some(size) => tx.send(CopyEvent::Resize(size)).await.is_ok(),
+                                None => true,
+                            };
+                            if !sent || tx.send(CopyEvent::Output(data.to_vec())).await.is_err() {
+                                copies = None;
+                            }
+                        }
+                        let (blocked, receipt, stopped) = {
+                            let mut guard = match instances.lock() {
+                                Ok(guard) => guard,
+                                Err(_) => break,
+                            };
+                            let entry = match guard.get_mut(&instance_id_clone) {
+                                Some(entry) => entry,
+                                None => break,
+                            };
+                            let before = entry.info.status.clone();
+                            let blocked = entry.record_output(&data);
+                            metrics.record_output(data.len());
+                            on_transition(&events, &metrics, &before, &entry.info);
+                            let stopped = before == InstanceStatus::Running && entry.info.status != InstanceStatus::Running;
+                            (blocked, stopped.then(|| entry.finished_receipt()).flatten(), stopped)
+                        };
+                        if let Some(receipt) = receipt {
+                            manager.append_receipt(receipt);
+                        }
+                        if stopped {
+                            manager.schedule_restart(&instance_id_clone);
+                        }
+                        events.publish(SessionEvent::OutputChunk {
+                            instance_id: instance_id_clone.clone(),
+                            data: data.into_owned(),
+                        });
+                        // Backpressure: wait for slow clients outside the lock, on
+                        // a blocking thread only while one is behind.
+                        if !blocked.is_empty() {
+                            let _ = tokio::task::spawn_blocking(move || {
+                                for (tx, chunk) in blocked {
+                                    let _ = tx.send(chunk);
+                                }
+                            })
+                            .await;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let instances = Arc::clone(&self.instances);
+        let instance_id_clone = instance_id.clone();
+        let events = self.events.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let status = wait_exit(&mut *child).await;
+            let ended_at = now_epoch();
+            let mut receipt = None;
+            let mut stopped = false;
+            if let Ok(mut guard) = instances.lock() {
+                if let Some(entry) = guard.get_mut(&instance_id_clone) {
+                    let before = entry.info.status.clone();
+                    let cancelled = entry.cancel_requested || before == InstanceStatus::Cancelled;
+                    entry.info.closed = true;
+                    entry.info.status = match status {
+                        _ if cancelled => InstanceStatus::Cancelled,
+                        // The command's own exit, reported before its shell closed.
+                        _ if matches!(before, InstanceStatus::Exited(_)) => before.clone(),
+                        Ok(exit) => InstanceStatus::Exited(exit.exit_code()),
+                        Err(err) => InstanceStatus::Error(err.to_string()),
+                    };
+                    entry.info.ended_at = entry.info.ended_at.or(Some(ended_at));
+                    if let InstanceStatus::Exited(_) = entry.info.status {
+                        if let Some(result) = entry.baseline.finish() {
+                            entry.info.baseline = Some(result);
+                        }
+                    }
+                    on_transition(&events, &metrics, &before, &entry.info);
+                    stopped = before == InstanceStatus::Running;
+                    receipt = entry.finished_receipt();
+                }
+            }
+            if let Some(receipt) = receipt {
+                manager.append_receipt(receipt);
+            }
+            if stopped {
+                manager.schedule_restart(&instance_id_clone);
             }
         });
 
         Ok(SpawnedInstance { info, master: pair.master, writer })
     }
 
-    pub fn spawn(&self, task: &Task, command: &str) -> Result<InstanceInfo> {
-        let spawned = self.spawn_raw(task, command)?;
-        self.return_master(&spawned.info.id, spawned.master, spawned.writer)?;
+    pub fn spawn(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+    ) -> Result<InstanceInfo> {
+        self.spawn_with(task, command, inputs, &LaunchOptions::default())
+    }
+
+    pub fn spawn_with(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+        options: &LaunchOptions,
+    ) -> Result<InstanceInfo> {
+        let spawned = self.spawn_raw_with(task, command, inputs, options)?;
+        self.detach_spawned(spawned)
+    }
+
+    /// Leaves a run started by [`Self::spawn_raw_with`] running unattached.
+    pub fn detach_spawned(&self, spawned: SpawnedInstance) -> Result<InstanceInfo> {
+        self.store_master(&spawned.info.id, spawned.master, spawned.writer)?;
         Ok(spawned.info)
     }
 
+    /// Errors when `task.unique` forbids another run right now; returns a
+    /// warning when it is allowed but already running in another process.
+    pub fn check_unique(&self, task: &Task) -> Result<Option<String>> {
+        let scope = task.unique.unwrap_or_default();
+        if scope == UniqueScope::None {
+            return Ok(None);
+        }
+        {
+            let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+            if let Some(entry) = guard
+                .values()
+                .find(|entry| entry.info.task_id == task.id && entry.info.status == InstanceStatus::Running)
+            {
+                return Err(anyhow!("task {} is already running as {}", task.id, entry.info.id));
+            }
+        }
+        let others = match self.registry() {
+            Some(registry) => registry.running_elsewhere(&task.id)?,
+            None => Vec::new(),
+        };
+        let other = match others.first() {
+            Some(other) => other,
+            None => return Ok(None),
+        };
+        let message = running_elsewhere_message(&task.id, other);
+        match scope {
+            UniqueScope::Global => Err(anyhow!(message)),
+            _ => Ok(Some(message)),
+        }
+    }
+
+    fn registry(&self) -> Option<Arc<RunRegistry>> {
+        self.registry
+            .get_or_init(|| match SessionStore::new() {
+                Ok(store) => {
+                    let registry = Arc::new(RunRegistry::new(store));
+                    let manager = self.clone();
+                    Arc::clone(&registry).listen(self.events.subscribe_lifecycle(), move |id| manager.buffer_snapshot(id).ok());
+                    Some(registry)
+                }
+                Err(err) => {
+                    log::warn!("session store unavailable, runs are not recorded and unique checks are local only: {}", err);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    fn register_run(
+        &self,
+        task: &Task,
+        command: &str,
+        inputs: &HashMap<String, String>,
+        info: &InstanceInfo,
+        adopt: Option<Uuid>,
+        claimed: bool,
+    ) {
+        let registry = match self.registry() {
+            Some(registry) => registry,
+            None => return,
+        };
+        let result = match adopt {
+            Some(session_id) => registry.adopt(session_id, info),
+            None if claimed => registry.started(info),
+            None => registry.register(task, command, inputs, info),
+        };
+        if let Err(err) = result {
+            log::warn!("failed to register {} in session store: {}", info.id, err);
+            return;
+        }
+        // A command that already finished published its exit before it was registered.
+        let status = self.get_status(&info.id).ok().flatten();
+        if status != Some(InstanceStatus::Running) {
+            let exit_code = status.and_then(|status| status.exit_code());
+            registry.finish(&info.id, exit_code, self.buffer_snapshot(&info.id).ok());
+        }
+    }
+
+    fn append_receipt(&self, (draft, ended_at, exit_code, cancelled): FinishedReceipt) {
+        let Some(log) = self.receipts.as_ref() else {
+            return;
+        };
+        if let Err(err) = log.append(draft, ended_at, exit_code, cancelled) {
+            log::warn!("failed to append receipt: {}", err);
+        }
+    }
+
+    fn start_recording(&self, task: &Task, info: &InstanceInfo, size: (u16, u16)) -> Option<Recorder> {
+        let store = self.recordings.as_ref()?;
+        if !task.record.unwrap_or(store.enabled()) {
+            return None;
+        }
+        let title = format!("{} ({})", task.name, info.id);
+        match store.start(&info.id, &title, info.started_at, size) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                log::warn!("failed to start recording for {}: {}", info.id, err);
+                None
+            }
+        }
+    }
+
+    fn start_event_log(&self, info: &InstanceInfo) -> Option<LogEventWriter> {
+        if !self.event_logs {
+            return None;
+        }
+        let path = self.registry()?.events_path(&info.id)?;
+        match LogEventWriter::open(&path, &info.id) {
+            Ok(writer) => Some(writer),
+            Err(err) => {
+                log::warn!("failed to open event log for {}: {}", info.id, err);
+                None
+            }
+        }
+    }
+
+    // Probes may be slow, so the snapshot is recorded off the spawn path.
+    fn capture_snapshot(&self, task: &Task, env: &HashMap<String, String>, instance_id: &str, now: u64) {
+        let store = match &self.snapshots {
+            Some(store) => Arc::clone(store),
+            None => return,
+        };
+        let instances = Arc::clone(&self.instances);
+        let task = task.clone();
+        let env = env.clone();
+        let instance_id = instance_id.to_string();
+        std::thread::spawn(move || match store.capture(&task, &env, &instance_id, now) {
+            Ok(path) => {
+                if let Ok(mut guard) = instances.lock() {
+                    if let Some(entry) = guard.get_mut(&instance_id) {
+                        entry.info.snapshot = Some(path);
+                    }
+                }
+            }
+            Err(err) => log::warn!("failed to capture snapshot for {}: {}", instance_id, err),
+        });
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn subscribe_events(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+
     pub fn list_instances(&self) -> Result<Vec<InstanceInfo>> {
         let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         Ok(guard.values().map(|entry| entry.info.clone()).collect())
     }
 
+    pub fn metrics_text(&self) -> Result<String> {
+        let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+        let running = guard
+            .values()
+            .filter(|entry| entry.info.status == InstanceStatus::Running)
+            .count();
+        let clients = guard.values().map(|entry| entry.subscribers.len()).sum();
+        Ok(self.metrics.render(running, clients))
+    }
+
     pub fn get_status(&self, id: &str) -> Result<Option<InstanceStatus>> {
         let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         Ok(guard.get(id).map(|entry| entry.info.status.clone()))
@@ -202,25 +1215,39 @@ impl SessionManager {
 
     pub fn append_output(&self, id: &str, data: &[u8]) -> Result<()> {
         let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
-        if let Some(entry) = guard.get_mut(id) {
-            entry.buffer.push(data);
-            let mut titles = Vec::new();
-            entry.osc_parser.collect_titles(data, &mut titles);
-            let mut last_title = None;
-            for title in titles {
-                if title.trim().starts_with("CMDHUB:") {
-                    let _ = apply_cmdhub_title(&title, &mut entry.info);
-                } else {
-                    last_title = Some(title);
-                }
-            }
-            if let Some(title) = last_title {
-                entry.info.title = Some(title);
-            }
+        let blocked = match guard.get_mut(id) {
+            Some(entry) => entry.record_output(data),
+            None => Vec::new(),
+        };
+        drop(guard);
+        for (tx, chunk) in blocked {
+            let _ = tx.send(chunk);
         }
         Ok(())
     }
 
+    /// Returns the buffered output together with a receiver for everything
+    /// read after it, so a client can replay and follow without gaps.
+    pub fn subscribe_output(&self, id: &str) -> Result<(Vec<u8>, OutputStream)> {
+        self.subscribe_output_with(id, SubscribeOptions::default())
+    }
+
+    pub fn subscribe_output_with(
+        &self,
+        id: &str,
+        options: SubscribeOptions,
+    ) -> Result<(Vec<u8>, OutputStream)> {
+        let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+        let entry = guard.get_mut(id).ok_or_else(|| anyhow!("instance not found"))?;
+        let (tx, rx) = mpsc::sync_channel(options.capacity.max(1));
+        entry.subscribers.push(Subscriber {
+            tx,
+            policy: options.policy,
+            dropped: 0,
+        });
+        Ok((entry.buffer.snapshot(), rx))
+    }
+
     pub fn buffer_snapshot(&self, id: &str) -> Result<Vec<u8>> {
         let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         Ok(guard
@@ -229,23 +1256,266 @@ impl SessionManager {
             .unwrap_or_default())
     }
 
-    pub fn kill(&self, id: &str) -> Result<()> {
+    /// Output appended after stream offset `offset`, for readers that cache
+    /// what they have already seen. The chunk starts later than `offset` when
+    /// the buffer has been trimmed past it.
+    pub fn buffer_since(&self, id: &str, offset: u64) -> Result<BufferChunk> {
+        let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+        Ok(guard
+            .get(id)
+            .map(|entry| entry.buffer.since(offset))
+            .unwrap_or_default())
+    }
+
+    /// Saves a successful run's normalized output as its task's baseline.
+    pub fn set_baseline(&self, id: &str) -> Result<PathBuf> {
+        let store = self.baselines.clone().ok_or_else(|| anyhow!("baselines are unavailable"))?;
         let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         let entry = guard.get_mut(id).ok_or_else(|| anyhow!("instance not found"))?;
-        entry.killer.kill()?;
+        if entry.info.status != InstanceStatus::Exited(0) {
+            return Err(anyhow!("{} did not succeed", id));
+        }
+        if entry.baseline.overflowed {
+            return Err(anyhow!("{} printed more than {} bytes", id, BASELINE_CAPTURE_LIMIT));
+        }
+        // Only runs of tasks with a baseline are captured; others fall back
+        // to their scrollback.
+        let output = match entry.baseline.expected {
+            Some(_) => entry.baseline.output.clone().unwrap_or_default(),
+            None => baseline::run_output(&entry.buffer.snapshot()).to_vec(),
+        };
+        let normalized = baseline::normalize(&output, &entry.baseline.ignore);
+        let path = store.save(&entry.info.task_id, &normalized)?;
+        entry.baseline.expected = Some(normalized);
+        entry.baseline.output = Some(output);
+        entry.info.baseline = Some(BaselineResult::Match);
+        Ok(path)
+    }
+
+    /// Names the run and sets its note, each cleared when blank, and records
+    /// both with the run's session so history shows them too.
+    pub fn annotate(&self, id: &str, label: Option<String>, note: Option<String>) -> Result<()> {
+        let clean = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let (label, note) = (clean(label), clean(note));
+        {
+            let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+            let entry = guard.get_mut(id).ok_or_else(|| anyhow!("instance not found"))?;
+            entry.info.label = label.clone();
+            entry.info.note = note.clone();
+        }
+        if let Some(registry) = self.registry.get().cloned().flatten() {
+            registry.annotate(id, label, note)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the run as its task's [`Termination`] says, in the background:
+    /// the command's process group is signalled, killed once the command has
+    /// exited or the grace period is over, and then the run's shell is closed.
+    pub fn kill(&self, id: &str) -> Result<()> {
+        if !self.stop_gracefully(id, false)? {
+            return Err(anyhow!("instance not found"));
+        }
         Ok(())
     }
 
+    /// Asks the running command to stop: runs the task's `cancel_command`, or
+    /// writes its `cancel_sequence` to the PTY, then kills the run if it is
+    /// still going after the grace period. A run waiting to be restarted stays
+    /// stopped instead. Returns false when nothing is running.
+    pub fn cancel(&self, id: &str) -> Result<bool> {
+        let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+        let entry = guard.get_mut(id).ok_or_else(|| anyhow!("instance not found"))?;
+        if entry.info.status != InstanceStatus::Running || entry.info.closed {
+            return Ok(entry.info.restart_at.take().is_some());
+        }
+        entry.cancel_requested = true;
+        let spec = entry.cancel.clone();
+        let pid = entry.info.child_pid;
+        // Written to without the lock, since a child not reading its input
+        // blocks the write; an attach meanwhile finds the run busy.
+        let writer = match spec.command {
+            Some(_) => None,
+            None => entry.writer.take(),
+        };
+        if spec.command.is_none() && writer.is_none() {
+            // An attached client holds the writer; signal the foreground group instead.
+            signal_group(pid, libc::SIGINT);
+        }
+        drop(guard);
+
+        // The grace period runs from now, however long asking takes.
+        let deadline = Instant::now() + spec.timeout;
+        let instances = Arc::clone(&self.instances);
+        let id = id.to_string();
+        {
+            let instances = Arc::clone(&instances);
+            let id = id.clone();
+            let spec = spec.clone();
+            std::thread::spawn(move || match (&spec.command, writer) {
+                (Some(command), _) => run_cancel_command(command, &spec, &id, pid, deadline),
+                (None, Some(mut writer)) => {
+                    if let Err(err) = writer.write_all(&spec.sequence).and_then(|_| writer.flush()) {
+                        log::warn!("failed to send cancel_sequence to {}: {}", id, err);
+                    }
+                    if let Ok(mut guard) = instances.lock() {
+                        if let Some(entry) = guard.get_mut(&id) {
+                            entry.writer.get_or_insert(writer);
+                        }
+                    }
+                }
+                (None, None) => {}
+            });
+        }
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(Duration::from_millis(100));
+                let mut guard = match instances.lock() {
+                    Ok(guard) => guard,
+                    Err(_) => return,
+                };
+                let entry = match guard.get_mut(&id) {
+                    Some(entry) => entry,
+                    None => return,
+                };
+                if !entry.cancel_requested || entry.info.closed {
+                    return;
+                }
+                if Instant::now() >= deadline {
+                    log::warn!("{} did not stop within {:?}, killing it", id, spec.timeout);
+                    entry.signal_groups(libc::SIGKILL);
+                    let _ = entry.killer.kill();
+                    return;
+                }
+            }
+        });
+        Ok(true)
+    }
+
+    /// Cancels the run if its command is still going after `timeout`.
+    fn watch_timeout(&self, id: &str, timeout: Duration) {
+        let manager = self.clone();
+        let id = id.to_string();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            if let Ok(true) = manager.cancel(&id) {
+                log::warn!("{} timed out after {:?}, cancelling it", id, timeout);
+            }
+        });
+    }
+
+    /// Relaunches the stopped run `id` after its backoff when the task's
+    /// restart policy asks for it. The new run gets the next `task#N` id and
+    /// its own session; the old run's shell is closed when it starts.
+    fn schedule_restart(&self, id: &str) {
+        // Runs are spawned on the runtime their output tasks belong to.
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        let (spec, attempt, delay) = {
+            let mut guard = match self.instances.lock() {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+            let entry = match guard.get_mut(id) {
+                Some(entry) => entry,
+                None => return,
+            };
+            let spec = match entry.restart.take() {
+                Some(spec) if spec.wants_restart(&entry.info.status) => spec,
+                _ => return,
+            };
+            let attempt = entry.info.restarts;
+            if attempt >= spec.max_restarts {
+                log::warn!("{} stopped after {} restarts, not restarting it again", id, attempt);
+                return;
+            }
+            let delay = spec.delay(attempt);
+            entry.info.restart_at = Some(now_epoch() + delay.as_secs());
+            (spec, attempt, delay)
+        };
+        let manager = self.clone();
+        let id = id.to_string();
+        std::thread::spawn(move || {
+            let _runtime = runtime.enter();
+            std::thread::sleep(delay);
+            {
+                let mut guard = match manager.instances.lock() {
+                    Ok(guard) => guard,
+                    Err(_) => return,
+                };
+                let entry = match guard.get_mut(&id) {
+                    Some(entry) => entry,
+                    None => return,
+                };
+                // Cancelled or killed while waiting.
+                if entry.info.restart_at.take().is_none() {
+                    return;
+                }
+                // Leftovers of the old run, like a worker holding its port,
+                // would get in the new one's way. They keep its group alive
+                // after its shell is gone, so it is still theirs to signal.
+                entry.signal_groups(libc::SIGKILL);
+                signal_group(entry.pgid, libc::SIGKILL);
+                if !entry.info.closed {
+                    let _ = entry.killer.kill();
+                }
+            }
+            let result = manager.spawn_inner(&spec.task, &spec.command, &spec.inputs, &spec.options, None, attempt + 1);
+            match result.and_then(|spawned| {
+                manager.store_master(&spawned.info.id, spawned.master, spawned.writer)?;
+                Ok(spawned.info)
+            }) {
+                Ok(info) => log::info!("restarted {} as {}", id, info.id),
+                Err(err) => log::warn!("failed to restart {}: {}", id, err),
+            }
+        });
+    }
+
+    /// Like [`SessionManager::kill`], and the run is removed once it has
+    /// stopped; until then it stays listed with its output.
     pub fn kill_and_remove(&self, id: &str) -> Result<bool> {
-        let entry = {
+        self.stop_gracefully(id, true)
+    }
+
+    fn stop_gracefully(&self, id: &str, remove: bool) -> Result<bool> {
+        let (pid, session, termination, mut killer) = {
             let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
-            guard.remove(id)
+            let entry = match guard.get_mut(id) {
+                Some(entry) => entry,
+                None => return Ok(false),
+            };
+            entry.restart = None;
+            entry.info.restart_at = None;
+            (entry.stop_target(), entry.session_group(), entry.termination, entry.killer.clone_killer())
+        };
+        let manager = self.clone();
+        let id = id.to_string();
+        std::thread::spawn(move || {
+            if let Some(pid) = pid {
+                terminate(pid, &termination, || {
+                    !matches!(manager.get_status(&id), Ok(Some(InstanceStatus::Running)))
+                });
+            }
+            let _ = killer.kill();
+            // Whatever the command left running in the run's group goes with it.
+            signal_group(session, libc::SIGKILL);
+            if remove {
+                manager.remove_stopped(&id);
+            }
+        });
+        Ok(true)
+    }
+
+    fn remove_stopped(&self, id: &str) {
+        let entry = match self.instances.lock() {
+            Ok(mut guard) => guard.remove(id),
+            Err(_) => return,
         };
-        if let Some(mut entry) = entry {
-            let _ = entry.killer.kill();
-            return Ok(true);
+        if let (Some(entry), Some(registry)) = (entry, self.registry.get().cloned().flatten()) {
+            registry.finish(id, entry.info.status.exit_code(), Some(entry.buffer.snapshot()));
         }
-        Ok(false)
     }
 
     pub fn remove(&self, id: &str) -> Result<bool> {
@@ -253,10 +1523,11 @@ impl SessionManager {
         Ok(guard.remove(id).is_some())
     }
 
-    pub fn take_master(&self, id: &str) -> Result<Option<(Box<dyn MasterPty + Send>, Box<dyn Write + Send>)>> {
+    pub fn take_master(&self, id: &str) -> Result<Option<PtyHandles>> {
         let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         if let Some(entry) = guard.get_mut(id) {
             if let (Some(master), Some(writer)) = (entry.master.take(), entry.writer.take()) {
+                self.events.publish(SessionEvent::SessionAttached { instance_id: id.to_string() });
                 return Ok(Some((master, writer)));
             }
         }
@@ -264,6 +1535,12 @@ impl SessionManager {
     }
 
     pub fn return_master(&self, id: &str, master: Box<dyn MasterPty + Send>, writer: Box<dyn Write + Send>) -> Result<()> {
+        self.store_master(id, master, writer)?;
+        self.events.publish(SessionEvent::SessionDetached { instance_id: id.to_string() });
+        Ok(())
+    }
+
+    fn store_master(&self, id: &str, master: Box<dyn MasterPty + Send>, writer: Box<dyn Write + Send>) -> Result<()> {
         let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         if let Some(entry) = guard.get_mut(id) {
             entry.master = Some(master);
@@ -275,7 +1552,7 @@ impl SessionManager {
     pub fn remove_if_exited(&self, id: &str) -> Result<bool> {
         let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
         if let Some(entry) = guard.get(id) {
-            if matches!(entry.info.status, InstanceStatus::Exited(_)) {
+            if matches!(entry.info.status, InstanceStatus::Exited(_) | InstanceStatus::Cancelled) {
                 guard.remove(id);
                 return Ok(true);
             }
@@ -284,32 +1561,343 @@ impl SessionManager {
     }
 
     pub fn terminate_all(&self, signal: i32) -> Result<()> {
-        let guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
-        for entry in guard.values() {
-            if let Some(pid) = entry.info.child_pid {
-                unsafe {
-                    libc::kill(pid as libc::pid_t, signal);
+        let mut guard = self.instances.lock().map_err(|_| anyhow!("instance lock poisoned"))?;
+        for entry in guard.values_mut() {
+            entry.restart = None;
+            entry.info.restart_at = None;
+            match (&entry.backend, entry.info.child_pid) {
+                (Backend::Container(_), _) if entry.info.closed => {}
+                // A container runtime client does not pass signals on to the command.
+                (Backend::Container(_), _) | (Backend::Local, None) => {
+                    let _ = entry.killer.clone_killer().kill();
                 }
-            } else {
-                let _ = entry.killer.clone_killer().kill();
+                (Backend::Local, Some(_)) => entry.signal_groups(signal),
             }
         }
         Ok(())
     }
 
-    fn next_instance_id(&self, task_id: &str) -> String {
-        let mut guard = self.counters.lock().expect("instance counters poisoned");
+    /// The next run number for `task_id`, from the session store so it stays
+    /// unique across processes and restarts, or counted locally without one.
+    fn next_seq(&self, task_id: &str) -> u32 {
+        let shared = self.registry().and_then(|registry| match registry.next_seq(task_id) {
+            Ok(seq) => Some(seq),
+            Err(err) => {
+                log::warn!("failed to allocate a run number for {}: {}", task_id, err);
+                None
+            }
+        });
+        // The counters stay consistent even if a holder panicked.
+        let mut guard = self.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
         let counter = guard.entry(task_id.to_string()).or_insert(0);
-        *counter += 1;
-        format!("{}#{}", task_id, *counter)
+        *counter = match shared {
+            Some(seq) => seq.max(*counter + 1),
+            None => *counter + 1,
+        };
+        *counter
+    }
+}
+
+/// A receipt draft with the run's end time, exit code and whether it was cancelled.
+type FinishedReceipt = (ReceiptDraft, u64, Option<u32>, bool);
+
+impl InstanceEntry {
+    /// The run's receipt once its command has stopped, at most once.
+    fn finished_receipt(&mut self) -> Option<FinishedReceipt> {
+        if self.info.status == InstanceStatus::Running {
+            return None;
+        }
+        let draft = self.receipt.take()?;
+        let ended_at = self.info.ended_at.unwrap_or_else(now_epoch);
+        let cancelled = self.info.status == InstanceStatus::Cancelled;
+        Some((draft, ended_at, self.info.status.exit_code(), cancelled))
+    }
+
+    /// The process group a kill signals first. Under a local shell with job
+    /// control this follows the foreground job; a container's client passes
+    /// no signals on, so it is only stopped through its killer.
+    fn stop_target(&self) -> Option<u32> {
+        match self.backend {
+            Backend::Local if !self.info.closed => self.info.child_pid,
+            _ => None,
+        }
+    }
+
+    /// The run's own process group, while its shell is there to lead it.
+    fn session_group(&self) -> Option<u32> {
+        self.pgid.filter(|_| !self.info.closed)
+    }
+
+    /// Signals the foreground job's group and the run's own group, so
+    /// nothing the command started is missed.
+    fn signal_groups(&self, signal: i32) {
+        let session = self.session_group();
+        let foreground = self.stop_target().filter(|pid| Some(*pid) != session);
+        signal_group(foreground, signal);
+        signal_group(session, signal);
+    }
+
+    fn record_output(&mut self, data: &[u8]) -> Vec<BlockedSend> {
+        self.buffer.push(data);
+        self.baseline.feed(data);
+        let mut titles = Vec::new();
+        self.osc_parser.collect_titles(data, &mut titles);
+        let mut last_title = None;
+        for title in titles {
+            if title.trim().starts_with("CMDHUB:") {
+                if apply_cmdhub_title(&title, &mut self.info) && self.cancel_requested {
+                    if let InstanceStatus::Exited(_) = self.info.status {
+                        self.info.status = InstanceStatus::Cancelled;
+                        self.cancel_requested = false;
+                    }
+                }
+            } else {
+                last_title = Some(title);
+            }
+        }
+        if let Some(title) = last_title {
+            self.info.title = Some(title);
+        }
+        if let InstanceStatus::Exited(_) = self.info.status {
+            if let Some(result) = self.baseline.finish() {
+                self.info.baseline = Some(result);
+            }
+        }
+        if let Some(parser) = self.status_parser.as_mut() {
+            if let Some(progress) = parser.feed(data) {
+                self.info.progress = Some(progress);
+            }
+        }
+        // Dropped receivers unsubscribe themselves.
+        let mut blocked = Vec::new();
+        self.subscribers.retain_mut(|sub| sub.offer(data, &mut blocked));
+        blocked
+    }
+}
+
+fn on_transition(events: &EventBus, metrics: &Metrics, before: &InstanceStatus, info: &InstanceInfo) {
+    if *before != InstanceStatus::Running {
+        return;
+    }
+    let fired = LifecycleEvent::for_status(&info.status);
+    if fired.is_empty() {
+        return;
+    }
+    if fired.contains(&LifecycleEvent::Failure) {
+        metrics.record_failure(&info.task_id);
+    }
+    events.publish(SessionEvent::TaskExited { info: info.clone() });
+}
+
+/// Where a task's stdin is read from when it is not the PTY.
+enum StdinSource {
+    Fifo(PathBuf),
+    File(PathBuf),
+}
+
+impl StdinSource {
+    /// Parses `stdin_from` and checks the path, creating the FIFO if it does not exist yet.
+    fn prepare(value: &str, task: &Task) -> Result<Self> {
+        let (kind, path) = value
+            .split_once(':')
+            .ok_or_else(|| anyhow!("task {}: stdin_from must be \"fifo:PATH\" or \"file:PATH\"", task.id))?;
+        let path = PathBuf::from(path);
+        let resolved = match &task.cwd {
+            Some(cwd) if path.is_relative() => cwd.join(&path),
+            _ => path.clone(),
+        };
+        match kind {
+            "fifo" => {
+                match fs::metadata(&resolved) {
+                    Ok(meta) if !std::os::unix::fs::FileTypeExt::is_fifo(&meta.file_type()) => {
+                        return Err(anyhow!("task {}: {} is not a FIFO", task.id, resolved.display()));
+                    }
+                    Ok(_) => {}
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => make_fifo(&resolved)
+                        .map_err(|err| anyhow!("task {}: mkfifo {}: {}", task.id, resolved.display(), err))?,
+                    Err(err) => return Err(err.into()),
+                }
+                Ok(StdinSource::Fifo(path))
+            }
+            "file" => {
+                if !resolved.is_file() {
+                    return Err(anyhow!("task {}: stdin_from file not found: {}", task.id, resolved.display()));
+                }
+                Ok(StdinSource::File(path))
+            }
+            _ => Err(anyhow!("task {}: unknown stdin_from kind: {}", task.id, kind)),
+        }
+    }
+
+    fn path(&self) -> &PathBuf {
+        match self {
+            StdinSource::Fifo(path) | StdinSource::File(path) => path,
+        }
+    }
+}
+
+fn make_fifo(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Wraps the command so only it reads from `source`; the shell keeps the PTY.
+/// The newline keeps a trailing comment or `&` in the command from eating the brace.
+fn redirect_stdin(command: &str, source: &StdinSource) -> String {
+    let path = source.path().to_string_lossy().replace('\'', "'\\''");
+    format!("{{ {}\n}} < '{}'", command, path)
+}
+
+/// Wraps the command so its stdout and stderr are pipes rather than the PTY,
+/// as under CI, with stderr lines tagged on the way; `pipefail` keeps its
+/// exit code instead of `cat`'s.
+fn without_terminal(command: &str) -> String {
+    format!(
+        "(set -o pipefail 2>/dev/null; {{ {{ {}\n}} < /dev/null 2>&1 1>&3 3>&- | {}; }} 3>&1 | cat)",
+        command,
+        streams::shell_filter()
+    )
+}
+
+fn signal_group(pid: Option<u32>, signal: i32) {
+    if let Some(pid) = pid {
+        unsafe {
+            libc::killpg(pid as libc::pid_t, signal);
+        }
+    }
+}
+
+/// Runs the task's `cancel_command`, killing it if it is still going at
+/// `deadline`.
+fn run_cancel_command(command: &str, spec: &CancelSpec, instance_id: &str, pid: Option<u32>, deadline: Instant) {
+    let mut cmd = std::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    if let Some(cwd) = &spec.cwd {
+        cmd.current_dir(cwd);
+    }
+    cmd.envs(&spec.env);
+    cmd.env("CMDHUB_INSTANCE_ID", instance_id);
+    if let Some(pid) = pid {
+        cmd.env("CMDHUB_PID", pid.to_string());
+    }
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            log::warn!("failed to run cancel_command for {}: {}", instance_id, err);
+            return;
+        }
+    };
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    log::warn!("cancel_command for {} exited with {}", instance_id, status);
+                }
+                return;
+            }
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                log::warn!("cancel_command for {} still running after the grace period, killing it", instance_id);
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Err(err) => {
+                log::warn!("failed to wait for cancel_command of {}: {}", instance_id, err);
+                return;
+            }
+        }
+    }
+}
+
+/// Waits for `child` to exit by checking on each SIGCHLD, so no thread sits
+/// in `wait` for the length of the run.
+async fn wait_exit(child: &mut (dyn Child + Send + Sync)) -> std::io::Result<ExitStatus> {
+    // Listening before the first check means an exit in between is not missed.
+    let mut sigchld = signal(SignalKind::child())?;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        sigchld.recv().await;
+    }
+}
+
+/// Where a run's output is copied besides its buffer: the `log_to` targets,
+/// the recording and the event log.
+struct OutputCopies {
+    tee: Option<LineTee>,
+    recorder: Option<Recorder>,
+    event_log: Option<LogEventWriter>,
+}
+
+impl OutputCopies {
+    /// Writes the copies on a thread of their own, since the writes block,
+    /// fed through the returned sender; dropping it finishes them.
+    fn spawn(mut self, instance_id: &str) -> Option<tokio::sync::mpsc::Sender<CopyEvent>> {
+        if self.tee.is_none() && self.recorder.is_none() && self.event_log.is_none() {
+            return None;
+        }
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<CopyEvent>(64);
+        let instance_id = instance_id.to_string();
+        std::thread::spawn(move || {
+            while let Some(event) = rx.blocking_recv() {
+                match event {
+                    CopyEvent::Output(data) => self.write(&instance_id, &data),
+                    CopyEvent::Resize(size) => self.resize(&instance_id, size),
+                }
+            }
+            if let Some(tee) = self.tee.as_mut() {
+                tee.finish();
+            }
+            if let Some(log) = self.event_log.as_mut() {
+                let _ = log.finish();
+            }
+        });
+        Some(tx)
+    }
+
+    fn write(&mut self, instance_id: &str, data: &[u8]) {
+        if let Some(tee) = self.tee.as_mut() {
+            tee.feed(data);
+        }
+        if let Some(rec) = self.recorder.as_mut() {
+            if let Err(err) = rec.write_output(data) {
+                log::warn!("recording stopped for {}: {}", instance_id, err);
+                self.recorder = None;
+            }
+        }
+        if let Some(log) = self.event_log.as_mut() {
+            if let Err(err) = log.feed(data) {
+                log::warn!("event log stopped for {}: {}", instance_id, err);
+                self.event_log = None;
+            }
+        }
+    }
+
+    fn resize(&mut self, instance_id: &str, size: (u16, u16)) {
+        if let Some(rec) = self.recorder.as_mut() {
+            if let Err(err) = rec.write_resize(size) {
+                log::warn!("recording stopped for {}: {}", instance_id, err);
+                self.recorder = None;
+            }
+        }
     }
 }
 
-fn now_epoch() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or_default()
+/// What the output reader hands to the copies, in the order it saw it.
+enum CopyEvent {
+    Output(Vec<u8>),
+    /// The PTY now has this many rows and columns.
+    Resize((u16, u16)),
 }
 
 const OSC_TITLE_LIMIT: usize = 2048;
@@ -381,6 +1969,64 @@ impl OscParser {
     }
 }
 
+const STATUS_LINE_LIMIT: usize = 4096;
+
+struct StatusParser {
+    pattern: Regex,
+    line: Vec<u8>,
+}
+
+impl StatusParser {
+    fn new(pattern: &str) -> Result<Self> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+            line: Vec::new(),
+        })
+    }
+
+    /// Feeds raw output and returns the progress from the last matching line.
+    /// Lines end on `\n` or `\r` so carriage-return progress bars are seen too.
+    fn feed(&mut self, data: &[u8]) -> Option<RunProgress> {
+        let mut latest = None;
+        for &b in data {
+            if b == b'\n' || b == b'\r' {
+                if let Some(progress) = self.match_line() {
+                    latest = Some(progress);
+                }
+                self.line.clear();
+            } else if self.line.len() < STATUS_LINE_LIMIT {
+                self.line.push(b);
+            }
+        }
+        // Also look at the unterminated tail so in-place updates show up immediately.
+        if let Some(progress) = self.match_line() {
+            latest = Some(progress);
+        }
+        latest
+    }
+
+    fn match_line(&self) -> Option<RunProgress> {
+        if self.line.is_empty() {
+            return None;
+        }
+        let plain = strip_ansi_escapes::strip(&self.line);
+        let text = String::from_utf8_lossy(&plain);
+        let caps = self.pattern.captures(&text)?;
+        let group = |name: &str| {
+            caps.name(name)
+                .map(|m| m.as_str().trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        Some(RunProgress {
+            phase: group("phase"),
+            percent: group("percent")
+                .and_then(|value| value.trim_end_matches('%').parse::<f64>().ok())
+                .map(|value| value.clamp(0.0, 100.0) as u8),
+            message: group("message"),
+        })
+    }
+}
+
 fn apply_cmdhub_title(title: &str, info: &mut InstanceInfo) -> bool {
     let title = title.trim();
     let payload = match title.strip_prefix("CMDHUB:") {
@@ -419,6 +2065,51 @@ fn apply_cmdhub_title(title: &str, info: &mut InstanceInfo) -> bool {
     }
 }
 
+/// The task's shell (or the user's) running `command`, kept open after it finishes.
+fn local_command(task: &Task, command: &str, env: &HashMap<String, String>) -> Result<CommandBuilder> {
+    let shell = task_shell(task);
+    let login = task.login_profile.unwrap_or(false);
+    let mut cmd = CommandBuilder::new(&shell);
+    if is_bash_shell(&shell) {
+        let rcfile = ensure_bash_rcfile()?;
+        cmd.arg("--noprofile");
+        cmd.arg("--rcfile");
+        cmd.arg(&rcfile);
+        cmd.arg("-i");
+        cmd.env("CMDHUB_INIT_CMD", command);
+        if login {
+            cmd.env("CMDHUB_LOGIN_PROFILE", "1");
+        }
+    } else {
+        if login {
+            cmd.arg("-l");
+        }
+        cmd.arg("-c");
+        // Ensure the shell remains open after the command finishes
+        let final_command = format!("{}; exec {}", command, shell);
+        cmd.arg(final_command);
+    }
+
+    if let Some(cwd) = task.cwd.clone() {
+        cmd.cwd(cwd);
+    }
+    if task.env_clear.unwrap_or(false) {
+        cmd.env_clear();
+    }
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    Ok(cmd)
+}
+
+/// The shell a local run starts: the task's `shell`, else `$SHELL`.
+pub fn task_shell(task: &Task) -> String {
+    task.shell
+        .clone()
+        .or_else(|| std::env::var("SHELL").ok())
+        .unwrap_or_else(|| "bash".to_string())
+}
+
 fn is_bash_shell(shell: &str) -> bool {
     shell.ends_with("bash") || shell.contains("/bash")
 }
@@ -449,8 +2140,8 @@ cmdhub_debug_trap() {
 }
 
 cmdhub_precmd() {
-    CMDHUB_IN_HOOK=1
     local code="$?"
+    CMDHUB_IN_HOOK=1
     cmdhub_emit "state=exited;code=$code"
     CMDHUB_IN_HOOK=
 }
@@ -477,11 +2168,25 @@ cmdhub_watch_fg() {
 CMDHUB_SHELL_PID="$$"
 CMDHUB_SHELL_PGID="$(ps -o pgid= -p "$CMDHUB_SHELL_PID" 2>/dev/null | tr -d ' ')"
 
-if [ -f /etc/bash.bashrc ]; then
-    . /etc/bash.bashrc
-fi
-if [ -f "$HOME/.bashrc" ]; then
-    . "$HOME/.bashrc"
+if [ -n "${CMDHUB_LOGIN_PROFILE-}" ]; then
+    # What a login shell reads, instead of the interactive rc files.
+    if [ -f /etc/profile ]; then
+        . /etc/profile
+    fi
+    for cmdhub_profile in "$HOME/.bash_profile" "$HOME/.bash_login" "$HOME/.profile"; do
+        if [ -f "$cmdhub_profile" ]; then
+            . "$cmdhub_profile"
+            break
+        fi
+    done
+    unset cmdhub_profile
+else
+    if [ -f /etc/bash.bashrc ]; then
+        . /etc/bash.bashrc
+    fi
+    if [ -f "$HOME/.bashrc" ]; then
+        . "$HOME/.bashrc"
+    fi
 fi
 
 if declare -p PROMPT_COMMAND 2>/dev/null | grep -q 'declare -a'; then
@@ -501,3 +2206,160 @@ fi
     let _ = RCFILE.set(path_str.clone());
     Ok(path_str)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(timeout: Duration) -> CancelSpec {
+        CancelSpec {
+            sequence: Vec::new(),
+            command: None,
+            cwd: None,
+            env: HashMap::new(),
+            timeout,
+        }
+    }
+
+    #[test]
+    fn env_overrides_are_not_serialized() {
+        let mut info = InstanceInfo::for_test("deploy", InstanceStatus::Running);
+        info.env_overrides.insert("API_TOKEN".to_string(), "hunter2".to_string());
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(!json.contains("hunter2") && !json.contains("API_TOKEN"), "{}", json);
+    }
+
+    #[test]
+    fn cancel_command_is_killed_at_the_deadline() {
+        let spec = spec(Duration::from_millis(200));
+        let started = Instant::now();
+        run_cancel_command("sleep 10", &spec, "task#1", None, started + spec.timeout);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn launch_options_override_the_task() {
+        let task = Task {
+            id: "t".into(),
+            env: Some(HashMap::from([
+                ("A".to_string(), EnvValue::from("1".to_string())),
+                ("B".to_string(), EnvValue::from("2".to_string())),
+            ])),
+            timeout_secs: Some(60),
+            tags: Some(vec!["ci".to_string()]),
+            ..Default::default()
+        };
+        let options = LaunchOptions {
+            env: BTreeMap::from([("B".to_string(), "3".to_string())]),
+            cwd: Some(PathBuf::from("/tmp")),
+            shell: Some("/bin/sh".to_string()),
+            login_profile: Some(true),
+            timeout_secs: Some(0),
+            pty: Some(false),
+            tags: vec!["ci".to_string(), "manual".to_string()],
+            group: None,
+        };
+        let task = options.apply(&task);
+        let env = task.env.unwrap();
+        assert_eq!(env["A"], EnvValue::from("1".to_string()));
+        assert_eq!(env["B"], EnvValue::from("3".to_string()));
+        assert_eq!(task.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(task.shell.as_deref(), Some("/bin/sh"));
+        assert_eq!(task.login_profile, Some(true));
+        assert_eq!(task.timeout_secs, None);
+        assert_eq!(task.pty, Some(false));
+        assert_eq!(task.tags, Some(vec!["ci".to_string(), "manual".to_string()]));
+    }
+
+    #[test]
+    fn baseline_compares_the_whole_run_up_to_its_exit() {
+        let lines: Vec<String> = (0..4000).map(|n| format!("line {}", n)).collect();
+        let mut check = BaselineCheck {
+            expected: Some(lines.join("\n")),
+            captured: Some(Vec::new()),
+            ..BaselineCheck::default()
+        };
+        for line in &lines {
+            check.feed(format!("{}\r\n", line).as_bytes());
+        }
+        check.feed(b"\x1b]0;CMDHUB:state=exited;code=0\x07user@host:~$ ");
+        assert_eq!(check.finish(), Some(BaselineResult::Match));
+        assert!(check.output.as_ref().unwrap().len() > 16 * 1024);
+        assert_eq!(check.finish(), None);
+    }
+
+    #[test]
+    fn baseline_without_a_store_captures_nothing() {
+        let mut check = BaselineCheck::default();
+        check.feed(b"hello\r\n");
+        assert_eq!(check.finish(), None);
+        assert_eq!(check.output.as_deref(), Some(&[][..]));
+    }
+
+    #[test]
+    fn only_tasks_with_a_baseline_capture_their_output() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-baseline-{}", Uuid::new_v4()));
+        let store = BaselineStore::in_dir(dir.clone());
+        let task = Task { id: "build".into(), ..Default::default() };
+        let check = BaselineCheck::for_task(&task, Some(&store)).unwrap();
+        assert!(check.captured.is_none());
+        store.save("build", "ok").unwrap();
+        let check = BaselineCheck::for_task(&task, Some(&store)).unwrap();
+        assert_eq!(check.captured.as_deref(), Some(&[][..]));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn baseline_capture_stops_at_its_limit() {
+        let mut check = BaselineCheck {
+            expected: Some("line".to_string()),
+            captured: Some(Vec::new()),
+            ..BaselineCheck::default()
+        };
+        let chunk = vec![b'x'; 1024 * 1024];
+        for _ in 0..(BASELINE_CAPTURE_LIMIT / chunk.len()) {
+            check.feed(&chunk);
+        }
+        assert_eq!(check.captured.as_ref().map(Vec::len), Some(BASELINE_CAPTURE_LIMIT));
+        check.feed(b"more\r\n");
+        assert!(check.captured.is_none());
+        check.feed(&chunk);
+        assert!(check.captured.is_none());
+        assert_eq!(check.finish(), Some(BaselineResult::TooLong { limit: BASELINE_CAPTURE_LIMIT }));
+    }
+
+    #[test]
+    fn cancel_command_sees_the_instance() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-cancel-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("seen");
+        let spec = spec(Duration::from_secs(5));
+        let command = format!("echo \"$CMDHUB_INSTANCE_ID $CMDHUB_PID\" > {}", out.display());
+        run_cancel_command(&command, &spec, "task#1", Some(42), Instant::now() + spec.timeout);
+        assert_eq!(fs::read_to_string(&out).unwrap().trim(), "task#1 42");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn receipt_is_written_when_the_command_exits() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-receipt-run-{}", Uuid::new_v4()));
+        let path = dir.join("receipts.jsonl");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let manager = SessionManager::new(1024).with_receipts(ReceiptLog::new(path.clone(), None));
+        let task = Task { id: "t".into(), name: "t".into(), command: "(exit 3)".into(), ..Default::default() };
+        let info = manager.spawn(&task, &task.command, &HashMap::new()).unwrap();
+
+        // The shell stays open after the command, so the receipt must not wait for it.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let receipts = ReceiptLog::new(path, None).verify().unwrap();
+        manager.kill_and_remove(&info.id).unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].instance_id, info.id);
+        assert_eq!(receipts[0].exit_code, Some(3));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,33 @@
+pub mod affinity;
+pub mod ansi;
+pub mod backend;
+pub mod baseline;
+pub mod clock;
 pub mod config;
+pub mod events;
+pub mod export;
 pub mod instance;
+pub mod lineprefix;
+pub mod logevents;
+pub mod logsink;
+pub mod metrics;
 pub mod models;
+pub mod notify;
+pub mod prelude;
 pub mod pty;
+pub mod receipt;
+pub mod recording;
+pub mod registry;
+pub mod script;
+pub mod sdnotify;
+pub mod secrets;
 pub mod session;
+pub mod snapshot;
+pub mod stats;
 pub mod storage;
+pub mod streams;
+pub mod supervisor;
 pub mod template;
+pub mod terminate;
+pub mod tmux;
+pub mod usage;
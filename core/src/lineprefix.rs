@@ -0,0 +1,63 @@
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Puts `[HH:MM:SS.mmm #N] ` in front of every output line as it is read,
+/// stamped when the line's first byte arrives, N being the run number.
+pub struct LinePrefixer {
+    ordinal: u32,
+    at_line_start: bool,
+}
+
+impl LinePrefixer {
+    pub fn new(ordinal: u32) -> Self {
+        Self { ordinal, at_line_start: true }
+    }
+
+    pub fn apply<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        if data.is_empty() {
+            return Cow::Borrowed(data);
+        }
+        let prefix = format!("[{} #{}] ", wall_clock(), self.ordinal);
+        let mut out = Vec::with_capacity(data.len() + prefix.len());
+        for piece in data.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                out.extend_from_slice(prefix.as_bytes());
+            }
+            out.extend_from_slice(piece);
+            self.at_line_start = piece.ends_with(b"\n");
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// `line` without the prefix `LinePrefixer` put in front of it, if any.
+pub fn strip(line: &str) -> &str {
+    let Some((head, rest)) = line.strip_prefix('[').and_then(|line| line.split_once("] ")) else {
+        return line;
+    };
+    let Some((time, ordinal)) = head.split_once(" #") else {
+        return line;
+    };
+    let is_time = time.len() == 12
+        && time.bytes().enumerate().all(|(idx, b)| match idx {
+            2 | 5 => b == b':',
+            8 => b == b'.',
+            _ => b.is_ascii_digit(),
+        });
+    if is_time && !ordinal.is_empty() && ordinal.bytes().all(|b| b.is_ascii_digit()) {
+        rest
+    } else {
+        line
+    }
+}
+
+/// Local time of day with milliseconds.
+fn wall_clock() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&secs, &mut tm);
+    }
+    format!("{:02}:{:02}:{:02}.{:03}", tm.tm_hour, tm.tm_min, tm.tm_sec, now.subsec_millis())
+}
@@ -0,0 +1,110 @@
+use crate::clock::now_millis;
+use crate::streams;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Longest line kept in one event; longer ones, like a progress bar redrawn
+/// with carriage returns, are split.
+const LINE_LIMIT: usize = 64 * 1024;
+
+/// One line of a run's output, as written to its `events.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEvent {
+    /// Milliseconds since the Unix epoch when the line's first byte was read.
+    pub timestamp: u64,
+    /// The run's `task#N` id.
+    pub run: String,
+    pub stream: LogStream,
+    /// The line without its newline, when it is valid UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The line's bytes in base64 when it is not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<String>,
+}
+
+/// PTY runs have one stream, reported as stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Appends captured output to an `events.jsonl` file as newline-delimited
+/// [`LogEvent`]s, one per line. Stderr is told apart by the tag
+/// [`streams`] puts on its lines.
+pub struct LogEventWriter {
+    writer: BufWriter<File>,
+    run: String,
+    line: Vec<u8>,
+    started: u64,
+}
+
+impl LogEventWriter {
+    pub fn open(path: &Path, run: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { writer: BufWriter::new(file), run: run.to_string(), line: Vec::new(), started: 0 })
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Result<()> {
+        for piece in data.split_inclusive(|&b| b == b'\n') {
+            if self.line.is_empty() {
+                self.started = now_millis();
+            }
+            self.line.extend_from_slice(piece);
+            if piece.ends_with(b"\n") || self.line.len() >= LINE_LIMIT {
+                self.emit()?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the last line if it had no newline.
+    pub fn finish(&mut self) -> Result<()> {
+        if !self.line.is_empty() {
+            self.emit()?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn emit(&mut self) -> Result<()> {
+        let line = std::mem::take(&mut self.line);
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let (stream, body) = match streams::untag(line) {
+            Some(body) => (LogStream::Stderr, body),
+            None => (LogStream::Stdout, line.to_vec()),
+        };
+        let (text, bytes) = match String::from_utf8(body) {
+            Ok(text) => (Some(text), None),
+            Err(err) => (None, Some(base64(err.as_bytes()))),
+        };
+        let event = LogEvent { timestamp: self.started, run: self.run.clone(), stream, text, bytes };
+        serde_json::to_writer(&mut self.writer, &event)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for (index, shift) in [18, 12, 6, 0].into_iter().enumerate() {
+            if index <= chunk.len() {
+                out.push(ALPHABET[(n >> shift & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use std::os::unix::net::UnixDatagram;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+const LINE_LIMIT: usize = 8 * 1024;
+const SYSLOG_IDENTIFIER: &str = "cmdhub";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogTarget {
+    Journald,
+    Syslog,
+}
+
+impl LogTarget {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "journald" | "journal" => Some(LogTarget::Journald),
+            "syslog" => Some(LogTarget::Syslog),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a task's `log_to` list, rejecting unknown targets.
+pub fn parse_targets(values: &[String]) -> Result<Vec<LogTarget>> {
+    values
+        .iter()
+        .map(|value| LogTarget::parse(value).ok_or_else(|| anyhow!("unknown log_to target: {}", value)))
+        .collect()
+}
+
+struct Sink {
+    target: LogTarget,
+    socket: UnixDatagram,
+}
+
+/// Mirrors output lines into the system journal, tagged with the task and run.
+pub struct LineTee {
+    sinks: Vec<Sink>,
+    task_id: String,
+    instance_id: String,
+    line: Vec<u8>,
+}
+
+impl LineTee {
+    /// Connects to each target; unreachable ones are skipped with a warning
+    /// so a missing journald never blocks a run.
+    pub fn connect(targets: &[LogTarget], task_id: &str, instance_id: &str) -> Option<Self> {
+        let mut sinks = Vec::new();
+        for &target in targets {
+            let path = match target {
+                LogTarget::Journald => JOURNALD_SOCKET,
+                LogTarget::Syslog => SYSLOG_SOCKET,
+            };
+            let socket = UnixDatagram::unbound().and_then(|socket| {
+                socket.connect(path)?;
+                Ok(socket)
+            });
+            match socket {
+                Ok(socket) => sinks.push(Sink { target, socket }),
+                Err(err) => log::warn!("log_to {:?} unavailable at {}: {}", target, path, err),
+            }
+        }
+        if sinks.is_empty() {
+            return None;
+        }
+        Some(Self {
+            sinks,
+            task_id: task_id.to_string(),
+            instance_id: instance_id.to_string(),
+            line: Vec::new(),
+        })
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for &b in data {
+            if b == b'\n' {
+                self.emit();
+            } else if self.line.len() < LINE_LIMIT {
+                self.line.push(b);
+            }
+        }
+    }
+
+    /// Sends any unterminated last line.
+    pub fn finish(&mut self) {
+        self.emit();
+    }
+
+    fn emit(&mut self) {
+        let raw = std::mem::take(&mut self.line);
+        // Keep what a terminal would show after carriage-return redraws.
+        let visible = raw.rsplit(|&b| b == b'\r').find(|part| !part.is_empty()).unwrap_or_default();
+        let plain = strip_ansi_escapes::strip(visible);
+        let text = String::from_utf8_lossy(&plain);
+        let text = text.trim_end();
+        if text.is_empty() {
+            return;
+        }
+        for sink in &self.sinks {
+            let packet = match sink.target {
+                LogTarget::Journald => journald_packet(text, &self.task_id, &self.instance_id),
+                LogTarget::Syslog => syslog_packet(text, &self.task_id, &self.instance_id),
+            };
+            let _ = sink.socket.send(&packet);
+        }
+    }
+}
+
+fn journald_packet(message: &str, task_id: &str, instance_id: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(message.len() + 128);
+    for (key, value) in [
+        ("MESSAGE", message),
+        ("PRIORITY", "6"),
+        ("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER),
+        ("CMDHUB_TASK", task_id),
+        ("CMDHUB_RUN", instance_id),
+    ] {
+        packet.extend_from_slice(key.as_bytes());
+        packet.push(b'=');
+        packet.extend_from_slice(value.as_bytes());
+        packet.push(b'\n');
+    }
+    packet
+}
+
+fn syslog_packet(message: &str, task_id: &str, instance_id: &str) -> Vec<u8> {
+    // <14> is facility user, severity info.
+    format!(
+        "<14>{}[{}]: [{} {}] {}",
+        SYSLOG_IDENTIFIER,
+        std::process::id(),
+        task_id,
+        instance_id,
+        message
+    )
+    .into_bytes()
+}
@@ -0,0 +1,170 @@
+use crate::instance::SessionManager;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Counters collected by the SessionManager; gauges are computed at scrape time.
+#[derive(Default)]
+pub struct Metrics {
+    runs: Mutex<BTreeMap<String, u64>>,
+    failures: Mutex<BTreeMap<String, u64>>,
+    output_bytes: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_run(&self, task_id: &str) {
+        if let Ok(mut runs) = self.runs.lock() {
+            *runs.entry(task_id.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn record_failure(&self, task_id: &str) {
+        if let Ok(mut failures) = self.failures.lock() {
+            *failures.entry(task_id.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn record_output(&self, bytes: usize) {
+        self.output_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self, running: usize, clients: usize) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP cmdhub_running_sessions Runs currently in the Running state.\n");
+        out.push_str("# TYPE cmdhub_running_sessions gauge\n");
+        out.push_str(&format!("cmdhub_running_sessions {}\n", running));
+        out.push_str("# HELP cmdhub_attach_clients Clients currently attached to run output.\n");
+        out.push_str("# TYPE cmdhub_attach_clients gauge\n");
+        out.push_str(&format!("cmdhub_attach_clients {}\n", clients));
+        out.push_str("# HELP cmdhub_pty_output_bytes_total Bytes read from task PTYs.\n");
+        out.push_str("# TYPE cmdhub_pty_output_bytes_total counter\n");
+        out.push_str(&format!(
+            "cmdhub_pty_output_bytes_total {}\n",
+            self.output_bytes.load(Ordering::Relaxed)
+        ));
+        render_per_task(
+            &mut out,
+            "cmdhub_task_runs_total",
+            "Runs started per task.",
+            &self.runs,
+        );
+        render_per_task(
+            &mut out,
+            "cmdhub_task_failures_total",
+            "Runs that exited non-zero or errored, per task.",
+            &self.failures,
+        );
+        out
+    }
+}
+
+fn render_per_task(out: &mut String, name: &str, help: &str, values: &Mutex<BTreeMap<String, u64>>) {
+    out.push_str(&format!("# HELP {} {}\n", name, help));
+    out.push_str(&format!("# TYPE {} counter\n", name));
+    if let Ok(values) = values.lock() {
+        for (task_id, value) in values.iter() {
+            out.push_str(&format!("{}{{task=\"{}\"}} {}\n", name, escape_label(task_id), value));
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// How long a scraper may take to send its request or read the response;
+/// requests are served one at a time, so a stalled client holds up the rest.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `GET /metrics` in the Prometheus text format on a background thread.
+pub fn serve(addr: &str, manager: SessionManager) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = handle_request(stream, &manager, REQUEST_TIMEOUT) {
+                log::debug!("metrics request failed: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_request(stream: TcpStream, manager: &SessionManager, timeout: Duration) -> Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain headers, up to the blank line ending them, so clients don't see
+    // a reset before the response.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end_matches(['\r', '\n']).is_empty() {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", manager.metrics_text()?)
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Instant;
+
+    /// Sends `request` to a served connection and returns the response, and
+    /// how long serving it took.
+    fn exchange(request: &[u8], timeout: Duration) -> (String, Duration) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let server = std::thread::spawn(move || {
+            let started = Instant::now();
+            let _ = handle_request(stream, &SessionManager::new(1024), timeout);
+            started.elapsed()
+        });
+        client.write_all(request).unwrap();
+        let mut response = String::new();
+        let _ = client.read_to_string(&mut response);
+        (response, server.join().unwrap())
+    }
+
+    #[test]
+    fn headers_end_at_the_blank_line() {
+        let request = b"GET /metrics HTTP/1.1\r\nHost: x\r\nA: b\r\n\r\n";
+        let (response, _) = exchange(request, Duration::from_secs(5));
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("cmdhub_running_sessions 0"));
+    }
+
+    #[test]
+    fn unknown_paths_are_not_found() {
+        let (response, _) = exchange(b"GET / HTTP/1.1\n\n", Duration::from_secs(5));
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    }
+
+    #[test]
+    fn stalled_clients_time_out() {
+        let (response, took) = exchange(b"GET /metrics HTTP/1.1\r\nHost: x\r\n", Duration::from_millis(200));
+        assert!(response.is_empty(), "{}", response);
+        assert!(took < Duration::from_secs(3));
+    }
+}
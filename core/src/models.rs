@@ -2,16 +2,126 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A runnable command as declared in `[[tasks]]`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Task {
     pub id: String,
     pub name: String,
-    pub command: String,
+    #[serde(default)]
+    pub command: String,                      // Left out for a group
     pub category: Option<String>,
-    pub cwd: Option<PathBuf>,
-    pub env: Option<HashMap<String, String>>,
+    pub description: Option<String>,          // Shown in the split view, matched by / in the task list
+    pub template: Option<String>, // Name in [task_templates] to inherit from
+    pub cwd: Option<PathBuf>,                 // May use {{ input }} placeholders and ~
+    pub cwd_create: Option<bool>,             // Create cwd when it is missing instead of refusing to start
+    pub env: Option<HashMap<String, EnvValue>>,
     pub env_clear: Option<bool>,
     pub inputs: Option<HashMap<String, InputConfig>>,
+    /// Regex with optional `phase`, `percent` and `message` named groups,
+    /// matched against output lines to report progress.
+    pub status_pattern: Option<String>,
+    pub snapshot_env: Option<bool>,          // Record env and tool versions per run
+    pub snapshot_probes: Option<Vec<String>>, // Extra probes on top of [snapshots] probes
+    pub input_groups: Option<Vec<String>>,    // Page order of the input form; unlisted groups follow
+    pub record: Option<bool>,                 // Overrides [recording] enabled for this task
+    pub log_to: Option<Vec<String>>,          // Mirror output lines to "journald" and/or "syslog"
+    pub cancel_sequence: Option<String>,      // Written to the PTY on cancel, e.g. "ctrl+c" (default)
+    pub cancel_command: Option<String>,       // Run instead of the sequence, e.g. "docker stop web"
+    pub cancel_timeout_secs: Option<u64>,     // Grace period before a cancel escalates to kill
+    pub kill_signal: Option<String>,          // Sent to the command's process group on kill; defaults to "TERM"
+    pub kill_grace_secs: Option<u64>,         // Wait this long for it before SIGKILL; defaults to 5
+    pub stdin_from: Option<String>,           // "fifo:/tmp/events" or "file:payload.json" instead of the PTY
+    pub cpuset: Option<String>,               // Pin the run to CPUs, e.g. "0-3" or "0,2,4-5"
+    pub script: Option<PathBuf>,              // Rhai hooks, relative to the config file's directory
+    pub unique: Option<UniqueScope>,          // Refuse or warn about a second concurrent run
+    pub baseline_ignore: Option<Vec<String>>, // Regexes masked before comparing output with the baseline
+    pub links: Option<Vec<TaskLink>>,         // Runbooks and dashboards, opened from the TUI
+    pub container: Option<ContainerConfig>,   // Run in docker/podman instead of the local shell
+    pub shell: Option<String>,                // Shell the command runs in; defaults to $SHELL
+    pub login_profile: Option<bool>,          // Load the shell's login profile (/etc/profile, ~/.profile) first
+    pub timeout_secs: Option<u64>,            // Cancel the run if it is still going after this long
+    #[serde(alias = "tty")]
+    pub pty: Option<bool>,                    // false: stdout and stderr are separate pipes, not a terminal
+    pub queue: Option<String>,                // Runs of tasks sharing a queue go one at a time, in order
+    pub restart: Option<RestartPolicy>,       // Relaunch the command when it stops on its own
+    pub max_restarts: Option<u32>,            // Give up after this many restarts; defaults to 5
+    pub restart_backoff_secs: Option<u64>,    // Delay before the first restart, doubled for each one after; defaults to 1
+    pub icon: Option<String>,                 // Shown before the name in the task list, e.g. "🚀"
+    pub color: Option<String>,                // Style of the name in the task list, e.g. "red bold"
+    pub tags: Option<Vec<String>>,            // Given to every run, for `cmdhub ls --tag`, e.g. ["deploy"]
+    pub autostart: Option<bool>,              // Start in the background with default inputs when the TUI opens
+    pub members: Option<Vec<String>>,         // Makes this a group: ids of tasks started together instead of a command
+    pub sequential: Option<bool>,             // Start a group's members one by one, each once the one before succeeded
+    #[serde(skip)]
+    pub source: Option<PathBuf>,              // File the task was read from, opened by the TUI's edit_task key
+}
+
+/// An `env` value: given as is, or fetched each time a run starts with
+/// `{ from = "keyring:service/key" }` or `{ from = "command:pass show x" }`,
+/// so it is never written to the config or the session store.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Value(String),
+    Secret { from: String },
+}
+
+impl EnvValue {
+    /// The value, or where a secret comes from, like `<keyring:app/token>`.
+    pub fn describe(&self) -> String {
+        match self {
+            EnvValue::Value(value) => value.clone(),
+            EnvValue::Secret { from } => format!("<{}>", from),
+        }
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        EnvValue::Value(value)
+    }
+}
+
+/// Runs a task's command in a container: a fresh one from `image`, or an
+/// existing one named by `exec`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContainerConfig {
+    pub image: Option<String>,
+    pub exec: Option<String>,
+    pub volumes: Option<Vec<String>>, // "host:container[:ro]"; ./relative host paths resolve against cwd
+    pub workdir: Option<String>,
+    pub runtime: Option<String>,      // "docker" (default) or "podman"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskLink {
+    pub name: String,
+    pub url: String,
+}
+
+/// Where a task may only run once at a time.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UniqueScope {
+    /// Block when it is running here or in another CmdHub process.
+    Global,
+    /// Block when it is running here; only warn about other processes.
+    Session,
+    #[default]
+    None,
+}
+
+/// When a run's command is relaunched after it stops. A cancelled or
+/// killed run is never restarted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// After a non-zero exit.
+    OnFailure,
+    /// After any exit, successful or not.
+    Always,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -20,25 +130,283 @@ pub enum InputConfig {
     Select {
         options: Vec<String>,
         default: String,
+        group: Option<String>, // Page of the input form this field is shown on
+        raw: Option<bool>,     // Substitute the value unquoted
     },
     Text {
         placeholder: Option<String>,
         default: Option<String>,
+        group: Option<String>,
+        raw: Option<bool>,
+        required: Option<bool>,
+    },
+    /// Rendered as "true" or "false".
+    Bool {
+        default: Option<bool>,
+        group: Option<String>,
+        raw: Option<bool>,
+    },
+    Number {
+        default: Option<f64>,
+        min: Option<f64>,
+        max: Option<f64>,
+        group: Option<String>,
+        raw: Option<bool>,
+    },
+    /// Rendered as the chosen options joined with `separator` (default ",").
+    MultiSelect {
+        options: Vec<String>,
+        defaults: Option<Vec<String>>,
+        separator: Option<String>,
+        group: Option<String>,
+        raw: Option<bool>,
+    },
+    /// Text that is masked while typing.
+    Password {
+        default: Option<String>,
+        group: Option<String>,
+        raw: Option<bool>,
+        required: Option<bool>,
     },
 }
 
+impl InputConfig {
+    pub fn group(&self) -> Option<&str> {
+        match self {
+            InputConfig::Select { group, .. }
+            | InputConfig::Text { group, .. }
+            | InputConfig::Bool { group, .. }
+            | InputConfig::Number { group, .. }
+            | InputConfig::MultiSelect { group, .. }
+            | InputConfig::Password { group, .. } => group.as_deref(),
+        }
+    }
+
+    /// Whether the value is substituted as typed instead of shell-quoted.
+    pub fn raw(&self) -> bool {
+        match self {
+            InputConfig::Select { raw, .. }
+            | InputConfig::Text { raw, .. }
+            | InputConfig::Bool { raw, .. }
+            | InputConfig::Number { raw, .. }
+            | InputConfig::MultiSelect { raw, .. }
+            | InputConfig::Password { raw, .. } => raw.unwrap_or(false),
+        }
+    }
+
+    /// The value used when none is given, already in its rendered form.
+    pub fn default_value(&self) -> Option<String> {
+        match self {
+            InputConfig::Select { default, .. } => Some(default.clone()),
+            InputConfig::Text { default, .. } | InputConfig::Password { default, .. } => default.clone(),
+            InputConfig::Bool { default, .. } => Some(default.unwrap_or(false).to_string()),
+            InputConfig::Number { default, .. } => default.map(|value| value.to_string()),
+            InputConfig::MultiSelect { defaults, .. } => Some(self.join(defaults.as_deref().unwrap_or_default())),
+        }
+    }
+
+    /// Replaces the default with `value`, given in its rendered form.
+    pub fn set_default(&mut self, value: &str) -> Result<(), String> {
+        match self {
+            InputConfig::Select { default, .. } => *default = value.to_string(),
+            InputConfig::Text { default, .. } | InputConfig::Password { default, .. } => {
+                *default = Some(value.to_string())
+            }
+            InputConfig::Bool { default, .. } => {
+                *default = Some(value.parse().map_err(|_| format!("expected true or false, got {}", value))?)
+            }
+            InputConfig::Number { default, .. } => {
+                *default = Some(value.parse().map_err(|_| format!("expected a number, got {}", value))?)
+            }
+            InputConfig::MultiSelect { .. } => {
+                let choices = self.split(value).into_iter().map(str::to_string).collect();
+                if let InputConfig::MultiSelect { defaults, .. } = self {
+                    *defaults = Some(choices);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Joins multiselect choices with the input's separator.
+    pub fn join(&self, choices: &[String]) -> String {
+        choices.join(self.separator())
+    }
+
+    /// Splits a rendered multiselect value back into its choices.
+    pub fn split<'a>(&self, value: &'a str) -> Vec<&'a str> {
+        value.split(self.separator()).map(str::trim).filter(|part| !part.is_empty()).collect()
+    }
+
+    fn separator(&self) -> &str {
+        match self {
+            InputConfig::MultiSelect { separator: Some(separator), .. } => separator,
+            _ => ",",
+        }
+    }
+
+    /// Whether a value has to be given: there is no default, or it is not
+    /// acceptable as is.
+    pub fn needs_value(&self) -> bool {
+        self.validate(&self.default_value().unwrap_or_default()).is_some()
+    }
+
+    /// Returns a short reason when `value` is not acceptable for this input.
+    pub fn validate(&self, value: &str) -> Option<String> {
+        match self {
+            InputConfig::Select { options, .. } if !options.iter().any(|opt| opt == value) => {
+                Some("not one of the options".to_string())
+            }
+            InputConfig::Text { required: Some(true), .. } | InputConfig::Password { required: Some(true), .. }
+                if value.trim().is_empty() =>
+            {
+                Some("required".to_string())
+            }
+            InputConfig::Bool { .. } if value != "true" && value != "false" => {
+                Some("must be true or false".to_string())
+            }
+            InputConfig::Number { min, max, .. } => match value.trim().parse::<f64>() {
+                Err(_) => Some("not a number".to_string()),
+                Ok(number) if min.is_some_and(|min| number < min) => Some(format!("at least {}", min.unwrap_or_default())),
+                Ok(number) if max.is_some_and(|max| number > max) => Some(format!("at most {}", max.unwrap_or_default())),
+                Ok(_) => None,
+            },
+            InputConfig::MultiSelect { options, .. } => self
+                .split(value)
+                .into_iter()
+                .find(|choice| !options.iter().any(|opt| opt == choice))
+                .map(|choice| format!("{} is not one of the options", choice)),
+            _ => None,
+        }
+    }
+}
+
+/// The merged contents of `config.toml` and `tasks/*.toml`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
+    /// Base configs loaded first and merged under this one, in order.
+    pub extends: Option<Vec<String>>,
+    #[serde(default)]
     pub tasks: Vec<Task>,
     pub history_limit: Option<usize>,
     pub ui: Option<UiConfig>,
+    pub theme: Option<ThemeConfig>,
     pub keys: Option<KeyBindings>,
+    pub receipts: Option<ReceiptConfig>,
+    pub notifications: Option<NotificationsConfig>,
+    pub attach: Option<AttachConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub snapshots: Option<SnapshotConfig>,
+    pub categories: Option<HashMap<String, CategoryConfig>>,
+    pub task_templates: Option<HashMap<String, TaskTemplate>>,
+    pub recording: Option<RecordingConfig>,
+    pub logs: Option<LogsConfig>,
+    pub supervisor: Option<SupervisorConfig>,
+    pub store: Option<StoreConfig>,
+    pub profiles: Option<HashMap<String, ProfileConfig>>,
+}
+
+/// Overrides for every task while the profile is selected, e.g. `[profiles.prod]`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProfileConfig {
+    pub env: Option<HashMap<String, EnvValue>>,    // Set over each task's own env
+    pub inputs: Option<HashMap<String, String>>, // Defaults of inputs by name, e.g. { region = "eu-west-1" }
+}
+
+/// Where session metadata, history and run numbers are kept.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StoreConfig {
+    pub backend: Option<String>, // "files" (default) or "sqlite"
+    pub path: Option<PathBuf>,   // SQLite database; defaults to ~/.cmdhub/sessions.db
+}
+
+/// Watches the session store in `serve` mode for runs whose host died.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SupervisorConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub interval_secs: Option<u64>, // Defaults to 5
+    #[serde(default)]
+    pub restart: bool, // Restart crashed runs in this process
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RecordingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub dir: Option<PathBuf>, // Defaults to ~/.cmdhub/recordings
+}
+
+/// How captured output is written to buffers, logs and recordings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LogsConfig {
+    #[serde(default)]
+    pub timestamps: bool, // Prefix each line with the time and run number, "[12:34:56.789 #3] "
+    pub events: Option<bool>, // Write each line as a JSON event to the run's events.jsonl (default true)
+}
+
+/// Defaults for every task whose `category` matches the table key; task
+/// fields win over these.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CategoryConfig {
+    pub cwd: Option<PathBuf>,
+    pub env: Option<HashMap<String, EnvValue>>,
+    pub env_clear: Option<bool>,
+    pub profile: Option<String>, // [profiles] entry its tasks use while no other is selected
+}
+
+/// A base for tasks that name it in `template`. Its command prefix goes in
+/// front of the task's command; its env and inputs are overridden key by key
+/// and its cwd as a whole by the task's own.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TaskTemplate {
+    pub command_prefix: Option<String>, // e.g. "npm run"; joined to the command with a space
+    pub cwd: Option<PathBuf>,
+    pub env: Option<HashMap<String, EnvValue>>,
+    pub inputs: Option<HashMap<String, InputConfig>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SnapshotConfig {
+    pub dir: Option<PathBuf>,         // Defaults to ~/.cmdhub/snapshots
+    pub probes: Option<Vec<String>>,  // Commands like "node -v" recorded with each snapshot
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetricsConfig {
+    pub listen: String, // e.g. "127.0.0.1:9464"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AttachConfig {
+    pub buffer_chunks: Option<usize>, // Output chunks queued per attached client
+    pub on_overflow: Option<String>,  // "drop" (default) or "block"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NotificationsConfig {
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub events: Option<Vec<String>>, // "start", "exit", "failure", "cancel"; all when omitted
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReceiptConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub path: Option<PathBuf>,    // Defaults to ~/.cmdhub/receipts.jsonl
+    pub key_env: Option<String>,  // Env var holding the HMAC signing key
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KeyBindings {
     #[serde(default)]
-    pub global: HashMap<String, String>,     // For future global keys
+    pub global: HashMap<String, String>,     // Keys in every TUI view
     #[serde(default)]
     pub task_list: HashMap<String, String>,  // Keys in the list view
     #[serde(default)]
@@ -54,16 +422,42 @@ impl Default for KeyBindings {
         task_list.insert("select".to_string(), "enter".to_string());
         task_list.insert("delete_instance".to_string(), "d".to_string());
         task_list.insert("kill_instance".to_string(), "X".to_string());
+        task_list.insert("cancel_instance".to_string(), "s".to_string());
         task_list.insert("fold_task".to_string(), "tab".to_string());
+        task_list.insert("toggle_layout".to_string(), "v".to_string());
+        task_list.insert("pin_instance".to_string(), "p".to_string());
+        task_list.insert("toggle_grid".to_string(), "g".to_string());
+        task_list.insert("enqueue_after".to_string(), "a".to_string());
+        task_list.insert("copy_mode".to_string(), "c".to_string());
+        task_list.insert("edit_env".to_string(), "e".to_string());
+        task_list.insert("mark_baseline".to_string(), "b".to_string());
+        task_list.insert("open_link".to_string(), "o".to_string());
+        task_list.insert("history".to_string(), "h".to_string());
+        task_list.insert("launch_options".to_string(), "l".to_string());
+        task_list.insert("scroll_up".to_string(), "pageup".to_string());
+        task_list.insert("scroll_down".to_string(), "pagedown".to_string());
+        task_list.insert("follow".to_string(), "f".to_string());
+        task_list.insert("rename_instance".to_string(), "n".to_string());
+        task_list.insert("annotate_instance".to_string(), "m".to_string());
+        task_list.insert("switch_profile".to_string(), "w".to_string());
+        task_list.insert("search_tasks".to_string(), "/".to_string());
+        task_list.insert("shell".to_string(), "!".to_string());
+        task_list.insert("command_palette".to_string(), ":".to_string());
+        task_list.insert("edit_task".to_string(), "t".to_string());
+
+        let mut global = HashMap::new();
+        global.insert("suspend".to_string(), "ctrl+z".to_string());
 
         let mut task_running = HashMap::new();
         task_running.insert("toggle_command_mode".to_string(), "ctrl+p".to_string());
         task_running.insert("back_to_list".to_string(), "b".to_string()); // Detach
         task_running.insert("quit_task".to_string(), "q".to_string()); // Actually detach/back, original code was 'q' -> back
         task_running.insert("kill_task".to_string(), "k".to_string());
+        task_running.insert("cancel_task".to_string(), "c".to_string());
+        task_running.insert("copy_mode".to_string(), "[".to_string());
 
         Self {
-            global: HashMap::new(),
+            global,
             task_list,
             task_running,
         }
@@ -76,6 +470,40 @@ pub struct UiConfig {
     pub status_bar_bg: Option<String>,
     pub command_mode_fg: Option<String>,
     pub command_mode_bg: Option<String>,
+    pub layout: Option<String>, // "list" (default), "split" or the name of a preset in `layouts`
+    pub layouts: Option<HashMap<String, LayoutPreset>>,
+    pub backend: Option<String>, // "pty" (default) or "tmux" to open runs as tmux windows
+    pub category_styles: Option<HashMap<String, String>>, // Category name to a style like "red bold"
+    pub alternate_screen: Option<bool>, // false draws in the main screen, keeping attached output in native scrollback
+}
+
+/// TUI colors: a built-in theme by `name`, with any of its styles replaced.
+/// Styles are written like "black on blue bold".
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ThemeConfig {
+    pub name: Option<String>,         // "dark" (default), "light" or "solarized"
+    pub highlight: Option<String>,    // Selected row
+    pub section: Option<String>,      // Section headers like Pinned
+    pub header: Option<String>,       // Category headers
+    pub border: Option<String>,
+    pub focus_border: Option<String>, // Focused grid tile and modal panes
+    pub title: Option<String>,        // Pane titles
+    pub log: Option<String>,          // Output in log panes
+    pub muted: Option<String>,        // Secondary text such as pids and URLs
+    pub hint: Option<String>,         // Prompts in the footer
+    pub error: Option<String>,        // Error messages
+}
+
+/// A named pane arrangement, applied with its `key` from the list or grid.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LayoutPreset {
+    pub key: Option<String>,
+    pub view: Option<String>,        // "list", "split" or "grid"
+    pub split: Option<u16>,          // Task list width in split view, in percent
+    pub column: Option<u16>,         // First grid column width, in percent
+    pub row: Option<u16>,            // First grid row height, in percent
+    pub arrangement: Option<String>, // "rows" (default) or "columns"
+    pub pins: Option<Vec<String>>,   // Task ids whose latest runs fill the grid, in tile order
 }
 
 impl Default for UiConfig {
@@ -85,6 +513,11 @@ impl Default for UiConfig {
             status_bar_bg: Some("blue".to_string()),
             command_mode_fg: Some("white bold".to_string()),
             command_mode_bg: Some("red".to_string()),
+            layout: None,
+            layouts: None,
+            backend: None,
+            category_styles: None,
+            alternate_screen: None,
         }
     }
 }
@@ -0,0 +1,145 @@
+use crate::clock::now_epoch;
+use crate::events::{LifecycleReceiver, SessionEvent};
+use crate::instance::{InstanceInfo, InstanceStatus};
+use crate::models::WebhookConfig;
+use serde::Serialize;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    Start,
+    Exit,
+    Failure,
+    Cancel,
+}
+
+impl LifecycleEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleEvent::Start => "start",
+            LifecycleEvent::Exit => "exit",
+            LifecycleEvent::Failure => "failure",
+            LifecycleEvent::Cancel => "cancel",
+        }
+    }
+
+    /// The events a finished instance status fires: `Exit` for every exit,
+    /// with `Failure` too when it did not succeed, or `Cancel`.
+    pub fn for_status(status: &InstanceStatus) -> &'static [Self] {
+        match status {
+            InstanceStatus::Running => &[],
+            InstanceStatus::Exited(0) => &[LifecycleEvent::Exit],
+            InstanceStatus::Exited(_) | InstanceStatus::Error(_) => &[LifecycleEvent::Exit, LifecycleEvent::Failure],
+            InstanceStatus::Cancelled => &[LifecycleEvent::Cancel],
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    event: &'a str,
+    session_id: &'a str,
+    task_id: &'a str,
+    task_name: &'a str,
+    status: String,
+    exit_code: Option<u32>,
+    duration_secs: u64,
+    timestamp: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: String,
+    events: Option<Vec<String>>,
+    timeout: Duration,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            events: config.events.clone(),
+            timeout: Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+        }
+    }
+
+    fn wants(&self, event: LifecycleEvent) -> bool {
+        match &self.events {
+            Some(events) => events.iter().any(|name| name == event.as_str()),
+            None => true,
+        }
+    }
+
+    /// Forwards start and exit events until the bus is dropped.
+    pub fn listen(self, events: LifecycleReceiver) {
+        std::thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                match event {
+                    SessionEvent::TaskStarted { info } => self.notify(LifecycleEvent::Start, &info),
+                    SessionEvent::TaskExited { info } => {
+                        for &event in LifecycleEvent::for_status(&info.status) {
+                            self.notify(event, &info);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// Posts the event in the background; delivery failures are only logged.
+    pub fn notify(&self, event: LifecycleEvent, info: &InstanceInfo) {
+        if !self.wants(event) {
+            return;
+        }
+        let now = now_epoch();
+        let (status, exit_code) = match &info.status {
+            InstanceStatus::Running => ("running".to_string(), None),
+            InstanceStatus::Exited(code) => ("exited".to_string(), Some(*code)),
+            InstanceStatus::Error(err) => (format!("error: {}", err), None),
+            InstanceStatus::Cancelled => ("cancelled".to_string(), None),
+        };
+        let payload = WebhookPayload {
+            event: event.as_str(),
+            session_id: &info.id,
+            task_id: &info.task_id,
+            task_name: &info.task_name,
+            status,
+            exit_code,
+            duration_secs: info.ended_at.unwrap_or(now).saturating_sub(info.started_at),
+            timestamp: now,
+        };
+        let body = match serde_json::to_value(&payload) {
+            Ok(body) => body,
+            Err(err) => {
+                log::warn!("failed to encode webhook payload: {}", err);
+                return;
+            }
+        };
+        let url = self.url.clone();
+        let timeout = self.timeout;
+        std::thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+            if let Err(err) = agent.post(&url).send_json(body) {
+                log::warn!("webhook delivery to {} failed: {}", url, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_exit_fires_exit_and_failed_ones_failure_too() {
+        use LifecycleEvent::*;
+        assert_eq!(LifecycleEvent::for_status(&InstanceStatus::Exited(0)), [Exit]);
+        assert_eq!(LifecycleEvent::for_status(&InstanceStatus::Exited(2)), [Exit, Failure]);
+        assert_eq!(LifecycleEvent::for_status(&InstanceStatus::Error("wait failed".to_string())), [Exit, Failure]);
+        assert_eq!(LifecycleEvent::for_status(&InstanceStatus::Cancelled), [Cancel]);
+        assert!(LifecycleEvent::for_status(&InstanceStatus::Running).is_empty());
+    }
+}
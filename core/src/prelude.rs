@@ -0,0 +1,32 @@
+//! Stable entry points for embedding CmdHub's task execution.
+//!
+//! ```no_run
+//! use cmdhub_core::prelude::*;
+//! use std::collections::HashMap;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let config = load_config_auto().await?;
+//! let manager = SessionManager::from_config(&config, 64 * 1024)?;
+//! let task = &config.tasks[0];
+//! let inputs = HashMap::new();
+//! let command = render_command(&task.command, &inputs, task.inputs.as_ref())?;
+//! let info = manager.spawn(task, &command, &inputs)?;
+//! let (backlog, stream) = manager.subscribe_output(&info.id)?;
+//! # let _ = (backlog, stream);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::config::{load_config, load_config_auto, resolve_config_path, resolve_config_paths};
+pub use crate::events::{EventBus, EventReceiver, SessionEvent};
+pub use crate::instance::{
+    BufferChunk, InstanceInfo, InstanceStatus, LaunchOptions, OutputStream, OverflowPolicy, PtyHandles,
+    RunProgress, SessionManager, SpawnedInstance, SubscribeOptions,
+};
+pub use crate::models::{AppConfig, InputConfig, Task};
+pub use crate::notify::LifecycleEvent;
+pub use crate::pty::PtySession;
+pub use crate::session::{HistoryQuery, NewSession, SessionHealth, SessionInfo, SessionStatus, SessionStore};
+pub use crate::script::prepare_command;
+pub use crate::template::{prepare_cwd, render_command};
+pub use crate::terminate::{terminate, Termination};
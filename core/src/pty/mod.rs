@@ -1,13 +1,19 @@
-use anyhow::Result;
-use portable_pty::{native_pty_system, Child, CommandBuilder, PtyPair, PtySize};
+use crate::terminate::{terminate, Termination};
+use anyhow::{anyhow, Result};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtyPair, PtySize};
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::path::PathBuf;
-use tokio::sync::mpsc;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, watch};
 
+/// A single command running under `sh -c` in its own PTY, without instance tracking.
 pub struct PtySession {
     pub pair: PtyPair,
     pub child: Box<dyn Child + Send + Sync>,
+    // Dropped with the session, which stops its readers.
+    shutdown: watch::Sender<()>,
 }
 
 impl PtySession {
@@ -42,32 +48,60 @@ impl PtySession {
 
         let child = pair.slave.spawn_command(cmd)?;
 
-        Ok(Self { pair, child })
+        Ok(Self { pair, child, shutdown: watch::channel(()).0 })
     }
 
+    /// Forwards output to `tx` until the PTY closes or the session is
+    /// dropped, waiting for the receiver when it falls behind.
+    ///
+    /// Output is read without blocking a thread, which switches the master
+    /// to non-blocking mode: writers taken from `pair.master` may return
+    /// `WouldBlock` when the child is not reading its input.
     pub async fn run(&self, tx: mpsc::Sender<Vec<u8>>) -> Result<()> {
-        let mut reader = self.pair.master.try_clone_reader()?;
-
-        tokio::task::spawn_blocking(move || {
-            let mut buf = [0u8; 1024];
-            while let Ok(n) = reader.read(&mut buf) {
-                if n == 0 {
-                    break;
-                }
-                if tx.blocking_send(buf[..n].to_vec()).is_err() {
+        let reader = PtyReader::new(&*self.pair.master)?;
+        let mut shutdown = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = tokio::select! {
+                    read = reader.read(&mut buf) => match read {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => n,
+                    },
+                    _ = shutdown.changed() => break,
+                };
+                if tx.send(buf[..n].to_vec()).await.is_err() {
                     break;
                 }
             }
         });
-
         Ok(())
     }
 
+    /// Kills the command's whole process group, which it leads since it is
+    /// started in a session of its own, so nothing it forked is left behind.
     pub fn kill(&mut self) -> Result<()> {
+        if let Some(pid) = self.child.process_id() {
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
         self.child.kill()?;
         Ok(())
     }
 
+    /// Stops the command and whatever it started as [`Termination`] says,
+    /// blocking for up to its grace period. Returns true when it stopped
+    /// before SIGKILL was needed.
+    pub fn terminate(&mut self, termination: &Termination) -> bool {
+        let pid = match self.child.process_id() {
+            Some(pid) => pid,
+            None => return true,
+        };
+        let child = &mut self.child;
+        terminate(pid, termination, || matches!(child.try_wait(), Ok(Some(_))))
+    }
+
     pub fn resize(&self, rows: u16, cols: u16) -> Result<()> {
         self.pair.master.resize(PtySize {
             rows,
@@ -78,3 +112,139 @@ impl PtySession {
         Ok(())
     }
 }
+
+/// Reads a PTY master on the tokio reactor instead of a blocking thread.
+pub(crate) struct PtyReader {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl PtyReader {
+    pub(crate) fn new(master: &dyn MasterPty) -> Result<Self> {
+        let fd = dup_master(master)?;
+        set_nonblocking(&fd)?;
+        Ok(Self { fd: AsyncFd::new(fd)? })
+    }
+
+    /// Like `Read::read`; 0 once the other side has closed.
+    pub(crate) async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let result = guard.try_io(|fd| {
+                let n = unsafe { libc::read(fd.get_ref().as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if n < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            match result {
+                Ok(Err(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // Linux reports a closed PTY as EIO.
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::EIO) => return Ok(0),
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Reads what is ready without waiting; `None` when nothing is.
+    pub(crate) fn try_read(&self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        let n = unsafe { libc::read(self.fd.get_ref().as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if n >= 0 {
+            return Ok(Some(n as usize));
+        }
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(None),
+            _ if err.raw_os_error() == Some(libc::EIO) => Ok(Some(0)),
+            _ => Err(err),
+        }
+    }
+}
+
+/// A master writer that waits for room when the PTY's input queue is full,
+/// since [`PtyReader`] put the shared file description in non-blocking mode.
+pub(crate) struct PtyWriter {
+    inner: Box<dyn Write + Send>,
+    fd: OwnedFd,
+}
+
+impl PtyWriter {
+    pub(crate) fn new(master: &dyn MasterPty, inner: Box<dyn Write + Send>) -> Result<Self> {
+        Ok(Self { inner, fd: dup_master(master)? })
+    }
+
+    fn wait_writable(&self) -> io::Result<()> {
+        let mut pollfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLOUT, revents: 0 };
+        if unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for PtyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.write(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => self.wait_writable()?,
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            match self.inner.flush() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => self.wait_writable()?,
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A descriptor of `master`'s own, which keeps the PTY open while it lives.
+pub(crate) fn dup_master(master: &dyn MasterPty) -> Result<OwnedFd> {
+    let fd = master.as_raw_fd().ok_or_else(|| anyhow!("PTY master has no file descriptor"))?;
+    let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// The PTY's current size as rows and columns, whoever last resized it.
+pub(crate) fn pty_size(fd: &OwnedFd) -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } < 0 {
+        return None;
+    }
+    Some((size.ws_row, size.ws_col))
+}
+
+fn set_nonblocking(fd: &OwnedFd) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pty_size_follows_resizes_made_elsewhere() {
+        let pair = native_pty_system()
+            .openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 })
+            .unwrap();
+        let fd = dup_master(&*pair.master).unwrap();
+        assert_eq!(pty_size(&fd), Some((24, 80)));
+        pair.master.resize(PtySize { rows: 50, cols: 132, pixel_width: 0, pixel_height: 0 }).unwrap();
+        assert_eq!(pty_size(&fd), Some((50, 132)));
+    }
+}
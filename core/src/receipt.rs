@@ -0,0 +1,365 @@
+use crate::models::ReceiptConfig;
+use crate::storage;
+use anyhow::{anyhow, Result};
+use fd_lock::RwLock;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub instance_id: String,
+    pub task_id: String,
+    pub command_sha256: String,
+    pub inputs_sha256: String,
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub exit_code: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    pub host: String,
+    pub user: String,
+    pub prev_hash: String,
+    pub hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The per-run facts captured at spawn time; the chain fields are filled in on append.
+#[derive(Debug, Clone)]
+pub struct ReceiptDraft {
+    pub instance_id: String,
+    pub task_id: String,
+    pub command_sha256: String,
+    pub inputs_sha256: String,
+    pub started_at: u64,
+}
+
+impl ReceiptDraft {
+    pub fn new(
+        instance_id: &str,
+        task_id: &str,
+        command: &str,
+        inputs: &HashMap<String, String>,
+        started_at: u64,
+    ) -> Self {
+        // Sort inputs so the hash does not depend on HashMap order.
+        let sorted: BTreeMap<&String, &String> = inputs.iter().collect();
+        let inputs_json = serde_json::to_string(&sorted).unwrap_or_default();
+        Self {
+            instance_id: instance_id.to_string(),
+            task_id: task_id.to_string(),
+            command_sha256: sha256_hex(command.as_bytes()),
+            inputs_sha256: sha256_hex(inputs_json.as_bytes()),
+            started_at,
+        }
+    }
+}
+
+/// Append-only, hash-chained JSONL log of run receipts.
+pub struct ReceiptLog {
+    path: PathBuf,
+    key: Option<Vec<u8>>,
+}
+
+impl ReceiptLog {
+    pub fn from_config(config: &ReceiptConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let path = match &config.path {
+            Some(path) => path.clone(),
+            None => default_receipt_path()?,
+        };
+        let key = match &config.key_env {
+            Some(var) => Some(
+                std::env::var(var)
+                    .map_err(|_| anyhow!("receipt signing key env {} not set", var))?
+                    .into_bytes(),
+            ),
+            None => None,
+        };
+        Ok(Some(Self::new(path, key)))
+    }
+
+    pub fn new(path: PathBuf, key: Option<Vec<u8>>) -> Self {
+        Self {
+            path,
+            key,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, draft: ReceiptDraft, ended_at: u64, exit_code: Option<u32>, cancelled: bool) -> Result<Receipt> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // The TUI and `cmdhub serve` append to the same log, so the last hash
+        // is read and the receipt written under the file's lock.
+        let mut lock = RwLock::new(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        let mut file = lock.write()?;
+        let prev_hash = last_hash(&self.path)?;
+        let mut receipt = Receipt {
+            instance_id: draft.instance_id,
+            task_id: draft.task_id,
+            command_sha256: draft.command_sha256,
+            inputs_sha256: draft.inputs_sha256,
+            started_at: draft.started_at,
+            ended_at,
+            exit_code,
+            cancelled,
+            host: hostname(),
+            user: std::env::var("USER").unwrap_or_default(),
+            prev_hash,
+            hash: String::new(),
+            signature: None,
+        };
+        receipt.hash = chain_hash(&receipt)?;
+        if let Some(key) = &self.key {
+            receipt.signature = Some(sign(key, &receipt.hash)?);
+        }
+        let mut line = serde_json::to_string(&receipt)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        Ok(receipt)
+    }
+
+    /// Re-computes the chain and signatures, returning the verified receipts.
+    pub fn verify(&self) -> Result<Vec<Receipt>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut prev = GENESIS_HASH.to_string();
+        let mut receipts = Vec::new();
+        for (idx, line) in content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let lineno = idx + 1;
+            let receipt: Receipt = serde_json::from_str(line)
+                .map_err(|err| anyhow!("line {}: invalid receipt: {}", lineno, err))?;
+            if receipt.prev_hash != prev {
+                return Err(anyhow!("line {}: chain broken (prev_hash mismatch)", lineno));
+            }
+            if chain_hash(&receipt)? != receipt.hash {
+                return Err(anyhow!("line {}: receipt hash mismatch", lineno));
+            }
+            if let Some(key) = &self.key {
+                if !signed_with(key, &receipt)? {
+                    return Err(anyhow!("line {}: invalid signature", lineno));
+                }
+            }
+            prev = receipt.hash.clone();
+            receipts.push(receipt);
+        }
+        Ok(receipts)
+    }
+}
+
+fn chain_hash(receipt: &Receipt) -> Result<String> {
+    let mut unsigned = receipt.clone();
+    unsigned.hash = String::new();
+    unsigned.signature = None;
+    let body = serde_json::to_string(&unsigned)?;
+    Ok(sha256_hex(body.as_bytes()))
+}
+
+/// The hash of the log's last receipt, read from the end of the file so an
+/// append doesn't read the whole log.
+fn last_hash(path: &Path) -> Result<String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(GENESIS_HASH.to_string()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut start = file.metadata()?.len();
+    let mut tail = Vec::new();
+    loop {
+        let end = tail.iter().rposition(|b| !b" \t\r\n".contains(b)).map_or(0, |pos| pos + 1);
+        let line = match tail[..end].iter().rposition(|&b| b == b'\n') {
+            Some(pos) => &tail[pos + 1..end],
+            None if start == 0 => &tail[..end],
+            None => {
+                let step = start.min(4096);
+                start -= step;
+                let mut chunk = vec![0; step as usize];
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut chunk)?;
+                chunk.extend_from_slice(&tail);
+                tail = chunk;
+                continue;
+            }
+        };
+        if line.is_empty() {
+            return Ok(GENESIS_HASH.to_string());
+        }
+        let receipt: Receipt = serde_json::from_slice(line)?;
+        return Ok(receipt.hash);
+    }
+}
+
+fn sign(key: &[u8], hash: &str) -> Result<String> {
+    Ok(to_hex(&mac(key, hash)?.finalize().into_bytes()))
+}
+
+/// Checks the receipt's signature in constant time.
+fn signed_with(key: &[u8], receipt: &Receipt) -> Result<bool> {
+    let signature = match receipt.signature.as_deref().and_then(from_hex) {
+        Some(signature) => signature,
+        None => return Ok(false),
+    };
+    Ok(mac(key, &receipt.hash)?.verify_slice(&signature).is_ok())
+}
+
+fn mac(key: &[u8], hash: &str) -> Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|err| anyhow!("invalid receipt key: {}", err))?;
+    mac.update(hash.as_bytes());
+    Ok(mac)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn default_receipt_path() -> Result<PathBuf> {
+    Ok(storage::data_dir()?.join("receipts.jsonl"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(key: Option<&str>) -> ReceiptLog {
+        let dir = std::env::temp_dir().join(format!("cmdhub-receipts-{}", uuid::Uuid::new_v4()));
+        ReceiptLog::new(dir.join("receipts.jsonl"), key.map(|key| key.as_bytes().to_vec()))
+    }
+
+    fn append(log: &ReceiptLog, n: u64) -> Receipt {
+        let draft = ReceiptDraft::new(&format!("task#{}", n), "task", "echo hi", &HashMap::new(), n);
+        log.append(draft, n + 1, Some(0), false).unwrap()
+    }
+
+    fn cleanup(log: &ReceiptLog) {
+        let _ = fs::remove_dir_all(log.path().parent().unwrap());
+    }
+
+    #[test]
+    fn receipts_chain_onto_the_last_one() {
+        let log = log(None);
+        let first = append(&log, 1);
+        let second = append(&log, 2);
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(log.verify().unwrap(), vec![first, second]);
+        cleanup(&log);
+    }
+
+    #[test]
+    fn appends_wait_for_other_writers() {
+        let log = log(None);
+        append(&log, 1);
+        // Another process appending holds the file's lock.
+        let mut other = RwLock::new(File::open(log.path()).unwrap());
+        let held = other.write().unwrap();
+        let path = log.path().to_path_buf();
+        let writer = std::thread::spawn(move || append(&ReceiptLog::new(path, None), 2));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!writer.is_finished());
+        drop(held);
+        let second = writer.join().unwrap();
+        let receipts = log.verify().unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(second.prev_hash, receipts[0].hash);
+        cleanup(&log);
+    }
+
+    #[test]
+    fn last_hash_reads_past_trailing_blank_lines_and_long_logs() {
+        let log = log(None);
+        let mut last = None;
+        for n in 0..20 {
+            last = Some(append(&log, n));
+        }
+        let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"\n\n").unwrap();
+        assert!(fs::metadata(log.path()).unwrap().len() > 4096);
+        let last = last.unwrap().hash;
+        assert_eq!(last_hash(log.path()).unwrap(), last);
+        assert_eq!(append(&log, 20).prev_hash, last);
+        assert_eq!(log.verify().unwrap().len(), 21);
+        cleanup(&log);
+    }
+
+    #[test]
+    fn edited_receipts_fail_verification() {
+        let log = log(None);
+        append(&log, 1);
+        append(&log, 2);
+        let content = fs::read_to_string(log.path()).unwrap();
+        fs::write(log.path(), content.replacen("\"exit_code\":0", "\"exit_code\":1", 1)).unwrap();
+        let err = log.verify().unwrap_err().to_string();
+        assert!(err.contains("line 1: receipt hash mismatch"), "{}", err);
+        cleanup(&log);
+    }
+
+    #[test]
+    fn removed_receipts_break_the_chain() {
+        let log = log(None);
+        for n in 0..3 {
+            append(&log, n);
+        }
+        let content = fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        fs::write(log.path(), format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let err = log.verify().unwrap_err().to_string();
+        assert!(err.contains("line 2: chain broken"), "{}", err);
+        cleanup(&log);
+    }
+
+    #[test]
+    fn signatures_need_the_key() {
+        let log = log(Some("k1"));
+        append(&log, 1);
+        assert_eq!(log.verify().unwrap().len(), 1);
+        let other = ReceiptLog::new(log.path().to_path_buf(), Some(b"k2".to_vec()));
+        let err = other.verify().unwrap_err().to_string();
+        assert!(err.contains("invalid signature"), "{}", err);
+        let unsigned = ReceiptLog::new(log.path().to_path_buf(), None);
+        append(&unsigned, 2);
+        let err = log.verify().unwrap_err().to_string();
+        assert!(err.contains("line 2: invalid signature"), "{}", err);
+        cleanup(&log);
+    }
+}
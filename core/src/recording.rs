@@ -0,0 +1,240 @@
+use crate::models::RecordingConfig;
+use crate::storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CAST_EXTENSION: &str = "cast";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CastHeader {
+    pub version: u8,
+    pub width: u16,
+    pub height: u16,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+/// Where asciicast v2 recordings are written and looked up.
+pub struct RecordingStore {
+    dir: PathBuf,
+    enabled: bool,
+}
+
+impl RecordingStore {
+    pub fn from_config(config: &RecordingConfig) -> Result<Self> {
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => default_dir()?,
+        };
+        Ok(Self { dir, enabled: config.enabled })
+    }
+
+    /// Whether runs are recorded when the task does not say otherwise.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn start(&self, instance_id: &str, title: &str, started_at: u64, size: (u16, u16)) -> Result<Recorder> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!(
+            "{}-{}.{}",
+            started_at,
+            storage::run_file_stem(instance_id),
+            CAST_EXTENSION
+        ));
+        Recorder::create(&path, title, started_at, size)
+    }
+
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        storage::run_files(&self.dir, CAST_EXTENSION)
+    }
+
+    /// Resolves a path, a file name, or the most recent recording of an instance id.
+    pub fn resolve(&self, name: &str) -> Result<PathBuf> {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        self.matching(name.trim_end_matches(".cast"))?
+            .pop()
+            .ok_or_else(|| anyhow!("no recording matching {}", name))
+    }
+
+    /// Every recording of an instance id, one per start of the run, oldest first.
+    pub fn matching(&self, instance_id: &str) -> Result<Vec<PathBuf>> {
+        storage::run_files_of(&self.dir, CAST_EXTENSION, instance_id)
+    }
+}
+
+/// Appends timed output events to one asciicast file.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,
+    // Trailing bytes of an incomplete UTF-8 sequence, held until the next chunk.
+    pending: Vec<u8>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, title: &str, started_at: u64, size: (u16, u16)) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut env = HashMap::new();
+        for key in ["SHELL", "TERM"] {
+            if let Ok(value) = std::env::var(key) {
+                env.insert(key.to_string(), value);
+            }
+        }
+        let header = CastHeader {
+            version: 2,
+            width: size.1,
+            height: size.0,
+            timestamp: started_at,
+            title: Some(title.to_string()),
+            env,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(Self { writer, started: Instant::now(), pending: Vec::new() })
+    }
+
+    pub fn write_output(&mut self, data: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // An error with no length means the input ended mid-sequence.
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        if valid == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+        self.pending.drain(..valid);
+        self.write_event("o", &text)
+    }
+
+    /// Records the terminal being resized to `size` rows and columns.
+    pub fn write_resize(&mut self, size: (u16, u16)) -> Result<()> {
+        self.write_event("r", &format!("{}x{}", size.1, size.0))
+    }
+
+    fn write_event(&mut self, kind: &str, data: &str) -> Result<()> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        serde_json::to_writer(&mut self.writer, &(elapsed, kind, data))?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayOptions {
+    pub speed: f64,
+    /// Longest pause between events, so idle stretches don't stall playback.
+    pub max_idle: Option<Duration>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self { speed: 1.0, max_idle: None }
+    }
+}
+
+/// Plays a recording's output events to `out` with their original timing.
+pub fn replay<W: Write>(path: &Path, options: ReplayOptions, out: &mut W) -> Result<CastHeader> {
+    let (header, events) = open_cast(path)?;
+    let speed = if options.speed > 0.0 { options.speed } else { 1.0 };
+    let mut last = 0.0f64;
+    for event in events {
+        let (time, kind, data) = event?;
+        let mut delay = Duration::from_secs_f64(((time - last) / speed).max(0.0));
+        if let Some(max_idle) = options.max_idle {
+            delay = delay.min(max_idle);
+        }
+        last = time;
+        std::thread::sleep(delay);
+        if kind == "o" {
+            out.write_all(data.as_bytes())?;
+            out.flush()?;
+        }
+    }
+    Ok(header)
+}
+
+/// A recording's header, the time of its last event in seconds, and its
+/// output with the timing dropped.
+pub fn cast_output(path: &Path) -> Result<(CastHeader, f64, String)> {
+    let (header, events) = open_cast(path)?;
+    let mut duration = 0.0f64;
+    let mut output = String::new();
+    for event in events {
+        let (time, kind, data) = event?;
+        duration = time;
+        if kind == "o" {
+            output.push_str(&data);
+        }
+    }
+    Ok((header, duration, output))
+}
+
+type CastEvent = (f64, String, String);
+
+fn open_cast(path: &Path) -> Result<(CastHeader, impl Iterator<Item = Result<CastEvent>>)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+    let header_line = lines.next().ok_or_else(|| anyhow!("{}: empty recording", path.display()))??;
+    let header: CastHeader =
+        serde_json::from_str(&header_line).map_err(|err| anyhow!("{}: bad header: {}", path.display(), err))?;
+    if header.version != 2 {
+        return Err(anyhow!("{}: unsupported asciicast version {}", path.display(), header.version));
+    }
+    let path = path.to_path_buf();
+    let events = lines
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(move |line| {
+            serde_json::from_str(&line?).map_err(|err| anyhow!("{}: bad event: {}", path.display(), err))
+        });
+    Ok((header, events))
+}
+
+fn default_dir() -> Result<PathBuf> {
+    Ok(storage::data_dir()?.join("recordings"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_the_size_and_its_changes() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-recording-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.cast");
+        let mut recorder = Recorder::create(&path, "run", 0, (40, 120)).unwrap();
+        recorder.write_output(b"before\n").unwrap();
+        recorder.write_resize((30, 100)).unwrap();
+        recorder.write_output(b"after\n").unwrap();
+        drop(recorder);
+
+        let (header, events) = open_cast(&path).unwrap();
+        assert_eq!((header.height, header.width), (40, 120));
+        let events: Vec<(String, String)> = events.map(|event| event.unwrap()).map(|(_, kind, data)| (kind, data)).collect();
+        let expected = [("o", "before\n"), ("r", "100x30"), ("o", "after\n")];
+        assert_eq!(events, expected.map(|(kind, data)| (kind.to_string(), data.to_string())));
+        let (_, _, output) = cast_output(&path).unwrap();
+        assert_eq!(output, "before\nafter\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
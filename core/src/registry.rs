@@ -0,0 +1,284 @@
+use crate::events::{LifecycleReceiver, SessionEvent};
+use crate::instance::{task_shell, InstanceInfo, InstanceStatus};
+use crate::models::Task;
+use crate::session::{local_host, NewSession, SessionInfo, SessionStatus, SessionStore, HEARTBEAT_INTERVAL};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Finished runs kept in the session store's history.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Publishes runs to the session store so other CmdHub processes can see
+/// them before launching a `unique` task, and keeps finished runs with their
+/// output as history.
+pub struct RunRegistry {
+    store: SessionStore,
+    runs: Mutex<HashMap<String, Uuid>>,
+}
+
+impl RunRegistry {
+    pub fn new(store: SessionStore) -> Self {
+        Self {
+            store,
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Live runs of `task_id` recorded by other processes.
+    pub fn running_elsewhere(&self, task_id: &str) -> Result<Vec<SessionInfo>> {
+        Ok(self
+            .store
+            .list_sessions()?
+            .into_iter()
+            .filter(|info| info.task_id == task_id && self.store.hosted_elsewhere(info))
+            .collect())
+    }
+
+    pub fn next_seq(&self, task_id: &str) -> Result<u32> {
+        self.store.next_seq(task_id)
+    }
+
+    pub fn register(&self, task: &Task, command: &str, inputs: &HashMap<String, String>, info: &InstanceInfo) -> Result<()> {
+        let session = new_session(task, command, inputs, info);
+        self.store.insert_session(&session)?;
+        self.track(info, session.id)
+    }
+
+    /// Registers the run unless another process has a live run of the task,
+    /// which is returned instead. Other processes cannot register a run of
+    /// the task between the check and the write.
+    pub fn claim(&self, task: &Task, command: &str, inputs: &HashMap<String, String>, info: &InstanceInfo) -> Result<Option<SessionInfo>> {
+        let session = new_session(task, command, inputs, info);
+        let other = self.store.claim_session(&session, |other| self.store.hosted_elsewhere(other))?;
+        if other.is_none() {
+            self.track(info, session.id)?;
+        }
+        Ok(other)
+    }
+
+    /// Records the pid of a claimed run once its command is spawned.
+    pub fn started(&self, info: &InstanceInfo) -> Result<()> {
+        let id = self.session_id(&info.id)?;
+        self.store.update_session(id, |session| session.child_pid = info.child_pid)?;
+        Ok(())
+    }
+
+    /// Drops the session of a claimed run whose command failed to spawn.
+    pub fn release(&self, instance_id: &str) -> Result<()> {
+        let id = self.runs.lock().map_err(|_| anyhow!("registry lock poisoned"))?.remove(instance_id);
+        match id {
+            Some(id) => self.store.discard_session(id),
+            None => Ok(()),
+        }
+    }
+
+    fn track(&self, info: &InstanceInfo, session_id: Uuid) -> Result<()> {
+        self.runs
+            .lock()
+            .map_err(|_| anyhow!("registry lock poisoned"))?
+            .insert(info.id.clone(), session_id);
+        Ok(())
+    }
+
+    fn session_id(&self, instance_id: &str) -> Result<Uuid> {
+        self.runs
+            .lock()
+            .map_err(|_| anyhow!("registry lock poisoned"))?
+            .get(instance_id)
+            .copied()
+            .ok_or_else(|| anyhow!("{} is not registered", instance_id))
+    }
+
+    /// The `events.jsonl` of the session a run is registered under.
+    pub fn events_path(&self, instance_id: &str) -> Option<PathBuf> {
+        let id = *self.runs.lock().ok()?.get(instance_id)?;
+        Some(self.store.session_events_path(id))
+    }
+
+    /// Points an existing session at a new run of its task, e.g. after the
+    /// process that hosted it died.
+    pub fn adopt(&self, session_id: Uuid, info: &InstanceInfo) -> Result<()> {
+        self.store.update_session(session_id, |session| {
+            session.status = SessionStatus::Running;
+            session.started_at = info.started_at;
+            session.ended_at = None;
+            session.duration_secs = None;
+            session.exit_code = None;
+            session.runner_pid = Some(std::process::id());
+            session.host = Some(local_host().to_string());
+            session.child_pid = info.child_pid;
+            session.restarts += 1;
+            session.heartbeat_at = Some(info.started_at);
+        })?;
+        self.track(info, session_id)
+    }
+
+    /// Records the run's name and note with its session, which may already
+    /// be in history.
+    pub fn annotate(&self, instance_id: &str, label: Option<String>, note: Option<String>) -> Result<()> {
+        let registered = self.runs.lock().map_err(|_| anyhow!("registry lock poisoned"))?.get(instance_id).copied();
+        let id = match registered {
+            Some(id) => id,
+            None => self.store.find_session(instance_id)?.id,
+        };
+        self.store.annotate(id, label, note)?;
+        Ok(())
+    }
+
+    /// Records the run's exit code and output, marks it exited and moves it
+    /// to the store's history.
+    pub fn finish(&self, instance_id: &str, exit_code: Option<u32>, output: Option<Vec<u8>>) {
+        let id = match self.runs.lock().ok().and_then(|mut runs| runs.remove(instance_id)) {
+            Some(id) => id,
+            None => return,
+        };
+        let result = self.store.load_session(id).and_then(|mut session| {
+            if let Some(output) = output {
+                self.store.write_log(id, &output)?;
+            }
+            session.exit_code = exit_code;
+            self.store.write_session(&session)?;
+            self.store.mark_exited(&mut session)?;
+            self.store.move_to_history(id, HISTORY_LIMIT)
+        });
+        if let Err(err) = result {
+            log::warn!("failed to record exit of {} in session store: {}", instance_id, err);
+        }
+    }
+
+    /// Finishes registered runs as their exit events arrive, keeping the
+    /// output `output` returns for each, and records heartbeats for the
+    /// runs still going.
+    pub fn listen(self: Arc<Self>, events: LifecycleReceiver, output: impl Fn(&str) -> Option<Vec<u8>> + Send + 'static) {
+        let registry = Arc::clone(&self);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+            let ids: Vec<Uuid> = match registry.runs.lock() {
+                Ok(runs) => runs.values().copied().collect(),
+                Err(_) => return,
+            };
+            for id in ids {
+                // Fails for a run finished in the meantime.
+                if let Err(err) = registry.store.beat(id) {
+                    log::debug!("no heartbeat for session {}: {}", id, err);
+                }
+            }
+        });
+        std::thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                if let SessionEvent::TaskExited { info } = event {
+                    if info.status != InstanceStatus::Running {
+                        self.finish(&info.id, info.status.exit_code(), output(&info.id));
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Why a `unique = "global"` task cannot start while `other` is running.
+pub fn running_elsewhere_message(task_id: &str, other: &SessionInfo) -> String {
+    format!(
+        "task {} is already running in another CmdHub session (pid {})",
+        task_id,
+        other.runner_pid.unwrap_or_default()
+    )
+}
+
+/// The session record of a run, ready to be written.
+fn new_session(task: &Task, command: &str, inputs: &HashMap<String, String>, info: &InstanceInfo) -> SessionInfo {
+    let mut session = SessionInfo::from(NewSession::for_task(task, Some(info.id.clone()), command));
+    session.status = SessionStatus::Running;
+    session.started_at = info.started_at;
+    session.runner_pid = Some(std::process::id());
+    session.child_pid = info.child_pid;
+    session.seq = Some(info.seq);
+    session.restarts = info.restarts;
+    if task.container.is_none() {
+        session.shell = Some(task_shell(task));
+    }
+    session.login_profile = task.login_profile.unwrap_or(false);
+    session.timeout_secs = task.timeout_secs;
+    session.pty = Some(task.pty.unwrap_or(true));
+    session.tags = info.tags.clone();
+    session.heartbeat_at = Some(info.started_at);
+    session.label = info.label.clone();
+    session.note = info.note.clone();
+    session.record_inputs(task, inputs);
+    session
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+    use std::time::{Duration, Instant};
+
+    fn registry() -> Arc<RunRegistry> {
+        let dir = std::env::temp_dir().join(format!("cmdhub-registry-{}", Uuid::new_v4()));
+        Arc::new(RunRegistry::new(SessionStore::in_dir(dir).unwrap()))
+    }
+
+    fn task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            name: id.to_string(),
+            command: "make".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exit_event_moves_the_run_to_history() {
+        let registry = registry();
+        let bus = EventBus::new(4);
+        Arc::clone(&registry).listen(bus.subscribe_lifecycle(), |_| Some(b"done\n".to_vec()));
+        let info = InstanceInfo::for_test("build", InstanceStatus::Running);
+        registry.register(&task("build"), "make", &HashMap::new(), &info).unwrap();
+        assert_eq!(registry.store.list_sessions().unwrap().len(), 1);
+
+        bus.publish(SessionEvent::TaskExited {
+            info: InstanceInfo::for_test("build", InstanceStatus::Exited(2)),
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let finished = loop {
+            let history = registry.store.list_history().unwrap();
+            if let Some(session) = history.into_iter().next() {
+                break session;
+            }
+            assert!(Instant::now() < deadline, "run never reached history");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(registry.store.list_sessions().unwrap().is_empty());
+        assert_eq!(finished.status, SessionStatus::Exited);
+        assert_eq!(finished.exit_code, Some(2));
+        assert_eq!(registry.store.read_history_log(finished.id).unwrap(), b"done\n");
+    }
+
+    #[test]
+    fn claimed_runs_get_their_pid_or_are_released() {
+        let registry = registry();
+        let mut info = InstanceInfo::for_test("build", InstanceStatus::Running);
+        assert!(registry.claim(&task("build"), "make", &HashMap::new(), &info).unwrap().is_none());
+        info.child_pid = Some(4242);
+        registry.started(&info).unwrap();
+        let sessions = registry.store.list_sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].child_pid, Some(4242));
+        assert_eq!(sessions[0].runner_pid, Some(std::process::id()));
+
+        registry.release(&info.id).unwrap();
+        assert!(registry.store.list_sessions().unwrap().is_empty());
+        assert!(registry.started(&info).is_err());
+    }
+
+    #[test]
+    fn finishing_an_unregistered_run_is_a_no_op() {
+        let registry = registry();
+        registry.finish("build#1", Some(0), None);
+        assert!(registry.store.list_history().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::models::Task;
+use crate::template::render_command;
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A task's Rhai script. Each hook is optional:
+///
+/// - `fn defaults(inputs)` returns a map of input values to pre-fill
+/// - `fn validate(inputs)` returns an error string (or `false`) to reject the inputs
+/// - `fn transform(command, inputs)` returns the command to spawn
+pub struct TaskScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl TaskScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(1_000_000);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| anyhow!("script {}: {}", path.display(), err))?;
+        Ok(Self { engine, ast })
+    }
+
+    pub fn for_task(task: &Task) -> Result<Option<Self>> {
+        match &task.script {
+            Some(path) => Ok(Some(Self::load(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn has_fn(&self, name: &str, params: usize) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+    }
+
+    fn call(&self, name: &str, args: impl rhai::FuncArgs) -> Result<Dynamic> {
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|err| anyhow!("script {}(): {}", name, err))
+    }
+
+    pub fn defaults(&self, values: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+        if !self.has_fn("defaults", 1) {
+            return Ok(HashMap::new());
+        }
+        let result = self.call("defaults", (to_map(values),))?;
+        let map = result
+            .try_cast::<Map>()
+            .ok_or_else(|| anyhow!("script defaults() must return a map"))?;
+        Ok(map.into_iter().map(|(key, value)| (key.to_string(), value.to_string())).collect())
+    }
+
+    /// Returns the script's reason for rejecting `values`, if any.
+    pub fn validate(&self, values: &HashMap<String, String>) -> Result<Option<String>> {
+        if !self.has_fn("validate", 1) {
+            return Ok(None);
+        }
+        let result = self.call("validate", (to_map(values),))?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+        if let Some(ok) = result.clone().try_cast::<bool>() {
+            return Ok((!ok).then(|| "inputs rejected by script".to_string()));
+        }
+        let message = result.to_string();
+        Ok((!message.trim().is_empty()).then_some(message))
+    }
+
+    pub fn transform(&self, command: String, values: &HashMap<String, String>) -> Result<String> {
+        if !self.has_fn("transform", 2) {
+            return Ok(command);
+        }
+        let result = self.call("transform", (command, to_map(values)))?;
+        result
+            .try_cast::<String>()
+            .ok_or_else(|| anyhow!("script transform() must return a string"))
+    }
+}
+
+fn to_map(values: &HashMap<String, String>) -> Map {
+    values
+        .iter()
+        .map(|(key, value)| (key.as_str().into(), Dynamic::from(value.clone())))
+        .collect()
+}
+
+/// Runs the task script's `validate` hook, if there is one.
+pub fn validate_inputs(task: &Task, values: &HashMap<String, String>) -> Result<Option<String>> {
+    match TaskScript::for_task(task)? {
+        Some(script) => script.validate(values),
+        None => Ok(None),
+    }
+}
+
+/// Renders the task's command from `values`, running its script hooks if it
+/// has one: missing values are filled from `defaults`, then `validate` and
+/// `transform` run around the template rendering.
+pub fn prepare_command(task: &Task, values: &mut HashMap<String, String>) -> Result<String> {
+    let script = TaskScript::for_task(task)?;
+    if let Some(script) = &script {
+        for (key, value) in script.defaults(values)? {
+            values.entry(key).or_insert(value);
+        }
+        if let Some(reason) = script.validate(values)? {
+            return Err(anyhow!("{}", reason));
+        }
+    }
+    let command = render_command(&task.command, values, task.inputs.as_ref())
+        .map_err(|err| anyhow!("render command: {}", err))?;
+    match &script {
+        Some(script) => script.transform(command, values),
+        None => Ok(command),
+    }
+}
@@ -0,0 +1,65 @@
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Sends `state`, like `READY=1`, to systemd when it started this process
+/// as a `Type=notify` service. Returns false when there is no one to tell.
+pub fn notify(state: &str) -> bool {
+    let path = match std::env::var(NOTIFY_SOCKET) {
+        Ok(path) if !path.is_empty() => path,
+        _ => return false,
+    };
+    let socket = match UnixDatagram::unbound() {
+        Ok(socket) => socket,
+        Err(_) => return false,
+    };
+    let sent = match path.strip_prefix('@') {
+        Some(name) => send_abstract(&socket, name, state),
+        None => socket.send_to(state.as_bytes(), &path).is_ok(),
+    };
+    if !sent {
+        log::debug!("failed to notify systemd at {}: {}", path, state);
+    }
+    sent
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &str, state: &str) -> bool {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+    SocketAddr::from_abstract_name(name)
+        .and_then(|addr| socket.send_to_addr(state.as_bytes(), &addr))
+        .is_ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_socket: &UnixDatagram, _name: &str, _state: &str) -> bool {
+    false
+}
+
+/// How often to send `WATCHDOG=1`: half the `WatchdogSec` systemd set for
+/// this process, if any.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Reports readiness and keeps the watchdog fed from a background thread
+/// for as long as the process lives.
+pub fn ready() {
+    if !notify("READY=1") {
+        return;
+    }
+    if let Some(interval) = watchdog_interval() {
+        std::thread::spawn(move || loop {
+            notify("WATCHDOG=1");
+            std::thread::sleep(interval);
+        });
+    }
+}
@@ -0,0 +1,153 @@
+use crate::models::EnvValue;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a keyring lookup or secret command may take, so a locked
+/// keyring waiting for an unlock prompt fails the run instead of hanging it.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches the secret `from` names: `keyring:service/key` from the OS
+/// keyring or `command:pass show x` from a command's output.
+pub fn fetch(from: &str) -> Result<String> {
+    let (scheme, target) = from.split_once(':').ok_or_else(|| anyhow!("secret source {:?} has no scheme", from))?;
+    let mut cmd = match scheme {
+        "keyring" => {
+            let (service, key) = target
+                .split_once('/')
+                .ok_or_else(|| anyhow!("expected keyring:service/key, got {}", from))?;
+            keyring_command(service, key)
+        }
+        "command" => {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(target);
+            cmd
+        }
+        _ => return Err(anyhow!("unknown secret source {}; use keyring: or command:", scheme)),
+    };
+    let (status, stdout, stderr) = run(&mut cmd, FETCH_TIMEOUT)
+        .map_err(|err| anyhow!("{}: cannot run {}: {}", from, cmd.get_program().to_string_lossy(), err))?;
+    let status = status.ok_or_else(|| anyhow!("{}: no answer within {}s", from, FETCH_TIMEOUT.as_secs()))?;
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        return Err(anyhow!("{}: {} {}", from, status, stderr.trim()));
+    }
+    let value = String::from_utf8(stdout).map_err(|_| anyhow!("{}: not UTF-8", from))?;
+    Ok(value.strip_suffix('\n').map(|value| value.strip_suffix('\r').unwrap_or(value)).unwrap_or(&value).to_string())
+}
+
+/// Runs `cmd` with its output collected; the status is `None` when it was
+/// killed, with whatever it started, for running past `timeout`.
+fn run(cmd: &mut Command, timeout: Duration) -> io::Result<(Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    Ok((status, stdout.join().unwrap_or_default(), stderr.join().unwrap_or_default()))
+}
+
+/// Reads `pipe` to the end on its own thread, so a full pipe can't stall the child.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_command(service: &str, key: &str) -> Command {
+    let mut cmd = Command::new("security");
+    cmd.args(["find-generic-password", "-s", service, "-a", key, "-w"]);
+    cmd
+}
+
+/// Looks the secret up in the Secret Service (GNOME Keyring, KWallet) under
+/// the attributes Python's keyring and most other clients store it with.
+#[cfg(not(target_os = "macos"))]
+fn keyring_command(service: &str, key: &str) -> Command {
+    let mut cmd = Command::new("secret-tool");
+    cmd.args(["lookup", "service", service, "username", key]);
+    cmd
+}
+
+/// Whether `env` has secrets to fetch before a command can get it.
+pub fn has_secrets(env: Option<&HashMap<String, EnvValue>>) -> bool {
+    env.into_iter().flatten().any(|(_, value)| matches!(value, EnvValue::Secret { .. }))
+}
+
+/// `env` as a command gets it, with each secret fetched now.
+pub fn resolve_env(env: Option<&HashMap<String, EnvValue>>) -> Result<HashMap<String, String>> {
+    env.into_iter()
+        .flatten()
+        .map(|(key, value)| {
+            let value = match value {
+                EnvValue::Value(value) => value.clone(),
+                EnvValue::Secret { from } => fetch(from).map_err(|err| anyhow!("env {}: {}", key, err))?,
+            };
+            Ok((key.clone(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_secrets_drop_the_trailing_newline() {
+        assert_eq!(fetch("command:printf 'hunter2\\n'").unwrap(), "hunter2");
+        assert_eq!(fetch("command:printf 'a\\nb'").unwrap(), "a\nb");
+    }
+
+    #[test]
+    fn failing_commands_report_their_stderr() {
+        let err = fetch("command:echo locked >&2; exit 3").unwrap_err().to_string();
+        assert!(err.contains("locked"), "{}", err);
+    }
+
+    #[test]
+    fn slow_commands_are_killed_with_their_children() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("sleep 30 & echo started; wait");
+        let started = Instant::now();
+        let (status, stdout, _) = run(&mut cmd, Duration::from_millis(300)).unwrap();
+        assert_eq!(status, None);
+        assert_eq!(stdout, b"started\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn has_secrets_looks_for_secret_values() {
+        let mut env = HashMap::new();
+        env.insert("A".to_string(), EnvValue::Value("1".to_string()));
+        assert!(!has_secrets(Some(&env)));
+        env.insert("B".to_string(), EnvValue::Secret { from: "command:true".to_string() });
+        assert!(has_secrets(Some(&env)));
+        assert!(!has_secrets(None));
+    }
+}
@@ -1,17 +1,35 @@
+mod sqlite;
+
+use crate::clock::now_epoch;
+use crate::models::{EnvValue, InputConfig, Task};
+use crate::storage::{self, SessionBackend};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
+use fd_lock::RwLock;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use sqlite::SqliteIndex;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::OnceLock;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// How often a process hosting runs records that it is still alive.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Heartbeats a host may miss before its runs are reported stale.
+const MISSED_HEARTBEATS: u64 = 3;
+/// Finished runs kept per task in the run index behind `cmdhub stats`.
+pub const RUN_INDEX_LIMIT: usize = 1000;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum SessionStatus {
     Pending,
     Running,
     Exited,
+    /// The process hosting the run died without recording an exit.
+    Crashed,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,28 +40,255 @@ pub struct SessionInfo {
     pub session_name: Option<String>,
     pub command: String,
     pub cwd: Option<PathBuf>,
-    pub env: Option<HashMap<String, String>>,
+    pub env: Option<HashMap<String, EnvValue>>,
     pub env_clear: bool,
     pub status: SessionStatus,
     pub started_at: u64,
     pub ended_at: Option<u64>,
     pub exit_code: Option<u32>,
     pub runner_pid: Option<u32>,
+    /// Machine `runner_pid` is on, from [`local_host`]. Runs recorded
+    /// without one are taken to be local.
+    #[serde(default)]
+    pub host: Option<String>,
     pub child_pid: Option<u32>,
     pub socket_path: Option<PathBuf>,
     #[serde(default)]
     pub running_task_pids: Vec<u32>,
+    #[serde(default)]
+    pub crashed_at: Option<u64>,
+    #[serde(default)]
+    pub restarts: u32,
+    /// Per-task run number, as in the run's `task#N` id.
+    #[serde(default)]
+    pub seq: Option<u32>,
+    /// Shell a local run started in; `cwd` and `env` above are likewise the
+    /// effective values, launch overrides included.
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub login_profile: bool,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub pty: Option<bool>,
+    /// Seconds from start to exit or crash, recorded when the run ends.
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// When the host last recorded a heartbeat for the run.
+    #[serde(default)]
+    pub heartbeat_at: Option<u64>,
+    /// Name and note the user gave the run, as on its [`InstanceInfo`].
+    ///
+    /// [`InstanceInfo`]: crate::instance::InstanceInfo
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Input values the command was rendered from, passwords left out.
+    #[serde(default)]
+    pub inputs: HashMap<String, String>,
+}
+
+impl SessionInfo {
+    /// Keeps `values` as the run's inputs, except those of `task`'s
+    /// password inputs.
+    pub fn record_inputs(&mut self, task: &Task, values: &HashMap<String, String>) {
+        let inputs = task.inputs.as_ref();
+        self.inputs = values
+            .iter()
+            .filter(|(name, _)| !matches!(inputs.and_then(|inputs| inputs.get(*name)), Some(InputConfig::Password { .. })))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+    }
+
+    /// `task` as this run started it: its cwd, env, shell, timeout, PTY
+    /// mode and tags, to run [`SessionInfo::command`] again.
+    pub fn rerun_task(&self, task: &Task) -> Task {
+        let mut task = task.clone();
+        task.cwd = self.cwd.clone();
+        task.env = self.env.clone();
+        task.env_clear = Some(self.env_clear);
+        if self.shell.is_some() {
+            task.shell = self.shell.clone();
+        }
+        task.login_profile = Some(self.login_profile);
+        task.timeout_secs = self.timeout_secs;
+        if self.pty.is_some() {
+            task.pty = self.pty;
+        }
+        task.tags = Some(self.tags.clone());
+        task
+    }
+
+    /// A live run whose host stopped recording heartbeats, so it may be hung
+    /// or gone on another machine sharing the store. Runs recorded before
+    /// heartbeats existed never are.
+    pub fn heartbeat_stale(&self) -> bool {
+        let limit = HEARTBEAT_INTERVAL.as_secs() * MISSED_HEARTBEATS;
+        self.status == SessionStatus::Running
+            && self.heartbeat_at.is_some_and(|at| now_epoch().saturating_sub(at) > limit)
+    }
+
+    /// Whether the run's host is this machine, so its pids mean something here.
+    pub fn is_local(&self) -> bool {
+        self.host.as_deref().is_none_or(|host| host == local_host())
+    }
+}
+
+/// A run about to be recorded with [`SessionStore::create_session`].
+pub struct NewSession {
+    pub task_id: String,
+    pub task_name: String,
+    pub session_name: Option<String>,
+    pub command: String,
+    pub cwd: Option<PathBuf>,
+    pub env: Option<HashMap<String, EnvValue>>,
+    pub env_clear: bool,
+}
+
+impl NewSession {
+    /// `command` run for `task`, with the task's cwd and env.
+    pub fn for_task(task: &Task, session_name: Option<String>, command: &str) -> Self {
+        Self {
+            task_id: task.id.clone(),
+            task_name: task.name.clone(),
+            session_name,
+            command: command.to_string(),
+            cwd: task.cwd.clone(),
+            env: task.env.clone(),
+            env_clear: task.env_clear.unwrap_or(false),
+        }
+    }
+}
+
+impl From<NewSession> for SessionInfo {
+    /// A pending session with a fresh id, started now.
+    fn from(new: NewSession) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            task_id: new.task_id,
+            task_name: new.task_name,
+            session_name: new.session_name,
+            command: new.command,
+            cwd: new.cwd,
+            env: new.env,
+            env_clear: new.env_clear,
+            status: SessionStatus::Pending,
+            started_at: now_epoch(),
+            ended_at: None,
+            exit_code: None,
+            runner_pid: None,
+            host: Some(local_host().to_string()),
+            child_pid: None,
+            socket_path: None,
+            running_task_pids: Vec::new(),
+            crashed_at: None,
+            restarts: 0,
+            seq: None,
+            shell: None,
+            login_profile: false,
+            timeout_secs: None,
+            pty: None,
+            duration_secs: None,
+            tags: Vec::new(),
+            heartbeat_at: None,
+            label: None,
+            note: None,
+            inputs: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionHealth {
+    Alive,
+    RunnerDead,
+    /// On another machine that stopped recording heartbeats for it.
+    HostSilent,
+}
+
+/// Narrows [`SessionStore::query_history`]; the default matches every
+/// finished run.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub task_id: Option<String>,
+    /// Exited 0, or with `false` anything else: a non-zero exit or a crash.
+    pub succeeded: Option<bool>,
+    /// Started at or after this epoch second.
+    pub since: Option<u64>,
+    /// Newest first, so this keeps the most recent.
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, info: &SessionInfo) -> bool {
+        self.task_id.as_ref().is_none_or(|task_id| &info.task_id == task_id)
+            && self.since.is_none_or(|since| info.started_at >= since)
+            && self.succeeded.is_none_or(|succeeded| {
+                (info.status == SessionStatus::Exited && info.exit_code == Some(0)) == succeeded
+            })
+    }
 }
 
+/// A finished run as kept in its task's run index, which outlives the
+/// pruning of history.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunSummary {
+    pub id: Uuid,
+    pub task_id: String,
+    pub status: SessionStatus,
+    pub exit_code: Option<u32>,
+    pub started_at: u64,
+    pub duration_secs: Option<u64>,
+}
+
+impl RunSummary {
+    pub fn succeeded(&self) -> bool {
+        self.status == SessionStatus::Exited && self.exit_code == Some(0)
+    }
+}
+
+impl From<&SessionInfo> for RunSummary {
+    fn from(info: &SessionInfo) -> Self {
+        Self {
+            id: info.id,
+            task_id: info.task_id.clone(),
+            status: info.status,
+            exit_code: info.exit_code,
+            started_at: info.started_at,
+            duration_secs: info.duration_secs,
+        }
+    }
+}
+
+/// Session metadata persisted as JSON under `~/.cmdhub/sessions`, or in a
+/// SQLite database when `[store] backend = "sqlite"`. Output logs are
+/// always files in the session directories.
 pub struct SessionStore {
     active_dir: PathBuf,
     history_dir: PathBuf,
+    index: Option<SqliteIndex>,
 }
 
 impl SessionStore {
     pub fn new() -> Result<Self> {
-        let home = std::env::var("HOME").map_err(|_| anyhow!("HOME not set"))?;
-        let base_dir = Path::new(&home).join(".cmdhub").join("sessions");
+        let index = match storage::session_backend() {
+            SessionBackend::Files => None,
+            SessionBackend::Sqlite(path) => Some(SqliteIndex::open(&path)?),
+        };
+        Self::open(storage::data_dir()?.join("sessions"), index)
+    }
+
+    /// A files-backed store under `base_dir`.
+    #[cfg(test)]
+    pub(crate) fn in_dir(base_dir: PathBuf) -> Result<Self> {
+        Self::open(base_dir, None)
+    }
+
+    fn open(base_dir: PathBuf, index: Option<SqliteIndex>) -> Result<Self> {
         let active_dir = base_dir.join("active");
         let history_dir = base_dir.join("history");
         fs::create_dir_all(&active_dir)?;
@@ -51,9 +296,42 @@ impl SessionStore {
         Ok(Self {
             active_dir,
             history_dir,
+            index,
         })
     }
 
+    /// Hands out the next run number for `task_id`, shared by every CmdHub
+    /// process using this store.
+    pub fn next_seq(&self, task_id: &str) -> Result<u32> {
+        if let Some(index) = &self.index {
+            return index.next_seq(task_id);
+        }
+        // The lock is a file of its own, as sequences.json is replaced on every update.
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.sequences_path().with_extension("lock"))?;
+        let mut lock = RwLock::new(file);
+        let _guard = lock.write()?;
+        let path = self.sequences_path();
+        let mut sequences: HashMap<String, u32> = match fs::read(&path) {
+            Ok(data) if !data.trim_ascii().is_empty() => serde_json::from_slice(&data)?,
+            Ok(_) => HashMap::new(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let seq = sequences.entry(task_id.to_string()).or_insert(0);
+        *seq += 1;
+        let next = *seq;
+        replace_file(&path, &serde_json::to_vec_pretty(&sequences)?)?;
+        Ok(next)
+    }
+
+    fn sequences_path(&self) -> PathBuf {
+        self.active_dir.with_file_name("sequences.json")
+    }
+
     pub fn session_dir(&self, id: Uuid) -> PathBuf {
         self.active_dir.join(id.to_string())
     }
@@ -66,68 +344,293 @@ impl SessionStore {
         self.session_dir(id).join("meta.json")
     }
 
+    fn session_lock_path(&self, id: Uuid) -> PathBuf {
+        self.session_dir(id).join(".lock")
+    }
+
     pub fn session_log_path(&self, id: Uuid) -> PathBuf {
         self.session_dir(id).join("output.log")
     }
 
-    pub fn create_session(
-        &self,
-        task_id: String,
-        task_name: String,
-        session_name: Option<String>,
-        command: String,
-        cwd: Option<PathBuf>,
-        env: Option<HashMap<String, String>>,
-        env_clear: bool,
-    ) -> Result<SessionInfo> {
-        let id = Uuid::new_v4();
-        let dir = self.session_dir(id);
-        fs::create_dir_all(&dir)?;
-        let info = SessionInfo {
-            id,
-            task_id,
-            task_name,
-            session_name,
-            command,
-            cwd,
-            env,
-            env_clear,
-            status: SessionStatus::Pending,
-            started_at: now_epoch(),
-            ended_at: None,
-            exit_code: None,
-            runner_pid: None,
-            child_pid: None,
-            socket_path: None,
-            running_task_pids: Vec::new(),
-        };
-        self.write_session(&info)?;
+    /// Where the run's output is written as JSON events while it runs.
+    pub fn session_events_path(&self, id: Uuid) -> PathBuf {
+        self.session_dir(id).join("events.jsonl")
+    }
+
+    pub fn create_session(&self, new: NewSession) -> Result<SessionInfo> {
+        let info = SessionInfo::from(new);
+        self.insert_session(&info)?;
         Ok(info)
     }
 
+    pub fn insert_session(&self, info: &SessionInfo) -> Result<()> {
+        fs::create_dir_all(self.session_dir(info.id))?;
+        self.write_session(info)
+    }
+
+    /// Inserts `info` unless an active session of the same task matches
+    /// `conflicts`, which is returned instead. Processes sharing the store
+    /// check and insert one at a time.
+    pub fn claim_session(&self, info: &SessionInfo, conflicts: impl Fn(&SessionInfo) -> bool) -> Result<Option<SessionInfo>> {
+        if let Some(index) = &self.index {
+            fs::create_dir_all(self.session_dir(info.id))?;
+            return index.claim(info, conflicts);
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.active_dir.with_file_name("claims.lock"))?;
+        let mut lock = RwLock::new(file);
+        let _guard = lock.write()?;
+        let other = list_sessions_in(&self.active_dir)?
+            .into_iter()
+            .find(|other| other.task_id == info.task_id && conflicts(other));
+        if other.is_none() {
+            self.insert_session(info)?;
+        }
+        Ok(other)
+    }
+
+    /// Deletes an active session, e.g. one claimed for a run that failed to start.
+    pub fn discard_session(&self, id: Uuid) -> Result<()> {
+        if let Some(index) = &self.index {
+            index.delete(id)?;
+        }
+        match fs::remove_dir_all(self.session_dir(id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
     pub fn load_session(&self, id: Uuid) -> Result<SessionInfo> {
+        if let Some(index) = &self.index {
+            return index.load(id);
+        }
         let meta_path = self.session_meta_path(id);
         let data = fs::read(&meta_path)?;
         let info: SessionInfo = serde_json::from_slice(&data)?;
         Ok(info)
     }
 
+    /// Finds a session, active or in history, by its id, a unique prefix of
+    /// the id, or its run's `task#N` id.
+    pub fn find_session(&self, query: &str) -> Result<SessionInfo> {
+        let sessions = self.list_sessions()?.into_iter().chain(self.list_history()?);
+        let mut prefixed = Vec::new();
+        for info in sessions {
+            let id = info.id.to_string();
+            if id == query || info.session_name.as_deref() == Some(query) {
+                return Ok(info);
+            }
+            if id.starts_with(query) {
+                prefixed.push(info);
+            }
+        }
+        match prefixed.len() {
+            0 => Err(anyhow!("no session matching {}", query)),
+            1 => Ok(prefixed.remove(0)),
+            count => Err(anyhow!("{} sessions match {}; give more of the id", count, query)),
+        }
+    }
+
     pub fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
-        list_sessions_in(&self.active_dir)
+        match &self.index {
+            Some(index) => index.list(false),
+            None => list_sessions_in(&self.active_dir),
+        }
     }
 
     pub fn list_history(&self) -> Result<Vec<SessionInfo>> {
-        list_sessions_in(&self.history_dir)
+        match &self.index {
+            Some(index) => index.list(true),
+            None => list_sessions_in(&self.history_dir),
+        }
+    }
+
+    /// Finished runs matching `query`, newest first. The SQLite backend
+    /// answers from its indexes; the file one reads every record.
+    pub fn query_history(&self, query: &HistoryQuery) -> Result<Vec<SessionInfo>> {
+        if let Some(index) = &self.index {
+            return index.query(query);
+        }
+        let mut runs: Vec<SessionInfo> = list_sessions_in(&self.history_dir)?
+            .into_iter()
+            .filter(|info| query.matches(info))
+            .collect();
+        runs.sort_by_key(|info| std::cmp::Reverse(info.started_at));
+        if let Some(limit) = query.limit {
+            runs.truncate(limit);
+        }
+        Ok(runs)
     }
 
+    /// Replaces meta.json atomically while holding the session's lock, so
+    /// readers never see a half-written file.
     pub fn write_session(&self, info: &SessionInfo) -> Result<()> {
-        let meta_path = self.session_meta_path(info.id);
-        let data = serde_json::to_vec_pretty(info)?;
-        fs::write(meta_path, data)?;
+        if let Some(index) = &self.index {
+            return index.put(info);
+        }
+        let mut lock = self.open_lock(info.id)?;
+        let _guard = lock.write()?;
+        self.replace_meta(info)
+    }
+
+    /// Reads, modifies and writes meta.json under the session's lock, so
+    /// concurrent writers (host, TUI, `kill`) do not lose each other's fields.
+    pub fn update_session(&self, id: Uuid, update: impl FnOnce(&mut SessionInfo)) -> Result<SessionInfo> {
+        if let Some(index) = &self.index {
+            return index.update(id, update);
+        }
+        let mut lock = self.open_lock(id)?;
+        let _guard = lock.write()?;
+        let mut info = self.load_session(id)?;
+        update(&mut info);
+        self.replace_meta(&info)?;
+        Ok(info)
+    }
+
+    /// Records that the run's host is alive.
+    pub fn beat(&self, id: Uuid) -> Result<()> {
+        self.update_session(id, |info| info.heartbeat_at = Some(now_epoch()))?;
         Ok(())
     }
 
+    /// Marks a live run whose host is gone as crashed and moves it to
+    /// history, for when no supervisor is around to.
+    pub fn prune(&self, id: Uuid, max_entries: usize) -> Result<()> {
+        self.update_session(id, |info| {
+            let now = now_epoch();
+            info.status = SessionStatus::Crashed;
+            info.crashed_at = Some(now);
+            info.duration_secs = Some(now.saturating_sub(info.started_at));
+            info.runner_pid = None;
+            info.socket_path = None;
+        })?;
+        self.move_to_history(id, max_entries)
+    }
+
+    /// Sets the run's name and note, whether it is active or in history.
+    pub fn annotate(&self, id: Uuid, label: Option<String>, note: Option<String>) -> Result<SessionInfo> {
+        let update = |info: &mut SessionInfo| {
+            info.label = label;
+            info.note = note;
+        };
+        if self.index.is_some() || self.session_dir(id).exists() {
+            return self.update_session(id, update);
+        }
+        let meta_path = self.history_session_dir(id).join("meta.json");
+        let mut info: SessionInfo = serde_json::from_slice(&fs::read(&meta_path)?)?;
+        update(&mut info);
+        let tmp_path = meta_path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&info)?)?;
+        fs::rename(&tmp_path, &meta_path)?;
+        Ok(info)
+    }
+
+    fn open_lock(&self, id: Uuid) -> Result<RwLock<File>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.session_lock_path(id))?;
+        Ok(RwLock::new(file))
+    }
+
+    fn replace_meta(&self, info: &SessionInfo) -> Result<()> {
+        replace_file(&self.session_meta_path(info.id), &serde_json::to_vec_pretty(info)?)
+    }
+
+    /// Checks that the runner process still exists, so callers can tell a
+    /// live run from one whose host died. Runs hosted on another machine
+    /// are judged by their heartbeats instead.
+    pub fn probe_session(&self, info: &SessionInfo) -> SessionHealth {
+        if !info.is_local() {
+            return match info.heartbeat_stale() {
+                true => SessionHealth::HostSilent,
+                false => SessionHealth::Alive,
+            };
+        }
+        if let Some(pid) = info.runner_pid {
+            if !pid_alive(pid) {
+                return SessionHealth::RunnerDead;
+            }
+        }
+        SessionHealth::Alive
+    }
+
+    /// Whether `info` is a live run hosted by another process, here or on
+    /// another machine.
+    pub fn hosted_elsewhere(&self, info: &SessionInfo) -> bool {
+        info.status == SessionStatus::Running
+            && info.runner_pid.is_some_and(|pid| !info.is_local() || pid != std::process::id())
+            && self.probe_session(info) == SessionHealth::Alive
+    }
+
+    pub fn mark_exited(&self, info: &mut SessionInfo) -> Result<()> {
+        *info = self.update_session(info.id, |current| {
+            current.status = SessionStatus::Exited;
+            if current.ended_at.is_none() {
+                current.ended_at = Some(now_epoch());
+            }
+            current.duration_secs = current.ended_at.map(|ended| ended.saturating_sub(current.started_at));
+            current.runner_pid = None;
+            current.socket_path = None;
+            current.running_task_pids.clear();
+        })?;
+        Ok(())
+    }
+
+    pub fn read_log(&self, id: Uuid) -> Result<Vec<u8>> {
+        let path = self.session_log_path(id);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(fs::read(path)?)
+    }
+
+    pub fn write_log(&self, id: Uuid, output: &[u8]) -> Result<()> {
+        fs::write(self.session_log_path(id), output)?;
+        Ok(())
+    }
+
+    /// `output.log` of an active session or of one in history.
+    pub fn read_session_log(&self, id: Uuid) -> Result<Vec<u8>> {
+        if self.session_log_path(id).exists() {
+            return self.read_log(id);
+        }
+        self.read_history_log(id)
+    }
+
+    /// `events.jsonl` of an active session or of one in history, if it has one.
+    pub fn read_session_events(&self, id: Uuid) -> Result<Option<Vec<u8>>> {
+        let active = self.session_events_path(id);
+        let path = if active.exists() { active } else { self.history_session_dir(id).join("events.jsonl") };
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+
+    /// Output of a finished run, as kept in history.
+    pub fn read_history_log(&self, id: Uuid) -> Result<Vec<u8>> {
+        let path = self.history_session_dir(id).join("output.log");
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(fs::read(path)?)
+    }
+
     pub fn move_to_history(&self, id: Uuid, max_entries: usize) -> Result<()> {
+        // Recorded before the move, while the run still loads as active.
+        match self.load_session(id) {
+            Ok(info) => {
+                if let Err(err) = self.record_run(&RunSummary::from(&info)) {
+                    log::warn!("failed to add {} to the run index: {}", id, err);
+                }
+            }
+            Err(err) => log::debug!("{} not in the run index: {}", id, err),
+        }
         let from = self.session_dir(id);
         let to = self.history_session_dir(id);
         if from.exists() {
@@ -136,11 +639,83 @@ impl SessionStore {
             }
             fs::rename(from, to)?;
         }
+        if let Some(index) = &self.index {
+            index.archive(id)?;
+        }
         self.prune_history(max_entries)?;
         Ok(())
     }
 
+    /// Finished runs of `task_id` from its run index, oldest first.
+    pub fn task_runs(&self, task_id: &str) -> Result<Vec<RunSummary>> {
+        if let Some(index) = &self.index {
+            return index.task_runs(task_id);
+        }
+        let path = self.run_index_path(task_id);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(parse_run_index(&data, task_id))
+    }
+
+    /// Adds a run to its task's index, replacing an earlier record of it and
+    /// dropping the oldest beyond [`RUN_INDEX_LIMIT`].
+    fn record_run(&self, run: &RunSummary) -> Result<()> {
+        if let Some(index) = &self.index {
+            return index.record_run(run, RUN_INDEX_LIMIT);
+        }
+        let path = self.run_index_path(&run.task_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        let mut lock = RwLock::new(file);
+        let mut guard = lock.write()?;
+        let mut data = String::new();
+        guard.read_to_string(&mut data)?;
+        let mut runs = parse_run_index(&data, &run.task_id);
+        runs.retain(|other| other.id != run.id);
+        runs.push(run.clone());
+        runs.sort_by_key(|run| run.started_at);
+        let excess = runs.len().saturating_sub(RUN_INDEX_LIMIT);
+        let mut out = Vec::new();
+        for run in &runs[excess..] {
+            serde_json::to_writer(&mut out, run)?;
+            out.push(b'\n');
+        }
+        guard.seek(SeekFrom::Start(0))?;
+        guard.set_len(0)?;
+        guard.write_all(&out)?;
+        Ok(())
+    }
+
+    /// One JSON line per run, in a file named after the task.
+    fn run_index_path(&self, task_id: &str) -> PathBuf {
+        let mut name = String::new();
+        for byte in task_id.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                name.push(byte as char);
+            } else {
+                name.push_str(&format!("%{:02x}", byte));
+            }
+        }
+        self.active_dir.with_file_name("runs").join(format!("{}.jsonl", name))
+    }
+
     pub fn prune_history(&self, max_entries: usize) -> Result<()> {
+        if let Some(index) = &self.index {
+            for id in index.prune(max_entries)? {
+                let _ = fs::remove_dir_all(self.history_session_dir(id));
+            }
+            return Ok(());
+        }
         let mut sessions = list_sessions_in(&self.history_dir)?;
         if sessions.len() <= max_entries {
             return Ok(());
@@ -157,6 +732,31 @@ impl SessionStore {
     }
 }
 
+/// Writes `data` to a temporary file beside `path` and renames it over
+/// `path`, so readers see the old contents or the new, never part of them.
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// This machine's hostname, which tells runs on a shared store apart by host.
+pub fn local_host() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return String::new();
+        }
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    })
+}
+
 fn list_sessions_in(dir: &Path) -> Result<Vec<SessionInfo>> {
     let mut sessions = Vec::new();
     for entry in fs::read_dir(dir)? {
@@ -178,9 +778,170 @@ fn list_sessions_in(dir: &Path) -> Result<Vec<SessionInfo>> {
     Ok(sessions)
 }
 
-fn now_epoch() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or_default()
+/// EPERM means the pid now belongs to another user's process, which a
+/// runner started by this user cannot be.
+fn pid_alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+fn parse_run_index(data: &str, task_id: &str) -> Vec<RunSummary> {
+    data.lines()
+        .filter_map(|line| serde_json::from_str::<RunSummary>(line).ok())
+        .filter(|run| run.task_id == task_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("cmdhub-sessions-{}", Uuid::new_v4()))
+    }
+
+    fn stores() -> Vec<SessionStore> {
+        let sqlite = temp_dir();
+        let index = SqliteIndex::open(&sqlite.join("sessions.db")).unwrap();
+        vec![
+            SessionStore::in_dir(temp_dir()).unwrap(),
+            SessionStore::open(sqlite, Some(index)).unwrap(),
+        ]
+    }
+
+    fn running(task_id: &str) -> SessionInfo {
+        let task = Task {
+            id: task_id.to_string(),
+            name: task_id.to_string(),
+            command: "make".to_string(),
+            ..Default::default()
+        };
+        let mut info = SessionInfo::from(NewSession::for_task(&task, None, "make"));
+        info.status = SessionStatus::Running;
+        info
+    }
+
+    fn is_running(info: &SessionInfo) -> bool {
+        info.status == SessionStatus::Running
+    }
+
+    #[test]
+    fn claim_refuses_a_second_live_run_of_the_task() {
+        for store in stores() {
+            let first = running("build");
+            assert!(store.claim_session(&first, is_running).unwrap().is_none());
+            let other = store.claim_session(&running("build"), is_running).unwrap();
+            assert_eq!(other.map(|other| other.id), Some(first.id));
+            assert!(store.claim_session(&running("test"), is_running).unwrap().is_none());
+            assert_eq!(store.list_sessions().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn claim_ignores_sessions_that_do_not_conflict() {
+        for store in stores() {
+            store.claim_session(&running("build"), is_running).unwrap();
+            assert!(store.claim_session(&running("build"), |_| false).unwrap().is_none());
+            assert_eq!(store.list_sessions().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn concurrent_claims_let_one_run_through() {
+        for store in stores() {
+            let store = Arc::new(store);
+            let claims: Vec<_> = (0..8)
+                .map(|_| {
+                    let store = Arc::clone(&store);
+                    std::thread::spawn(move || store.claim_session(&running("build"), is_running).unwrap().is_none())
+                })
+                .collect();
+            let won = claims.into_iter().map(|claim| claim.join().unwrap()).filter(|&won| won).count();
+            assert_eq!(won, 1);
+            assert_eq!(store.list_sessions().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn concurrent_run_numbers_are_unique() {
+        for store in stores() {
+            let store = Arc::new(store);
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let store = Arc::clone(&store);
+                    std::thread::spawn(move || (0..10).map(|_| store.next_seq("build").unwrap()).collect::<Vec<_>>())
+                })
+                .collect();
+            let mut seqs: Vec<u32> = threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect();
+            seqs.sort_unstable();
+            assert_eq!(seqs, (1..=80).collect::<Vec<_>>());
+            assert_eq!(store.next_seq("test").unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn discarded_sessions_are_gone() {
+        for store in stores() {
+            let info = running("build");
+            store.claim_session(&info, is_running).unwrap();
+            store.discard_session(info.id).unwrap();
+            assert!(store.list_sessions().unwrap().is_empty());
+            assert!(!store.session_dir(info.id).exists());
+        }
+    }
+
+    #[test]
+    fn remote_runs_are_judged_by_heartbeat() {
+        let store = SessionStore::in_dir(temp_dir()).unwrap();
+        let mut info = running("build");
+        info.host = Some(format!("{}-elsewhere", local_host()));
+        // A pid that is alive here, and one that is not, mean nothing there.
+        info.runner_pid = Some(std::process::id());
+        info.heartbeat_at = Some(now_epoch());
+        assert_eq!(store.probe_session(&info), SessionHealth::Alive);
+        assert!(store.hosted_elsewhere(&info));
+        info.runner_pid = Some(u32::MAX / 2);
+        assert_eq!(store.probe_session(&info), SessionHealth::Alive);
+        info.heartbeat_at = Some(now_epoch() - HEARTBEAT_INTERVAL.as_secs() * (MISSED_HEARTBEATS + 1));
+        assert_eq!(store.probe_session(&info), SessionHealth::HostSilent);
+        assert!(!store.hosted_elsewhere(&info));
+    }
+
+    #[test]
+    fn sessions_without_a_host_are_local() {
+        let mut info = running("build");
+        assert_eq!(info.host.as_deref(), Some(local_host()));
+        assert!(info.is_local());
+        info.host = None;
+        assert!(info.is_local());
+        let store = SessionStore::in_dir(temp_dir()).unwrap();
+        info.runner_pid = Some(u32::MAX / 2);
+        assert_eq!(store.probe_session(&info), SessionHealth::RunnerDead);
+    }
+
+    #[test]
+    fn reaped_runners_are_dead() {
+        assert!(pid_alive(std::process::id()));
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!pid_alive(pid));
+        let mut info = running("build");
+        info.runner_pid = Some(pid);
+        assert_eq!(SessionStore::in_dir(temp_dir()).unwrap().probe_session(&info), SessionHealth::RunnerDead);
+    }
+
+    #[test]
+    fn own_runs_are_not_hosted_elsewhere() {
+        let store = SessionStore::in_dir(temp_dir()).unwrap();
+        let mut info = running("build");
+        info.runner_pid = Some(std::process::id());
+        assert!(!store.hosted_elsewhere(&info));
+        let mut other = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+        info.runner_pid = Some(other.id());
+        assert!(store.hosted_elsewhere(&info));
+        other.kill().unwrap();
+        other.wait().unwrap();
+        assert!(!store.hosted_elsewhere(&info));
+    }
 }
@@ -0,0 +1,255 @@
+use super::{HistoryQuery, RunSummary, SessionInfo};
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use uuid::Uuid;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The full record is kept as JSON; the other columns exist for queries.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    task_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    exit_code INTEGER,
+    started_at INTEGER NOT NULL,
+    archived INTEGER NOT NULL DEFAULT 0,
+    info TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_by_start ON sessions (archived, started_at);
+CREATE INDEX IF NOT EXISTS sessions_by_task ON sessions (task_id, started_at);
+CREATE TABLE IF NOT EXISTS runs (
+    id TEXT PRIMARY KEY,
+    task_id TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    summary TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_by_task ON runs (task_id, started_at);
+CREATE TABLE IF NOT EXISTS sequences (
+    task_id TEXT PRIMARY KEY,
+    seq INTEGER NOT NULL
+);
+";
+
+/// Session metadata in a SQLite database. Writes are short transactions
+/// that wait for other writers instead of failing. The default rollback
+/// journal is kept because WAL does not work on network filesystems.
+pub(super) struct SqliteIndex {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(|err| anyhow!("cannot open {}: {}", path.display(), err))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow!("session database lock poisoned"))
+    }
+
+    pub fn next_seq(&self, task_id: &str) -> Result<u32> {
+        let seq = self.conn()?.query_row(
+            "INSERT INTO sequences (task_id, seq) VALUES (?1, 1)
+             ON CONFLICT (task_id) DO UPDATE SET seq = seq + 1
+             RETURNING seq",
+            params![task_id],
+            |row| row.get(0),
+        )?;
+        Ok(seq)
+    }
+
+    /// Inserts or replaces the record, leaving an archived run archived.
+    pub fn put(&self, info: &SessionInfo) -> Result<()> {
+        put(&*self.conn()?, info)
+    }
+
+    pub fn load(&self, id: Uuid) -> Result<SessionInfo> {
+        load(&*self.conn()?, id)
+    }
+
+    pub fn update(&self, id: Uuid, update: impl FnOnce(&mut SessionInfo)) -> Result<SessionInfo> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut info = load(&tx, id)?;
+        update(&mut info);
+        put(&tx, &info)?;
+        tx.commit()?;
+        Ok(info)
+    }
+
+    /// Puts `info` unless an active run of its task matches `conflicts`,
+    /// in one write transaction.
+    pub fn claim(&self, info: &SessionInfo, conflicts: impl Fn(&SessionInfo) -> bool) -> Result<Option<SessionInfo>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut other = None;
+        {
+            let mut stmt = tx.prepare("SELECT info FROM sessions WHERE archived = 0 AND task_id = ?1")?;
+            let rows = stmt.query_map(params![info.task_id], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                if let Ok(session) = serde_json::from_str::<SessionInfo>(&row?) {
+                    if conflicts(&session) {
+                        other = Some(session);
+                        break;
+                    }
+                }
+            }
+        }
+        if other.is_none() {
+            put(&tx, info)?;
+        }
+        tx.commit()?;
+        Ok(other)
+    }
+
+    pub fn delete(&self, id: Uuid) -> Result<()> {
+        self.conn()?.execute("DELETE FROM sessions WHERE id = ?1", params![id.to_string()])?;
+        Ok(())
+    }
+
+    /// Active runs, or finished ones with `archived`, oldest first.
+    pub fn list(&self, archived: bool) -> Result<Vec<SessionInfo>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT info FROM sessions WHERE archived = ?1 ORDER BY started_at")?;
+        let rows = stmt.query_map(params![archived], |row| row.get::<_, String>(0))?;
+        let mut sessions = Vec::new();
+        for row in rows {
+            if let Ok(info) = serde_json::from_str(&row?) {
+                sessions.push(info);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Archived runs matching `query`, newest first.
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<SessionInfo>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT info FROM sessions
+             WHERE archived = 1
+               AND (?1 IS NULL OR task_id = ?1)
+               AND (?2 IS NULL OR started_at >= ?2)
+               AND (?3 IS NULL OR (status = 'exited' AND COALESCE(exit_code, -1) = 0) = ?3)
+             ORDER BY started_at DESC
+             LIMIT ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                query.task_id,
+                query.since.map(|since| since as i64),
+                query.succeeded,
+                query.limit.map_or(-1, |limit| limit as i64),
+            ],
+            |row| row.get::<_, String>(0),
+        )?;
+        let mut sessions = Vec::new();
+        for row in rows {
+            if let Ok(info) = serde_json::from_str(&row?) {
+                sessions.push(info);
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub fn archive(&self, id: Uuid) -> Result<()> {
+        self.conn()?
+            .execute("UPDATE sessions SET archived = 1 WHERE id = ?1", params![id.to_string()])?;
+        Ok(())
+    }
+
+    /// Adds a run to its task's index and drops that task's oldest beyond
+    /// `max_entries`.
+    pub fn record_run(&self, run: &RunSummary, max_entries: usize) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO runs (id, task_id, started_at, summary) VALUES (?1, ?2, ?3, ?4)",
+            params![run.id.to_string(), run.task_id, run.started_at as i64, serde_json::to_string(run)?],
+        )?;
+        tx.execute(
+            "DELETE FROM runs WHERE id IN (
+                 SELECT id FROM runs WHERE task_id = ?1 ORDER BY started_at DESC LIMIT -1 OFFSET ?2
+             )",
+            params![run.task_id, max_entries as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The task's indexed runs, oldest first.
+    pub fn task_runs(&self, task_id: &str) -> Result<Vec<RunSummary>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT summary FROM runs WHERE task_id = ?1 ORDER BY started_at")?;
+        let rows = stmt.query_map(params![task_id], |row| row.get::<_, String>(0))?;
+        let mut runs = Vec::new();
+        for row in rows {
+            if let Ok(run) = serde_json::from_str(&row?) {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Drops the oldest archived runs beyond `max_entries` and returns their ids.
+    pub fn prune(&self, max_entries: usize) -> Result<Vec<Uuid>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM sessions WHERE archived = 1 ORDER BY started_at DESC LIMIT -1 OFFSET ?1",
+            )?;
+            let rows = stmt.query_map(params![max_entries as i64], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for id in &ids {
+            tx.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+}
+
+fn load(conn: &Connection, id: Uuid) -> Result<SessionInfo> {
+    let data: Option<String> = conn
+        .query_row(
+            "SELECT info FROM sessions WHERE id = ?1 AND archived = 0",
+            params![id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let data = data.ok_or_else(|| anyhow!("session {} not found", id))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn put(conn: &Connection, info: &SessionInfo) -> Result<()> {
+    let status = serde_json::to_value(info.status)?;
+    conn.execute(
+        "INSERT INTO sessions (id, task_id, status, exit_code, started_at, info)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (id) DO UPDATE SET
+             task_id = excluded.task_id,
+             status = excluded.status,
+             exit_code = excluded.exit_code,
+             started_at = excluded.started_at,
+             info = excluded.info",
+        params![
+            info.id.to_string(),
+            info.task_id,
+            status.as_str().unwrap_or_default(),
+            info.exit_code,
+            info.started_at as i64,
+            serde_json::to_string(info)?,
+        ],
+    )?;
+    Ok(())
+}
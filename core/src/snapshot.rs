@@ -0,0 +1,283 @@
+use crate::models::{EnvValue, SnapshotConfig, Task};
+use crate::storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const PROBE_OUTPUT_LIMIT: usize = 4096;
+const SECRET_MARKERS: [&str; 5] = ["TOKEN", "SECRET", "PASSWORD", "PASSWD", "KEY"];
+
+/// Environment and tool versions recorded when a run starts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnvSnapshot {
+    pub instance_id: String,
+    pub task_id: String,
+    pub captured_at: u64,
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+    pub probes: BTreeMap<String, String>,
+}
+
+pub struct SnapshotStore {
+    dir: PathBuf,
+    probes: Vec<String>,
+}
+
+impl SnapshotStore {
+    pub fn from_config(config: &SnapshotConfig) -> Result<Self> {
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => default_dir()?,
+        };
+        Ok(Self { dir, probes: config.probes.clone().unwrap_or_default() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Captures the environment the task's shell will see, with `env` the
+    /// task's variables as resolved for the run, and writes it to disk.
+    pub fn capture(&self, task: &Task, env: &HashMap<String, String>, instance_id: &str, captured_at: u64) -> Result<PathBuf> {
+        let env = task_env(task, env);
+        let mut probes = BTreeMap::new();
+        for probe in self.probes.iter().chain(task.snapshot_probes.iter().flatten()) {
+            probes.insert(probe.clone(), run_probe(probe, task, &env));
+        }
+        let snapshot = EnvSnapshot {
+            instance_id: instance_id.to_string(),
+            task_id: task.id.clone(),
+            captured_at,
+            cwd: task.cwd.clone().or_else(|| std::env::current_dir().ok()),
+            env: env.iter().map(|(key, value)| (key.clone(), redact(task, key, value))).collect(),
+            probes,
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}-{}.json", captured_at, storage::run_file_stem(instance_id)));
+        fs::write(&path, serde_json::to_vec_pretty(&snapshot)?)?;
+        Ok(path)
+    }
+
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        storage::run_files(&self.dir, "json")
+    }
+
+    /// Resolves a path, or a file name / instance id inside the snapshot dir.
+    pub fn resolve(&self, name: &str) -> Result<PathBuf> {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        storage::run_files_of(&self.dir, "json", name)?
+            .pop()
+            .ok_or_else(|| anyhow!("no snapshot matching {}", name))
+    }
+}
+
+pub fn load_snapshot(path: &Path) -> Result<EnvSnapshot> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data).map_err(|err| anyhow!("{}: {}", path.display(), err))
+}
+
+/// Lists differences as `-`/`+` lines; empty when the environments match.
+pub fn diff_snapshots(left: &EnvSnapshot, right: &EnvSnapshot) -> Vec<String> {
+    let mut lines = Vec::new();
+    if left.cwd != right.cwd {
+        lines.push(format!("cwd: {:?} -> {:?}", left.cwd, right.cwd));
+    }
+    diff_maps("probe", &left.probes, &right.probes, &mut lines);
+    diff_maps("env", &left.env, &right.env, &mut lines);
+    lines
+}
+
+fn diff_maps(
+    label: &str,
+    left: &BTreeMap<String, String>,
+    right: &BTreeMap<String, String>,
+    lines: &mut Vec<String>,
+) {
+    for (key, value) in left {
+        match right.get(key) {
+            Some(other) if other == value => {}
+            Some(other) => {
+                lines.push(format!("{} {}:", label, key));
+                lines.push(format!("  - {}", value));
+                lines.push(format!("  + {}", other));
+            }
+            None => lines.push(format!("- {} {}={}", label, key, value)),
+        }
+    }
+    for (key, value) in right {
+        if !left.contains_key(key) {
+            lines.push(format!("+ {} {}={}", label, key, value));
+        }
+    }
+}
+
+fn task_env(task: &Task, overrides: &HashMap<String, String>) -> BTreeMap<String, String> {
+    let mut env: BTreeMap<String, String> = if task.env_clear.unwrap_or(false) {
+        BTreeMap::new()
+    } else {
+        std::env::vars().collect()
+    };
+    env.extend(overrides.iter().map(|(key, value)| (key.clone(), value.clone())));
+    env
+}
+
+/// What the snapshot keeps of a variable: where a secret comes from, only
+/// that a secret-looking one is set, and any other value as is.
+fn redact(task: &Task, key: &str, value: &str) -> String {
+    if let Some(secret @ EnvValue::Secret { .. }) = task.env.as_ref().and_then(|env| env.get(key)) {
+        return secret.describe();
+    }
+    let upper = key.to_ascii_uppercase();
+    if SECRET_MARKERS.iter().any(|marker| upper.contains(marker)) {
+        "<set>".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn run_probe(probe: &str, task: &Task, env: &BTreeMap<String, String>) -> String {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(format!("{} 2>&1", probe))
+        .env_clear()
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    if let Some(cwd) = &task.cwd {
+        cmd.current_dir(cwd);
+    }
+    let mut child = match cmd.process_group(0).spawn() {
+        Ok(child) => child,
+        Err(err) => return format!("<error: {}>", err),
+    };
+    // Read while the probe runs, so output beyond the pipe's capacity can't
+    // block it; what is past the limit is read and dropped.
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut stdout) = stdout {
+            let _ = (&mut stdout).take(PROBE_OUTPUT_LIMIT as u64).read_to_end(&mut output);
+            let _ = io::copy(&mut stdout, &mut io::sink());
+        }
+        let _ = tx.send(output);
+    });
+    // The probe's group, including whatever it left running in the background.
+    let kill = |child: &mut Child| {
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        let _ = child.wait();
+    };
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                kill(&mut child);
+                return "<timed out>".to_string();
+            }
+            Err(err) => return format!("<error: {}>", err),
+        }
+    };
+    let output = match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(output) => output,
+        Err(_) => {
+            kill(&mut child);
+            rx.recv().unwrap_or_default()
+        }
+    };
+    let text = String::from_utf8_lossy(&output).trim().to_string();
+    if status.success() {
+        text
+    } else {
+        format!("<exit {}> {}", status.code().unwrap_or(-1), text)
+    }
+}
+
+fn default_dir() -> Result<PathBuf> {
+    Ok(storage::data_dir()?.join("snapshots"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(command: &str) -> (String, Duration) {
+        let started = Instant::now();
+        let output = run_probe(command, &Task::default(), &std::env::vars().collect());
+        (output, started.elapsed())
+    }
+
+    #[test]
+    fn probes_with_more_output_than_a_pipe_holds_finish() {
+        let (output, took) = probe("head -c 200000 /dev/zero | tr '\\0' x");
+        assert_eq!(output.len(), PROBE_OUTPUT_LIMIT);
+        assert!(took < PROBE_TIMEOUT);
+    }
+
+    #[test]
+    fn probes_report_failures() {
+        assert_eq!(probe("echo nope; exit 2").0, "<exit 2> nope");
+    }
+
+    #[test]
+    fn background_children_do_not_hold_up_the_probe() {
+        let (output, took) = probe("echo 1.2.3; sleep 30 &");
+        assert_eq!(output, "1.2.3");
+        assert!(took < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn snapshots_resolve_to_the_latest_of_a_run() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-snapshots-{}", uuid::Uuid::new_v4()));
+        let store = SnapshotStore { dir: dir.clone(), probes: Vec::new() };
+        let task = Task { id: "build".to_string(), ..Task::default() };
+        let env = HashMap::new();
+        let first = store.capture(&task, &env, "build#1", 100).unwrap();
+        let later = store.capture(&task, &env, "build#1", 200).unwrap();
+        store.capture(&task, &env, "build#11", 300).unwrap();
+        assert_eq!(store.list().unwrap().len(), 3);
+        assert_eq!(store.resolve("build#1").unwrap(), later);
+        assert_eq!(store.resolve(first.to_str().unwrap()).unwrap(), first);
+        assert!(store.resolve("build#2").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn probes_see_secrets_the_snapshot_does_not_keep() {
+        let dir = std::env::temp_dir().join(format!("cmdhub-snapshots-{}", uuid::Uuid::new_v4()));
+        let store = SnapshotStore { dir: dir.clone(), probes: vec!["echo $DB_PASSWORD $API $MODE".to_string()] };
+        let task: Task = toml::from_str(
+            "id = 'build'\nname = 'build'\ncommand = 'true'\nenv_clear = true\n\
+             [env]\nMODE = 'ci'\nDB_PASSWORD = 'hunter2'\nAPI = { from = 'command:echo s3cret' }\n",
+        )
+        .unwrap();
+        let env = HashMap::from([
+            ("MODE".to_string(), "ci".to_string()),
+            ("DB_PASSWORD".to_string(), "hunter2".to_string()),
+            ("API".to_string(), "s3cret".to_string()),
+        ]);
+        let snapshot = load_snapshot(&store.capture(&task, &env, "build#1", 100).unwrap()).unwrap();
+        assert_eq!(snapshot.probes.values().next().unwrap(), "hunter2 s3cret ci");
+        assert_eq!(snapshot.env["MODE"], "ci");
+        assert_eq!(snapshot.env["DB_PASSWORD"], "<set>");
+        assert_eq!(snapshot.env["API"], "<command:echo s3cret>");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::session::RunSummary;
+
+/// Outcome and duration figures over a task's indexed runs.
+#[derive(Debug, Clone, Default)]
+pub struct TaskStats {
+    pub runs: usize,
+    pub succeeded: usize,
+    /// In seconds, over the runs that recorded a duration.
+    pub avg_secs: Option<u64>,
+    pub p50_secs: Option<u64>,
+    pub p90_secs: Option<u64>,
+    pub p99_secs: Option<u64>,
+    pub last_failure: Option<RunSummary>,
+}
+
+impl TaskStats {
+    pub fn from_runs(runs: &[RunSummary]) -> Self {
+        let mut durations: Vec<u64> = runs.iter().filter_map(|run| run.duration_secs).collect();
+        durations.sort_unstable();
+        let percentile = |p: usize| {
+            // Nearest rank: the smallest duration at least p% of runs took no longer than.
+            let rank = (durations.len() * p).div_ceil(100).max(1);
+            durations.get(rank - 1).copied()
+        };
+        Self {
+            runs: runs.len(),
+            succeeded: runs.iter().filter(|run| run.succeeded()).count(),
+            avg_secs: (!durations.is_empty()).then(|| durations.iter().sum::<u64>() / durations.len() as u64),
+            p50_secs: percentile(50),
+            p90_secs: percentile(90),
+            p99_secs: percentile(99),
+            last_failure: runs
+                .iter()
+                .filter(|run| !run.succeeded())
+                .max_by_key(|run| run.started_at)
+                .cloned(),
+        }
+    }
+
+    /// Whole percent of runs that exited 0; `None` before the first run.
+    pub fn success_rate(&self) -> Option<u32> {
+        (self.runs > 0).then(|| (self.succeeded * 100 / self.runs) as u32)
+    }
+}
+
+/// Like `42s`, `3m05s` or `1h20m`.
+pub fn format_secs(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
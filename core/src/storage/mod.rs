@@ -1 +1,124 @@
+use crate::models::StoreConfig;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+/// Overrides where CmdHub keeps its state; inherited by child processes.
+pub const DATA_DIR_ENV: &str = "CMDHUB_DATA_DIR";
+const NO_STORE_ENV: &str = "CMDHUB_NO_STORE";
+const DEFAULT_DATABASE: &str = "sessions.db";
+
+static EPHEMERAL: OnceLock<()> = OnceLock::new();
+static SESSION_BACKEND: OnceLock<SessionBackend> = OnceLock::new();
+
+/// Root of sessions, recordings, snapshots, receipts and baselines:
+/// `$CMDHUB_DATA_DIR`, or `~/.cmdhub`.
+pub fn data_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = std::env::var("HOME").map_err(|_| anyhow!("HOME not set"))?;
+    Ok(Path::new(&home).join(".cmdhub"))
+}
+
+/// Why state is kept in a temporary directory for this process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ephemeral {
+    /// Asked for with `--no-store` or `CMDHUB_NO_STORE=1`.
+    Requested,
+    /// The data directory could not be written.
+    Unwritable(String),
+}
+
+/// Picks where state lives before anything is stored. With `no_store`, or
+/// when the data directory can't be written (read-only HOME in containers
+/// and CI), a fresh temp directory is used instead and exported through
+/// `CMDHUB_DATA_DIR`. Must be called before other threads start.
+pub fn init(no_store: bool) -> Result<Option<(PathBuf, Ephemeral)>> {
+    let requested = no_store || std::env::var(NO_STORE_ENV).is_ok_and(|value| value == "1" || value == "true");
+    let reason = if requested {
+        Ephemeral::Requested
+    } else {
+        match data_dir().and_then(|dir| check_writable(&dir)) {
+            Ok(()) => return Ok(None),
+            Err(err) => Ephemeral::Unwritable(err.to_string()),
+        }
+    };
+    let dir = std::env::temp_dir().join(format!("cmdhub-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|err| anyhow!("cannot create {}: {}", dir.display(), err))?;
+    std::env::set_var(DATA_DIR_ENV, &dir);
+    let _ = EPHEMERAL.set(());
+    Ok(Some((dir, reason)))
+}
+
+/// Where session metadata is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionBackend {
+    /// A `meta.json` per run under `sessions/`.
+    Files,
+    /// One SQLite database, which several processes may write at once.
+    Sqlite(PathBuf),
+}
+
+/// Selects the session backend from `[store]` for this process; the first
+/// call wins. A temporary data directory always keeps its own database, so
+/// `--no-store` never writes to a configured shared one.
+pub fn configure(config: Option<&StoreConfig>) -> Result<()> {
+    let backend = match config.and_then(|store| store.backend.as_deref()) {
+        None | Some("files") => SessionBackend::Files,
+        Some("sqlite") => {
+            let path = match config.and_then(|store| store.path.as_ref()) {
+                Some(path) if EPHEMERAL.get().is_none() => data_dir()?.join(path),
+                _ => data_dir()?.join(DEFAULT_DATABASE),
+            };
+            SessionBackend::Sqlite(path)
+        }
+        Some(other) => return Err(anyhow!("unknown store backend {:?}, expected \"files\" or \"sqlite\"", other)),
+    };
+    let _ = SESSION_BACKEND.set(backend);
+    Ok(())
+}
+
+/// The part of a file name that names the run `instance_id`.
+pub fn run_file_stem(instance_id: &str) -> String {
+    instance_id.replace(['#', '/'], "-")
+}
+
+/// The `.{extension}` files in `dir`, named `<started_at>-<run>`, oldest first.
+pub fn run_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Those of [`run_files`] for the run `instance_id`, oldest first.
+pub fn run_files_of(dir: &Path, extension: &str, instance_id: &str) -> Result<Vec<PathBuf>> {
+    let needle = run_file_stem(instance_id);
+    Ok(run_files(dir, extension)?
+        .into_iter()
+        .filter(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| stem == needle || stem.ends_with(&format!("-{}", needle)))
+        })
+        .collect())
+}
+
+pub fn session_backend() -> SessionBackend {
+    SESSION_BACKEND.get().cloned().unwrap_or(SessionBackend::Files)
+}
+
+fn check_writable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).map_err(|err| anyhow!("{}: {}", dir.display(), err))?;
+    let probe = dir.join(format!(".probe-{}", std::process::id()));
+    fs::write(&probe, b"").map_err(|err| anyhow!("{}: {}", dir.display(), err))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+/// Starts a stderr line in captured output of runs whose stdout and stderr
+/// are pipes: an APC string, which terminals ignore, then red.
+pub const STDERR_TAG: &str = "\x1b_CMDHUB:stderr\x1b\\\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether a raw output line came from stderr.
+pub fn is_stderr(line: &[u8]) -> bool {
+    let tag = STDERR_TAG.as_bytes();
+    line.windows(tag.len()).any(|window| window == tag)
+}
+
+/// The line without its stderr tag and color reset, if it has one.
+pub fn untag(line: &[u8]) -> Option<Vec<u8>> {
+    let tag = STDERR_TAG.as_bytes();
+    let pos = line.windows(tag.len()).position(|window| window == tag)?;
+    let rest = &line[pos + tag.len()..];
+    let mut out = line[..pos].to_vec();
+    out.extend_from_slice(rest.strip_suffix(RESET.as_bytes()).unwrap_or(rest));
+    Some(out)
+}
+
+/// A POSIX filter that tags each line on its stdin. `read` takes a pipe a
+/// byte at a time, so each line is passed on as soon as it ends and stderr
+/// stays interleaved with stdout; awk would wait for a full block.
+pub fn shell_filter() -> &'static str {
+    r#"while IFS= read -r line || [ -n "$line" ]; do printf '\033_CMDHUB:stderr\033\\\033[31m%s\033[0m\n' "$line"; done"#
+}
+
+/// Tags stderr bytes as they are read, for runs captured without a shell.
+/// Only whole lines are returned, so a tagged line is never split by
+/// output from the other stream.
+#[derive(Default)]
+pub struct StderrTagger {
+    partial: Vec<u8>,
+}
+
+impl StderrTagger {
+    pub fn tag(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for piece in data.split_inclusive(|&b| b == b'\n') {
+            match piece.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.partial.extend_from_slice(line);
+                    push_line(&mut out, &std::mem::take(&mut self.partial));
+                }
+                None => self.partial.extend_from_slice(piece),
+            }
+        }
+        out
+    }
+
+    /// The last line if it had no newline.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.partial.is_empty() {
+            push_line(&mut out, &std::mem::take(&mut self.partial));
+        }
+        out
+    }
+}
+
+fn push_line(out: &mut Vec<u8>, line: &[u8]) {
+    out.extend_from_slice(STDERR_TAG.as_bytes());
+    out.extend_from_slice(line);
+    out.extend_from_slice(RESET.as_bytes());
+    out.push(b'\n');
+}
@@ -0,0 +1,106 @@
+use crate::clock::now_epoch;
+use crate::instance::SessionManager;
+use crate::models::{SupervisorConfig, Task};
+use crate::session::{SessionHealth, SessionInfo, SessionStatus, SessionStore};
+use anyhow::Result;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL_SECS: u64 = 5;
+
+/// Finds recorded runs whose host process died without recording an exit,
+/// marks them crashed (keeping their session directory) and optionally
+/// restarts them in this process under the same session.
+pub struct Supervisor {
+    store: SessionStore,
+    manager: SessionManager,
+    tasks: Vec<Task>,
+    config: SupervisorConfig,
+}
+
+impl Supervisor {
+    pub fn new(manager: SessionManager, tasks: Vec<Task>, config: SupervisorConfig) -> Result<Self> {
+        Ok(Self {
+            store: SessionStore::new()?,
+            manager,
+            tasks,
+            config,
+        })
+    }
+
+    /// Scans in a background thread; must be called inside a Tokio runtime,
+    /// which restarted runs are spawned on.
+    pub fn start(self) {
+        let interval = Duration::from_secs(self.config.interval_secs.unwrap_or(DEFAULT_INTERVAL_SECS).max(1));
+        let runtime = tokio::runtime::Handle::current();
+        std::thread::spawn(move || {
+            let _runtime = runtime.enter();
+            loop {
+                if let Err(err) = self.scan() {
+                    log::warn!("supervisor scan failed: {}", err);
+                }
+                std::thread::sleep(interval);
+            }
+        });
+    }
+
+    pub fn scan(&self) -> Result<()> {
+        let own_pid = std::process::id();
+        for session in self.store.list_sessions()? {
+            let hosted = matches!(session.status, SessionStatus::Running | SessionStatus::Pending);
+            if !hosted || session.runner_pid.is_none_or(|pid| session.is_local() && pid == own_pid) {
+                continue;
+            }
+            if self.store.probe_session(&session) != SessionHealth::RunnerDead {
+                continue;
+            }
+            if let Some(crashed) = self.mark_crashed(&session)? {
+                self.handle_crash(crashed, session.runner_pid.unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+
+    /// Flips the session to crashed unless another supervisor got there first.
+    fn mark_crashed(&self, session: &SessionInfo) -> Result<Option<SessionInfo>> {
+        let mut claimed = false;
+        let info = self.store.update_session(session.id, |current| {
+            if current.status == SessionStatus::Exited || current.status == SessionStatus::Crashed {
+                return;
+            }
+            if current.runner_pid != session.runner_pid {
+                return;
+            }
+            claimed = true;
+            current.status = SessionStatus::Crashed;
+            current.crashed_at = Some(now_epoch());
+            current.duration_secs = current.crashed_at.map(|crashed| crashed.saturating_sub(current.started_at));
+            current.runner_pid = None;
+            current.socket_path = None;
+        })?;
+        Ok(claimed.then_some(info))
+    }
+
+    fn handle_crash(&self, session: SessionInfo, host_pid: u32) {
+        log::error!(
+            "host pid {} of session {} ({}) exited without recording an exit; kept {}",
+            host_pid,
+            session.id,
+            session.task_id,
+            self.store.session_dir(session.id).display()
+        );
+        if !self.config.restart {
+            return;
+        }
+        let task = match self.tasks.iter().find(|task| task.id == session.task_id) {
+            Some(task) => task,
+            None => {
+                log::warn!("cannot restart session {}: task {} is no longer configured", session.id, session.task_id);
+                return;
+            }
+        };
+        match self.manager.readopt_session(task, &session) {
+            Ok(info) => log::info!("restarted session {} as {}", session.id, info.id),
+            Err(err) => log::warn!("failed to restart session {}: {}", session.id, err),
+        }
+    }
+}
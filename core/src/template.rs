@@ -1,18 +1,58 @@
-use crate::models::InputConfig;
+use crate::models::{InputConfig, Task};
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-fn default_from_input(config: &InputConfig) -> Option<String> {
-    match config {
-        InputConfig::Select { default, .. } => Some(default.clone()),
-        InputConfig::Text { default, .. } => default.clone(),
+/// Substitutes `{{ name }}` placeholders. A placeholder may pipe its value
+/// through filters, applied left to right: `{{ branch | default:main | upper }}`.
+/// For compatibility, `{{ name | value }}` where `value` is not a filter is
+/// an inline default.
+///
+/// Values are shell-quoted on substitution, so the placeholder must not sit
+/// inside quotes in the command; inputs with `raw = true` are inserted as is.
+pub fn render_command(
+    command: &str,
+    values: &HashMap<String, String>,
+    inputs: Option<&HashMap<String, InputConfig>>,
+) -> Result<String> {
+    render(command, values, inputs, true)
+}
+
+/// The task's `cwd` with its placeholders filled in, unquoted, and a leading
+/// `~` expanded. It must be a directory already unless `cwd_create` is set,
+/// in which case it is created.
+pub fn prepare_cwd(task: &Task, values: &HashMap<String, String>) -> Result<Option<PathBuf>> {
+    let cwd = match &task.cwd {
+        Some(cwd) => cwd.to_string_lossy(),
+        None => return Ok(None),
+    };
+    let rendered = render(&cwd, values, task.inputs.as_ref(), false).map_err(|err| anyhow!("cwd {}: {}", cwd, err))?;
+    let path = match rendered.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("cwd {}: HOME is not set", cwd))?;
+            PathBuf::from(format!("{}{}", home.to_string_lossy(), rest))
+        }
+        _ => PathBuf::from(rendered),
+    };
+    if path.is_dir() {
+        return Ok(Some(path));
+    }
+    if path.exists() {
+        return Err(anyhow!("cwd {} is not a directory", path.display()));
     }
+    if !task.cwd_create.unwrap_or(false) {
+        return Err(anyhow!("cwd {} does not exist (set cwd_create = true to create it)", path.display()));
+    }
+    std::fs::create_dir_all(&path).map_err(|err| anyhow!("cannot create cwd {}: {}", path.display(), err))?;
+    Ok(Some(path))
 }
 
-pub fn render_command(
+/// Values are shell-quoted unless `quote` is false.
+fn render(
     command: &str,
     values: &HashMap<String, String>,
     inputs: Option<&HashMap<String, InputConfig>>,
+    quote: bool,
 ) -> Result<String> {
     let mut rendered = String::with_capacity(command.len());
     let mut cursor = 0;
@@ -33,22 +73,175 @@ pub fn render_command(
         if name.is_empty() {
             return Err(anyhow!("empty template variable"));
         }
-        let inline_default = parts.next().map(|value| value.trim().to_string());
+        let rest = parts.next().map(str::trim);
 
         let fallback = inputs
             .and_then(|map| map.get(name))
-            .and_then(default_from_input);
-        let value = values
-            .get(name)
-            .cloned()
-            .or(inline_default)
-            .or(fallback)
-            .ok_or_else(|| anyhow!("missing value for template variable: {}", name))?;
-
-        rendered.push_str(&value);
+            .and_then(InputConfig::default_value);
+        let raw = inputs.and_then(|map| map.get(name)).is_some_and(InputConfig::raw);
+        let mut quoted = false;
+        let mut value = values.get(name).cloned();
+        match rest {
+            Some(rest) if Filter::parse(first_segment(rest)).is_none() => {
+                value = value.or_else(|| Some(rest.to_string())).or(fallback);
+            }
+            Some(rest) => {
+                let mut fallback = fallback;
+                for segment in rest.split('|') {
+                    let filter = Filter::parse(segment)
+                        .ok_or_else(|| anyhow!("unknown filter {:?} for template variable {}", segment.trim(), name))?;
+                    quoted |= matches!(filter, Filter::Quote);
+                    value = filter.apply(value, &mut fallback);
+                }
+                value = value.or(fallback);
+            }
+            None => value = value.or(fallback),
+        }
+        let value = value.ok_or_else(|| anyhow!("missing value for template variable: {}", name))?;
+
+        if raw || quoted || !quote {
+            rendered.push_str(&value);
+        } else {
+            rendered.push_str(&shell_quote(&value));
+        }
         cursor = end + 2;
     }
 
     rendered.push_str(&command[cursor..]);
     Ok(rendered)
 }
+
+fn first_segment(rest: &str) -> &str {
+    rest.split('|').next().unwrap_or_default()
+}
+
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    Quote,
+    Default(String),
+}
+
+impl Filter {
+    fn parse(segment: &str) -> Option<Self> {
+        let segment = segment.trim();
+        if let Some(value) = segment.strip_prefix("default:") {
+            return Some(Filter::Default(value.to_string()));
+        }
+        match segment {
+            "upper" => Some(Filter::Upper),
+            "lower" => Some(Filter::Lower),
+            "trim" => Some(Filter::Trim),
+            "quote" => Some(Filter::Quote),
+            _ => None,
+        }
+    }
+
+    /// `default` fills in a missing or empty value; the other filters first
+    /// fall back to the input's configured default when there's no value.
+    fn apply(&self, value: Option<String>, fallback: &mut Option<String>) -> Option<String> {
+        match self {
+            Filter::Default(default) => match value {
+                Some(value) if !value.is_empty() => Some(value),
+                _ => Some(default.clone()),
+            },
+            _ => value.or_else(|| fallback.take()).map(|value| self.transform(value)),
+        }
+    }
+
+    fn transform(&self, value: String) -> String {
+        match self {
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Trim => value.trim().to_string(),
+            Filter::Quote => shell_quote(&value),
+            Filter::Default(_) => value,
+        }
+    }
+}
+
+/// Quotes `value` as a single POSIX shell word. Words made only of
+/// characters the shell never interprets are left bare.
+pub fn shell_quote(value: &str) -> String {
+    let plain = |ch: char| ch.is_ascii_alphanumeric() || "_-.,:/=@%+".contains(ch);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn inputs(toml: &str) -> HashMap<String, InputConfig> {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn values_are_quoted_as_one_shell_word() {
+        let values = values(&[("msg", "it's $HOME; rm -rf /"), ("branch", "main")]);
+        let rendered = render_command("git commit -m {{ msg }} && git push origin {{branch}}", &values, None).unwrap();
+        assert_eq!(rendered, r"git commit -m 'it'\''s $HOME; rm -rf /' && git push origin main");
+    }
+
+    #[test]
+    fn empty_values_are_quoted() {
+        assert_eq!(render_command("echo {{ name }}", &values(&[("name", "")]), None).unwrap(), "echo ''");
+    }
+
+    #[test]
+    fn raw_inputs_are_inserted_as_is() {
+        let inputs = inputs("[args]\ntype = \"text\"\nraw = true\n");
+        let rendered = render_command("ls {{ args }}", &values(&[("args", "-la /tmp")]), Some(&inputs)).unwrap();
+        assert_eq!(rendered, "ls -la /tmp");
+    }
+
+    #[test]
+    fn filters_apply_before_quoting() {
+        let values = values(&[("env", "  staging lab ")]);
+        assert_eq!(render_command("deploy {{ env | trim | upper }}", &values, None).unwrap(), "deploy 'STAGING LAB'");
+        assert_eq!(render_command("deploy {{ env | quote }}", &values, None).unwrap(), "deploy '  staging lab '");
+    }
+
+    #[test]
+    fn defaults_are_quoted_too() {
+        let inputs = inputs("[dir]\ntype = \"text\"\ndefault = \"my dir\"\n");
+        assert_eq!(render_command("cd {{ dir }}", &HashMap::new(), Some(&inputs)).unwrap(), "cd 'my dir'");
+        assert_eq!(render_command("cd {{ other | default:a b }}", &HashMap::new(), None).unwrap(), "cd 'a b'");
+        assert_eq!(render_command("cd {{ other | two words }}", &HashMap::new(), None).unwrap(), "cd 'two words'");
+    }
+
+    #[test]
+    fn missing_values_and_unknown_filters_are_errors() {
+        assert!(render_command("echo {{ name }}", &HashMap::new(), None).is_err());
+        assert!(render_command("echo {{ name | upper | shout }}", &values(&[("name", "x")]), None).is_err());
+        assert!(render_command("echo {{ name", &values(&[("name", "x")]), None).is_err());
+    }
+
+    #[test]
+    fn cwd_is_rendered_unquoted() {
+        let dir = std::env::temp_dir().join(format!("cmdhub template {}", uuid::Uuid::new_v4()));
+        let task: Task = toml::from_str(&format!(
+            "id = 't'\nname = 't'\ncommand = 'true'\ncwd = '{}'\ncwd_create = true\n",
+            dir.join("{{ name }}").display()
+        ))
+        .unwrap();
+        let cwd = prepare_cwd(&task, &values(&[("name", "it's here")])).unwrap().unwrap();
+        assert_eq!(cwd, dir.join("it's here"));
+        assert!(cwd.is_dir());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shell_quote_leaves_plain_words_bare() {
+        assert_eq!(shell_quote("feature/x-1.2"), "feature/x-1.2");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("'"), r"''\'''");
+    }
+}
@@ -0,0 +1,88 @@
+use crate::models::Task;
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+
+const DEFAULT_KILL_GRACE_SECS: u64 = 5;
+const POLL: Duration = Duration::from_millis(50);
+
+/// How a run is killed: `signal` goes to its process group first, and
+/// whatever is left of the group after `grace` gets SIGKILL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termination {
+    pub signal: i32,
+    pub grace: Duration,
+}
+
+impl Default for Termination {
+    fn default() -> Self {
+        Self { signal: libc::SIGTERM, grace: Duration::from_secs(DEFAULT_KILL_GRACE_SECS) }
+    }
+}
+
+impl Termination {
+    /// From the task's `kill_signal` and `kill_grace_secs`.
+    pub fn for_task(task: &Task) -> Result<Self> {
+        let mut termination = Self::default();
+        if let Some(name) = task.kill_signal.as_deref() {
+            termination.signal =
+                parse_signal(name).ok_or_else(|| anyhow!("task {}: unknown kill_signal {:?}", task.id, name))?;
+        }
+        if let Some(secs) = task.kill_grace_secs {
+            termination.grace = Duration::from_secs(secs);
+        }
+        Ok(termination)
+    }
+}
+
+/// `TERM`, `SIGTERM` or `15`, for the signals a command is asked to stop with.
+pub fn parse_signal(name: &str) -> Option<i32> {
+    if let Ok(number) = name.parse::<i32>() {
+        return (1..32).contains(&number).then_some(number);
+    }
+    let upper = name.to_ascii_uppercase();
+    let signal = match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "TERM" => libc::SIGTERM,
+        "INT" => libc::SIGINT,
+        "HUP" => libc::SIGHUP,
+        "QUIT" => libc::SIGQUIT,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "KILL" => libc::SIGKILL,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+/// Sends `termination.signal` to the process group led by `pid` and waits
+/// until the group is gone or `stopped` says the command has exited, for up
+/// to the grace period. Then whatever is left of the group is killed, so
+/// nothing the command started outlives it. Returns true when the command
+/// stopped before the grace period ran out.
+pub fn terminate(pid: u32, termination: &Termination, mut stopped: impl FnMut() -> bool) -> bool {
+    let pgid = pid as libc::pid_t;
+    if unsafe { libc::killpg(pgid, termination.signal) } != 0 {
+        // Nothing left to signal.
+        return true;
+    }
+    let deadline = Instant::now() + termination.grace;
+    let graceful = loop {
+        if stopped() || !group_alive(pgid) {
+            break true;
+        }
+        if Instant::now() >= deadline {
+            break false;
+        }
+        std::thread::sleep(POLL);
+    };
+    if group_alive(pgid) {
+        unsafe {
+            libc::killpg(pgid, libc::SIGKILL);
+        }
+    }
+    stopped();
+    graceful
+}
+
+fn group_alive(pgid: libc::pid_t) -> bool {
+    unsafe { libc::killpg(pgid, 0) == 0 }
+}
@@ -0,0 +1,43 @@
+use crate::config::{CONFIG_ENV, TASKS_DIR_ENV};
+use crate::storage::DATA_DIR_ENV;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Variables a window's command needs from this process; tmux starts new
+/// windows with its server's environment instead.
+const FORWARDED_ENV: &[&str] = &[DATA_DIR_ENV, CONFIG_ENV, TASKS_DIR_ENV, "CMDHUB_CONFIG_DIR"];
+
+/// Whether this process runs in a tmux pane.
+pub fn inside_tmux() -> bool {
+    std::env::var_os("TMUX").is_some()
+}
+
+/// Opens a tmux window named `name` that runs `argv` in `cwd`, in the
+/// background when `detached`, and returns its window id. The user's shell
+/// takes over the window once the command exits, like a CmdHub PTY.
+pub fn open_window(name: &str, cwd: &Path, argv: &[String], detached: bool) -> Result<String> {
+    if !inside_tmux() {
+        return Err(anyhow!("not inside tmux ($TMUX is not set)"));
+    }
+    let mut cmd = Command::new("tmux");
+    cmd.args(["new-window", "-P", "-F", "#{window_id}", "-n", name, "-c"]).arg(cwd);
+    if detached {
+        cmd.arg("-d");
+    }
+    for key in FORWARDED_ENV {
+        if let Ok(value) = std::env::var(key) {
+            cmd.arg("-e").arg(format!("{}={}", key, value));
+        }
+    }
+    cmd.args(["sh", "-c", r#""$@"; exec "${SHELL:-sh}""#, "sh"]).args(argv);
+    let output = cmd
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| anyhow!("tmux: {}", err))?;
+    if !output.status.success() {
+        return Err(anyhow!("tmux new-window failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// CPU and memory of a run, summed over the processes of its group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// Since the previous sample, where 100 is one core; `None` on the first.
+    pub cpu_percent: Option<f32>,
+    pub rss_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// Like `12% 48M`.
+    pub fn summary(&self) -> String {
+        let memory = format_bytes(self.rss_bytes);
+        match self.cpu_percent {
+            Some(cpu) => format!("{:.0}% {}", cpu, memory),
+            None => memory,
+        }
+    }
+}
+
+/// Reads [`ResourceUsage`] for process groups from `/proc`, keeping the CPU
+/// time seen last so each sample reports the usage since the one before.
+/// Samples are empty where there is no `/proc`.
+#[derive(Debug, Default)]
+pub struct UsageSampler {
+    last: HashMap<u32, (u64, Instant)>,
+}
+
+impl UsageSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Samples each of `pgids` in one pass over `/proc`; groups not asked
+    /// about are forgotten.
+    pub fn sample(&mut self, pgids: &[u32]) -> HashMap<u32, ResourceUsage> {
+        let mut totals: HashMap<u32, (u64, u64)> = HashMap::new();
+        if !pgids.is_empty() {
+            for stat in process_stats() {
+                if pgids.contains(&stat.pgid) {
+                    let total = totals.entry(stat.pgid).or_default();
+                    total.0 += stat.cpu_ticks;
+                    total.1 += stat.rss_pages;
+                }
+            }
+        }
+        let now = Instant::now();
+        let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f32;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+        self.last.retain(|pgid, _| totals.contains_key(pgid));
+        totals
+            .into_iter()
+            .map(|(pgid, (ticks, pages))| {
+                let cpu_percent = self.last.get(&pgid).and_then(|(last_ticks, at)| {
+                    let elapsed = now.duration_since(*at).as_secs_f32();
+                    (elapsed > 0.0)
+                        .then(|| ticks.saturating_sub(*last_ticks) as f32 / ticks_per_sec / elapsed * 100.0)
+                });
+                self.last.insert(pgid, (ticks, now));
+                (pgid, ResourceUsage { cpu_percent, rss_bytes: pages * page_size })
+            })
+            .collect()
+    }
+}
+
+struct ProcessStat {
+    pgid: u32,
+    cpu_ticks: u64,
+    rss_pages: u64,
+}
+
+fn process_stats() -> Vec<ProcessStat> {
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            name.to_str()?.parse::<u32>().ok()?;
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            parse_stat(&stat)
+        })
+        .collect()
+}
+
+/// The fields of `/proc/<pid>/stat` after the command name, which may
+/// itself contain spaces and parentheses.
+fn parse_stat(stat: &str) -> Option<ProcessStat> {
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 1..)?.split_whitespace().collect();
+    let field = |index: usize| fields.get(index).and_then(|value| value.parse::<u64>().ok());
+    // Numbered from the state, the third field of the whole line.
+    Some(ProcessStat {
+        pgid: field(2)? as u32,
+        cpu_ticks: field(11)? + field(12)?,
+        rss_pages: field(21)?,
+    })
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
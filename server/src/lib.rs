@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Result};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use clap::Args;
+use cmdhub_core::prelude::*;
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// Read when `--token` is not given.
+pub const TOKEN_ENV: &str = "CMDHUB_API_TOKEN";
+
+/// Where the API listens and what clients must send to use it.
+#[derive(Args, Clone)]
+pub struct ServeArgs {
+    #[arg(long, default_value_t = DEFAULT_PORT)]
+    pub port: u16,
+    /// Address to listen on; any but loopback needs a token
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: IpAddr,
+    /// Require "Authorization: Bearer TOKEN" on every request [env: CMDHUB_API_TOKEN]
+    #[arg(long, value_name = "TOKEN")]
+    pub token: Option<String>,
+}
+
+impl ServeArgs {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+
+    /// The token from `--token` or the environment. Errors when there is
+    /// none and the API would be reachable beyond this machine.
+    pub fn token(&self) -> Result<Option<String>> {
+        self.token_or(std::env::var(TOKEN_ENV).ok())
+    }
+
+    fn token_or(&self, from_env: Option<String>) -> Result<Option<String>> {
+        let token = self.token.clone().or(from_env).filter(|token| !token.is_empty());
+        if token.is_none() && !self.host.is_loopback() {
+            return Err(anyhow!(
+                "refusing to serve on {} without a token; pass --token or set {}",
+                self.host,
+                TOKEN_ENV
+            ));
+        }
+        Ok(token)
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    config: Arc<AppConfig>,
+    manager: SessionManager,
+}
+
+#[derive(Serialize)]
+struct TaskSummary<'a> {
+    id: &'a str,
+    name: &'a str,
+    category: Option<&'a str>,
+    command: &'a str,
+    inputs: Option<&'a HashMap<String, InputConfig>>,
+}
+
+#[derive(Deserialize, Default)]
+struct StartRunRequest {
+    #[serde(default)]
+    inputs: HashMap<String, String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorBody { error: self.1 })).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+/// The API's routes; with a `token`, requests without it are refused.
+pub fn router(config: AppConfig, manager: SessionManager, token: Option<String>) -> Router {
+    let state = ApiState {
+        config: Arc::new(config),
+        manager,
+    };
+    let router = Router::new()
+        .route("/api/tasks", get(list_tasks))
+        .route("/api/tasks/:id/runs", post(start_run))
+        .route("/api/runs", get(list_runs))
+        .route("/api/runs/:id", delete(kill_run))
+        .route("/api/runs/:id/cancel", post(cancel_run))
+        .route("/api/runs/:id/baseline", post(set_baseline))
+        .route("/api/runs/:id/logs", get(stream_logs))
+        .with_state(state);
+    match token {
+        Some(token) => router.route_layer(middleware::from_fn_with_state(Arc::<str>::from(token), require_token)),
+        None => router,
+    }
+}
+
+/// Serves the JSON control API until the process is stopped.
+pub async fn serve(config: AppConfig, manager: SessionManager, addr: SocketAddr, token: Option<String>) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log::info!("CmdHub API listening on http://{}", addr);
+    cmdhub_core::sdnotify::ready();
+    axum::serve(listener, router(config, manager, token)).await?;
+    Ok(())
+}
+
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let sent = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match sent {
+        Some(sent) if same_token(sent.as_bytes(), token.as_bytes()) => next.run(request).await,
+        _ => ApiError(StatusCode::UNAUTHORIZED, "missing or wrong bearer token".to_string()).into_response(),
+    }
+}
+
+/// Compares in time that depends only on the lengths.
+fn same_token(sent: &[u8], token: &[u8]) -> bool {
+    sent.len() == token.len() && sent.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn list_tasks(State(state): State<ApiState>) -> Json<serde_json::Value> {
+    let tasks: Vec<TaskSummary> = state
+        .config
+        .tasks
+        .iter()
+        .map(|task| TaskSummary {
+            id: &task.id,
+            name: &task.name,
+            category: task.category.as_deref(),
+            command: &task.command,
+            inputs: task.inputs.as_ref(),
+        })
+        .collect();
+    Json(serde_json::json!({ "tasks": tasks }))
+}
+
+async fn start_run(
+    State(state): State<ApiState>,
+    Path(task_id): Path<String>,
+    body: Option<Json<StartRunRequest>>,
+) -> Result<(StatusCode, Json<InstanceInfo>), ApiError> {
+    let task: &Task = state
+        .config
+        .tasks
+        .iter()
+        .find(|task| task.id == task_id)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("task not found: {}", task_id)))?;
+    if task.members.is_some() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, format!("{} is a group; start its members instead", task.id)));
+    }
+    let (mut values, tags) = body.map(|Json(body)| (body.inputs, body.tags)).unwrap_or_default();
+    let command = prepare_command(task, &mut values)
+        .map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+    state
+        .manager
+        .check_unique(task)
+        .map_err(|err| ApiError(StatusCode::CONFLICT, err.to_string()))?;
+    // Fetching the task's secrets may block on a keyring or command.
+    let task = task.clone();
+    let manager = state.manager.clone();
+    let info = tokio::task::spawn_blocking(move || {
+        manager.spawn_with(&task, &command, &values, &LaunchOptions { tags, ..Default::default() })
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!("run failed to start: {}", err))??;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn list_runs(State(state): State<ApiState>) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut runs = state.manager.list_instances()?;
+    runs.sort_by_key(|info| info.started_at);
+    Ok(Json(serde_json::json!({ "runs": runs })))
+}
+
+async fn kill_run(State(state): State<ApiState>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    if state.manager.kill_and_remove(&id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError(StatusCode::NOT_FOUND, format!("run not found: {}", id)))
+    }
+}
+
+async fn cancel_run(State(state): State<ApiState>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    match state.manager.cancel(&id) {
+        Ok(true) => Ok(StatusCode::ACCEPTED),
+        Ok(false) => Err(ApiError(StatusCode::CONFLICT, format!("run is not running: {}", id))),
+        Err(err) => Err(ApiError(StatusCode::NOT_FOUND, err.to_string())),
+    }
+}
+
+/// Makes a successful run the baseline its task's later runs are compared with.
+async fn set_baseline(State(state): State<ApiState>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    match state.manager.get_status(&id) {
+        Ok(Some(_)) => {}
+        _ => return Err(ApiError(StatusCode::NOT_FOUND, format!("run not found: {}", id))),
+    }
+    state
+        .manager
+        .set_baseline(&id)
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(|err| ApiError(StatusCode::CONFLICT, err.to_string()))
+}
+
+/// Streams buffered output followed by live output as server-sent events.
+async fn stream_logs(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (replay, output_rx) = state
+        .manager
+        .subscribe_output(&id)
+        .map_err(|err| ApiError(StatusCode::NOT_FOUND, err.to_string()))?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(64);
+    tokio::task::spawn_blocking(move || {
+        if !replay.is_empty() && tx.blocking_send(replay).is_err() {
+            return;
+        }
+        while let Ok(chunk) = output_rx.recv() {
+            if tx.blocking_send(chunk).is_err() {
+                break;
+            }
+        }
+    });
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        // JSON-encode so carriage returns and newlines survive the SSE framing.
+        let text = String::from_utf8_lossy(&chunk);
+        let event = Event::default()
+            .event("output")
+            .json_data(serde_json::json!({ "data": text }))
+            .unwrap_or_else(|_| Event::default().comment("encode error"));
+        Some((Ok(event), rx))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    fn args(host: &str, token: Option<&str>) -> ServeArgs {
+        ServeArgs {
+            port: 0,
+            host: host.parse().unwrap(),
+            token: token.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn non_loopback_binds_need_a_token() {
+        assert!(args("0.0.0.0", None).token_or(None).is_err());
+        assert!(args("::", None).token_or(None).is_err());
+        assert!(args("0.0.0.0", Some("")).token_or(None).is_err());
+        assert!(args("0.0.0.0", None).token_or(Some(String::new())).is_err());
+        assert_eq!(args("0.0.0.0", Some("s3cret")).token_or(None).unwrap().as_deref(), Some("s3cret"));
+        assert_eq!(args("127.0.0.1", None).token_or(None).unwrap(), None);
+        assert_eq!(args("::1", None).token_or(None).unwrap(), None);
+    }
+
+    #[test]
+    fn the_flag_wins_over_the_environment() {
+        let from_env = || Some("from-env".to_string());
+        assert_eq!(args("0.0.0.0", None).token_or(from_env()).unwrap().as_deref(), Some("from-env"));
+        assert_eq!(args("0.0.0.0", Some("flag")).token_or(from_env()).unwrap().as_deref(), Some("flag"));
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(same_token(b"s3cret", b"s3cret"));
+        assert!(!same_token(b"s3cres", b"s3cret"));
+        assert!(!same_token(b"s3cre", b"s3cret"));
+        assert!(!same_token(b"", b"s3cret"));
+    }
+
+    /// The status code of a `GET /api/tasks` sent with `authorization`.
+    fn get_tasks(addr: SocketAddr, authorization: Option<&str>) -> String {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        let header = authorization.map(|value| format!("Authorization: {}\r\n", value)).unwrap_or_default();
+        write!(stream, "GET /api/tasks HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", header).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.split_whitespace().nth(1).unwrap_or_default().to_string()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn requests_without_the_token_are_refused() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config: AppConfig = serde_json::from_str(r#"{"tasks": []}"#).unwrap();
+        let router = router(config, SessionManager::new(1024), Some("s3cret".to_string()));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let statuses = tokio::task::spawn_blocking(move || {
+            [None, Some("Bearer wrong"), Some("s3cret"), Some("Bearer s3cret")].map(|header| get_tasks(addr, header))
+        })
+        .await
+        .unwrap();
+        assert_eq!(statuses, ["401", "401", "401", "200"]);
+    }
+}
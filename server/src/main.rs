@@ -1,4 +1,55 @@
-#[tokio::main]
-async fn main() {
+use anyhow::Result;
+use clap::Parser;
+use cmdhub_core::config;
+use cmdhub_core::prelude::*;
+use cmdhub_core::storage;
+use cmdhub_server::ServeArgs;
+use std::path::PathBuf;
+
+const BUFFER_CAP: usize = 16 * 1024;
+
+#[derive(Parser)]
+#[command(name = "cmdhub-server", about = "Serve the CmdHub HTTP API")]
+struct Cli {
+    #[command(flatten)]
+    serve: ServeArgs,
+    /// Keep all state in a temporary directory, removed on exit
+    #[arg(long)]
+    no_store: bool,
+    /// Config file to load instead of searching for config.toml [env: CMDHUB_CONFIG]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Directory of task files to merge instead of tasks/ next to the config [env: CMDHUB_TASKS_DIR]
+    #[arg(long, value_name = "DIR")]
+    tasks_dir: Option<PathBuf>,
+    /// Apply the env and input defaults of this [profiles] entry [env: CMDHUB_PROFILE]
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+    let token = cli.serve.token()?;
+    // These export env vars, so they run before the runtime starts its threads.
+    config::set_overrides(cli.config.as_deref(), cli.tasks_dir.as_deref(), cli.profile.as_deref())?;
+    let ephemeral = storage::init(cli.no_store)?;
+    if let Some((dir, storage::Ephemeral::Unwritable(err))) = &ephemeral {
+        eprintln!("cannot store state ({}); keeping it in {} for this run", err, dir.display());
+    }
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(serve(cli, token));
+    if let Some((dir, _)) = ephemeral {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    result
+}
+
+async fn serve(cli: Cli, token: Option<String>) -> Result<()> {
     println!("CmdHub Server starting...");
+    let mut config = load_config_auto().await?;
+    config.tasks = config::profile_tasks(&config, config::selected_profile().as_deref())?;
+    storage::configure(config.store.as_ref())?;
+    let manager = SessionManager::from_config(&config, BUFFER_CAP)?;
+    cmdhub_server::serve(config, manager, cli.serve.addr(), token).await
 }